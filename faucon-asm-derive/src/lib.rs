@@ -15,429 +15,191 @@ pub fn instruction(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
     // Build the impl.
-    impl_instruction(&ast).unwrap().into()
+    impl_instruction(&ast)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
 
 fn impl_instruction(ast: &DeriveInput) -> Result<proc_macro2::TokenStream> {
     if let syn::Data::Enum(data) = &ast.data {
         let name = &ast.ident;
 
-        // Prepare the opcode tables.
-        let mut mrr = vec![quote! { None }; 0x3];
-        let mut srri8 = vec![quote! { None }; 0x10];
-        let mut srwi8 = vec![quote! { None }; 0x10];
-        let mut srwi16 = vec![quote! { None }; 0x10];
-        let mut sri8 = vec![quote! { None }; 0x10];
-        let mut sri16 = vec![quote! { None }; 0x10];
-        let mut swi8 = vec![quote! { None }; 0x10];
-        let mut smi8 = vec![quote! { None }; 0x10];
-        let mut smi16 = vec![quote! { None }; 0x10];
-        let mut srri16 = vec![quote! { None }; 0x10];
-        let mut srr = vec![quote! { None }; 0x10];
-        let mut srw = vec![quote! { None }; 0x10];
-        let mut swr = vec![quote! { None }; 0x10];
-        let mut smr = vec![quote! { None }; 0x10];
-        let mut srrw = vec![quote! { None }; 0x10];
-        let mut sm = vec![quote! { None }; 0x10];
-        let mut i24 = vec![quote! { None }; 0x10];
-        let mut rwi8 = vec![quote! { None }; 0x10];
-        let mut ri32 = vec![quote! { None }; 0x1];
-        let mut rwi16 = vec![quote! { None }; 0x10];
-        let mut mi8 = vec![quote! { None }; 0x10];
-        let mut mi16 = vec![quote! { None }; 0x10];
-        let mut ri8 = vec![quote! { None }; 0x10];
-        let mut i8 = vec![quote! { None }; 0x40];
-        let mut i16 = vec![quote! { None }; 0x40];
-        let mut rir = vec![quote! { None }; 0x10];
-        let mut n = vec![quote! { None }; 0x10];
-        let mut r = vec![quote! { None }; 0x10];
-        let mut rr = vec![quote! { None }; 0x10];
-        let mut w = vec![quote! { None }; 0x10];
-        let mut mr = vec![quote! { None }; 0x10];
-        let mut rw = vec![quote! { None }; 0x10];
-        let mut rrw = vec![quote! { None }; 0x10];
-
-        let mut register_instruction =
-            |vname: &syn::Ident, opcode: u8, subopcode: u8, operands: Vec<syn::Meta>| {
-                let (size, a, b) = parse_opcode(opcode);
-                let b = b as usize;
-                let subopcode = subopcode as usize;
-
-                let mut real_operands = Vec::new();
-                real_operands.extend(operands.iter().map(|o| quote! { #o }));
-                while real_operands.len() < 3 {
-                    real_operands.push(quote! { NOP })
+        // Prepare the instruction list and the opcode lookup table.
+        let mut instructions = Vec::new();
+        let mut opcode_table = vec![vec![quote! { None }; SUBOPCODE_COUNT]; OPCODE_COUNT];
+
+        // The variants that claimed the slots of the opcode table so far, along
+        // with the encoding they were claimed by.
+        let mut owners: Vec<Vec<Option<(syn::Ident, u8, u8)>>> =
+            vec![vec![None; SUBOPCODE_COUNT]; OPCODE_COUNT];
+
+        let mut register_instruction = |vname: &syn::Ident, attribute: InsnAttribute| {
+            let InsnAttribute {
+                opcode,
+                subopcode,
+                operands,
+                cycles,
+                flags,
+                ..
+            } = attribute;
+            let index = instructions.len();
+            let slots = get_table_slots(opcode, subopcode);
+
+            // Two encodings in the same slot would silently shadow each other.
+            for &(slot_opcode, slot_subopcode) in &slots {
+                let owner = &mut owners[slot_opcode as usize][slot_subopcode as usize];
+                if let Some((ref other, other_opcode, other_subopcode)) = *owner {
+                    return Err(conflict_error(
+                        vname,
+                        (opcode, subopcode),
+                        other,
+                        (other_opcode, other_subopcode),
+                        (slot_opcode, slot_subopcode),
+                    ));
                 }
-
-                let value = quote! {
-                    Some(instruction_meta!(#vname, #opcode, #subopcode, [#(#real_operands),*]))
-                };
-
-                match size {
-                    0x0..=0x2 => match a {
-                        0x0 => {
-                            mrr[subopcode] = value;
-                        }
-                        0x1 => {
-                            srwi8[b] = value;
-                        }
-                        0x2 => {
-                            srwi16[b] = value;
-                        }
-                        0x3 => match b {
-                            0x0 => {
-                                sri8[subopcode] = value;
-                            }
-                            0x1 => {
-                                sri16[subopcode] = value;
-                            }
-                            0x2 => {
-                                srr[subopcode] = value;
-                            }
-                            0x4 => {
-                                swi8[subopcode] = value;
-                            }
-                            0x5 => {
-                                srri8[subopcode] = value;
-                            }
-                            0x6 => {
-                                smi8[subopcode] = value;
-                            }
-                            0x7 => {
-                                smi16[subopcode] = value;
-                            }
-                            0x8 => {
-                                srri16[subopcode] = value;
-                            }
-                            0x9 => {
-                                srw[subopcode] = value;
-                            }
-                            0xA => {
-                                swr[subopcode] = value;
-                            }
-                            0xB => {
-                                smr[subopcode] = value;
-                            }
-                            0xC => {
-                                srrw[subopcode] = value;
-                            }
-                            0xD => {
-                                sm[subopcode] = value;
-                            }
-                            0xE => {
-                                i24[subopcode] = value;
-                            }
-                            0xF => {
-                                srr[subopcode] = value;
-                            }
-                            _ => unreachable!(),
-                        },
-                        _ => unreachable!(),
-                    },
-                    0x3 => match a {
-                        0x0 => {
-                            rwi8[b] = value;
-                        }
-                        0x1 => {
-                            ri32[0] = value;
-                        }
-                        0x2 => {
-                            rwi16[b] = value;
-                        }
-                        0x3 => match b {
-                            0x0 => {
-                                mi8[subopcode] = value;
-                            }
-                            0x1 => {
-                                mi16[subopcode] = value;
-                            }
-                            0x2 => {
-                                ri8[subopcode] = value;
-                            }
-                            0x3 => {
-                                i16[subopcode] = value;
-                            }
-                            0x4 => {
-                                i8[subopcode] = value;
-                            }
-                            0x5 => {
-                                i16[subopcode] = value;
-                            }
-                            0x6 => {
-                                rir[subopcode] = value;
-                            }
-                            0x7 => {
-                                rir[subopcode] = value;
-                            }
-                            0x8 => {
-                                n[subopcode] = value;
-                            }
-                            0x9 => {
-                                r[subopcode] = value;
-                            }
-                            0xA => {
-                                rr[subopcode] = value;
-                            }
-                            0xC => {
-                                w[subopcode] = value;
-                            }
-                            0xD => {
-                                mr[subopcode] = value;
-                            }
-                            0xE => {
-                                rw[subopcode] = value;
-                            }
-                            0xF => {
-                                rrw[subopcode] = value;
-                            }
-                            _ => unreachable!(),
-                        },
-                        _ => unreachable!(),
-                    },
-                    _ => unreachable!(),
-                };
-            };
-
-        for variant in data
-            .variants
-            .iter()
-            .filter(|v| v.ident != syn::Ident::new("XXX", Span::call_site()))
-            .collect::<Vec<&syn::Variant>>()
-        {
-            let vname = &variant.ident;
-
-            for result in extract_insn_attributes(variant)? {
-                let (opcode, subopcode, operands) = result;
-
-                register_instruction(vname, opcode, subopcode, operands);
+                *owner = Some((vname.clone(), opcode, subopcode));
             }
-        }
-
-        Ok(quote! {
-            const FORM_MRR: [Option<InstructionMeta>; 0x3] = [
-                #(#mrr),*
-            ];
-
-            const FORM_SRRI8: [Option<InstructionMeta>; 0x10] = [
-                #(#srri8),*
-            ];
-
-            const FORM_SRWI8: [Option<InstructionMeta>; 0x10] = [
-                #(#srwi8),*
-            ];
-
-            const FORM_SRWI16: [Option<InstructionMeta>; 0x10] = [
-                #(#srwi16),*
-            ];
-
-            const FORM_SRI8: [Option<InstructionMeta>; 0x10] = [
-                #(#sri8),*
-            ];
-
-            const FORM_SRI16: [Option<InstructionMeta>; 0x10] = [
-                #(#sri16),*
-            ];
-
-            const FORM_SWI8: [Option<InstructionMeta>; 0x10] = [
-                #(#swi8),*
-            ];
-
-            const FORM_SMI8: [Option<InstructionMeta>; 0x10] = [
-                #(#smi8),*
-            ];
-
-            const FORM_SMI16: [Option<InstructionMeta>; 0x10] = [
-                #(#smi16),*
-            ];
-
-            const FORM_SRRI16: [Option<InstructionMeta>; 0x10] = [
-                #(#srri16),*
-            ];
-
-            const FORM_SRR: [Option<InstructionMeta>; 0x10] = [
-                #(#srr),*
-            ];
-
-            const FORM_SRW: [Option<InstructionMeta>; 0x10] = [
-                #(#srw),*
-            ];
-
-            const FORM_SWR: [Option<InstructionMeta>; 0x10] = [
-                #(#swr),*
-            ];
 
-            const FORM_SMR: [Option<InstructionMeta>; 0x10] = [
-                #(#smr),*
-            ];
-
-            const FORM_SRRW: [Option<InstructionMeta>; 0x10] = [
-                #(#srrw),*
-            ];
-
-            const FORM_SM: [Option<InstructionMeta>; 0x10] = [
-                #(#sm),*
-            ];
-
-            const FORM_I24: [Option<InstructionMeta>; 0x10] = [
-                #(#i24),*
-            ];
-
-            const FORM_RWI8: [Option<InstructionMeta>; 0x10] = [
-                #(#rwi8),*
-            ];
-
-            const FORM_RI32: [Option<InstructionMeta>; 0x1] = [
-                #(#ri32),*
-            ];
+            let mut real_operands = Vec::new();
+            real_operands.extend(operands.iter().map(|o| quote! { #o }));
+            while real_operands.len() < 3 {
+                real_operands.push(quote! { NOP })
+            }
 
-            const FORM_RWI16: [Option<InstructionMeta>; 0x10] = [
-                #(#rwi16),*
-            ];
+            instructions.push(quote! {
+                instruction_meta!(
+                    #vname,
+                    #opcode,
+                    #subopcode,
+                    [#(#real_operands),*],
+                    #cycles,
+                    &[#(Flag::#flags),*]
+                )
+            });
+
+            for (opcode, subopcode) in slots {
+                opcode_table[opcode as usize][subopcode as usize] =
+                    quote! { Some(&INSTRUCTIONS[#index]) };
+            }
 
-            const FORM_MI8: [Option<InstructionMeta>; 0x10] = [
-                #(#mi8),*
-            ];
+            Ok(())
+        };
 
-            const FORM_MI16: [Option<InstructionMeta>; 0x10] = [
-                #(#mi16),*
-            ];
+        // The range of entries in the instruction list and the mnemonic that
+        // belong to every variant, indexed by its discriminant.
+        let mut encoding_ranges = Vec::new();
+        let mut mnemonics: Vec<(String, &syn::Ident)> = Vec::new();
+        let mut instruction_count: usize = 0;
 
-            const FORM_RI8: [Option<InstructionMeta>; 0x10] = [
-                #(#ri8),*
-            ];
-
-            const FORM_I8: [Option<InstructionMeta>; 0x40] = [
-                #(#i8),*
-            ];
+        for variant in data.variants.iter() {
+            let vname = &variant.ident;
 
-            const FORM_I16: [Option<InstructionMeta>; 0x40] = [
-                #(#i16),*
-            ];
+            if let Some((_, ref discriminant)) = variant.discriminant {
+                return Err(Error::new_spanned(
+                    discriminant,
+                    "#[derive(Instruction)] does not support explicit discriminants",
+                ));
+            }
 
-            const FORM_RIR: [Option<InstructionMeta>; 0x10] = [
-                #(#rir),*
-            ];
+            if vname == "XXX" {
+                encoding_ranges.push(quote! { (#instruction_count, #instruction_count) });
+                mnemonics.push(("???".to_string(), vname));
+                continue;
+            }
 
-            const FORM_N: [Option<InstructionMeta>; 0x10] = [
-                #(#n),*
-            ];
+            let attributes = extract_insn_attributes(variant)?;
+            let mnemonic = variant_mnemonic(vname, &attributes)?;
+            if let Some((_, other)) = mnemonics.iter().find(|(m, _)| *m == mnemonic) {
+                return Err(Error::new(
+                    vname.span(),
+                    format!(
+                        "`{}` has the same mnemonic \"{}\" as `{}`",
+                        vname, mnemonic, other
+                    ),
+                ));
+            }
+            mnemonics.push((mnemonic, vname));
 
-            const FORM_R: [Option<InstructionMeta>; 0x10] = [
-                #(#r),*
-            ];
+            let start = instruction_count;
+            for attribute in attributes {
+                register_instruction(vname, attribute)?;
+                instruction_count += 1;
+            }
+            encoding_ranges.push(quote! { (#start, #instruction_count) });
+        }
 
-            const FORM_RR: [Option<InstructionMeta>; 0x10] = [
-                #(#rr),*
-            ];
+        let variant_count = encoding_ranges.len();
+        let mnemonics = mnemonics.iter().map(|(mnemonic, _)| mnemonic);
+        let opcode_table = opcode_table
+            .iter()
+            .map(|row| quote! { [#(#row),*] })
+            .collect::<Vec<_>>();
 
-            const FORM_W: [Option<InstructionMeta>; 0x10] = [
-                #(#w),*
+        Ok(quote! {
+            /// All instruction encodings that are known to the ISA.
+            ///
+            /// Entries of the [`OPCODE_TABLE`] refer to these objects.
+            ///
+            /// [`OPCODE_TABLE`]: static.OPCODE_TABLE.html
+            pub static INSTRUCTIONS: [InstructionMeta; #instruction_count] = [
+                #(#instructions),*
             ];
 
-            const FORM_MR: [Option<InstructionMeta>; 0x10] = [
-                #(#mr),*
+            /// The opcode lookup table for identifying instructions.
+            ///
+            /// It is indexed by the first instruction byte and the subopcode that
+            /// is extracted from the location denoted by [`get_subopcode_location`].
+            /// Since some forms encode their subopcode in 6 bits, the table holds
+            /// 0x40 subopcode slots for every opcode.
+            ///
+            /// [`get_subopcode_location`]: ../opcode/fn.get_subopcode_location.html
+            pub static OPCODE_TABLE: [[Option<&InstructionMeta>; 0x40]; 0x100] = [
+                #(#opcode_table),*
             ];
 
-            const FORM_RW: [Option<InstructionMeta>; 0x10] = [
-                #(#rw),*
+            // The bounds of the encodings of every instruction kind in the
+            // `INSTRUCTIONS` array, indexed by the discriminant of the kind.
+            static ENCODING_RANGES: [(usize, usize); #variant_count] = [
+                #(#encoding_ranges),*
             ];
 
-            const FORM_RRW: [Option<InstructionMeta>; 0x10] = [
-                #(#rrw),*
+            // The mnemonics of all instruction kinds, indexed by the discriminant
+            // of the kind.
+            static MNEMONICS: [&str; #variant_count] = [
+                #(#mnemonics),*
             ];
 
             impl #name {
-                /// Checks if the instruction is invalid or unknown.
-                pub fn invalid(&self) -> bool {
-                    match self {
-                        #name::XXX => true,
-                        _ => false,
-                    }
-                }
-
-                /// Parses a sized instruction in form 1.
+                /// Gets the mnemonic of the instruction in the assembly syntax.
                 ///
-                /// This covers the opcode range from 0x00 to 0xBF. Form 1 essentially
-                /// means that `a` decides the form and `b` selects the subopcode.
-                pub fn parse_sized_form_1(a: u8, b: u8, subopcode: u8) -> Option<InstructionMeta> {
-                    let b = b as usize;
-                    let subopcode = subopcode as usize;
-
-                    match a {
-                        0x0 => FORM_MRR[subopcode].clone(),
-                        0x1 => FORM_SRWI8[b].clone(),
-                        0x2 => FORM_SRWI16[b].clone(),
-                        _ => None,
-                    }
-                }
-
-                /// Parses a sized instruction in form 2.
+                /// This is the lowercase name of the variant, unless it is
+                /// overridden through the `mnemonic` argument of `#[insn]`.
+                /// Invalid instructions are represented by `???`.
                 ///
-                /// This covers the opcode range from 0x00 to 0xBF. Form 2 essentially means that
-                /// `b` decides the form and `subopcode` is used to match the actual instruction.
-                pub fn parse_sized_form_2(b: u8, subopcode: u8) -> Option<InstructionMeta> {
-                    let subopcode = subopcode as usize;
-
-                    match b {
-                        0x0 => FORM_SRI8[subopcode].clone(),
-                        0x1 => FORM_SRI16[subopcode].clone(),
-                        0x2 => FORM_SRR[0x2].clone(),
-                        0x4 => FORM_SWI8[subopcode].clone(),
-                        0x5 => FORM_SRRI8[subopcode].clone(),
-                        0x6 => FORM_SMI8[subopcode].clone(),
-                        0x7 => FORM_SMI16[subopcode].clone(),
-                        0x8 => FORM_SRRI16[subopcode].clone(),
-                        0x9 => FORM_SRW[subopcode].clone(),
-                        0xA => FORM_SWR[subopcode].clone(),
-                        0xB => FORM_SMR[subopcode].clone(),
-                        0xC => FORM_SRRW[subopcode].clone(),
-                        0xD => FORM_SM[subopcode].clone(),
-                        0xE => FORM_I24[subopcode].clone(),
-                        0xF => FORM_SRR[subopcode].clone(),
-                        _ => None,
-                    }
+                /// ```
+                /// use faucon_asm::InstructionKind;
+                ///
+                /// assert_eq!(InstructionKind::LCALL.mnemonic(), "lcall");
+                /// assert_eq!("lcall".parse(), Ok(InstructionKind::LCALL));
+                /// ```
+                pub fn mnemonic(&self) -> &'static str {
+                    MNEMONICS[*self as usize]
                 }
 
-                /// Parses an unsized instruction in form 1.
-                ///
-                /// This covers the opcode range from 0xC0 to 0xFF. Form 1 essentially
-                /// means that `a` decides the form and `b` selects the subopcode.
-                pub fn parse_unsized_form_1(a: u8, b: u8) -> Option<InstructionMeta> {
-                    let b = b as usize;
-
-                    match a {
-                        0x0 => FORM_RWI8[b].clone(),
-                        0x1 => FORM_RI32[0].clone(),
-                        0x2 => FORM_RWI16[b].clone(),
-                        _ => None,
-                    }
+                /// Checks if the instruction is invalid or unknown.
+                pub fn invalid(&self) -> bool {
+                    *self == #name::XXX
                 }
 
-                /// Parses an unsized instruction in form 2.
+                /// Gets all built-in encodings of the instruction from the
+                /// [`INSTRUCTIONS`] array.
                 ///
-                /// This covers the opcode range from 0xC0 to 0xFF. Form 2 essentially means that
-                /// `b` decides the form and `subopcode` is used to match the actual instruction.
-                pub fn parse_unsized_form_2(b: u8, subopcode: u8) -> Option<InstructionMeta> {
-                    let subopcode = subopcode as usize;
-
-                    match b {
-                        0x0 => FORM_MI8[subopcode].clone(),
-                        0x1 => FORM_MI16[subopcode].clone(),
-                        0x2 => FORM_RI8[subopcode].clone(),
-                        0x3 => FORM_I16[subopcode].clone(),
-                        0x4 => FORM_I8[subopcode].clone(),
-                        0x5 => FORM_I16[subopcode].clone(),
-                        0x6 => FORM_RIR[subopcode].clone(),
-                        0x7 => FORM_RIR[subopcode].clone(),
-                        0x8 => FORM_N[subopcode].clone(),
-                        0x9 => FORM_R[subopcode].clone(),
-                        0xA => FORM_RR[subopcode].clone(),
-                        0xC => FORM_W[subopcode].clone(),
-                        0xD => FORM_MR[subopcode].clone(),
-                        0xE => FORM_RW[subopcode].clone(),
-                        0xF => FORM_RRW[subopcode].clone(),
-                        _ => None,
-                    }
+                /// Invalid instructions have no encodings.
+                ///
+                /// [`INSTRUCTIONS`]: static.INSTRUCTIONS.html
+                pub fn encodings(&self) -> &'static [InstructionMeta] {
+                    let (start, end) = ENCODING_RANGES[*self as usize];
+                    &INSTRUCTIONS[start..end]
                 }
             }
         })
@@ -449,11 +211,84 @@ fn impl_instruction(ast: &DeriveInput) -> Result<proc_macro2::TokenStream> {
     }
 }
 
+// Builds the error for an encoding that claims a slot of the opcode table which
+// is already taken by another one, pointing at both of the variants.
+fn conflict_error(
+    vname: &syn::Ident,
+    encoding: (u8, u8),
+    other: &syn::Ident,
+    other_encoding: (u8, u8),
+    slot: (u8, u8),
+) -> Error {
+    let mut error = Error::new(
+        vname.span(),
+        format!(
+            "`{}` (opcode 0x{:02X}, subopcode 0x{:02X}) conflicts with `{}` (opcode 0x{:02X}, \
+             subopcode 0x{:02X}) in the opcode table slot 0x{:02X}/0x{:02X}",
+            vname,
+            encoding.0,
+            encoding.1,
+            other,
+            other_encoding.0,
+            other_encoding.1,
+            slot.0,
+            slot.1
+        ),
+    );
+    error.combine(Error::new(
+        other.span(),
+        format!("`{}` claims the conflicting encoding here", other),
+    ));
+
+    error
+}
+
+const OPCODE_COUNT: usize = 0x100;
+const SUBOPCODE_COUNT: usize = 0x40;
+
+fn get_table_slots(opcode: u8, subopcode: u8) -> Vec<(u8, u8)> {
+    let (size, a, b) = parse_opcode(opcode);
+
+    match (size, a, b) {
+        // Sized forms where the subopcode is encoded in the size bits and a register
+        // in the low 4 bits of the opcode.
+        (0x0..=0x2, 0x0, _) => (0..0x10).map(|b| (opcode & 0xF0 | b, subopcode)).collect(),
+
+        // Sized forms where the subopcode is encoded in the size bits.
+        (0x0..=0x2, 0x3, 0xE) => vec![(opcode, subopcode)],
+
+        // Sized forms, which exist for every operand size.
+        (0x0..=0x2, _, _) => (0..0x3)
+            .map(|size| (size << 6 | opcode & 0x3F, subopcode))
+            .collect(),
+
+        // Unsized forms with a register encoded in the low 4 bits of the opcode,
+        // which happen to be the subopcode bits.
+        (0x3, 0x1, _) => (0..0x10).map(|b| (opcode & 0xF0 | b, b)).collect(),
+
+        // Regular unsized forms.
+        _ => vec![(opcode, subopcode)],
+    }
+}
+
 fn parse_opcode(opcode: u8) -> (u8, u8, u8) {
     (opcode >> 6, opcode >> 4 & 0x3, opcode & 0xF)
 }
 
-fn extract_insn_attributes(variant: &syn::Variant) -> Result<Vec<(u8, u8, Vec<syn::Meta>)>> {
+// The number of CPU cycles of an instruction that doesn't specify them.
+const DEFAULT_CYCLES: usize = 1;
+
+// The arguments of an `#[insn]` attribute.
+struct InsnAttribute {
+    opcode: u8,
+    subopcode: u8,
+    operands: Vec<syn::Meta>,
+    cycles: usize,
+    mnemonic: Option<String>,
+    flags: Vec<syn::Ident>,
+}
+
+fn extract_insn_attributes(variant: &syn::Variant) -> Result<Vec<InsnAttribute>> {
     let mut results = Vec::new();
 
     for attr in variant
@@ -462,7 +297,7 @@ fn extract_insn_attributes(variant: &syn::Variant) -> Result<Vec<(u8, u8, Vec<sy
         .filter(|a| a.path.segments.len() == 1 && a.path.segments[0].ident == "insn")
     {
         if let syn::Meta::List(ref nested_list) = attr.parse_meta()? {
-            if nested_list.nested.len() == 3 {
+            if (3..=6).contains(&nested_list.nested.len()) {
                 let mut arguments = Vec::new();
                 let mut operands = None;
 
@@ -482,11 +317,35 @@ fn extract_insn_attributes(variant: &syn::Variant) -> Result<Vec<(u8, u8, Vec<sy
                 let opcode = parse_int_arg(arguments[0], "opcode")?;
                 let subopcode = parse_int_arg(arguments[1], "subopcode")?;
                 let operands = parse_operands_vec(operands.unwrap(), "operands")?;
-                results.push((opcode, subopcode, operands));
+                let mut cycles = DEFAULT_CYCLES;
+                let mut mnemonic = None;
+                let mut flags = Vec::new();
+                for argument in arguments.iter().skip(2) {
+                    if argument.path.is_ident("mnemonic") {
+                        mnemonic = Some(parse_str_arg(argument, "mnemonic")?);
+                    } else if argument.path.is_ident("flags") {
+                        flags = parse_flags_arg(argument, "flags")?;
+                    } else if argument.path.is_ident("cycles") {
+                        cycles = parse_int_arg(argument, "cycles")?;
+                    } else {
+                        return Err(Error::new_spanned(
+                            &argument.path,
+                            "unknown #[insn] argument",
+                        ));
+                    }
+                }
+                results.push(InsnAttribute {
+                    opcode,
+                    subopcode,
+                    operands,
+                    cycles,
+                    mnemonic,
+                    flags,
+                });
             } else {
                 return Err(Error::new(
                     attr.path.segments[0].ident.span(),
-                    "#[insn] is expecting 3 arguments",
+                    "#[insn] is expecting 3 to 6 arguments",
                 ));
             }
         } else {
@@ -507,7 +366,11 @@ fn extract_insn_attributes(variant: &syn::Variant) -> Result<Vec<(u8, u8, Vec<sy
     }
 }
 
-fn parse_int_arg(meta: &syn::MetaNameValue, name: &str) -> Result<u8> {
+fn parse_int_arg<N>(meta: &syn::MetaNameValue, name: &str) -> Result<N>
+where
+    N: std::str::FromStr,
+    N::Err: std::fmt::Display,
+{
     verify_ident_name(&meta.path, name)?;
 
     if let syn::Lit::Int(ref int) = meta.lit {
@@ -520,6 +383,71 @@ fn parse_int_arg(meta: &syn::MetaNameValue, name: &str) -> Result<u8> {
     }
 }
 
+fn parse_str_arg(meta: &syn::MetaNameValue, name: &str) -> Result<String> {
+    verify_ident_name(&meta.path, name)?;
+
+    if let syn::Lit::Str(ref string) = meta.lit {
+        Ok(string.value())
+    } else {
+        Err(Error::new(
+            Span::call_site(),
+            format!("Failed to parse the \"{}\" string literal", name),
+        ))
+    }
+}
+
+// Parses a comma-separated list of flag names, such as "c,o,s,z", into the
+// identifiers of the corresponding `Flag` variants.
+fn parse_flags_arg(meta: &syn::MetaNameValue, name: &str) -> Result<Vec<syn::Ident>> {
+    verify_ident_name(&meta.path, name)?;
+
+    if let syn::Lit::Str(ref string) = meta.lit {
+        let mut flags = Vec::new();
+        for flag in string.value().split(',').map(str::trim) {
+            if flag.is_empty() || !flag.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(Error::new(
+                    string.span(),
+                    format!("\"{}\" is not a valid flag name", flag),
+                ));
+            }
+            flags.push(syn::Ident::new(&flag.to_uppercase(), string.span()));
+        }
+
+        Ok(flags)
+    } else {
+        Err(Error::new(
+            Span::call_site(),
+            format!("Failed to parse the \"{}\" string literal", name),
+        ))
+    }
+}
+
+// Determines the mnemonic of a variant from the `mnemonic` arguments of its
+// `#[insn]` attributes, which must all agree. Variants without one use their
+// name in lowercase.
+fn variant_mnemonic(variant: &syn::Ident, attributes: &[InsnAttribute]) -> Result<String> {
+    let mut mnemonic: Option<&String> = None;
+    for attribute in attributes {
+        match (mnemonic, &attribute.mnemonic) {
+            (Some(mnemonic), Some(candidate)) if mnemonic != candidate => {
+                return Err(Error::new(
+                    variant.span(),
+                    format!(
+                        "`{}` has the conflicting mnemonics \"{}\" and \"{}\"",
+                        variant, mnemonic, candidate
+                    ),
+                ));
+            }
+            (None, Some(candidate)) => mnemonic = Some(candidate),
+            _ => {}
+        }
+    }
+
+    Ok(mnemonic
+        .cloned()
+        .unwrap_or_else(|| variant.to_string().to_lowercase()))
+}
+
 fn parse_operands_vec(meta: &syn::MetaList, name: &str) -> Result<Vec<syn::Meta>> {
     verify_ident_name(&meta.path, name)?;
 
@@ -0,0 +1,234 @@
+//! Generates the instruction definitions of the crate from the ISA specification
+//! in `isa.toml`.
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use toml::Value;
+
+// The path to the ISA specification, relative to the manifest.
+const SPEC_PATH: &str = "isa.toml";
+
+// The maximum length of the lines of generated documentation.
+const DOC_WIDTH: usize = 72;
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SPEC_PATH);
+
+    let spec = fs::read_to_string(SPEC_PATH).expect("Failed to read the ISA specification");
+    let spec = spec
+        .parse::<Value>()
+        .unwrap_or_else(|e| panic!("Failed to parse the ISA specification: {}", e));
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("isa.rs"), generate(&spec)).unwrap();
+}
+
+// Generates the `InstructionKind` enum along with the aliases of its variants.
+fn generate(spec: &Value) -> String {
+    let instructions = spec
+        .get("instructions")
+        .and_then(Value::as_array)
+        .expect("The ISA specification has no `instructions` array");
+
+    let mut kinds = HashSet::new();
+    let mut mnemonics = HashSet::new();
+    let mut variants = String::new();
+    let mut aliases = Vec::new();
+    for instruction in instructions {
+        let kind = string(instruction, "kind");
+        let mnemonic = instruction
+            .get("mnemonic")
+            .map(|_| string(instruction, "mnemonic"));
+        if !kinds.insert(kind) {
+            panic!("Instruction `{}` is specified more than once", kind);
+        }
+        if !mnemonics.insert(mnemonic.map_or_else(|| kind.to_lowercase(), str::to_string)) {
+            panic!("The mnemonic of `{}` is used by another instruction", kind);
+        }
+
+        writeln!(variants, "    /// The {} instruction.", kind).unwrap();
+        writeln!(variants, "    ///").unwrap();
+        for line in wrap(string(instruction, "description"), DOC_WIDTH) {
+            writeln!(variants, "    /// {}", line).unwrap();
+        }
+
+        let forms = instruction
+            .get("forms")
+            .and_then(Value::as_array)
+            .unwrap_or_else(|| panic!("Instruction `{}` has no `forms` array", kind));
+        if forms.is_empty() {
+            panic!("Instruction `{}` has no forms", kind);
+        }
+        let flags = instruction
+            .get("flags")
+            .map(|_| string(instruction, "flags"));
+
+        // The mnemonic only needs to be given to one of the forms.
+        for (i, form) in forms.iter().enumerate() {
+            let mnemonic = if i == 0 { mnemonic } else { None };
+            writeln!(
+                variants,
+                "    {}",
+                insn_attribute(kind, form, mnemonic, flags)
+            )
+            .unwrap();
+        }
+        writeln!(variants, "    {},\n", kind).unwrap();
+
+        for alias in instruction
+            .get("aliases")
+            .map(|value| strings(value, kind, "aliases"))
+            .unwrap_or_default()
+        {
+            aliases.push((alias, kind));
+        }
+    }
+
+    let mut constants = String::new();
+    let mut alias_mnemonics = String::new();
+    for &(alias, kind) in &aliases {
+        if !kinds.insert(alias) || !mnemonics.insert(alias.to_lowercase()) {
+            panic!("The alias `{}` of `{}` is already in use", alias, kind);
+        }
+
+        writeln!(
+            constants,
+            "    /// The former name of [`{0}`](#variant.{0}).",
+            kind
+        )
+        .unwrap();
+        writeln!(
+            constants,
+            "    #[deprecated(note = \"renamed to `{}`\")]",
+            kind
+        )
+        .unwrap();
+        writeln!(
+            constants,
+            "    pub const {}: InstructionKind = InstructionKind::{};",
+            alias, kind
+        )
+        .unwrap();
+        writeln!(
+            alias_mnemonics,
+            "    ({:?}, InstructionKind::{}),",
+            alias.to_lowercase(),
+            kind
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"/// Assembly instruction kinds within the Falcon ISA.
+///
+/// Through internal implementation details, this enum is responsible for
+/// generating the [`OPCODE_TABLE`] that can be used to identify instructions
+/// and their variants. It is generated from the [`SPEC`].
+///
+/// [`OPCODE_TABLE`]: static.OPCODE_TABLE.html
+/// [`SPEC`]: constant.SPEC.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Instruction)]
+pub enum InstructionKind {{
+{}    /// An invalid or unknown instruction.
+    XXX,
+}}
+
+impl InstructionKind {{
+{}}}
+
+// The former mnemonics of renamed instructions, which are still accepted.
+const MNEMONIC_ALIASES: &[(&str, InstructionKind)] = &[
+{}];
+"#,
+        variants, constants, alias_mnemonics
+    )
+}
+
+// Generates the `#[insn]` attribute that describes a form of an instruction.
+fn insn_attribute(kind: &str, form: &Value, mnemonic: Option<&str>, flags: Option<&str>) -> String {
+    let integer = |key, max| match form.get(key).map(Value::as_integer) {
+        Some(Some(value)) if value >= 0 && value <= max => Some(value),
+        Some(_) => panic!("The `{}` of a form of `{}` is out of range", key, kind),
+        None => None,
+    };
+
+    let opcode =
+        integer("opcode", 0xFF).unwrap_or_else(|| panic!("A form of `{}` has no `opcode`", kind));
+    let subopcode = integer("subopcode", 0x3F)
+        .unwrap_or_else(|| panic!("A form of `{}` has no `subopcode`", kind));
+    let operands = strings(
+        form.get("operands")
+            .unwrap_or_else(|| panic!("A form of `{}` has no `operands` array", kind)),
+        kind,
+        "operands",
+    );
+    if operands.len() > 3 {
+        panic!("A form of `{}` has more than 3 operands", kind);
+    }
+
+    let mut attribute = format!(
+        "#[insn(opcode = 0x{:02X}, subopcode = 0x{:02X}, operands({})",
+        opcode,
+        subopcode,
+        operands.join(", ")
+    );
+    if let Some(cycles) = integer("cycles", i64::from(u32::MAX)) {
+        write!(attribute, ", cycles = {}", cycles).unwrap();
+    }
+    if let Some(mnemonic) = mnemonic {
+        write!(attribute, ", mnemonic = {:?}", mnemonic).unwrap();
+    }
+    if let Some(flags) = flags {
+        write!(attribute, ", flags = {:?}", flags).unwrap();
+    }
+    attribute.push_str(")]");
+
+    attribute
+}
+
+// Gets a string value of an instruction in the specification.
+fn string<'a>(instruction: &'a Value, key: &str) -> &'a str {
+    instruction
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| panic!("An instruction has no `{}` string: {}", key, instruction))
+}
+
+// Gets the strings of an array that belongs to the given instruction.
+fn strings<'a>(value: &'a Value, kind: &str, key: &str) -> Vec<&'a str> {
+    value
+        .as_array()
+        .unwrap_or_else(|| panic!("The `{}` of `{}` must be an array", key, kind))
+        .iter()
+        .map(|element| {
+            element
+                .as_str()
+                .unwrap_or_else(|| panic!("The {} of `{}` must be strings", key, kind))
+        })
+        .collect()
+}
+
+// Breaks text into lines of at most `width` characters, where possible.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
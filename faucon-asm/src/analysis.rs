@@ -0,0 +1,317 @@
+//! Static analysis of Falcon binary images.
+//!
+//! Firmware images commonly interleave code with data, such as jump tables or
+//! constant pools. Disassembling such an image linearly produces garbage for the
+//! data parts, so this module provides heuristics for telling them apart.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::disassembler::read_instruction_at;
+use crate::isa::InstructionKind;
+use crate::operands::Operand;
+use crate::Instruction;
+
+/// The kinds of regions that a binary image is made up of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    /// A region that contains executable instructions.
+    Code,
+    /// A region that contains data which is not meant to be executed.
+    Data,
+}
+
+/// A contiguous range of addresses in a binary image that shares the same
+/// [`RegionKind`].
+///
+/// [`RegionKind`]: enum.RegionKind.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// The address of the first byte in the region.
+    pub start: u32,
+    /// The address after the last byte in the region.
+    pub end: u32,
+    /// The classification of the region.
+    pub kind: RegionKind,
+}
+
+impl Region {
+    /// Gets the size of the region in bytes.
+    pub fn len(&self) -> usize {
+        self.end.wrapping_sub(self.start) as usize
+    }
+
+    /// Checks whether the region contains no bytes at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Classifies the bytes of a binary image as code or data.
+///
+/// `base_pc` denotes the address at which the start of `code` is mapped and
+/// `entry_points` are the addresses where execution is known to start, with
+/// `base_pc` always being considered one of them.
+///
+/// The classification is based on the following heuristics:
+///
+/// - bytes that are reachable through control flow from the entry points are code
+/// - addresses that are referenced through immediates in reachable code, but are
+///   not reachable themselves, are assumed to be the start of data
+/// - remaining ranges are code if they consist of valid instructions that end in
+///   a control flow transfer, otherwise they are data
+///
+/// The resulting regions are sorted by address and cover the whole image.
+///
+/// ```
+/// use faucon_asm::analysis::{classify, RegionKind};
+///
+/// let code = [0xF8u8, 0x00u8, 0xFFu8, 0xFFu8, 0xFFu8, 0xFFu8];
+/// let regions = classify(&code, 0, &[]);
+///
+/// assert_eq!(regions.len(), 2);
+/// assert_eq!(regions[0].kind, RegionKind::Code);
+/// assert_eq!((regions[1].start, regions[1].kind), (2, RegionKind::Data));
+/// ```
+pub fn classify(code: &[u8], base_pc: u32, entry_points: &[u32]) -> Vec<Region> {
+    let mut kinds = vec![None; code.len()];
+    let mut data_refs = BTreeSet::new();
+
+    // Recursively follow the control flow from all entry points.
+    let mut worklist = vec![0];
+    worklist.extend(
+        entry_points
+            .iter()
+            .map(|&address| address.wrapping_sub(base_pc) as usize),
+    );
+    while let Some(mut offset) = worklist.pop() {
+        while offset < code.len() && kinds[offset].is_none() {
+            let insn = match read_instruction_at(code, offset, base_pc) {
+                Ok((insn, _)) if offset + insn.len() <= code.len() => insn,
+                _ => break,
+            };
+
+            for byte in kinds[offset..offset + insn.len()].iter_mut() {
+                *byte = Some(RegionKind::Code);
+            }
+
+            for value in immediates(&insn) {
+                let target = value.wrapping_sub(base_pc) as usize;
+                if target >= code.len() {
+                    continue;
+                }
+
+                if insn.kind().is_branch() {
+                    worklist.push(target);
+                } else {
+                    data_refs.insert(target);
+                }
+            }
+
+            if is_terminator(insn.kind()) {
+                break;
+            }
+            offset += insn.len();
+        }
+    }
+
+    // Classify the remaining gaps, which are further split at data references.
+    let mut offset = 0;
+    while offset < code.len() {
+        if kinds[offset].is_some() {
+            offset += 1;
+            continue;
+        }
+
+        let mut end = offset + 1;
+        while end < code.len() && kinds[end].is_none() && !data_refs.contains(&end) {
+            end += 1;
+        }
+
+        let kind = if data_refs.contains(&offset) || !looks_like_code(code, offset, end) {
+            RegionKind::Data
+        } else {
+            RegionKind::Code
+        };
+        for byte in kinds[offset..end].iter_mut() {
+            *byte = Some(kind);
+        }
+
+        offset = end;
+    }
+
+    // Merge adjacent bytes of the same kind into regions.
+    let mut regions: Vec<Region> = Vec::new();
+    for (offset, kind) in kinds.into_iter().enumerate() {
+        let kind = kind.unwrap();
+        let address = base_pc.wrapping_add(offset as u32);
+
+        match regions.last_mut() {
+            Some(region) if region.kind == kind => region.end = address.wrapping_add(1),
+            _ => regions.push(Region {
+                start: address,
+                end: address.wrapping_add(1),
+                kind,
+            }),
+        }
+    }
+
+    regions
+}
+
+/// A line in a disassembly listing produced by [`listing`].
+///
+/// [`listing`]: fn.listing.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    /// A decoded instruction from a code region.
+    Instruction(Box<Instruction>),
+    /// A 32-bit word from a data region at the given address.
+    Word(u32, u32),
+    /// A single byte from a data region at the given address.
+    Byte(u32, u8),
+}
+
+impl Line {
+    /// Gets the address of the line in the image.
+    pub fn address(&self) -> u32 {
+        match self {
+            Line::Instruction(insn) => insn.program_counter(),
+            Line::Word(address, _) => *address,
+            Line::Byte(address, _) => *address,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instruction(insn) => write!(f, "{}", insn),
+            Line::Word(_, value) => write!(f, ".word {:#010x}", value),
+            Line::Byte(_, value) => write!(f, ".byte {:#04x}", value),
+        }
+    }
+}
+
+/// Produces a disassembly listing of a binary image from its [`Region`]s, as
+/// obtained through [`classify`].
+///
+/// Code regions are emitted as instructions, whereas data regions are emitted
+/// as `.word` directives for aligned words and `.byte` directives for the rest.
+///
+/// ```
+/// use faucon_asm::analysis::{classify, listing};
+///
+/// let code = [0xF8u8, 0x00u8, 0xFFu8, 0xFFu8, 0x78u8, 0x56u8, 0x34u8, 0x12u8];
+/// let lines = listing(&code, 0, &classify(&code, 0, &[]))
+///     .iter()
+///     .map(|line| line.to_string())
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(lines, ["ret", ".byte 0xff", ".byte 0xff", ".word 0x12345678"]);
+/// ```
+///
+/// Data that is branched over is not mistaken for the code that follows an
+/// unconditional branch, whereas the target of the branch is:
+///
+/// ```
+/// use faucon_asm::analysis::{classify, listing};
+/// use faucon_asm::assembler::assemble_str;
+///
+/// let code = assemble_str("bra skip\n.byte 0xff\n.dword 0x12345678\nskip:\nret").unwrap();
+/// let lines = listing(&code, 0, &classify(&code, 0, &[]))
+///     .iter()
+///     .map(|line| line.to_string())
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(lines, ["bra 0x8", ".byte 0xff", ".word 0x12345678", "ret"]);
+/// ```
+///
+/// [`Region`]: struct.Region.html
+/// [`classify`]: fn.classify.html
+pub fn listing(code: &[u8], base_pc: u32, regions: &[Region]) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    for region in regions {
+        let start = region.start.wrapping_sub(base_pc) as usize;
+        let end = (start + region.len()).min(code.len());
+
+        let mut offset = start;
+        while offset < end {
+            let address = base_pc.wrapping_add(offset as u32);
+
+            if region.kind == RegionKind::Code {
+                if let Ok((insn, next_offset)) = read_instruction_at(code, offset, base_pc) {
+                    if next_offset <= end {
+                        lines.push(Line::Instruction(Box::new(insn)));
+                        offset = next_offset;
+                        continue;
+                    }
+                }
+            } else if address & 3 == 0 && offset + 4 <= end {
+                let bytes = [
+                    code[offset],
+                    code[offset + 1],
+                    code[offset + 2],
+                    code[offset + 3],
+                ];
+                lines.push(Line::Word(address, u32::from_le_bytes(bytes)));
+                offset += 4;
+                continue;
+            }
+
+            // Anything that cannot be represented otherwise is emitted bytewise.
+            lines.push(Line::Byte(address, code[offset]));
+            offset += 1;
+        }
+    }
+
+    lines
+}
+
+fn looks_like_code(code: &[u8], start: usize, end: usize) -> bool {
+    // Runs of zeroes are most likely padding.
+    if code[start..end].iter().all(|&b| b == 0) {
+        return false;
+    }
+
+    let mut offset = start;
+    let mut last_kind = None;
+    while offset < end {
+        match read_instruction_at(code, offset, 0) {
+            Ok((insn, next_offset)) if next_offset <= end => {
+                last_kind = Some(insn.kind());
+                offset = next_offset;
+            }
+            _ => return false,
+        }
+    }
+
+    matches!(last_kind, Some(kind) if is_terminator(kind))
+}
+
+fn immediates(insn: &Instruction) -> Vec<u32> {
+    insn.operands()
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::I16(value) => Some(value as u32),
+            Operand::I24(value) => Some(value),
+            Operand::I32(value) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_terminator(kind: InstructionKind) -> bool {
+    matches!(
+        kind,
+        InstructionKind::RET
+            | InstructionKind::MPOPRET
+            | InstructionKind::MPOPADDRET
+            | InstructionKind::IRET
+            | InstructionKind::EXIT
+            | InstructionKind::BRA
+            | InstructionKind::JMP
+            | InstructionKind::LJMP
+    )
+}
@@ -177,6 +177,12 @@ pub const I16: Argument = immediate!(U16, 2, 2, false, None, None);
 /// These are used for most 32-bit instructions.
 pub const I16ZX32: Argument = immediate!(U32, 2, 2, false, None, None);
 
+/// An unsigned 16-bit immediate zero-extended to 32 bits and shifted left
+/// by 16.
+///
+/// These are used by the SETHI instruction.
+pub const I16ZX32S16: Argument = immediate!(U32, 2, 2, false, Some(16), None);
+
 /// An unsigned 16-bit immediate zero-extended to 32 bits.
 ///
 /// These are used for Falcon v5 call instructions.
@@ -229,6 +235,16 @@ pub const I24SX32: Argument = immediate!(I32, 1, 3, true, None, None);
 /// These are used for mov instructions.
 pub const I32: Argument = immediate!(U32, 1, 4, false, None, None);
 
+/// A signed 8-bit offset relative to the program counter.
+///
+/// These are used for the targets of short branches.
+pub const PC8: Argument = immediate!(PcRel, 2, 1, true, None, None);
+
+/// A signed 16-bit offset relative to the program counter.
+///
+/// These are used for the targets of long branches.
+pub const PC16: Argument = immediate!(PcRel, 2, 2, true, None, None);
+
 /// A Falcon general-purpose register, encoded in the low 4 bits of the first
 /// instruction byte.
 pub const R0: Argument = register!(Gpr, 0, false);
@@ -263,6 +279,12 @@ pub const FLAGS: Argument = register!(Spr, 8);
 /// instructions that operate on the flag bits.
 pub const FLAG: Argument = immediate!(Flag, 2, 1, false, None, Some(0x1F));
 
+/// A predicate bit in the `$flags` register.
+///
+/// It is used by the BP and BNP instructions and is encoded in the low three
+/// bits of instruction byte 1, the subopcode in this case.
+pub const PRED: Argument = immediate!(Flag, 1, 1, false, None, Some(0x7));
+
 /// A software trap value.
 ///
 /// It is used by the TRAP instruction and is encoded in the low two bits of
@@ -441,6 +463,8 @@ pub enum Argument {
     U32(Immediate<u32>),
     /// A signed 32-bit immediate.
     I32(Immediate<i32>),
+    /// A signed offset relative to the program counter of an instruction.
+    PcRel(Immediate<i32>),
 
     /// A CPU register.
     Register(Register),
@@ -471,6 +495,7 @@ impl Argument {
             Argument::I24(imm) => imm.position,
             Argument::U32(imm) => imm.position,
             Argument::I32(imm) => imm.position,
+            Argument::PcRel(imm) => imm.position,
             Argument::Register(reg) => reg.position,
             Argument::Flag(imm) => imm.position,
             Argument::Memory(mem) => mem.position(),
@@ -492,12 +517,54 @@ impl Argument {
             Argument::I24(imm) => imm.width,
             Argument::U32(imm) => imm.width,
             Argument::I32(imm) => imm.width,
+            Argument::PcRel(imm) => imm.width,
             Argument::Register(_) => 1,
             Argument::Flag(imm) => imm.width,
             Argument::Memory(mem) => mem.width(),
             _ => 0,
         }
     }
+    /// Encodes an immediate value into the given instruction bytes, given that
+    /// the [`Argument`] represents an immediate or a flag bit.
+    ///
+    /// Returns `false` if the value cannot be represented by the encoding.
+    ///
+    /// [`Argument`]: enum.Argument.html
+    pub fn write_immediate(&self, insn: &mut [u8], value: i64) -> bool {
+        match self {
+            Argument::U8(imm) => imm.write(insn, value, 8),
+            Argument::I8(imm) => imm.write(insn, value, 8),
+            Argument::U16(imm) => imm.write(insn, value, 16),
+            Argument::I16(imm) => imm.write(insn, value, 16),
+            Argument::U24(imm) => imm.write(insn, value, 32),
+            Argument::I24(imm) => imm.write(insn, value, 32),
+            Argument::U32(imm) => imm.write(insn, value, 32),
+            Argument::I32(imm) => imm.write(insn, value, 32),
+            Argument::PcRel(imm) => imm.write(insn, value, 32),
+            Argument::Flag(imm) => imm.write(insn, value, 8),
+            _ => false,
+        }
+    }
+
+    /// Gets the position, the width and the signedness of an immediate which
+    /// is stored as a plain little-endian integer in instruction bytes.
+    ///
+    /// Such immediates can be patched after the fact, without considering any
+    /// shifts or masks.
+    pub fn plain_immediate(&self) -> Option<(usize, usize, bool)> {
+        match self {
+            Argument::U8(imm) => imm.plain(),
+            Argument::I8(imm) => imm.plain(),
+            Argument::U16(imm) => imm.plain(),
+            Argument::I16(imm) => imm.plain(),
+            Argument::U24(imm) => imm.plain(),
+            Argument::I24(imm) => imm.plain(),
+            Argument::U32(imm) => imm.plain(),
+            Argument::I32(imm) => imm.plain(),
+            Argument::PcRel(imm) => imm.plain(),
+            _ => None,
+        }
+    }
 }
 
 /// An immediate number in Falcon assembly.
@@ -516,6 +583,14 @@ pub struct Immediate<T> {
 }
 
 impl<T: PrimInt + NumCast> Immediate<T> {
+    fn plain(&self) -> Option<(usize, usize, bool)> {
+        if self.shift.is_none() && self.mask.is_none() && self.raw_value.is_none() {
+            Some((self.position, self.width, self.sign))
+        } else {
+            None
+        }
+    }
+
     fn shift(&self) -> usize {
         self.shift.unwrap_or(0)
     }
@@ -575,6 +650,38 @@ impl<T: PrimInt + NumCast> Immediate<T> {
 
         value << self.shift()
     }
+    /// Encodes a value into the given instruction bytes, so that it can be read
+    /// back through [`Immediate::read`]. `bits` denotes the size of the values
+    /// that are represented by this [`Immediate`].
+    ///
+    /// Returns `false` if the value cannot be represented by the encoding.
+    ///
+    /// [`Immediate::read`]: struct.Immediate.html#method.read
+    /// [`Immediate`]: struct.Immediate.html
+    pub fn write(&self, insn: &mut [u8], value: i64, bits: u32) -> bool {
+        // Bring negative values into two's complement representation.
+        let value_mask = (1u64 << bits) - 1;
+        if value < -(1i64 << (bits - 1)) || value > value_mask as i64 {
+            return false;
+        }
+        let value = value as u64 & value_mask;
+
+        if let Some(raw) = self.raw_value {
+            return raw.to_i64().map(|v| v as u64 & value_mask) == Some(value);
+        }
+
+        let raw = value >> self.shift();
+        let mask = self.mask() as u64;
+        for i in 0..self.width {
+            let byte_mask = (mask >> (i * 8)) as u8;
+            let byte = &mut insn[self.position + i];
+            *byte = *byte & !byte_mask | (raw >> (i * 8)) as u8 & byte_mask;
+        }
+
+        // Verify the encoding by reading the value back, which catches all the
+        // cases where bits were lost to masking, shifting or sign extension.
+        self.read(insn).to_i64().map(|v| v as u64 & value_mask) == Some(value)
+    }
 }
 
 /// A CPU register in Falcon assembly.
@@ -611,6 +718,27 @@ impl Register {
 
         self.get_value(insn[self.position])
     }
+    /// Encodes the value of a register into the given instruction bytes.
+    ///
+    /// Returns `false` if the register cannot be represented by the encoding.
+    pub fn write(&self, insn: &mut [u8], value: u8) -> bool {
+        if let Some(reg) = self.raw_value {
+            return reg == value;
+        }
+
+        if value > 0xF {
+            return false;
+        }
+
+        let byte = &mut insn[self.position];
+        if self.high {
+            *byte = *byte & 0xF | value << 4;
+        } else {
+            *byte = *byte & 0xF0 | value;
+        }
+
+        true
+    }
 }
 
 // FIXME: These Options technically can never be None, change the types and unwrap the arg!
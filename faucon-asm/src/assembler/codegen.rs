@@ -0,0 +1,1719 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use crate::arguments::{Argument, MemoryAccess as ArgMemoryAccess, Register as ArgRegister};
+use crate::debug_info::{self, DebugInfo};
+use crate::isa::{instruction_encodings, InstructionKind, InstructionMeta};
+use crate::object::{self, Object, RelocationKind, Target};
+use crate::opcode::{get_opcode_form, get_subopcode_location, OperandSize, SubopcodeLocation};
+use crate::operands::{Flag, MemorySpace, Register};
+use crate::MAX_INSTRUCTION_LENGTH;
+
+use super::parser::{
+    Base, ChecksumKind, EvalError, Expr, MemoryOffset, Operand, OperandKind, Relocatable,
+    Statement, StatementKind,
+};
+use super::{
+    report, ParseError, ParseErrors, Program, Section, SecureRegion, Signature, Span,
+    SECURE_PAGE_SIZE, SIGNATURE_SIZE,
+};
+
+/// The name of the section that code is placed in unless specified otherwise.
+pub const DEFAULT_SECTION: &str = "code";
+
+/// The maximum number of bytes that a single directive may emit, which
+/// guards against runaway counts and sizes.
+pub const MAX_DIRECTIVE_SIZE: i64 = 0x100_0000;
+
+/// The settings that control how code is generated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Whether to replace instructions by equivalent ones with shorter
+    /// encodings and to compact the layout where possible.
+    pub optimize: bool,
+    /// Whether values that don't fit into their fields are truncated with a
+    /// warning, rather than rejected.
+    pub truncate: bool,
+}
+
+/// Assembles a sequence of parsed [`Statement`]s into the images of all
+/// sections they define.
+///
+/// As the encoding of an instruction depends on the values of its operands,
+/// label addresses are determined by repeatedly laying out the code until no
+/// statement changes its size anymore.
+///
+/// [`Statement`]: ../parser/struct.Statement.html
+pub fn assemble(statements: &[Statement], options: Options) -> Result<Program, ParseErrors> {
+    let assembly = Assembly::new(statements, false, options)?;
+
+    // Describe the code section, leaving out the generated names of local
+    // labels. The paths to the source files are only known to the caller.
+    let code = assembly
+        .images
+        .iter()
+        .position(|i| i.name == DEFAULT_SECTION);
+    let origin = code.map_or(0, |code| assembly.images[code].origin);
+    let mut debug_info = DebugInfo::default();
+    for &(section, offset, span) in &assembly.lines {
+        if Some(section) == code {
+            debug_info.lines.push(debug_info::Line {
+                address: origin + offset,
+                file: span.file,
+                line: span.line,
+            });
+        }
+    }
+    for &(name, section, offset) in &assembly.labels {
+        if Some(section) == code && !name.contains('@') {
+            debug_info.symbols.push(debug_info::Symbol {
+                name: name.to_string(),
+                address: origin + offset,
+            });
+        }
+    }
+    debug_info.symbols.sort_by_key(|s| s.address);
+
+    let sections = assembly
+        .images
+        .into_iter()
+        .map(|image| Section {
+            name: image.name.to_string(),
+            origin: image.origin,
+            data: image.data,
+        })
+        .collect();
+
+    Ok(Program {
+        sections,
+        debug_info: Some(debug_info),
+        secure_regions: assembly.secure_regions,
+        signatures: assembly.signatures,
+        warnings: assembly.warnings,
+    })
+}
+
+/// Assembles a sequence of parsed [`Statement`]s into a relocatable
+/// [`Object`].
+///
+/// Labels are resolved relative to the start of their section and symbols
+/// that are declared through `.extern` are left to be defined by other
+/// objects. Every field that depends on such values is described by a
+/// relocation.
+///
+/// Values that don't fit into their fields are always rejected, as objects
+/// have no means to carry the warnings about truncating them.
+///
+/// [`Statement`]: ../parser/struct.Statement.html
+/// [`Object`]: ../../object/struct.Object.html
+pub fn assemble_object(statements: &[Statement], options: Options) -> Result<Object, ParseErrors> {
+    let options = Options {
+        truncate: false,
+        ..options
+    };
+    let assembly = Assembly::new(statements, true, options)?;
+
+    // All labels are recorded in the symbol table, followed by the symbols
+    // which are referenced, but not defined.
+    let mut symbols = assembly
+        .labels
+        .iter()
+        .map(|&(name, section, offset)| object::Symbol {
+            name: name.to_string(),
+            section: Some(section),
+            offset,
+            global: assembly.globals.contains(name),
+        })
+        .collect::<Vec<_>>();
+
+    let mut sections = Vec::with_capacity(assembly.images.len());
+    for image in assembly.images {
+        let mut relocations = Vec::with_capacity(image.fixups.len());
+        for fixup in image.fixups {
+            let target = match fixup.base {
+                None => Target::Absolute,
+                Some(Base::Section(index)) => Target::Section(index),
+                Some(Base::Symbol(name)) => {
+                    let index = symbols.iter().position(|s| s.name == name);
+                    Target::Symbol(index.unwrap_or_else(|| {
+                        symbols.push(object::Symbol {
+                            name: name.to_string(),
+                            section: None,
+                            offset: 0,
+                            global: true,
+                        });
+                        symbols.len() - 1
+                    }))
+                }
+            };
+
+            relocations.push(object::Relocation {
+                offset: fixup.offset as u32,
+                kind: fixup.kind,
+                target,
+                addend: fixup.addend,
+            });
+        }
+
+        sections.push(object::Section {
+            name: image.name.to_string(),
+            alignment: image.alignment,
+            data: image.data,
+            relocations,
+        });
+    }
+
+    Ok(Object { sections, symbols })
+}
+
+/// Assembles a single instruction [`Statement`] that is located at address
+/// `pc` into machine code.
+///
+/// As there are no labels to refer to, the instruction must not use symbols.
+///
+/// [`Statement`]: ../parser/struct.Statement.html
+pub fn assemble_instruction(statement: &Statement, pc: u32) -> Result<Vec<u8>, ParseError> {
+    let resolver = |name: &str| Err(EvalError::UndefinedSymbol(name.to_string()));
+    Encoder::new()
+        .encode(statement, &resolver, 0, Relocatable::absolute(pc.into()))
+        .map(|chunk| chunk.bytes)
+}
+
+/// A field in emitted code whose value is not known until link time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixup<'a> {
+    /// The offset of the field in the emitted code.
+    pub offset: usize,
+    /// The encoding of the field.
+    pub kind: RelocationKind,
+    /// The location that the field refers to, if any.
+    pub base: Option<Base<'a>>,
+    /// The value that is added to the base.
+    pub addend: i64,
+}
+
+/// The code that is emitted for a statement, along with the fields that must
+/// be patched when linking.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    /// The emitted bytes.
+    pub bytes: Vec<u8>,
+    /// The fields in the bytes whose values are unknown.
+    pub fixups: Vec<Fixup<'a>>,
+    /// The warnings about the emitted bytes, such as for truncated values.
+    pub warnings: Vec<ParseError>,
+}
+
+// The contents of a section after assembling all statements.
+struct Image<'a> {
+    name: &'a str,
+    origin: u32,
+    alignment: u32,
+    data: Vec<u8>,
+    fixups: Vec<Fixup<'a>>,
+}
+
+// The sections and labels that result from assembling statements.
+struct Assembly<'a> {
+    images: Vec<Image<'a>>,
+    // The labels along with their sections and their offsets in them.
+    labels: Vec<(&'a str, usize, u32)>,
+    // The labels that are visible to other objects.
+    globals: HashSet<&'a str>,
+    // The sections and offsets of all statements that emit code, along with
+    // their locations in the source code.
+    lines: Vec<(usize, u32, Span)>,
+    secure_regions: Vec<SecureRegion>,
+    signatures: Vec<Signature>,
+    warnings: Vec<ParseError>,
+}
+
+impl<'a> Assembly<'a> {
+    // Lays out and emits all statements. When `relocatable` is set, labels
+    // are relative to their sections and external symbols can be referenced.
+    //
+    // Errors in individual statements are collected, so that all of them can
+    // be reported at once. When optimizing, the peephole optimizations of
+    // the encoder are enabled and the final layout is compacted.
+    fn new(
+        statements: &'a [Statement],
+        relocatable: bool,
+        options: Options,
+    ) -> Result<Self, ParseErrors> {
+        let encoder = Encoder {
+            optimize: options.optimize,
+            truncate: options.truncate,
+            ..Encoder::new()
+        };
+        let mut errors = Vec::new();
+
+        // Reject duplicate symbol definitions upfront. Only constants that are
+        // defined through `.set` may be redefined.
+        let mut definitions = HashMap::new();
+        for statement in statements {
+            let (name, redefinable) = match &statement.kind {
+                StatementKind::Label(name) => (name, false),
+                StatementKind::Constant {
+                    name, redefinable, ..
+                } => (name, *redefinable),
+                _ => continue,
+            };
+
+            match definitions.insert(name.as_str(), redefinable) {
+                Some(previous) if !(previous && redefinable) => {
+                    let error = ParseError::new(
+                        statement.span,
+                        format!("symbol `{}` is defined multiple times", name),
+                    );
+                    report(
+                        &mut errors,
+                        error.with_note(
+                            "only constants that are defined through `.set` may be redefined",
+                        ),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        // Global symbols must be labels of this object, whereas external
+        // symbols must not be defined by it.
+        let labels = statements
+            .iter()
+            .filter_map(|statement| match &statement.kind {
+                StatementKind::Label(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let mut globals = HashSet::new();
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Global(names) => {
+                    for name in names {
+                        if !labels.contains(name.as_str()) {
+                            let problem = match definitions.get(name.as_str()) {
+                                Some(_) => "is not a label",
+                                None => "is not defined",
+                            };
+                            report(
+                                &mut errors,
+                                ParseError::new(
+                                    statement.span,
+                                    format!("global symbol `{}` {}", name, problem),
+                                ),
+                            );
+                        }
+                        globals.insert(name.as_str());
+                    }
+                }
+                StatementKind::Extern(names) => {
+                    if let Some(name) = names.iter().find(|n| definitions.contains_key(n.as_str()))
+                    {
+                        report(
+                            &mut errors,
+                            ParseError::new(
+                                statement.span,
+                                format!("external symbol `{}` is defined locally", name),
+                            ),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Without a layout, the remaining statements cannot be checked.
+        let layout = match Layout::new(statements, relocatable) {
+            Ok(layout) => layout,
+            Err(e) => {
+                report(&mut errors, e);
+                return Err(ParseErrors { errors });
+            }
+        };
+
+        let mut sizes = vec![0; statements.len()];
+        layout.grow(statements, &encoder, &mut sizes);
+
+        // Growing instructions may leave others larger than they would need to
+        // be in the final layout. Shrinking them moves code around again, so
+        // this is repeated a limited number of times only.
+        if options.optimize {
+            for _ in 0..MAX_COMPACTIONS {
+                if !layout.shrink(statements, &encoder, &mut sizes) {
+                    break;
+                }
+                layout.grow(statements, &encoder, &mut sizes);
+            }
+        }
+
+        // Emit the final code with all symbols resolved.
+        let addresses = layout.addresses(&sizes);
+        let symbols = Symbols::new(statements, &addresses, relocatable);
+
+        let mut images = layout
+            .names
+            .iter()
+            .zip(layout.origins.iter())
+            .map(|(&name, &origin)| Image {
+                name,
+                origin,
+                alignment: 1,
+                data: Vec::new(),
+                fixups: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut labels = Vec::new();
+        let mut lines = Vec::new();
+        let mut checksums = Vec::new();
+        let mut secure_regions: Vec<SecureRegion> = Vec::new();
+        let mut signatures = Vec::new();
+        let mut warnings = Vec::new();
+        // The secure region that is currently open, along with its definition.
+        let mut open_region: Option<(SecureRegion, usize, Span)> = None;
+
+        for (index, (statement, &size)) in statements.iter().zip(sizes.iter()).enumerate() {
+            let resolver = |name| symbols.resolve(name, index, true);
+            let constants = |name| symbols.resolve(name, index, false);
+
+            let address = addresses[index];
+            let start = layout.start(index);
+            let chunk = match emit(
+                &encoder, statement, &resolver, &constants, size, address, start,
+            ) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    report(&mut errors, e);
+                    continue;
+                }
+            };
+            debug_assert_eq!(chunk.bytes.len(), size);
+
+            let image = &mut images[layout.sections[index]];
+            let offset = image.data.len();
+            match &statement.kind {
+                StatementKind::Label(name) => {
+                    labels.push((name.as_str(), layout.sections[index], offset as u32));
+                }
+                StatementKind::Align(alignment) => {
+                    // The alignment was already validated by emitting it.
+                    let alignment = evaluate_constant(alignment, &constants, statement.span);
+                    image.alignment = image.alignment.max(alignment.unwrap_or(1) as u32);
+                }
+                StatementKind::SecureStart(name) => {
+                    if let Some((region, ..)) = &open_region {
+                        report(
+                            &mut errors,
+                            ParseError::new(
+                                statement.span,
+                                format!("secure region `{}` is still open", region.name),
+                            )
+                            .with_note("secure regions cannot be nested"),
+                        );
+                    } else {
+                        if secure_regions.iter().any(|r| r.name == *name) {
+                            report(
+                                &mut errors,
+                                ParseError::new(
+                                    statement.span,
+                                    format!("secure region `{}` is defined multiple times", name),
+                                ),
+                            );
+                        }
+
+                        // The region starts behind the padding to the next page.
+                        let start = image.origin + (offset + size) as u32;
+                        let region = SecureRegion {
+                            name: name.clone(),
+                            section: image.name.to_string(),
+                            start,
+                            end: start,
+                        };
+                        open_region = Some((region, layout.sections[index], statement.span));
+                    }
+                }
+                StatementKind::SecureEnd => match open_region.take() {
+                    Some((mut region, section, _)) if section == layout.sections[index] => {
+                        region.end = image.origin + (offset + size) as u32;
+                        secure_regions.push(region);
+                    }
+                    Some((region, ..)) => report(
+                        &mut errors,
+                        ParseError::new(
+                            statement.span,
+                            format!(
+                                "secure region `{}` must end in section `{}`",
+                                region.name, region.section
+                            ),
+                        ),
+                    ),
+                    None => report(
+                        &mut errors,
+                        ParseError::new(statement.span, "`.endsecure` without `.secure`"),
+                    ),
+                },
+                StatementKind::Signature { region, .. } => {
+                    signatures.push((
+                        Signature {
+                            region: region.clone(),
+                            section: image.name.to_string(),
+                            address: image.origin + offset as u32,
+                            size: size as u32,
+                        },
+                        statement.span,
+                    ));
+                }
+                StatementKind::Checksum { kind, start, end } => {
+                    // The range was already validated by emitting it.
+                    let address = |expr: &'a Expr| expr.evaluate(&resolver).map_or(0, |a| a.offset);
+                    checksums.push(Checksum {
+                        kind: *kind,
+                        section: layout.sections[index],
+                        offset,
+                        range: (address(start), address(end)),
+                        span: statement.span,
+                    });
+                }
+                _ => {}
+            }
+            if size > 0 {
+                lines.push((layout.sections[index], offset as u32, statement.span));
+            }
+
+            warnings.extend(chunk.warnings);
+            image.data.extend(chunk.bytes);
+            image
+                .fixups
+                .extend(chunk.fixups.into_iter().map(|fixup| Fixup {
+                    offset: offset + fixup.offset,
+                    ..fixup
+                }));
+        }
+
+        if let Some((region, _, span)) = open_region {
+            report(
+                &mut errors,
+                ParseError::new(
+                    span,
+                    format!(
+                        "secure region `{}` is missing its `.endsecure`",
+                        region.name
+                    ),
+                ),
+            );
+        }
+        let signatures = signatures
+            .into_iter()
+            .filter_map(|(signature, span)| {
+                if secure_regions.iter().any(|r| r.name == signature.region) {
+                    return Some(signature);
+                }
+
+                let message = format!("secure region `{}` is not defined", signature.region);
+                report(&mut errors, ParseError::new(span, message));
+                None
+            })
+            .collect();
+
+        // Checksums are computed once all code is in place, in order of their
+        // appearance. Thus they may cover the checksums in front of them.
+        for checksum in checksums {
+            if let Err(e) = checksum.apply(&mut images[checksum.section]) {
+                report(&mut errors, e);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ParseErrors { errors });
+        }
+
+        Ok(Assembly {
+            images,
+            labels,
+            globals,
+            lines,
+            secure_regions,
+            signatures,
+            warnings,
+        })
+    }
+}
+
+// A checksum that is computed over the final contents of a section.
+struct Checksum {
+    kind: ChecksumKind,
+    section: usize,
+    // The offset of the checksum in its section.
+    offset: usize,
+    // The start and end addresses of the range that is covered.
+    range: (i64, i64),
+    span: Span,
+}
+
+impl Checksum {
+    // Computes the checksum and writes it into the image of its section.
+    fn apply(&self, image: &mut Image) -> Result<(), ParseError> {
+        let origin = i64::from(image.origin);
+        let (start, end) = (self.range.0 - origin, self.range.1 - origin);
+        if start < 0 || start > end || end > image.data.len() as i64 {
+            return Err(ParseError::new(
+                self.span,
+                "range is not within the section of the checksum",
+            ));
+        }
+        let (start, end) = (start as usize, end as usize);
+        if start < self.offset + 4 && self.offset < end {
+            return Err(ParseError::new(
+                self.span,
+                "range must not cover the checksum itself",
+            ));
+        }
+
+        let data = &image.data[start..end];
+        let value = match self.kind {
+            ChecksumKind::Sum32 => {
+                let words = data.chunks_exact(4);
+                if !words.remainder().is_empty() {
+                    return Err(ParseError::new(
+                        self.span,
+                        "range must be a multiple of 4 bytes long",
+                    ));
+                }
+
+                words.fold(0u32, |sum, word| {
+                    sum.wrapping_add(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                })
+            }
+            ChecksumKind::Crc32 => crc32(data),
+        };
+
+        image.data[self.offset..self.offset + 4].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+}
+
+// Computes the CRC-32 of some data with the reflected polynomial 0xEDB88320.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+// Emits the bytes of a single statement that is located at `address`, in a
+// section that starts at `origin`.
+//
+// `constants` only resolves symbols whose values don't depend on the code
+// layout, which is required for sizing alignment and padding.
+fn emit<'a>(
+    encoder: &Encoder,
+    statement: &'a Statement,
+    resolver: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+    constants: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+    min_length: usize,
+    address: Relocatable<'a>,
+    origin: u32,
+) -> Result<Chunk<'a>, ParseError> {
+    let constant = |expr: &'a Expr| evaluate_constant(expr, constants, statement.span);
+    let bytes = |bytes: Vec<u8>| Chunk {
+        bytes,
+        ..Chunk::default()
+    };
+    let fill = |value: u8, count: i64| {
+        if count > MAX_DIRECTIVE_SIZE {
+            return Err(too_large(statement.span));
+        }
+        Ok(bytes(vec![value; count as usize]))
+    };
+
+    match &statement.kind {
+        StatementKind::Label(_)
+        | StatementKind::Constant { .. }
+        | StatementKind::Global(_)
+        | StatementKind::Extern(_)
+        | StatementKind::Section { .. } => Ok(Chunk::default()),
+        StatementKind::Instruction { .. } => {
+            encoder.encode(statement, resolver, min_length, address)
+        }
+        StatementKind::Data { width, values } => {
+            let mut chunk = Chunk {
+                bytes: Vec::with_capacity(width * values.len()),
+                ..Chunk::default()
+            };
+            for (expr, span) in values {
+                let value = expr
+                    .evaluate(resolver)
+                    .map_err(|e| ParseError::new(*span, e.to_string()))?;
+
+                push_value(&mut chunk, value, *width, *span, encoder.truncate)?;
+            }
+
+            Ok(chunk)
+        }
+        StatementKind::Bytes(string) => Ok(bytes(string.clone())),
+        StatementKind::Align(alignment) => {
+            let alignment = constant(alignment)?;
+            if alignment <= 0 || alignment & (alignment - 1) != 0 {
+                return Err(ParseError::new(
+                    statement.span,
+                    "alignment must be a power of two",
+                ));
+            }
+
+            let padding = (alignment - address.offset % alignment) % alignment;
+            fill(0, padding)
+        }
+        StatementKind::Org(target) => {
+            // The address space was already validated by the layout.
+            let target = constant(target)?;
+            if target < address.offset {
+                return Err(ParseError::new(
+                    statement.span,
+                    format!("address {:#x} overlaps the code in front of it", target),
+                ));
+            }
+
+            fill(0, target - address.offset)
+        }
+        StatementKind::Pad { size, fill: value } => {
+            let size = constant(size)?;
+            let value = value.as_ref().map_or(Ok(0), constant)?;
+            if size <= 0 {
+                return Err(ParseError::new(statement.span, "size must be positive"));
+            }
+            if !OperandSize::EightBit.fits(value) {
+                return Err(ParseError::new(
+                    statement.span,
+                    "fill value does not fit into a byte",
+                ));
+            }
+
+            let offset = address.offset - i64::from(origin);
+            let padding = (size - offset % size) % size;
+            fill(value as u8, padding)
+        }
+        StatementKind::SecureStart(_) | StatementKind::SecureEnd => {
+            // Secure regions cover whole pages.
+            let page = i64::from(SECURE_PAGE_SIZE);
+            let padding = (page - address.offset % page) % page;
+            Ok(bytes(vec![0; padding as usize]))
+        }
+        StatementKind::Signature { size, .. } => {
+            let size = size
+                .as_ref()
+                .map_or(Ok(i64::from(SIGNATURE_SIZE)), constant)?;
+            if size <= 0 {
+                return Err(ParseError::new(statement.span, "size must be positive"));
+            }
+
+            // The signature is filled in by an external signing tool.
+            fill(0, size)
+        }
+        StatementKind::Checksum { start, end, .. } => {
+            for expr in &[start, end] {
+                let value = expr
+                    .evaluate(resolver)
+                    .map_err(|e| ParseError::new(statement.span, e.to_string()))?;
+                if value.base.is_some() {
+                    return Err(ParseError::new(
+                        statement.span,
+                        "range must be known at assembly time",
+                    ));
+                }
+            }
+
+            // The checksum is filled in once the final code is known.
+            Ok(bytes(vec![0; 4]))
+        }
+        StatementKind::Skip { count, fill: value } => {
+            let count = constant(count)?;
+            let value = value.as_ref().map_or(Ok(0), constant)?;
+            if count < 0 {
+                return Err(ParseError::new(
+                    statement.span,
+                    "count must not be negative",
+                ));
+            }
+            if !OperandSize::EightBit.fits(value) {
+                return Err(ParseError::new(
+                    statement.span,
+                    "fill value does not fit into a byte",
+                ));
+            }
+
+            fill(value as u8, count)
+        }
+        StatementKind::Fill { count, size, value } => {
+            let count = constant(count)?;
+            let size = size.as_ref().map_or(Ok(1), constant)?;
+            if count < 0 {
+                return Err(ParseError::new(
+                    statement.span,
+                    "count must not be negative",
+                ));
+            }
+            if !matches!(size, 1 | 2 | 4) {
+                return Err(ParseError::new(statement.span, "size must be 1, 2 or 4"));
+            }
+            let total = count
+                .checked_mul(size)
+                .filter(|&total| total <= MAX_DIRECTIVE_SIZE)
+                .ok_or_else(|| too_large(statement.span))?;
+            let value = match value {
+                Some(expr) => expr
+                    .evaluate(resolver)
+                    .map_err(|e| ParseError::new(statement.span, e.to_string()))?,
+                None => Relocatable::absolute(0),
+            };
+
+            let mut chunk = Chunk {
+                bytes: Vec::with_capacity(total as usize),
+                ..Chunk::default()
+            };
+            for _ in 0..count {
+                push_value(
+                    &mut chunk,
+                    value,
+                    size as usize,
+                    statement.span,
+                    encoder.truncate,
+                )?;
+            }
+
+            Ok(chunk)
+        }
+    }
+}
+
+// Creates the error for a directive that emits more than MAX_DIRECTIVE_SIZE
+// bytes.
+fn too_large(span: Span) -> ParseError {
+    let note = format!("directives may emit up to {:#x} bytes", MAX_DIRECTIVE_SIZE);
+    ParseError::new(span, "directive emits too many bytes").with_note(note)
+}
+
+// Appends a value of `width` bytes to a chunk. Values that are only known at
+// link time are left as zeros, along with a fixup. Values that are too wide
+// are either rejected or, with `truncate` set, cut off with a warning.
+fn push_value<'a>(
+    chunk: &mut Chunk<'a>,
+    value: Relocatable<'a>,
+    width: usize,
+    span: Span,
+    truncate: bool,
+) -> Result<(), ParseError> {
+    if let Some(base) = value.base {
+        let kind = match width {
+            1 => RelocationKind::U8,
+            2 => RelocationKind::U16,
+            _ => RelocationKind::U32,
+        };
+        chunk.fixups.push(Fixup {
+            offset: chunk.bytes.len(),
+            kind,
+            base: Some(base),
+            addend: value.offset,
+        });
+        chunk.bytes.extend(vec![0; width]);
+        return Ok(());
+    }
+
+    if !fits(value.offset, width * 8) {
+        let error = ParseError::new(span, format!("value does not fit into {} bits", width * 8));
+        if !truncate {
+            return Err(error);
+        }
+
+        let truncated = truncate_value(value.offset, width * 8, false);
+        chunk
+            .warnings
+            .push(error.with_note(truncation_note(value.offset, truncated)));
+    }
+    chunk
+        .bytes
+        .extend_from_slice(&value.offset.to_le_bytes()[..width]);
+
+    Ok(())
+}
+
+// Evaluates an expression that must not depend on the code layout.
+fn evaluate_constant<'a>(
+    expr: &'a Expr,
+    constants: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+    span: Span,
+) -> Result<i64, ParseError> {
+    match expr.evaluate(constants) {
+        Ok(Relocatable { base: None, offset }) => Ok(offset),
+        Ok(_) | Err(EvalError::UndefinedSymbol(_)) => {
+            Err(ParseError::new(span, "expected a constant expression")
+                .with_note("the value must not depend on labels or symbols that are defined later"))
+        }
+        Err(e) => Err(ParseError::new(span, e.to_string())),
+    }
+}
+
+// Checks whether a value can be represented in `bits` bits, either as a signed
+// or as an unsigned integer.
+fn fits(value: i64, bits: usize) -> bool {
+    value >= -(1 << (bits - 1)) && value < 1 << bits
+}
+
+// Cuts off all but the low `bits` bits of a value, which are sign-extended
+// for signed fields.
+fn truncate_value(value: i64, bits: usize, signed: bool) -> i64 {
+    let shift = 64 - bits;
+    if signed {
+        value << shift >> shift
+    } else {
+        ((value as u64) << shift >> shift) as i64
+    }
+}
+
+// Describes the truncation of a value, for the notes of warnings.
+fn truncation_note(value: i64, truncated: i64) -> String {
+    let hex = |value: i64| match value {
+        value if value < 0 => format!("-{:#x}", (value as u64).wrapping_neg()),
+        value => format!("{:#x}", value),
+    };
+
+    format!("truncated {} to {}", hex(value), hex(truncated))
+}
+
+// The maximum number of times the layout is compacted when optimizing, which
+// guards against instructions that keep alternating between their sizes.
+const MAX_COMPACTIONS: usize = 16;
+
+// The assignment of statements to the sections they are placed in.
+struct Layout<'a> {
+    // The names and origins of all sections, in order of appearance.
+    names: Vec<&'a str>,
+    origins: Vec<u32>,
+    // The index of the section that contains every statement.
+    sections: Vec<usize>,
+    // Whether addresses are relative to their sections.
+    relocatable: bool,
+}
+
+impl<'a> Layout<'a> {
+    fn new(statements: &'a [Statement], relocatable: bool) -> Result<Self, ParseError> {
+        // Origins are evaluated before any code is laid out, so they may only
+        // refer to constants.
+        let addresses = vec![Relocatable::absolute(0); statements.len()];
+        let symbols = Symbols::new(statements, &addresses, false);
+
+        let mut names = vec![DEFAULT_SECTION];
+        let mut origins = vec![None];
+        // Whether any statement that may emit code was placed in a section.
+        let mut used = vec![false];
+        let mut sections = Vec::with_capacity(statements.len());
+        let mut current = 0;
+
+        for (index, statement) in statements.iter().enumerate() {
+            let constants = |name| symbols.resolve(name, index, false);
+
+            match &statement.kind {
+                StatementKind::Section { name, origin } => {
+                    current = match names.iter().position(|n| n == name) {
+                        Some(section) => section,
+                        None => {
+                            names.push(name.as_str());
+                            origins.push(None);
+                            used.push(false);
+                            names.len() - 1
+                        }
+                    };
+
+                    if let Some(origin) = origin {
+                        if relocatable {
+                            return Err(ParseError::new(
+                                statement.span,
+                                "sections of relocatable objects cannot have an origin",
+                            ));
+                        }
+
+                        let value = evaluate_constant(origin, &constants, statement.span)?;
+                        if value < 0 || value > i64::from(u32::MAX) {
+                            return Err(ParseError::new(
+                                statement.span,
+                                "origin is out of the address space",
+                            ));
+                        }
+                        if origins[current].replace(value as u32).is_some() {
+                            return Err(ParseError::new(
+                                statement.span,
+                                format!("origin of section `{}` is specified multiple times", name),
+                            ));
+                        }
+                    }
+                }
+                StatementKind::SecureStart(_)
+                | StatementKind::SecureEnd
+                | StatementKind::Signature { .. }
+                    if relocatable =>
+                {
+                    return Err(ParseError::new(
+                        statement.span,
+                        "secure regions cannot be used in relocatable objects",
+                    ));
+                }
+                StatementKind::Checksum { .. } if relocatable => {
+                    return Err(ParseError::new(
+                        statement.span,
+                        "checksums cannot be computed for relocatable objects",
+                    ));
+                }
+                StatementKind::Org(target) => {
+                    if relocatable {
+                        return Err(ParseError::new(
+                            statement.span,
+                            "`.org` cannot be used in relocatable objects",
+                        ));
+                    }
+
+                    let value = evaluate_constant(target, &constants, statement.span)?;
+                    if value < 0 || value > i64::from(u32::MAX) {
+                        return Err(ParseError::new(
+                            statement.span,
+                            "address is out of the address space",
+                        ));
+                    }
+
+                    // Sections without an origin start at their first `.org`.
+                    if !used[current] && origins[current].is_none() {
+                        origins[current] = Some(value as u32);
+                    }
+                }
+                StatementKind::Label(_)
+                | StatementKind::Constant { .. }
+                | StatementKind::Global(_)
+                | StatementKind::Extern(_) => {}
+                _ => used[current] = true,
+            }
+
+            sections.push(current);
+        }
+
+        Ok(Layout {
+            names,
+            origins: origins.into_iter().map(|o| o.unwrap_or(0)).collect(),
+            sections,
+            relocatable,
+        })
+    }
+
+    // Lays out the code until the sizes of all statements are stable. Sizes
+    // of instructions only ever grow and the remaining statements are sized
+    // by their address at most, so this is guaranteed to terminate.
+    fn grow(&self, statements: &'a [Statement], encoder: &Encoder, sizes: &mut [usize]) {
+        loop {
+            let addresses = self.addresses(sizes);
+            let symbols = Symbols::new(statements, &addresses, self.relocatable);
+
+            let mut changed = false;
+            for (index, (statement, size)) in statements.iter().zip(sizes.iter_mut()).enumerate() {
+                let resolver = |name| {
+                    let value = symbols.resolve(name, index, true);
+                    Ok(value.unwrap_or_else(|_| Relocatable::absolute(0)))
+                };
+                let constants = |name| symbols.resolve(name, index, false);
+
+                let address = addresses[index];
+                let start = self.start(index);
+                if let Ok(chunk) = emit(
+                    encoder, statement, &resolver, &constants, *size, address, start,
+                ) {
+                    let grown = match statement.kind {
+                        StatementKind::Instruction { .. } => chunk.bytes.len() > *size,
+                        _ => chunk.bytes.len() != *size,
+                    };
+                    if grown {
+                        *size = chunk.bytes.len();
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // Reduces every instruction to the size of its shortest encoding in the
+    // current layout. Returns whether any instruction has become smaller.
+    fn shrink(&self, statements: &'a [Statement], encoder: &Encoder, sizes: &mut [usize]) -> bool {
+        let addresses = self.addresses(sizes);
+        let symbols = Symbols::new(statements, &addresses, self.relocatable);
+
+        let mut changed = false;
+        for (index, (statement, size)) in statements.iter().zip(sizes.iter_mut()).enumerate() {
+            if let StatementKind::Instruction { .. } = statement.kind {
+                let resolver = |name| symbols.resolve(name, index, true);
+                if let Ok(chunk) = encoder.encode(statement, &resolver, 0, addresses[index]) {
+                    if chunk.bytes.len() < *size {
+                        *size = chunk.bytes.len();
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    // Gets the address at which the section of a statement starts.
+    fn start(&self, index: usize) -> u32 {
+        self.origins[self.sections[index]]
+    }
+
+    // Computes the address of every statement when laid out with the given
+    // sizes. Every section has its own location counter.
+    fn addresses(&self, sizes: &[usize]) -> Vec<Relocatable<'a>> {
+        let mut counters = self.origins.clone();
+
+        self.sections
+            .iter()
+            .zip(sizes.iter())
+            .map(|(&section, &size)| {
+                let address = counters[section];
+                counters[section] = address.wrapping_add(size as u32);
+
+                Relocatable {
+                    base: Some(Base::Section(section)).filter(|_| self.relocatable),
+                    offset: i64::from(address),
+                }
+            })
+            .collect()
+    }
+}
+
+// The symbols that are defined by a sequence of statements.
+struct Symbols<'a> {
+    labels: HashMap<&'a str, Relocatable<'a>>,
+    // Every definition of a constant along with the index of its statement.
+    constants: HashMap<&'a str, Vec<(usize, &'a Expr)>>,
+    // The symbols that are declared to be defined by other objects.
+    externs: HashSet<&'a str>,
+    // The values of constants by the index of their definition and whether
+    // labels were resolved. Definitions that are being evaluated are marked
+    // as circular until their value is known.
+    values: RefCell<HashMap<(usize, bool), Result<Relocatable<'a>, EvalError>>>,
+}
+
+impl<'a> Symbols<'a> {
+    // Collects the symbols from statements that are laid out at the given
+    // addresses. External symbols are only resolved when `external` is set.
+    fn new(statements: &'a [Statement], addresses: &[Relocatable<'a>], external: bool) -> Self {
+        let mut labels = HashMap::new();
+        let mut constants: HashMap<_, Vec<_>> = HashMap::new();
+        let mut externs = HashSet::new();
+
+        for (index, (statement, &address)) in statements.iter().zip(addresses.iter()).enumerate() {
+            match &statement.kind {
+                StatementKind::Label(name) => {
+                    labels.insert(name.as_str(), address);
+                }
+                StatementKind::Constant { name, value, .. } => {
+                    constants
+                        .entry(name.as_str())
+                        .or_default()
+                        .push((index, value));
+                }
+                StatementKind::Extern(names) if external => {
+                    externs.extend(names.iter().map(String::as_str));
+                }
+                _ => {}
+            }
+        }
+
+        Symbols {
+            labels,
+            constants,
+            externs,
+            values: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Resolves the value of a symbol as seen by the statement at `position`.
+    //
+    // Redefined constants take the value of their last definition before the
+    // statement, or of their very last definition if there is none. Labels
+    // and external symbols are only resolved when `labels` is set.
+    fn resolve(
+        &self,
+        name: &'a str,
+        position: usize,
+        labels: bool,
+    ) -> Result<Relocatable<'a>, EvalError> {
+        let undefined = || EvalError::UndefinedSymbol(name.to_string());
+
+        match self.constants.get(name) {
+            Some(definitions) => {
+                let &(index, value) = definitions
+                    .iter()
+                    .rev()
+                    .find(|(index, _)| *index < position)
+                    .or_else(|| definitions.last())
+                    .ok_or_else(undefined)?;
+
+                if let Some(result) = self.values.borrow().get(&(index, labels)) {
+                    return result.clone();
+                }
+
+                let circular = Err(EvalError::CircularDefinition(name.to_string()));
+                self.values.borrow_mut().insert((index, labels), circular);
+                let result = value.evaluate(&|name| self.resolve(name, index, labels));
+                self.values
+                    .borrow_mut()
+                    .insert((index, labels), result.clone());
+
+                result
+            }
+            None if labels => match self.labels.get(name) {
+                Some(&address) => Ok(address),
+                None if self.externs.contains(name) => Ok(Relocatable {
+                    base: Some(Base::Symbol(name)),
+                    offset: 0,
+                }),
+                None => Err(undefined()),
+            },
+            None => Err(undefined()),
+        }
+    }
+}
+
+// An encoding variant of an instruction, derived from the opcode table.
+struct Form {
+    opcode: u8,
+    subopcode: u8,
+    location: SubopcodeLocation,
+    operands: Vec<Argument>,
+    length: usize,
+}
+
+// The reasons why an instruction form doesn't match its operands.
+enum Mismatch {
+    Operands,
+    OutOfRange(Span),
+    NotRelocatable(Span),
+}
+
+// The machine code of an instruction that is being encoded.
+struct Encoding<'a> {
+    insn: [u8; MAX_INSTRUCTION_LENGTH],
+    // Some forms use the same register field for multiple operands, which is
+    // why previously written registers are tracked for consistency.
+    registers: Vec<(ArgRegister, u8)>,
+    fixups: Vec<Fixup<'a>>,
+    // Whether immediates that are too wide are truncated, which is recorded.
+    truncate: bool,
+    truncations: Vec<Truncation>,
+}
+
+// An immediate that was truncated to fit into its field, given by its span,
+// its original value and its truncated value.
+type Truncation = (Span, i64, i64);
+
+// Operands with all their expressions evaluated.
+enum Value<'a> {
+    Register(Register),
+    Immediate(Option<Relocatable<'a>>, Option<u8>),
+    Memory(MemorySpace, Register, Offset<'a>),
+}
+
+enum Offset<'a> {
+    None,
+    Register(Register, u8),
+    Immediate(Relocatable<'a>),
+}
+
+/// Encodes instructions by matching their operands against all known forms.
+pub struct Encoder {
+    forms: HashMap<(InstructionKind, OperandSize), Vec<Form>>,
+    /// Whether instructions may be replaced by equivalent ones with shorter
+    /// encodings.
+    pub optimize: bool,
+    /// Whether immediates that don't fit into any encoding are truncated to
+    /// the widest one, rather than rejected.
+    pub truncate: bool,
+}
+
+impl Encoder {
+    /// Builds the encoding tables from the ISA definitions, including the
+    /// encodings that were registered at runtime.
+    pub fn new() -> Self {
+        // Collect every distinct encoding from the opcode table. Some forms are
+        // replicated over multiple slots, which only differ in bits that will be
+        // overwritten when encoding operands anyway.
+        let mut forms: HashMap<_, Vec<Form>> = HashMap::new();
+        let mut seen = Vec::new();
+        for (opcode, subopcode, meta) in instruction_encodings() {
+            let size_bits = opcode >> 6;
+            let key = (meta as *const InstructionMeta, size_bits);
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+
+            let form = Form::new(opcode, subopcode, meta);
+            let size = match form.location {
+                SubopcodeLocation::OH => OperandSize::Unsized,
+                _ => OperandSize::from(opcode),
+            };
+            forms.entry((meta.kind, size)).or_default().push(form);
+        }
+
+        Encoder {
+            forms,
+            optimize: false,
+            truncate: false,
+        }
+    }
+
+    /// Encodes an instruction statement into machine code.
+    ///
+    /// Out of all encodings that can represent the operands, the shortest one
+    /// that is at least `min_length` bytes long is chosen. Relocatable operands
+    /// prefer the encodings with the widest fields instead. `pc` denotes the
+    /// address of the instruction, which PC-relative operands are encoded
+    /// against. Other statements produce no code at all.
+    ///
+    /// When optimizing, the encodings of equivalent instructions, such as
+    /// `call` for `lcall`, are considered as well and are preferred over the
+    /// original instruction if they are not any longer.
+    ///
+    /// When truncating, immediates that don't fit into any encoding are cut
+    /// off to the widest one, which is reported through a warning. Branch
+    /// targets and relocated values are never truncated.
+    pub fn encode<'a>(
+        &self,
+        statement: &'a Statement,
+        resolver: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+        min_length: usize,
+        pc: Relocatable<'a>,
+    ) -> Result<Chunk<'a>, ParseError> {
+        let (mnemonic, size, operands) = match &statement.kind {
+            StatementKind::Instruction {
+                mnemonic,
+                size,
+                operands,
+            } => (mnemonic, size, operands),
+            _ => return Ok(Chunk::default()),
+        };
+
+        let kind = mnemonic
+            .parse::<InstructionKind>()
+            .map_err(|e| ParseError::new(statement.span, e.to_string()))?;
+        let size = size.unwrap_or(OperandSize::Unsized);
+        let forms = self.forms.get(&(kind, size)).ok_or_else(|| {
+            let message = match size {
+                OperandSize::Unsized => format!("`{}` requires an operand size", kind),
+                _ => format!("`{}` cannot be used with operand size{}", kind, size),
+            };
+            ParseError::new(statement.span, message)
+        })?;
+
+        let values = operands
+            .iter()
+            .map(|operand| evaluate(operand, resolver))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The forms of equivalent instructions come first, so that sorting
+        // favors them over the original forms of the same length.
+        let mut rewrites = Vec::new();
+        if self.optimize {
+            for (kind, size, count) in equivalents(kind, &values) {
+                if let Some(forms) = self.forms.get(&(kind, size)) {
+                    rewrites.extend(forms.iter().map(|form| (form, count)));
+                }
+            }
+        }
+
+        let mut out_of_range = None;
+        let mut not_relocatable = None;
+        let mut candidates = Vec::new();
+        let mut try_form = |form: &Form, count: usize, truncate: bool| {
+            match form.encode(&values[..count], &operands[..count], pc, truncate) {
+                Ok((mut chunk, truncations)) => {
+                    for (span, value, truncated) in truncations {
+                        let message = format!("value does not fit into any encoding of `{}`", kind);
+                        chunk.warnings.push(
+                            ParseError::new(span, message)
+                                .with_note(truncation_note(value, truncated)),
+                        );
+                    }
+                    candidates.push(chunk);
+                }
+                Err(Mismatch::OutOfRange(span)) => {
+                    out_of_range.get_or_insert(span);
+                }
+                Err(Mismatch::NotRelocatable(span)) => {
+                    not_relocatable.get_or_insert(span);
+                }
+                Err(Mismatch::Operands) => {}
+            }
+            !candidates.is_empty()
+        };
+        let mut found = false;
+        for (form, count) in rewrites
+            .into_iter()
+            .chain(forms.iter().map(|f| (f, values.len())))
+        {
+            found = try_form(form, count, false);
+        }
+
+        // Targets of calls and jumps that don't fit into any of their regular
+        // forms may still be reached through the long forms.
+        let long_kind = match kind {
+            InstructionKind::CALL => Some(InstructionKind::LCALL),
+            InstructionKind::JMP => Some(InstructionKind::LJMP),
+            _ => None,
+        };
+        if let (false, Some(kind)) = (found, long_kind) {
+            for form in &self.forms[&(kind, OperandSize::Unsized)] {
+                found = try_form(form, values.len(), false);
+            }
+        }
+
+        // Values that are out of range for all forms are truncated as a last
+        // resort. Only the longest of the resulting encodings is kept, as it
+        // retains the most bits of the value.
+        if !found && self.truncate {
+            for form in forms {
+                try_form(form, values.len(), true);
+            }
+            let length = candidates.iter().map(|c| c.bytes.len()).max();
+            candidates.retain(|chunk| Some(chunk.bytes.len()) == length);
+        }
+        candidates.sort_by_key(|chunk| {
+            let width = chunk.fixups.iter().map(|f| f.kind.width()).min();
+            (Reverse(width), chunk.bytes.len())
+        });
+
+        candidates
+            .iter()
+            .find(|chunk| chunk.bytes.len() >= min_length)
+            .or_else(|| candidates.last())
+            .cloned()
+            .ok_or_else(|| match (out_of_range, not_relocatable) {
+                (Some(span), _) => ParseError::new(
+                    span,
+                    format!("value does not fit into any encoding of `{}`", kind),
+                ),
+                (None, Some(span)) => ParseError::new(
+                    span,
+                    format!("value cannot be relocated in any encoding of `{}`", kind),
+                ),
+                (None, None) => ParseError::new(
+                    statement.span,
+                    format!("invalid operands for `{}{}`", kind, size),
+                ),
+            })
+    }
+}
+
+// Lists the instructions that have the same effect as an instruction with
+// the given operands, along with their operand sizes and the number of
+// leading operands they take over.
+fn equivalents(
+    kind: InstructionKind,
+    values: &[Value],
+) -> Vec<(InstructionKind, OperandSize, usize)> {
+    match (kind, values) {
+        // Long calls and jumps only differ from the regular ones in that their
+        // immediate is always 24 bits wide.
+        (InstructionKind::LCALL, [_]) => vec![(InstructionKind::CALL, OperandSize::Unsized, 1)],
+        (InstructionKind::LJMP, [_]) => vec![(InstructionKind::JMP, OperandSize::Unsized, 1)],
+        // Moving zero into a register clears all of its bits.
+        (
+            InstructionKind::MOV,
+            [Value::Register(_), Value::Immediate(
+                Some(Relocatable {
+                    base: None,
+                    offset: 0,
+                }),
+                _,
+            )],
+        ) => vec![(InstructionKind::CLEAR, OperandSize::ThirtyTwoBit, 1)],
+        _ => Vec::new(),
+    }
+}
+
+impl Form {
+    fn new(opcode: u8, subopcode: u8, meta: &InstructionMeta) -> Self {
+        let size = OperandSize::from(opcode);
+        let (a, b) = get_opcode_form(opcode);
+        let location = get_subopcode_location(size.value(), a, b).unwrap();
+
+        let operands = meta
+            .operands
+            .iter()
+            .filter(|arg| **arg != Argument::Nop)
+            .map(|arg| match arg {
+                Argument::SizeConverter(c) => c(size.value()),
+                arg => arg.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let length = operands
+            .iter()
+            .map(|arg| arg.position() + arg.width())
+            .fold(location.get() as usize + 1, usize::max);
+
+        Form {
+            opcode,
+            subopcode,
+            location,
+            operands,
+            length,
+        }
+    }
+
+    fn encode<'a>(
+        &self,
+        values: &[Value<'a>],
+        operands: &[Operand],
+        pc: Relocatable<'a>,
+        truncate: bool,
+    ) -> Result<(Chunk<'a>, Vec<Truncation>), Mismatch> {
+        if values.len() != self.operands.len() {
+            return Err(Mismatch::Operands);
+        }
+
+        let mut encoding = Encoding {
+            insn: [0; MAX_INSTRUCTION_LENGTH],
+            registers: Vec::new(),
+            fixups: Vec::new(),
+            truncate,
+            truncations: Vec::new(),
+        };
+        encoding.insn[0] = self.opcode;
+        self.location.write(&mut encoding.insn, self.subopcode);
+
+        for ((arg, value), operand) in self.operands.iter().zip(values).zip(operands) {
+            write_operand(&mut encoding, arg, value, operand.span, pc)?;
+        }
+
+        let chunk = Chunk {
+            bytes: encoding.insn[..self.length].to_vec(),
+            fixups: encoding.fixups,
+            warnings: Vec::new(),
+        };
+        Ok((chunk, encoding.truncations))
+    }
+}
+
+fn evaluate<'a>(
+    operand: &'a Operand,
+    resolver: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+) -> Result<Value<'a>, ParseError> {
+    let error = |e: EvalError| ParseError::new(operand.span, e.to_string());
+
+    Ok(match &operand.kind {
+        OperandKind::Register(reg) => Value::Register(*reg),
+        OperandKind::Immediate(expr) => {
+            // Flag bits are referred to by their names, which take precedence
+            // over symbols. A value is only required when there's no flag.
+            let flag = match expr {
+                Expr::Symbol(name) => name.parse::<Flag>().ok().map(Flag::bit),
+                _ => None,
+            };
+
+            match expr.evaluate(resolver) {
+                Ok(Relocatable {
+                    base: Some(Base::Symbol(_)),
+                    ..
+                }) if flag.is_some() => Value::Immediate(None, flag),
+                Ok(value) => Value::Immediate(Some(value), flag),
+                Err(_) if flag.is_some() => Value::Immediate(None, flag),
+                Err(e) => return Err(error(e)),
+            }
+        }
+        OperandKind::Memory {
+            space,
+            base,
+            offset,
+        } => {
+            let offset = match offset {
+                None => Offset::None,
+                Some(MemoryOffset::Register(reg, scale)) => Offset::Register(*reg, *scale),
+                Some(MemoryOffset::Immediate(expr)) => {
+                    Offset::Immediate(expr.evaluate(resolver).map_err(error)?)
+                }
+            };
+
+            Value::Memory(*space, *base, offset)
+        }
+    })
+}
+
+fn write_operand<'a>(
+    encoding: &mut Encoding<'a>,
+    arg: &Argument,
+    value: &Value<'a>,
+    span: Span,
+    pc: Relocatable<'a>,
+) -> Result<(), Mismatch> {
+    match (arg, value) {
+        (Argument::Register(arg), Value::Register(reg)) => write_register(encoding, arg, reg),
+        (Argument::PcRel(_), Value::Immediate(value, _)) => match value {
+            // Targets with the same base as the instruction are encoded
+            // directly, all others relative to the address of the field.
+            Some(target) if target.base == pc.base => {
+                let offset = Relocatable::absolute(target.offset.wrapping_sub(pc.offset));
+                write_immediate(encoding, arg, Some(offset), span)
+            }
+            Some(target) => {
+                let addend = target.offset.wrapping_add(arg.position() as i64);
+                write_fixup(encoding, arg, target.base, addend, span)
+            }
+            None => Err(Mismatch::Operands),
+        },
+        (Argument::Flag(_), Value::Immediate(value, flag)) => {
+            let flag = flag.map(|flag| Relocatable::absolute(flag.into()));
+            write_immediate(encoding, arg, flag.or(*value), span)
+        }
+        (Argument::Memory(mem), Value::Memory(space, base, offset)) => {
+            write_memory(encoding, mem, *space, base, offset, span)
+        }
+        (Argument::Register(_), _) | (Argument::Memory(_), _) => Err(Mismatch::Operands),
+        (_, Value::Immediate(value, _)) => write_immediate(encoding, arg, *value, span),
+        _ => Err(Mismatch::Operands),
+    }
+}
+
+fn write_register(
+    encoding: &mut Encoding,
+    arg: &ArgRegister,
+    reg: &Register,
+) -> Result<(), Mismatch> {
+    let value = reg.1 as u8;
+    let conflict = encoding
+        .registers
+        .iter()
+        .any(|(written, v)| written == arg && *v != value);
+
+    if arg.kind == reg.0 && !conflict && arg.write(&mut encoding.insn, value) {
+        encoding.registers.push((arg.clone(), value));
+        Ok(())
+    } else {
+        Err(Mismatch::Operands)
+    }
+}
+
+fn write_immediate<'a>(
+    encoding: &mut Encoding<'a>,
+    arg: &Argument,
+    value: Option<Relocatable<'a>>,
+    span: Span,
+) -> Result<(), Mismatch> {
+    match value {
+        Some(Relocatable { base: None, offset }) => {
+            if arg.write_immediate(&mut encoding.insn, offset) {
+                return Ok(());
+            }
+
+            // Only plain immediates can be truncated in a meaningful way, but
+            // never branch targets.
+            let field = match arg {
+                Argument::PcRel(_) => None,
+                _ => arg.plain_immediate(),
+            };
+            match field {
+                Some((_, width, signed)) if encoding.truncate => {
+                    let truncated = truncate_value(offset, width * 8, signed);
+                    if !arg.write_immediate(&mut encoding.insn, truncated) {
+                        return Err(Mismatch::OutOfRange(span));
+                    }
+
+                    encoding.truncations.push((span, offset, truncated));
+                    Ok(())
+                }
+                _ => Err(Mismatch::OutOfRange(span)),
+            }
+        }
+        Some(Relocatable { base, offset }) => write_fixup(encoding, arg, base, offset, span),
+        None => Err(Mismatch::Operands),
+    }
+}
+
+// Leaves an immediate for the linker to fill in, which requires it to be
+// stored as a plain integer.
+fn write_fixup<'a>(
+    encoding: &mut Encoding<'a>,
+    arg: &Argument,
+    base: Option<Base<'a>>,
+    addend: i64,
+    span: Span,
+) -> Result<(), Mismatch> {
+    let (position, width, sign) = arg
+        .plain_immediate()
+        .ok_or(Mismatch::NotRelocatable(span))?;
+    let kind = match (arg, width, sign) {
+        (Argument::PcRel(_), 1, _) => RelocationKind::Pc8,
+        (Argument::PcRel(_), 2, _) => RelocationKind::Pc16,
+        (Argument::PcRel(_), _, _) => return Err(Mismatch::NotRelocatable(span)),
+        (_, 1, false) => RelocationKind::U8,
+        (_, 2, false) => RelocationKind::U16,
+        (_, 3, false) => RelocationKind::U24,
+        (_, 1, true) => RelocationKind::I8,
+        (_, 2, true) => RelocationKind::I16,
+        (_, 3, true) => RelocationKind::I24,
+        (_, _, false) => RelocationKind::U32,
+        (_, _, true) => RelocationKind::I32,
+    };
+
+    encoding.fixups.push(Fixup {
+        offset: position,
+        kind,
+        base,
+        addend,
+    });
+    Ok(())
+}
+
+fn write_memory<'a>(
+    encoding: &mut Encoding<'a>,
+    mem: &ArgMemoryAccess,
+    space: MemorySpace,
+    base: &Register,
+    offset: &Offset<'a>,
+    span: Span,
+) -> Result<(), Mismatch> {
+    match (mem, offset) {
+        (ArgMemoryAccess::Reg(s, reg), Offset::None) if *s == space => {
+            write_register(encoding, reg.as_ref().unwrap(), base)
+        }
+        (ArgMemoryAccess::RegReg(s, reg1, reg2, scale), Offset::Register(offset, o_scale))
+            if *s == space && scale == o_scale =>
+        {
+            write_register(encoding, reg1.as_ref().unwrap(), base)?;
+            write_register(encoding, reg2.as_ref().unwrap(), offset)
+        }
+        (ArgMemoryAccess::RegImm(s, reg, imm), Offset::None)
+        | (ArgMemoryAccess::RegImm(s, reg, imm), Offset::Immediate(_))
+            if *s == space =>
+        {
+            // An omitted offset is equivalent to an offset of zero.
+            let value = match offset {
+                Offset::Immediate(value) => *value,
+                _ => Relocatable::absolute(0),
+            };
+
+            write_register(encoding, reg.as_ref().unwrap(), base)?;
+            write_immediate(
+                encoding,
+                &Argument::U32(imm.clone().unwrap()),
+                Some(value),
+                span,
+            )
+        }
+        _ => Err(Mismatch::Operands),
+    }
+}
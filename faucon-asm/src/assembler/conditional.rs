@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+
+use super::lexer::{Token, TokenKind};
+use super::parser::{self, EvalError, Relocatable};
+use super::{ParseError, Span};
+
+/// Resolves conditional blocks line by line, before files are included and
+/// macros are defined and expanded.
+///
+/// Conditions may refer to the defines and to the constants and labels that
+/// are defined in the source text in front of them. Conditional blocks inside
+/// of macro definitions and `.rept` blocks, as well as `.if` blocks whose
+/// conditions cannot be evaluated yet, are left in place for the parser,
+/// which resolves them once macros were expanded.
+pub struct Conditions {
+    // The conditional blocks that enclose the current line.
+    conditions: Vec<Condition>,
+    // The nesting depth of the conditional block that is left for the parser,
+    // if the current line is inside of one.
+    deferred: usize,
+    // The nesting depth of the macro definitions and repetition blocks that
+    // enclose the current line.
+    bodies: usize,
+    // The values of the constants that were defined so far.
+    constants: HashMap<String, i64>,
+    // The names of all symbols that were defined or declared so far.
+    symbols: HashSet<String>,
+}
+
+// A conditional block that is opened through `.if`, `.ifdef` or `.ifndef`.
+struct Condition {
+    span: Span,
+    // Whether the lines of the current branch are assembled.
+    active: bool,
+    // Whether a branch of the block was already taken, or the whole block is
+    // nested inside an inactive branch.
+    taken: bool,
+    // Whether the block already has an `.else` branch.
+    has_else: bool,
+}
+
+impl Conditions {
+    /// Creates the state for resolving the conditional blocks of source code
+    /// that is assembled with the given defines.
+    pub fn new(defines: &HashMap<String, i64>) -> Self {
+        Conditions {
+            conditions: Vec::new(),
+            deferred: 0,
+            bodies: 0,
+            constants: defines.clone(),
+            symbols: defines.keys().cloned().collect(),
+        }
+    }
+
+    /// Processes the tokens of the next line, without its newline, and
+    /// returns whether the line is kept in the source code.
+    ///
+    /// Lines that open, switch or close resolved conditional blocks are
+    /// dropped along with all lines in inactive branches.
+    pub fn line(&mut self, line: &[Token]) -> Result<bool, ParseError> {
+        let (name, span) = match line.first() {
+            Some(Token {
+                kind: TokenKind::Directive(name),
+                span,
+            }) => (name.as_str(), *span),
+            _ => {
+                if self.is_active() && self.deferred == 0 && self.bodies == 0 {
+                    self.define_labels(line);
+                }
+                return Ok(self.is_active());
+            }
+        };
+
+        if self.deferred > 0 {
+            match name {
+                "if" | "ifdef" | "ifndef" => self.deferred += 1,
+                "endif" => self.deferred -= 1,
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        if self.bodies > 0 {
+            match name {
+                "macro" | "rept" => self.bodies += 1,
+                "endm" | "endr" => self.bodies -= 1,
+                // The values of constants that are defined in bodies are
+                // unknown until the bodies are expanded.
+                "equ" | "set" => {
+                    if let Some(TokenKind::Identifier(symbol)) = line.get(1).map(|t| &t.kind) {
+                        self.constants.remove(symbol);
+                        self.symbols.insert(symbol.clone());
+                    }
+                }
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let enclosing = self.is_active();
+                let active = if !enclosing {
+                    false
+                } else if name == "if" {
+                    match self.evaluate(&line[1..], span) {
+                        Some(value) => value != 0,
+                        // Conditions that cannot be evaluated yet are left for
+                        // the parser, which reports errors in them.
+                        None => {
+                            self.deferred = 1;
+                            return Ok(true);
+                        }
+                    }
+                } else {
+                    let defined = match line.get(1).map(|t| &t.kind) {
+                        Some(TokenKind::Identifier(symbol)) => self.symbols.contains(symbol),
+                        _ => {
+                            let span = line.get(1).map_or(span, |t| t.span);
+                            return Err(ParseError::new(span, "expected a symbol name"));
+                        }
+                    };
+                    expect_end(&line[2..])?;
+                    defined == (name == "ifdef")
+                };
+
+                self.conditions.push(Condition {
+                    span,
+                    active,
+                    taken: active || !enclosing,
+                    has_else: false,
+                });
+            }
+            "else" => {
+                expect_end(&line[1..])?;
+                let condition = match self.conditions.last_mut() {
+                    Some(condition) if !condition.has_else => condition,
+                    Some(_) => {
+                        return Err(ParseError::new(
+                            span,
+                            "conditional block has multiple `.else` branches",
+                        ))
+                    }
+                    None => return Err(ParseError::new(span, "`.else` without `.if`")),
+                };
+
+                condition.active = !condition.taken;
+                condition.taken = true;
+                condition.has_else = true;
+            }
+            "endif" => {
+                expect_end(&line[1..])?;
+                if self.conditions.pop().is_none() {
+                    return Err(ParseError::new(span, "`.endif` without `.if`"));
+                }
+            }
+            _ if !self.is_active() => {}
+            "macro" | "rept" => {
+                self.bodies += 1;
+                return Ok(true);
+            }
+            "equ" | "set" => {
+                self.define_constant(line);
+                return Ok(true);
+            }
+            "extern" => {
+                for token in &line[1..] {
+                    if let TokenKind::Identifier(symbol) = &token.kind {
+                        self.symbols.insert(symbol.clone());
+                    }
+                }
+                return Ok(true);
+            }
+            _ => return Ok(true),
+        }
+
+        Ok(false)
+    }
+
+    /// Checks that all conditional blocks were closed at the end of the
+    /// source code.
+    pub fn finish(&self) -> Result<(), ParseError> {
+        match self.conditions.last() {
+            Some(condition) => Err(ParseError::new(
+                condition.span,
+                "conditional block is missing its `.endif`",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        !matches!(self.conditions.last(), Some(condition) if !condition.active)
+    }
+
+    // Evaluates a condition over the constants that were defined so far.
+    fn evaluate(&self, tokens: &[Token], span: Span) -> Option<i64> {
+        let expr = parser::parse_expression(tokens, span).ok()?;
+        let resolver = |name: &str| match self.constants.get(name) {
+            Some(&value) => Ok(Relocatable::absolute(value)),
+            None => Err(EvalError::UndefinedSymbol(name.to_string())),
+        };
+        let value = expr.evaluate(&resolver).ok()?.offset;
+
+        Some(value)
+    }
+
+    // Records the constant that is defined by an `.equ` or `.set` line, whose
+    // value is unknown unless it can be evaluated right away.
+    fn define_constant(&mut self, line: &[Token]) {
+        let symbol = match line.get(1).map(|t| &t.kind) {
+            Some(TokenKind::Identifier(symbol)) => symbol.clone(),
+            _ => return,
+        };
+
+        let value = match line.get(2).map(|t| &t.kind) {
+            Some(TokenKind::Comma) => self.evaluate(&line[3..], line[2].span),
+            _ => None,
+        };
+        match value {
+            Some(value) => self.constants.insert(symbol.clone(), value),
+            None => self.constants.remove(&symbol),
+        };
+        self.symbols.insert(symbol);
+    }
+
+    // Records the labels that are defined at the start of a line.
+    fn define_labels(&mut self, line: &[Token]) {
+        let mut position = 0;
+        while let (Some(label), Some(TokenKind::Colon)) =
+            (line.get(position), line.get(position + 1).map(|t| &t.kind))
+        {
+            if let TokenKind::Identifier(symbol) = &label.kind {
+                self.symbols.insert(symbol.clone());
+            }
+            position += 2;
+        }
+    }
+}
+
+// Checks that no tokens follow a conditional directive.
+fn expect_end(rest: &[Token]) -> Result<(), ParseError> {
+    match rest.first() {
+        Some(token) => Err(ParseError::new(token.span, "unexpected token")),
+        None => Ok(()),
+    }
+}
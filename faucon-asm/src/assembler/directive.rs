@@ -0,0 +1,120 @@
+//! Extension point for directives that are implemented by library users.
+
+use super::lexer::Token;
+use super::parser::{self, Statement, StatementKind};
+use super::{ParseError, Span};
+
+/// A handler that implements a custom assembler directive.
+///
+/// Handlers are registered with an [`Assembler`] through
+/// [`Assembler::register_directive`]. Whenever the parser encounters the
+/// directive, the handler is invoked with the tokens of its arguments and
+/// emits code through a [`DirectiveContext`]:
+///
+/// ```
+/// use faucon_asm::assembler::{
+///     Assembler, DirectiveContext, DirectiveHandler, ParseError, Token, TokenKind,
+/// };
+///
+/// // Emits a descriptor with a magic value, followed by the given entry points.
+/// struct Descriptor;
+///
+/// impl DirectiveHandler for Descriptor {
+///     fn handle(&self, arguments: &[Token], context: &mut DirectiveContext) -> Result<(), ParseError> {
+///         context.bytes(b"DESC");
+///         for entry in arguments.split(|t| t.kind == TokenKind::Comma) {
+///             context.value(2, entry)?;
+///         }
+///
+///         Ok(())
+///     }
+/// }
+///
+/// let mut assembler = Assembler::new();
+/// assembler.register_directive("descriptor", Descriptor);
+///
+/// let code = assembler.assemble_str(".descriptor start, start + 2\nstart:\nret").unwrap();
+/// assert_eq!(code, b"DESC\x08\x00\x0A\x00\xF8\x00");
+/// ```
+///
+/// Handlers take precedence over the built-in directives of the same name,
+/// except for the ones that control conditional and repeated blocks.
+///
+/// [`Assembler`]: struct.Assembler.html
+/// [`Assembler::register_directive`]: struct.Assembler.html#method.register_directive
+/// [`DirectiveContext`]: struct.DirectiveContext.html
+pub trait DirectiveHandler: Send + Sync {
+    /// Handles an invocation of the directive, where `arguments` holds the
+    /// tokens up to the end of the line.
+    ///
+    /// Errors abort the directive, but not the assembly of the other lines.
+    fn handle(&self, arguments: &[Token], context: &mut DirectiveContext)
+        -> Result<(), ParseError>;
+}
+
+/// The code that is emitted by a custom directive.
+///
+/// Everything is emitted in order at the location of the directive, as if it
+/// had been written out in the source code.
+pub struct DirectiveContext {
+    span: Span,
+    statements: Vec<Statement>,
+}
+
+impl DirectiveContext {
+    pub(super) fn new(span: Span) -> Self {
+        DirectiveContext {
+            span,
+            statements: Vec::new(),
+        }
+    }
+
+    pub(super) fn into_statements(self) -> Vec<Statement> {
+        self.statements
+    }
+
+    /// Gets the location of the directive in the source code, which is
+    /// useful for reporting errors.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Emits raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.push(StatementKind::Bytes(bytes.to_vec()));
+    }
+
+    /// Emits the value of an expression in little-endian byte order, where
+    /// `width` is the size of the value in bytes and must be 1, 2 or 4.
+    ///
+    /// The expression may refer to labels and constants, like the operands of
+    /// the `.byte`, `.word` and `.dword` directives.
+    pub fn value(&mut self, width: usize, tokens: &[Token]) -> Result<(), ParseError> {
+        if !matches!(width, 1 | 2 | 4) {
+            return Err(ParseError::new(self.span, "width must be 1, 2 or 4"));
+        }
+
+        let expr = parser::parse_expression(tokens, self.span)?;
+        let span = tokens
+            .iter()
+            .fold(tokens[0].span, |span, t| span.merge(t.span));
+        self.push(StatementKind::Data {
+            width,
+            values: vec![(expr, span)],
+        });
+
+        Ok(())
+    }
+
+    /// Defines a label at the current location.
+    pub fn label(&mut self, name: &str) {
+        self.push(StatementKind::Label(name.to_string()));
+    }
+
+    fn push(&mut self, kind: StatementKind) {
+        self.statements.push(Statement {
+            kind,
+            span: self.span,
+        });
+    }
+}
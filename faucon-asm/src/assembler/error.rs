@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A range of characters in assembly source code.
+///
+/// Spans are used to point at the source of an error, which is why they
+/// carry the byte offsets of the range along with the human-readable line
+/// and column where it starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    /// The byte offset of the first character in the source code.
+    pub start: usize,
+    /// The byte offset after the last character in the source code.
+    pub end: usize,
+    /// The line where the span starts, counting from 1.
+    pub line: usize,
+    /// The column where the span starts, counting from 1.
+    pub column: usize,
+    /// The index of the source file that contains the span, where `0`
+    /// denotes the main source and included files are counted from `1`.
+    pub file: usize,
+}
+
+impl Span {
+    /// Creates a new span that covers both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        Span {
+            start: first.start,
+            end: first.end.max(last.end),
+            line: first.line,
+            column: first.column,
+            file: first.file,
+        }
+    }
+}
+
+/// An error that occurs when assembling Falcon source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The location of the code that caused the error.
+    pub span: Span,
+    /// A description of the error.
+    pub message: String,
+    /// The path to the file that contains the code, if known.
+    pub file: Option<PathBuf>,
+    /// The line of source code that contains the start of the span, if known.
+    pub source_line: Option<Box<str>>,
+    /// An additional note on how to fix the error.
+    pub note: Option<Box<str>>,
+}
+
+impl ParseError {
+    /// Constructs a new error for a given [`Span`] of source code.
+    ///
+    /// [`Span`]: struct.Span.html
+    pub fn new<S: Into<String>>(span: Span, message: S) -> Self {
+        ParseError {
+            span,
+            message: message.into(),
+            file: None,
+            source_line: None,
+            note: None,
+        }
+    }
+
+    /// Attaches a note on how to fix the error.
+    pub fn with_note<S: Into<Box<str>>>(mut self, note: S) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    /// Renders the error along with the offending line of source code, where
+    /// the span is underlined.
+    ///
+    /// With `color` set, the output is highlighted through ANSI escape codes.
+    /// The [`Display`] implementation renders errors without colors.
+    ///
+    /// ```
+    /// let errors = faucon_asm::assembler::assemble_str("ret\n.byte 0x1, 0x100").unwrap_err();
+    ///
+    /// assert_eq!(
+    ///     errors.first().render(false),
+    ///     "error: value does not fit into 8 bits\n \
+    ///      --> 2:12\n  \
+    ///       |\n\
+    ///      2 | .byte 0x1, 0x100\n  \
+    ///       |            ^^^^^",
+    /// );
+    /// ```
+    ///
+    /// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+    pub fn render(&self, color: bool) -> String {
+        self.render_as("error", ERROR_STYLE, color)
+    }
+
+    /// Renders the error like [`render`], but as a warning, which is how the
+    /// [`Program::warnings`] are meant to be reported.
+    ///
+    /// [`render`]: #method.render
+    /// [`Program::warnings`]: struct.Program.html#structfield.warnings
+    pub fn render_warning(&self, color: bool) -> String {
+        self.render_as("warning", WARNING_STYLE, color)
+    }
+
+    // Renders the error with the given severity and its style.
+    fn render_as(&self, severity: &str, severity_style: &str, color: bool) -> String {
+        let style = |code: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut output = format!(
+            "{}{}",
+            style(severity_style, severity),
+            style(BOLD_STYLE, &format!(": {}", self.message))
+        );
+
+        // Errors that don't refer to source code carry no line information.
+        let location = match (&self.file, self.span.line) {
+            (Some(file), 0) => Some(file.display().to_string()),
+            (Some(file), line) => Some(format!("{}:{}:{}", file.display(), line, self.span.column)),
+            (None, 0) => None,
+            (None, line) => Some(format!("{}:{}", line, self.span.column)),
+        };
+        let gutter = " ".repeat(self.span.line.to_string().len());
+        if let Some(location) = location {
+            output += &format!("\n{}{} {}", gutter, style(GUTTER_STYLE, "-->"), location);
+        }
+
+        if let (Some(line), true) = (&self.source_line, self.span.line > 0) {
+            // Underline the span, but no further than the end of the line. Tabs
+            // are kept in the indentation so that the carets line up.
+            let start = self.span.column - 1;
+            let indent = line
+                .chars()
+                .take(start)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let length = line.chars().count().saturating_sub(start);
+            let carets = "^".repeat((self.span.end - self.span.start).min(length).max(1));
+
+            let bar = style(GUTTER_STYLE, "|");
+            output += &format!("\n{} {}", gutter, bar);
+            output += &format!(
+                "\n{} {} {}",
+                style(GUTTER_STYLE, &self.span.line.to_string()),
+                bar,
+                line
+            );
+            output += &format!(
+                "\n{} {} {}{}",
+                gutter,
+                bar,
+                indent,
+                style(severity_style, &carets)
+            );
+        }
+
+        if let Some(note) = &self.note {
+            output += &format!("\n{} {} {}", gutter, style(BOLD_STYLE, "= note:"), note);
+        }
+
+        output
+    }
+}
+
+// The ANSI styles of the parts of rendered errors.
+const ERROR_STYLE: &str = "1;31";
+const WARNING_STYLE: &str = "1;33";
+const BOLD_STYLE: &str = "1";
+const GUTTER_STYLE: &str = "1;34";
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+impl Error for ParseError {}
+
+/// All errors that were found when assembling Falcon source code, in the order
+/// of their discovery.
+///
+/// The assembler doesn't stop at the first error in a statement, so that a
+/// batch of mistakes can be fixed at once.
+///
+/// ```
+/// let errors = faucon_asm::assembler::assemble_str("mov $r1 0x10\nfoo\nret $r1").unwrap_err();
+///
+/// assert_eq!(errors.errors.len(), 2);
+/// assert_eq!(errors.first().message, "unknown mnemonic `foo`");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseErrors {
+    /// The individual errors, of which there is at least one.
+    pub errors: Vec<ParseError>,
+}
+
+impl ParseErrors {
+    /// Gets the first error that was found.
+    pub fn first(&self) -> &ParseError {
+        &self.errors[0]
+    }
+
+    /// Renders all errors, separated by empty lines.
+    ///
+    /// See [`ParseError::render`] for details.
+    ///
+    /// [`ParseError::render`]: struct.ParseError.html#method.render
+    pub fn render(&self, color: bool) -> String {
+        self.errors
+            .iter()
+            .map(|e| e.render(color))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl From<ParseError> for ParseErrors {
+    fn from(error: ParseError) -> Self {
+        ParseErrors {
+            errors: vec![error],
+        }
+    }
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+impl Error for ParseErrors {}
+
+// Records an error, unless the same error was already reported for another
+// repetition of the code.
+pub(super) fn report(errors: &mut Vec<ParseError>, error: ParseError) {
+    if !errors.contains(&error) {
+        errors.push(error);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::conditional::Conditions;
+use super::lexer::{self, line_end, Token, TokenKind};
+use super::{ParseError, Span};
+
+/// Tokenizes source files and resolves their `.include` directives.
+///
+/// Conditional blocks are resolved along the way, so that files which are
+/// included in inactive branches are never read. Every file that is read gets an index in the table of source files, which
+/// is recorded in the [`Span`]s of its tokens so that errors can point at the
+/// file the code originates from.
+///
+/// [`Span`]: ../struct.Span.html
+pub struct Includer<'a> {
+    include_paths: &'a [PathBuf],
+    files: Vec<Option<PathBuf>>,
+    // The source code of every file, by its index.
+    sources: Vec<String>,
+    // The canonical paths of all files that are currently being included.
+    stack: Vec<PathBuf>,
+    // The state of the conditional blocks that enclose the current line.
+    conditions: Conditions,
+}
+
+impl<'a> Includer<'a> {
+    /// Creates a new includer for a main source file at an optional `path`.
+    ///
+    /// Included files are looked up relative to the including file first and
+    /// in the given `include_paths` afterwards. Conditions may refer to the
+    /// given `defines`.
+    pub fn new(
+        include_paths: &'a [PathBuf],
+        path: Option<&Path>,
+        defines: &HashMap<String, i64>,
+    ) -> Self {
+        Includer {
+            include_paths,
+            files: vec![path.map(Path::to_path_buf)],
+            sources: Vec::new(),
+            stack: path
+                .and_then(|p| fs::canonicalize(p).ok())
+                .into_iter()
+                .collect(),
+            conditions: Conditions::new(defines),
+        }
+    }
+
+    /// Gets the path to the source file with the given index, if known.
+    pub fn path(&self, file: usize) -> Option<&Path> {
+        self.files.get(file).and_then(|p| p.as_deref())
+    }
+
+    /// Gets the source code of the file with the given index, if it was read.
+    pub fn source(&self, file: usize) -> Option<&str> {
+        self.sources.get(file).map(String::as_str)
+    }
+
+    /// Consumes the includer and returns the paths to all source files by
+    /// their indices.
+    pub fn into_files(self) -> Vec<Option<PathBuf>> {
+        self.files
+    }
+
+    /// Tokenizes the main source code and splices the tokens of all included
+    /// files into it.
+    pub fn tokenize(&mut self, source: &str) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+        self.sources.push(source.to_string());
+        self.tokenize_file(source, 0, &mut tokens)?;
+        self.conditions.finish()?;
+
+        Ok(tokens)
+    }
+
+    fn tokenize_file(
+        &mut self,
+        source: &str,
+        file: usize,
+        output: &mut Vec<Token>,
+    ) -> Result<(), ParseError> {
+        let tokens = lexer::tokenize(source, file)?;
+
+        let mut position = 0;
+        while position < tokens.len() {
+            let end = line_end(&tokens, position);
+            let line = &tokens[position..end];
+            if !self.conditions.line(line)? {
+                position = end + 1;
+                continue;
+            }
+
+            match line {
+                [Token {
+                    kind: TokenKind::Directive(name),
+                    span,
+                }, rest @ ..]
+                    if name == "include" =>
+                {
+                    let name = match rest {
+                        [Token {
+                            kind: TokenKind::String(name),
+                            ..
+                        }] => String::from_utf8_lossy(name).into_owned(),
+                        [Token {
+                            kind: TokenKind::String(_),
+                            ..
+                        }, token, ..] => {
+                            return Err(ParseError::new(token.span, "unexpected token"))
+                        }
+                        [token, ..] => {
+                            return Err(ParseError::new(token.span, "expected a file name"))
+                        }
+                        [] => return Err(ParseError::new(*span, "expected a file name")),
+                    };
+
+                    self.include(&name, span.merge(rest[0].span), output)?;
+                }
+                _ => output.extend_from_slice(line),
+            }
+
+            if let Some(newline) = tokens.get(end) {
+                output.push(newline.clone());
+            }
+            position = end + 1;
+        }
+
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        name: &str,
+        span: Span,
+        output: &mut Vec<Token>,
+    ) -> Result<(), ParseError> {
+        let path = self
+            .resolve(name, span.file)
+            .ok_or_else(|| ParseError::new(span, format!("cannot find include file `{}`", name)))?;
+
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.stack.contains(&canonical) {
+            return Err(ParseError::new(
+                span,
+                format!("`{}` is included recursively", name),
+            ));
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| {
+            ParseError::new(span, format!("failed to read {}: {}", path.display(), e))
+        })?;
+
+        let file = self.files.len();
+        self.files.push(Some(path));
+        self.sources.push(source.clone());
+        self.stack.push(canonical);
+        self.tokenize_file(&source, file, output)?;
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    // Looks up an included file relative to the including file and in the
+    // include paths.
+    fn resolve(&self, name: &str, file: usize) -> Option<PathBuf> {
+        let name = Path::new(name);
+        if name.is_absolute() {
+            return Some(name.to_path_buf()).filter(|p| p.is_file());
+        }
+
+        let parent = self
+            .path(file)
+            .and_then(Path::parent)
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+
+        Some(parent)
+            .into_iter()
+            .chain(self.include_paths.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|p| p.is_file())
+    }
+}
@@ -0,0 +1,365 @@
+//! Incremental re-assembly of projects that consist of multiple files.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{codegen, Assembler, ParseErrors, Program};
+use crate::linker::{LinkError, Linker, PlacedSymbol};
+use crate::object::Object;
+
+/// An error that occurs when building a project through an
+/// [`IncrementalAssembler`].
+///
+/// [`IncrementalAssembler`]: struct.IncrementalAssembler.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Files of the project failed to assemble, with the errors of all files
+    /// in the order of the files.
+    Assemble(ParseErrors),
+    /// The objects of the files failed to link.
+    Link(LinkError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Assemble(e) => write!(f, "{}", e),
+            BuildError::Link(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl From<ParseErrors> for BuildError {
+    fn from(errors: ParseErrors) -> Self {
+        BuildError::Assemble(errors)
+    }
+}
+
+impl From<LinkError> for BuildError {
+    fn from(error: LinkError) -> Self {
+        BuildError::Link(error)
+    }
+}
+
+/// An error that occurs when a function is re-encoded through
+/// [`IncrementalAssembler::reassemble_function`].
+///
+/// [`IncrementalAssembler::reassemble_function`]: struct.IncrementalAssembler.html#method.reassemble_function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// No global symbol of the given name was placed by the most recent
+    /// successful build.
+    UnknownFunction(String),
+    /// The new code of the function failed to assemble.
+    Assemble(ParseErrors),
+    /// The new code of the function failed to link against the project.
+    Link(LinkError),
+    /// The new code places contents into the given section, which is not the
+    /// section of the function.
+    ForeignSection(String),
+    /// The new code of the function is larger than the old one.
+    TooLarge {
+        /// The size of the new code in bytes.
+        size: usize,
+        /// The size of the old code in bytes.
+        available: usize,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFunction(name) => write!(f, "unknown function `{}`", name),
+            PatchError::Assemble(e) => write!(f, "{}", e),
+            PatchError::Link(e) => write!(f, "{}", e),
+            PatchError::ForeignSection(name) => write!(
+                f,
+                "the function cannot place contents into section `{}`",
+                name
+            ),
+            PatchError::TooLarge { size, available } => write!(
+                f,
+                "the function takes {} bytes, but only {} bytes are available",
+                size, available
+            ),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+impl From<ParseErrors> for PatchError {
+    fn from(errors: ParseErrors) -> Self {
+        PatchError::Assemble(errors)
+    }
+}
+
+impl From<LinkError> for PatchError {
+    fn from(error: LinkError) -> Self {
+        PatchError::Link(error)
+    }
+}
+
+/// The new code of a single function, which replaces the old code at the
+/// address that the function was linked to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    /// The name of the section that contains the function.
+    pub section: String,
+    /// The address of the function.
+    pub address: u32,
+    /// The new code of the function.
+    pub data: Vec<u8>,
+}
+
+/// An assembler that keeps the objects of the files of a project around, so
+/// that rebuilding the project only assembles the files that changed.
+///
+/// This is meant for interactive tools, like editor integrations, that
+/// rebuild a project after every edit. The contents of the files are handed
+/// in by the tool, so that unsaved edits are picked up as well. A file is
+/// also assembled again when any of the files it includes changed on disk.
+/// All objects are linked in the order in which their files were added:
+///
+/// ```
+/// use faucon_asm::assembler::{Assembler, IncrementalAssembler};
+/// use faucon_asm::linker::Linker;
+///
+/// let mut project = IncrementalAssembler::new(Assembler::new(), Linker::new());
+/// project.update("main.s", ".extern exit\nlcall exit");
+/// project.update("exit.s", ".global exit\nexit: ret");
+///
+/// let program = project.build().unwrap();
+/// assert_eq!(program.sections[0].data, [0x7E, 0x04, 0x00, 0x00, 0xF8, 0x00]);
+///
+/// // Only `exit.s` is assembled again, whereas `main.s` is merely relinked.
+/// assert!(project.update("exit.s", ".global exit\nnop\nexit: ret"));
+/// assert!(!project.update("main.s", ".extern exit\nlcall exit"));
+///
+/// let program = project.build().unwrap();
+/// assert_eq!(program.sections[0].data[..4], [0x7E, 0x08, 0x00, 0x00]);
+/// ```
+///
+/// Single functions of the most recent build can also be re-encoded on their
+/// own, as described in [`IncrementalAssembler::reassemble_function`].
+///
+/// [`IncrementalAssembler::reassemble_function`]: struct.IncrementalAssembler.html#method.reassemble_function
+#[derive(Clone, Debug)]
+pub struct IncrementalAssembler {
+    assembler: Assembler,
+    linker: Linker,
+    files: Vec<File>,
+    // The object of every file, which is only valid for assembled files.
+    objects: Vec<Object>,
+    // The global symbols that were placed by the most recent successful build.
+    symbols: Vec<PlacedSymbol>,
+}
+
+// A source file of a project, along with the state of its object.
+#[derive(Clone, Debug)]
+struct File {
+    path: PathBuf,
+    source: String,
+    assembled: bool,
+    // The paths and contents of all files that were included when the file
+    // was last assembled.
+    includes: Vec<(PathBuf, String)>,
+}
+
+impl File {
+    // Checks whether the object of the file is outdated.
+    fn is_stale(&self) -> bool {
+        !self.assembled
+            || self
+                .includes
+                .iter()
+                .any(|(path, source)| fs::read_to_string(path).ok().as_ref() != Some(source))
+    }
+}
+
+impl IncrementalAssembler {
+    /// Creates a new incremental assembler without any files, which
+    /// assembles and links the files of the project with the given
+    /// configurations.
+    pub fn new(assembler: Assembler, linker: Linker) -> Self {
+        IncrementalAssembler {
+            assembler,
+            linker,
+            files: Vec::new(),
+            objects: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Sets the contents of the file at `path`, which is added to the project
+    /// if it isn't part of it yet.
+    ///
+    /// Returns whether the contents have changed, in which case the file is
+    /// assembled again by the next build.
+    pub fn update<P: Into<PathBuf>>(&mut self, path: P, source: &str) -> bool {
+        let path = path.into();
+        match self.files.iter_mut().find(|f| f.path == path) {
+            Some(file) if file.source == source => false,
+            Some(file) => {
+                file.source = source.to_string();
+                file.assembled = false;
+                true
+            }
+            None => {
+                self.files.push(File {
+                    path,
+                    source: source.to_string(),
+                    assembled: false,
+                    includes: Vec::new(),
+                });
+                self.objects.push(Object::default());
+                true
+            }
+        }
+    }
+
+    /// Removes the file at `path` from the project and returns whether it was
+    /// part of it.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        match self.files.iter().position(|f| f.path == path.as_ref()) {
+            Some(index) => {
+                self.files.remove(index);
+                self.objects.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Assembles all files that changed since the previous build and links
+    /// the objects of all files into a [`Program`].
+    ///
+    /// Files that fail to assemble are attempted again by the next build,
+    /// even if they don't change in the meantime.
+    ///
+    /// [`Program`]: struct.Program.html
+    pub fn build(&mut self) -> Result<Program, BuildError> {
+        let mut errors = Vec::new();
+        for (file, object) in self.files.iter_mut().zip(self.objects.iter_mut()) {
+            if !file.is_stale() {
+                continue;
+            }
+
+            file.assembled = false;
+            let result =
+                self.assembler
+                    .assemble(&file.source, Some(&file.path), codegen::assemble_object);
+            match result {
+                Ok((assembled, includer)) => {
+                    // Included files are counted from 1 and have all been read
+                    // by the time assembling succeeds.
+                    file.includes = (1..)
+                        .map(|i| (includer.path(i), includer.source(i)))
+                        .take_while(|(path, _)| path.is_some())
+                        .filter_map(|(path, source)| Some((path?.to_path_buf(), source?.into())))
+                        .collect();
+                    file.assembled = true;
+                    *object = assembled;
+                }
+                Err(e) => errors.extend(e.errors),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(BuildError::Assemble(ParseErrors { errors }));
+        }
+
+        let (program, symbols) = self.linker.link_with(&self.objects, &HashMap::new())?;
+        self.symbols = symbols;
+
+        Ok(program)
+    }
+
+    /// Re-encodes the global function `name` of the most recent successful
+    /// build from new source code, without assembling or linking anything
+    /// else.
+    ///
+    /// This is meant for patching the function in an image that is already
+    /// loaded, such as the code of a processor in the debugger. The new code
+    /// is placed at the address of the old one and may refer to all global
+    /// symbols of the project through `.extern`, which keep their addresses.
+    /// It must fit into the space of the old function, which extends up to
+    /// the next global symbol in its section or the end of its file.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::{Assembler, IncrementalAssembler, PatchError};
+    /// use faucon_asm::linker::Linker;
+    ///
+    /// let mut project = IncrementalAssembler::new(Assembler::new(), Linker::new());
+    /// project.update("main.s", ".extern function\nlcall function");
+    /// project.update("function.s", ".global function\n.global fail\nfunction: nop\nnop\nret\nfail: exit");
+    /// project.build().unwrap();
+    ///
+    /// let patch = project
+    ///     .reassemble_function("function", ".extern fail\nbra fail")
+    ///     .unwrap();
+    /// assert_eq!((patch.address, patch.data.as_slice()), (0x4, &[0xF5, 0x0E, 0x0A, 0x00][..]));
+    ///
+    /// assert_eq!(
+    ///     project.reassemble_function("function", "nop\nnop\nnop\nret"),
+    ///     Err(PatchError::TooLarge { size: 14, available: 10 })
+    /// );
+    /// ```
+    pub fn reassemble_function(&self, name: &str, source: &str) -> Result<Patch, PatchError> {
+        let function = self
+            .symbols
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| PatchError::UnknownFunction(name.to_string()))?;
+        let available = self
+            .symbols
+            .iter()
+            .filter(|s| s.section == function.section && s.address > function.address)
+            .fold(function.limit, |limit, s| limit.min(s.address))
+            - function.address;
+
+        let (mut object, _) = self
+            .assembler
+            .assemble(source, None, codegen::assemble_object)?;
+        for section in &mut object.sections {
+            if section.name != function.section && !section.data.is_empty() {
+                return Err(PatchError::ForeignSection(section.name.clone()));
+            }
+
+            // The code must start right at the old address.
+            section.alignment = 1;
+        }
+
+        let mut linker = Linker::new();
+        linker
+            .bases
+            .insert(function.section.clone(), function.address);
+        let external = self
+            .symbols
+            .iter()
+            .map(|s| (s.name.clone(), s.address))
+            .collect();
+        let (program, _) = linker.link_with(&[object], &external)?;
+
+        let data = program
+            .section(&function.section)
+            .map_or_else(Vec::new, |s| s.data.clone());
+        if data.len() > available as usize {
+            return Err(PatchError::TooLarge {
+                size: data.len(),
+                available: available as usize,
+            });
+        }
+
+        Ok(Patch {
+            section: function.section.clone(),
+            address: function.address,
+            data,
+        })
+    }
+}
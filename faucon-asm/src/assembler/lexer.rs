@@ -0,0 +1,437 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag_no_case, take_while, take_while1},
+    character::complete::{char, digit1, hex_digit1},
+    combinator::{map_res, recognize},
+    sequence::{pair, preceded},
+    IResult,
+};
+
+use super::{ParseError, Span};
+
+/// The kinds of tokens that make up Falcon assembly source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// An identifier, such as a mnemonic, a label or a flag name.
+    Identifier(String),
+    /// An assembler directive, without the leading `.`.
+    Directive(String),
+    /// A CPU register, without the leading `$`.
+    Register(String),
+    /// An integer literal, or the value of a character literal like `'A'`.
+    Number(i64),
+    /// A reference to a local numeric label, like `1f` or `1b`, along with
+    /// whether it refers forward.
+    LocalLabel(i64, bool),
+    /// A string literal, with all escape sequences resolved.
+    String(Vec<u8>),
+    /// A `:` character.
+    Colon,
+    /// A `,` character.
+    Comma,
+    /// A `[` character.
+    LBracket,
+    /// A `]` character.
+    RBracket,
+    /// A `+` character.
+    Plus,
+    /// A `-` character.
+    Minus,
+    /// A `*` character.
+    Star,
+    /// A `/` character.
+    Slash,
+    /// A `%` character.
+    Percent,
+    /// A `&` character.
+    Ampersand,
+    /// A `|` character.
+    Pipe,
+    /// A `^` character.
+    Caret,
+    /// A `~` character.
+    Tilde,
+    /// The `<<` operator.
+    ShiftLeft,
+    /// The `>>` operator.
+    ShiftRight,
+    /// A `(` character.
+    LParen,
+    /// A `)` character.
+    RParen,
+    /// The end of a line, which terminates statements.
+    Newline,
+}
+
+/// A token in assembly source code, along with its location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// The kind of token.
+    pub kind: TokenKind,
+    /// The location of the token in the source code.
+    pub span: Span,
+}
+
+/// Splits assembly source code into a sequence of [`Token`]s.
+///
+/// `file` denotes the index of the source file that is recorded in the
+/// [`Span`]s of the tokens.
+///
+/// [`Token`]: struct.Token.html
+/// [`Span`]: ../struct.Span.html
+pub fn tokenize(source: &str, file: usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut cursor = Cursor::new(source, file);
+
+    while let Some(c) = cursor.peek() {
+        let start = cursor.span();
+
+        // Skip whitespace and comments, but keep track of newlines.
+        if c == '\n' {
+            cursor.advance(1);
+            tokens.push(cursor.token(TokenKind::Newline, start));
+            continue;
+        } else if c.is_whitespace() {
+            cursor.advance(c.len_utf8());
+            continue;
+        } else if c == ';' || cursor.rest().starts_with("//") {
+            let length = cursor
+                .rest()
+                .find('\n')
+                .unwrap_or_else(|| cursor.rest().len());
+            cursor.advance(length);
+            continue;
+        } else if cursor.rest().starts_with("/*") {
+            // Block comments act like whitespace, even when they span lines.
+            match cursor.rest()[2..].find("*/") {
+                Some(length) => cursor.advance(length + 4),
+                None => {
+                    cursor.advance(cursor.rest().len());
+                    return Err(ParseError::new(
+                        cursor.token_span(start),
+                        "unterminated block comment",
+                    ));
+                }
+            }
+            continue;
+        }
+
+        let operator = if cursor.rest().starts_with("<<") {
+            Some(TokenKind::ShiftLeft)
+        } else if cursor.rest().starts_with(">>") {
+            Some(TokenKind::ShiftRight)
+        } else {
+            None
+        };
+        if let Some(kind) = operator {
+            cursor.advance(2);
+            tokens.push(cursor.token(kind, start));
+            continue;
+        }
+
+        let punctuation = match c {
+            ':' => Some(TokenKind::Colon),
+            ',' => Some(TokenKind::Comma),
+            '[' => Some(TokenKind::LBracket),
+            ']' => Some(TokenKind::RBracket),
+            '+' => Some(TokenKind::Plus),
+            '-' => Some(TokenKind::Minus),
+            '*' => Some(TokenKind::Star),
+            '/' => Some(TokenKind::Slash),
+            '%' => Some(TokenKind::Percent),
+            '&' => Some(TokenKind::Ampersand),
+            '|' => Some(TokenKind::Pipe),
+            '^' => Some(TokenKind::Caret),
+            '~' => Some(TokenKind::Tilde),
+            '(' => Some(TokenKind::LParen),
+            ')' => Some(TokenKind::RParen),
+            _ => None,
+        };
+        if let Some(kind) = punctuation {
+            cursor.advance(1);
+            tokens.push(cursor.token(kind, start));
+            continue;
+        }
+
+        if c == '"' {
+            let string = string(&mut cursor, start)?;
+            tokens.push(cursor.token(TokenKind::String(string), start));
+            continue;
+        } else if c == '\'' {
+            let value = character(&mut cursor, start)?;
+            tokens.push(cursor.token(TokenKind::Number(value), start));
+            continue;
+        }
+
+        let (mut rest, mut kind) = if let Ok((rest, number)) = number(cursor.rest()) {
+            (rest, TokenKind::Number(number))
+        } else if let Ok((rest, name)) = directive(cursor.rest()) {
+            (rest, TokenKind::Directive(name.to_string()))
+        } else if let Ok((rest, name)) = register(cursor.rest()) {
+            (rest, TokenKind::Register(name.to_string()))
+        } else if let Ok((rest, name)) = identifier(cursor.rest()) {
+            (rest, TokenKind::Identifier(name.to_string()))
+        } else {
+            cursor.advance(c.len_utf8());
+            return Err(ParseError::new(
+                cursor.token_span(start),
+                format!("unexpected character `{}`", c),
+            ));
+        };
+
+        // Decimal literals with a trailing `f` or `b` refer to local labels.
+        if let TokenKind::Number(number) = kind {
+            let literal = &cursor.rest()[..cursor.rest().len() - rest.len()];
+            let mut chars = rest.chars();
+            let direction = chars.next().filter(|&c| c == 'f' || c == 'b');
+            let terminated = !matches!(chars.next(), Some(c) if is_identifier_char(c));
+
+            if let (Some(direction), true) = (direction, terminated) {
+                if literal.chars().all(|c| c.is_ascii_digit()) {
+                    kind = TokenKind::LocalLabel(number, direction == 'f');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        // Literals must not run into identifiers, like in `0x12zz`.
+        if let TokenKind::Number(_) = kind {
+            if rest.starts_with(is_identifier_char) {
+                let length = cursor.rest().len() - rest.len();
+                let length = length + rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+                cursor.advance(length);
+                return Err(ParseError::new(
+                    cursor.token_span(start),
+                    "invalid numeric literal",
+                ));
+            }
+        }
+
+        cursor.advance(cursor.rest().len() - rest.len());
+        tokens.push(cursor.token(kind, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Finds the position of the [`TokenKind::Newline`] that terminates the line
+/// starting at `position`, or the end of the tokens if there is none.
+///
+/// [`TokenKind::Newline`]: enum.TokenKind.html#variant.Newline
+pub fn line_end(tokens: &[Token], position: usize) -> usize {
+    tokens[position..]
+        .iter()
+        .position(|t| t.kind == TokenKind::Newline)
+        .map_or(tokens.len(), |i| position + i)
+}
+
+// Keeps track of the current location while walking over source code.
+struct Cursor<'a> {
+    source: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+    file: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str, file: usize) -> Self {
+        Cursor {
+            source,
+            offset: 0,
+            line: 1,
+            column: 1,
+            file,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn advance(&mut self, length: usize) {
+        for c in self.source[self.offset..self.offset + length].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+
+        self.offset += length;
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset,
+            line: self.line,
+            column: self.column,
+            file: self.file,
+        }
+    }
+
+    fn token_span(&self, start: Span) -> Span {
+        Span {
+            end: self.offset,
+            ..start
+        }
+    }
+
+    fn token(&self, kind: TokenKind, start: Span) -> Token {
+        Token {
+            kind,
+            span: self.token_span(start),
+        }
+    }
+}
+
+// Reads a string literal at the cursor position and resolves its escape
+// sequences.
+fn string(cursor: &mut Cursor<'_>, start: Span) -> Result<Vec<u8>, ParseError> {
+    let mut string = Vec::new();
+
+    cursor.advance(1);
+    loop {
+        let c = match cursor.peek() {
+            Some('"') => break,
+            Some(c) if c != '\n' => c,
+            _ => {
+                return Err(ParseError::new(
+                    cursor.token_span(start),
+                    "unterminated string literal",
+                ))
+            }
+        };
+        let escape = cursor.span();
+        cursor.advance(c.len_utf8());
+
+        if c == '\\' {
+            string.push(escape_sequence(cursor, escape)?);
+        } else {
+            let mut buf = [0; 4];
+            string.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+    cursor.advance(1);
+
+    Ok(string)
+}
+
+// Reads a character literal at the cursor position and returns the value of
+// its character, which is the code point for characters outside of ASCII.
+fn character(cursor: &mut Cursor<'_>, start: Span) -> Result<i64, ParseError> {
+    cursor.advance(1);
+
+    let c = match cursor.peek() {
+        Some('\'') => {
+            cursor.advance(1);
+            return Err(ParseError::new(
+                cursor.token_span(start),
+                "empty character literal",
+            ));
+        }
+        Some(c) if c != '\n' => c,
+        _ => {
+            return Err(ParseError::new(
+                cursor.token_span(start),
+                "unterminated character literal",
+            ))
+        }
+    };
+    let escape = cursor.span();
+    cursor.advance(c.len_utf8());
+
+    let value = if c == '\\' {
+        i64::from(escape_sequence(cursor, escape)?)
+    } else {
+        i64::from(u32::from(c))
+    };
+    if cursor.peek() != Some('\'') {
+        return Err(ParseError::new(
+            cursor.token_span(start),
+            "unterminated character literal",
+        ));
+    }
+    cursor.advance(1);
+
+    Ok(value)
+}
+
+// Resolves the escape sequence that follows a `\` at `escape`, which is one
+// of `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'` or `\xNN` with two hex digits.
+fn escape_sequence(cursor: &mut Cursor<'_>, escape: Span) -> Result<u8, ParseError> {
+    let byte = match cursor.peek() {
+        Some('n') => Some(b'\n'),
+        Some('r') => Some(b'\r'),
+        Some('t') => Some(b'\t'),
+        Some('0') => Some(b'\0'),
+        Some('\\') => Some(b'\\'),
+        Some('"') => Some(b'"'),
+        Some('\'') => Some(b'\''),
+        Some('x') => cursor
+            .rest()
+            .get(1..3)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok()),
+        _ => None,
+    };
+    match byte {
+        Some(byte) => {
+            let length = if cursor.peek() == Some('x') { 3 } else { 1 };
+            cursor.advance(length);
+            Ok(byte)
+        }
+        None => {
+            // Point at the whole escape sequence, without running into the
+            // following characters.
+            let length = cursor.peek().map_or(0, char::len_utf8);
+            cursor.advance(length);
+            Err(ParseError::new(
+                cursor.token_span(escape),
+                "invalid escape sequence",
+            ))
+        }
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        take_while1(is_identifier_start),
+        take_while(is_identifier_char),
+    ))(input)
+}
+
+fn directive(input: &str) -> IResult<&str, &str> {
+    preceded(char('.'), identifier)(input)
+}
+
+fn register(input: &str) -> IResult<&str, &str> {
+    preceded(char('$'), take_while1(is_identifier_char))(input)
+}
+
+fn number(input: &str) -> IResult<&str, i64> {
+    alt((
+        map_res(preceded(tag_no_case("0x"), hex_digit1), |s| {
+            i64::from_str_radix(s, 16)
+        }),
+        map_res(
+            preceded(tag_no_case("0b"), take_while1(|c| c == '0' || c == '1')),
+            |s| i64::from_str_radix(s, 2),
+        ),
+        map_res(digit1, |s: &str| s.parse::<i64>()),
+    ))(input)
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use super::lexer::{line_end, Token, TokenKind};
+use super::{ParseError, Span};
+
+/// The maximum nesting depth of macro invocations, which guards against
+/// infinitely recursive macros.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+// A macro definition that was collected from the source code.
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Collects all macro definitions from a sequence of [`Token`]s and expands
+/// their invocations.
+///
+/// Macros are defined through `.macro name param, ...` and `.endm` and are
+/// invoked like instructions, with their arguments separated by commas. In
+/// the body of a macro, every identifier that names a parameter is replaced
+/// by the tokens of the corresponding argument. Labels that are defined in
+/// the body are local to every expansion of the macro.
+///
+/// [`Token`]: ../lexer/struct.Token.html
+pub fn expand(tokens: &[Token]) -> Result<Vec<Token>, ParseError> {
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+
+    let mut output = Vec::with_capacity(tokens.len());
+    expander.expand(tokens, &mut output, 0)?;
+
+    Ok(output)
+}
+
+struct Expander {
+    macros: HashMap<String, Macro>,
+    // A counter of expansions, used to make local labels unique.
+    expansions: usize,
+}
+
+impl Expander {
+    fn expand(
+        &mut self,
+        tokens: &[Token],
+        output: &mut Vec<Token>,
+        depth: usize,
+    ) -> Result<(), ParseError> {
+        let mut position = 0;
+        while position < tokens.len() {
+            let end = line_end(tokens, position);
+            let line = &tokens[position..end];
+
+            match line.first() {
+                Some(Token {
+                    kind: TokenKind::Directive(name),
+                    ..
+                }) if name == "macro" => {
+                    position = self.define(tokens, position)?;
+                    continue;
+                }
+                Some(Token {
+                    kind: TokenKind::Directive(name),
+                    span,
+                }) if name == "endm" => {
+                    return Err(ParseError::new(*span, "`.endm` without `.macro`"));
+                }
+                _ => {}
+            }
+
+            // Skip the label definitions in front of a potential invocation.
+            let mut start = 0;
+            while is_label(line, start) {
+                start += 2;
+            }
+
+            match line.get(start) {
+                Some(Token {
+                    kind: TokenKind::Identifier(name),
+                    span,
+                }) if self.macros.contains_key(name) => {
+                    if depth >= MAX_EXPANSION_DEPTH {
+                        return Err(ParseError::new(
+                            *span,
+                            "macro expansion is nested too deeply",
+                        ));
+                    }
+
+                    output.extend_from_slice(&line[..start]);
+                    let body = self.instantiate(name, *span, &line[start + 1..])?;
+                    self.expand(&body, output, depth + 1)?;
+                }
+                _ => output.extend_from_slice(line),
+            }
+
+            if let Some(newline) = tokens.get(end) {
+                output.push(newline.clone());
+            }
+            position = end + 1;
+        }
+
+        Ok(())
+    }
+
+    // Parses a macro definition that starts at `position` and returns the
+    // position of the first token after it.
+    fn define(&mut self, tokens: &[Token], position: usize) -> Result<usize, ParseError> {
+        let end = line_end(tokens, position);
+        let header = &tokens[position..end];
+        let span = header[0].span;
+
+        let name = match header.get(1) {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                ..
+            }) => name.clone(),
+            token => {
+                let span = token.map_or(span, |t| t.span);
+                return Err(ParseError::new(span, "expected a macro name"));
+            }
+        };
+        if self.macros.contains_key(&name) {
+            return Err(ParseError::new(
+                header[1].span,
+                format!("macro `{}` is defined multiple times", name),
+            ));
+        }
+
+        // Parse the parameter names, which may optionally be separated by commas.
+        let mut params: Vec<String> = Vec::new();
+        for token in &header[2..] {
+            match &token.kind {
+                TokenKind::Comma => {}
+                TokenKind::Identifier(param) if !params.contains(param) => {
+                    params.push(param.clone());
+                }
+                TokenKind::Identifier(param) => {
+                    return Err(ParseError::new(
+                        token.span,
+                        format!("parameter `{}` is defined multiple times", param),
+                    ));
+                }
+                _ => return Err(ParseError::new(token.span, "expected a parameter name")),
+            }
+        }
+
+        // Collect the body until the matching `.endm`.
+        let start = end + 1;
+        let mut position = start;
+        while position < tokens.len() {
+            let end = line_end(tokens, position);
+            match tokens[position].kind {
+                TokenKind::Directive(ref directive) if directive == "endm" => {
+                    if let Some(token) = tokens[position + 1..end].first() {
+                        return Err(ParseError::new(token.span, "unexpected token"));
+                    }
+
+                    let body = tokens[start..position].to_vec();
+                    self.macros.insert(name, Macro { params, body });
+                    return Ok(end + 1);
+                }
+                TokenKind::Directive(ref directive) if directive == "macro" => {
+                    return Err(ParseError::new(
+                        tokens[position].span,
+                        "macros cannot be defined inside of macros",
+                    ));
+                }
+                _ => position = end + 1,
+            }
+        }
+
+        Err(ParseError::new(
+            span,
+            format!("macro `{}` is missing its `.endm`", name),
+        ))
+    }
+
+    // Creates the tokens of a macro invocation with the given arguments.
+    fn instantiate(
+        &mut self,
+        name: &str,
+        span: Span,
+        arguments: &[Token],
+    ) -> Result<Vec<Token>, ParseError> {
+        let definition = &self.macros[name];
+
+        let arguments = if arguments.is_empty() {
+            Vec::new()
+        } else {
+            arguments
+                .split(|t| t.kind == TokenKind::Comma)
+                .collect::<Vec<_>>()
+        };
+        if arguments.len() != definition.params.len() {
+            return Err(ParseError::new(
+                span,
+                format!(
+                    "macro `{}` takes {} arguments, but {} were given",
+                    name,
+                    definition.params.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        if let Some(index) = arguments.iter().position(|arg| arg.is_empty()) {
+            return Err(ParseError::new(
+                span,
+                format!("argument {} of macro `{}` is empty", index + 1, name),
+            ));
+        }
+
+        // Labels that are defined in the body are renamed for every expansion.
+        self.expansions += 1;
+        let locals = (0..definition.body.len())
+            .filter(|&i| definition.body.get(i + 1).map(|t| &t.kind) == Some(&TokenKind::Colon))
+            .filter_map(|i| match &definition.body[i].kind {
+                TokenKind::Identifier(label) => Some(label.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut body = Vec::with_capacity(definition.body.len());
+        for token in &definition.body {
+            match &token.kind {
+                TokenKind::Identifier(ident) => {
+                    if let Some(index) = definition.params.iter().position(|p| p == ident) {
+                        body.extend_from_slice(arguments[index]);
+                    } else if locals.contains(&ident.as_str()) {
+                        body.push(Token {
+                            kind: TokenKind::Identifier(format!("{}@{}", ident, self.expansions)),
+                            span: token.span,
+                        });
+                    } else {
+                        body.push(token.clone());
+                    }
+                }
+                _ => body.push(token.clone()),
+            }
+        }
+
+        Ok(body)
+    }
+}
+
+// Checks whether the tokens at `position` in a line define a label.
+fn is_label(line: &[Token], position: usize) -> bool {
+    match (line.get(position), line.get(position + 1)) {
+        (Some(first), Some(second)) => {
+            matches!(first.kind, TokenKind::Identifier(_) | TokenKind::Number(_))
+                && second.kind == TokenKind::Colon
+        }
+        _ => false,
+    }
+}
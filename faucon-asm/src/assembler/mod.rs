@@ -0,0 +1,805 @@
+//! Assembler for the Falcon ISA.
+//!
+//! The assembler turns Falcon assembly source code into machine code. The
+//! syntax matches the output of the disassembler, so that instructions can be
+//! round-tripped:
+//!
+//! ```text
+//! // Comments span until the end of the line, ...
+//! ; ... which may also be written like this.
+//! entry: /* Block comments may go anywhere. */
+//!     mov $r1 0x10
+//!     ld b32 $r2 D[$r1 + 0x4]
+//!     add b32 $r2 $r2 0x1
+//!     st b32 D[$r1] $r2
+//!     lcall entry
+//!     ret
+//! ```
+//!
+//! Every line holds at most one instruction, optionally preceded by label
+//! definitions. Operands may be separated by whitespace or commas. Labels can be
+//! used wherever an immediate is expected and evaluate to the address of their
+//! definition, relative to the start of the assembled code.
+//!
+//! Labels may also be plain decimal numbers, which can be defined any number
+//! of times. Such a local label is referred to through `Nb` for its closest
+//! preceding or `Nf` for its closest following definition:
+//!
+//! ```text
+//! 1:
+//!     sub b32 $r1 $r1 0x1
+//!     bz 2f
+//!     bra 1b
+//! 2:
+//! ```
+//!
+//! A few pseudo-instructions stand for real instructions: `nop` assembles to
+//! `add $sp $sp 0x0`, which leaves the flags untouched, and `clr [size] $rX` to
+//! `clear` with a size of `b32` unless specified otherwise. `call` and `jmp`
+//! fall back to `lcall` and `ljmp` when their target doesn't fit into any of
+//! their regular forms.
+//!
+//! The former mnemonics `ptlb`, `vtlb` and `itlb` are still accepted for the
+//! instructions `imblk`, `imtag` and `iminv`, which they were renamed to:
+//!
+//! ```
+//! use faucon_asm::assembler::assemble_str;
+//!
+//! assert_eq!(
+//!     assemble_str("ptlb $r1 $r2\nvtlb $r1 $r2\nitlb $r2").unwrap(),
+//!     assemble_str("imblk $r1 $r2\nimtag $r1 $r2\niminv $r2").unwrap(),
+//! );
+//! ```
+//!
+//! Besides instructions, the following directives are supported for emitting
+//! data alongside the code:
+//!
+//! | Directive              | Description                                        |
+//! |------------------------|----------------------------------------------------|
+//! | `.byte a, b, ...`      | Emits 8-bit values                                 |
+//! | `.word a, b, ...`      | Emits 16-bit little-endian values                  |
+//! | `.dword a, b, ...`     | Emits 32-bit little-endian values                  |
+//! | `.str "text"`          | Emits the bytes of a string, without a terminator  |
+//! | `.align n`             | Pads with zeros to the next multiple of `n` bytes  |
+//! | `.org addr`            | Pads with zeros up to the address `addr`           |
+//! | `.pad n[, fill]`       | Pads the section to a multiple of `n` bytes        |
+//! | `.sum32 start, end`    | Emits the 32-bit sum of the words in a range       |
+//! | `.crc32 start, end`    | Emits the CRC-32 of the bytes in a range           |
+//! | `.secure name`         | Starts a secure region on a new page               |
+//! | `.endsecure`           | Ends a secure region, padding to the page end      |
+//! | `.signature name[, n]` | Reserves `n` bytes for the signature of a region   |
+//! | `.skip n[, fill]`      | Emits `n` bytes of `fill`, which defaults to zero  |
+//! | `.fill n[, size[, v]]` | Emits `n` copies of the `size`-byte value `v`      |
+//! | `.equ NAME, value`     | Defines a constant symbol                          |
+//! | `.set NAME, value`     | Defines a constant symbol that may be redefined    |
+//! | `.section name[, org]` | Switches sections, optionally setting the origin   |
+//! | `.code [org]`          | Shorthand for `.section code`                      |
+//! | `.data [org]`          | Shorthand for `.section data`                      |
+//! | `.global a, b, ...`    | Makes labels visible to other objects              |
+//! | `.extern a, b, ...`    | Declares symbols that other objects define         |
+//! | `.if expr`             | Assembles the following block if `expr` is nonzero |
+//! | `.ifdef NAME`          | Assembles the following block if `NAME` is defined |
+//! | `.ifndef NAME`         | Like `.ifdef`, but if `NAME` is undefined          |
+//! | `.else`                | Starts the alternative block of a condition        |
+//! | `.endif`               | Ends a conditional block                           |
+//! | `.rept n`              | Repeats the following block `n` times              |
+//! | `.endr`                | Ends a repeated block                              |
+//!
+//! String literals support the escape sequences `\n`, `\r`, `\t`, `\0`,
+//! `\\`, `\"`, `\'` and `\xNN` for arbitrary bytes. Character literals like
+//! `'A'` or `'\n'` evaluate to the value of their character and may be used
+//! wherever a value is expected, which comes in handy for message tables:
+//!
+//! ```
+//! use faucon_asm::assembler::assemble_str;
+//!
+//! let code = assemble_str(".str \"OK\\r\\n\\0\"\n.byte '!', '\\x7F'").unwrap();
+//! assert_eq!(code, b"OK\r\n\0!\x7F");
+//! ```
+//!
+//! Code and data can be split into sections, like `.code` for IMEM and `.data`
+//! for DMEM, which each have their own location counter starting at their
+//! origin. Statements are placed in the `code` section until the first section
+//! directive. [`assemble_program_str`] produces the images of all sections,
+//! whereas [`assemble_str`] only produces the contents of the `code` section.
+//!
+//! Within a section, `.org` continues the code at a fixed address, so that
+//! PC-relative branches and absolute calls match where the code is loaded.
+//! When it comes before any code of a section without an explicit origin, it
+//! sets the origin instead. Code may never be placed at addresses that are
+//! already taken by the code in front of it:
+//!
+//! ```
+//! let program = faucon_asm::assembler::assemble_program_str(
+//!     ".org 0x100\nentry:\nlcall handler\n.org 0x200\nhandler:\nret",
+//! ).unwrap();
+//! let code = &program.sections[0];
+//!
+//! assert_eq!((code.origin, code.data.len()), (0x100, 0x102));
+//! assert_eq!(code.data[..4], [0x7E, 0x00, 0x02, 0x00]);
+//!
+//! let error = faucon_asm::assembler::assemble_str(".org 0x10\nret\n.org 0x11").unwrap_err();
+//! assert_eq!(error.first().message, "address 0x11 overlaps the code in front of it");
+//! ```
+//!
+//! Firmware images often need to be padded to whole pages and carry checksums
+//! that loaders verify. `.pad` counts from the start of the section rather
+//! than from address zero. Checksums are computed over the final contents of
+//! their section, so they may cover code that follows them:
+//!
+//! ```
+//! let source = ".crc32 body, end\nbody:\nret\n.pad 0x10, 0xFF\nend:";
+//! let code = faucon_asm::assembler::assemble_str(source).unwrap();
+//!
+//! assert_eq!(code.len(), 0x10);
+//! assert_eq!(code[4..6], [0xF8, 0x00]);
+//!
+//! let error = faucon_asm::assembler::assemble_str("ret\n.pad 0x100000000").unwrap_err();
+//! assert_eq!(error.first().message, "directive emits too many bytes");
+//! ```
+//!
+//! Code that runs in secure mode is placed between `.secure name` and
+//! `.endsecure`, which start and end the region on page boundaries. Blocks
+//! for signatures are reserved through `.signature name` and are left for
+//! external signing tools to fill in, which find them in the [`Program`]:
+//!
+//! ```
+//! let source = "lcall payload\nexit\n.secure hs\npayload:\nret\n.endsecure\n.signature hs";
+//! let program = faucon_asm::assembler::assemble_program_str(source).unwrap();
+//!
+//! let region = &program.secure_regions[0];
+//! assert_eq!((region.start, region.end), (0x100, 0x200));
+//! assert_eq!((program.signatures[0].address, program.signatures[0].size), (0x200, 0x10));
+//!
+//! let source = ".secure hs\nret\n.endsecure\n.signature hs, 0x100000000";
+//! let error = faucon_asm::assembler::assemble_program_str(source).unwrap_err();
+//! assert_eq!(error.first().message, "directive emits too many bytes");
+//! ```
+//!
+//! For separate compilation, [`assemble_object_str`] produces a relocatable
+//! [`Object`] instead. Its sections don't have fixed origins and symbols that
+//! are declared through `.extern` are left for the linker to resolve against
+//! the `.global` labels of other objects. With the `parallel` feature,
+//! [`Assembler::assemble_object_files`] assembles the files of larger projects
+//! concurrently. Interactive tools that rebuild a project after every edit can
+//! use an [`IncrementalAssembler`], which only assembles the changed files.
+//!
+//! Wherever a value is expected, arithmetic expressions over numbers and
+//! symbols, like `BASE + 0x40`, `end - start` or `(FLAGS << 8) | ~MASK`, may
+//! be used. They support the operators `+`, `-`, `*`, `/`, `%`, `<<`, `>>`,
+//! `&`, `|`, `^` and `~` with the precedence of C, as well as parentheses.
+//! A symbol that is redefined through `.set` takes the value of the latest
+//! definition in front of its use. Constants whose definitions refer to
+//! themselves, directly or through other constants, are rejected:
+//!
+//! ```
+//! use faucon_asm::assembler::assemble_str;
+//!
+//! assert_eq!(assemble_str(".set A, 1\n.set A, A + 1\nmov $r0 A").unwrap(), [0x00, 0x02]);
+//!
+//! let error = assemble_str(".equ A, B + 1\n.equ B, A\nmov $r0 A").unwrap_err();
+//! assert_eq!(error.first().message, "circular definition of `A`");
+//! let error = assemble_str(".equ A, 1 / 0\nmov $r0 A").unwrap_err();
+//! assert_eq!(error.first().message, "division by zero");
+//! ```
+//!
+//! Recurring instruction sequences can be written once as macros. Parameters
+//! are referred to by their names and labels inside of a macro are local to
+//! every invocation of it:
+//!
+//! ```text
+//! .macro spin reg, count
+//!     mov reg count
+//! loop:
+//!     sub b32 reg reg 0x1
+//!     bnz loop
+//! .endm
+//!
+//!     spin $r1, 0x100
+//! ```
+//!
+//! Conditional blocks select code for different targets from the same source.
+//! Conditions may refer to the [`Assembler::defines`] and to the constants
+//! that are defined in front of them, whereas `.ifdef` also recognizes the
+//! preceding labels:
+//!
+//! ```text
+//! .ifdef SECURE
+//!     mov $r1 SECURE_BASE
+//! .else
+//!     mov $r1 0x0
+//! .endif
+//! ```
+//!
+//! Conditions are evaluated before files are included and macros are
+//! expanded, so files in inactive branches are never read and both branches
+//! may define the same macro. Only constants and labels that are spelled out
+//! in the source text are known to them at that point. Conditions inside of
+//! macros and `.rept` blocks, as well as `.if` conditions that refer to
+//! constants of unknown value, are evaluated once macros were expanded:
+//!
+//! ```
+//! use faucon_asm::assembler::assemble_str;
+//!
+//! let source = ".ifdef BOARD\n.include \"board.inc\"\n.endif\nret";
+//! assert_eq!(assemble_str(source).unwrap(), [0xF8, 0x00]);
+//!
+//! let source = ".ifdef FAST\n.macro leave\nret\n.endm\n\
+//!               .else\n.macro leave\nexit\n.endm\n.endif\n\
+//!               leave";
+//! assert_eq!(assemble_str(source).unwrap(), assemble_str("exit").unwrap());
+//! ```
+//!
+//! Likewise, the number of repetitions of a `.rept` block has to be known in
+//! front of it. Nested blocks may not repeat their contents more than 65536
+//! times in total, and no single directive like `.fill`, `.skip` or `.org`
+//! may emit more than 16 MiB. Together with `.fill`, this is handy for tables:
+//!
+//! ```text
+//! .set vector, 0
+//! .rept 4
+//!     .dword handlers + vector * 4
+//!     .set vector, vector + 1
+//! .endr
+//!     .fill 12, 4, default_handler
+//! ```
+//!
+//! ```
+//! let error = faucon_asm::assembler::assemble_str(".rept 100000000\nret\n.endr").unwrap_err();
+//! assert_eq!(error.first().message, "too many repetitions");
+//!
+//! let error = faucon_asm::assembler::assemble_str(".fill 0x7fffffffffffffff, 4").unwrap_err();
+//! assert_eq!(error.first().message, "directive emits too many bytes");
+//! let error = faucon_asm::assembler::assemble_str("ret\n.org 0xFFFFFFFF").unwrap_err();
+//! assert_eq!(error.first().message, "directive emits too many bytes");
+//! ```
+//!
+//! Shared definitions can be moved into separate files which are pulled in
+//! through `.include "file.inc"`. Included files are searched relative to the
+//! including file first and in the [`Assembler::include_paths`] afterwards.
+//!
+//! As IMEM is scarce, [`Assembler::optimize`] lets the assembler replace
+//! instructions by equivalent ones with shorter encodings:
+//!
+//! ```
+//! use faucon_asm::assembler::Assembler;
+//!
+//! let mut assembler = Assembler::new();
+//! assembler.optimize = true;
+//!
+//! // `lcall` always takes a 24-bit immediate, whereas `call` has shorter forms.
+//! assert_eq!(assembler.assemble_str("lcall 0x10").unwrap().len(), 3);
+//! ```
+//!
+//! Values that don't fit into the fields of an instruction or a data directive
+//! are rejected. Legacy sources that rely on values being cut off can set
+//! [`Assembler::truncate_immediates`] instead, which reports every truncation
+//! as a warning:
+//!
+//! ```
+//! use faucon_asm::assembler::Assembler;
+//!
+//! let mut assembler = Assembler::new();
+//! assert!(assembler.assemble_program_str(".byte 0x1FF").is_err());
+//!
+//! assembler.truncate_immediates = true;
+//! let program = assembler.assemble_program_str(".byte 0x1FF").unwrap();
+//! assert_eq!(program.sections[0].data, [0xFF]);
+//! assert_eq!(program.warnings[0].message, "value does not fit into 8 bits");
+//! ```
+//!
+//! Tools that need directives of their own, such as for emitting firmware
+//! descriptors, can plug them in through [`Assembler::register_directive`].
+//!
+//! [`assemble_program_str`]: fn.assemble_program_str.html
+//! [`assemble_str`]: fn.assemble_str.html
+//! [`assemble_object_str`]: fn.assemble_object_str.html
+//! [`Object`]: ../object/struct.Object.html
+//! [`Program`]: struct.Program.html
+//! [`Assembler::include_paths`]: struct.Assembler.html#structfield.include_paths
+//! [`Assembler::defines`]: struct.Assembler.html#structfield.defines
+//! [`Assembler::optimize`]: struct.Assembler.html#structfield.optimize
+//! [`Assembler::assemble_object_files`]: struct.Assembler.html#method.assemble_object_files
+//! [`IncrementalAssembler`]: struct.IncrementalAssembler.html
+//! [`Assembler::register_directive`]: struct.Assembler.html#method.register_directive
+//! [`Assembler::truncate_immediates`]: struct.Assembler.html#structfield.truncate_immediates
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::debug_info::DebugInfo;
+use crate::object::Object;
+
+pub use directive::{DirectiveContext, DirectiveHandler};
+pub use error::*;
+pub use incremental::{BuildError, IncrementalAssembler, Patch, PatchError};
+pub use lexer::{Token, TokenKind};
+
+mod codegen;
+mod conditional;
+mod directive;
+mod error;
+mod include;
+mod incremental;
+mod lexer;
+mod macros;
+mod parser;
+
+/// The machine code and data that was assembled from source code, split into
+/// the sections it defines.
+///
+/// ```
+/// let program = faucon_asm::assembler::assemble_program_str(
+///     ".data 0x100\nvalue: .dword 0x1234\n.code\nmov $r1 value",
+/// )
+/// .unwrap();
+///
+/// let data = program.section("data").unwrap();
+/// assert_eq!((data.origin, data.data.as_slice()), (0x100, &[0x34, 0x12, 0, 0][..]));
+/// assert_eq!(program.section("code").unwrap().data, [0x41, 0x00, 0x01]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// All sections in order of their first appearance in the source code,
+    /// starting with the `code` section.
+    pub sections: Vec<Section>,
+    /// The source locations and labels of the `code` section, if known.
+    pub debug_info: Option<DebugInfo>,
+    /// The regions of code that run in secure mode, in order of appearance.
+    pub secure_regions: Vec<SecureRegion>,
+    /// The blocks that are reserved for the signatures of secure regions.
+    pub signatures: Vec<Signature>,
+    /// The problems in the source code that didn't prevent assembling it, such
+    /// as values that were truncated to fit into their fields.
+    pub warnings: Vec<ParseError>,
+}
+
+impl Program {
+    /// Gets the section with the given name, if it is defined.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    // Converts the program into a flat binary, which is only possible when
+    // all the data is placed in the default section.
+    fn into_flat(self) -> Result<Vec<u8>, ParseError> {
+        let mut code = Vec::new();
+        for section in self.sections {
+            if section.name == codegen::DEFAULT_SECTION {
+                code = section.data;
+            } else if !section.data.is_empty() {
+                return Err(ParseError::new(
+                    Span::default(),
+                    format!(
+                        "section `{}` cannot be represented in a flat binary",
+                        section.name
+                    ),
+                ));
+            }
+        }
+
+        Ok(code)
+    }
+}
+
+/// A range of a section that is marked for execution in secure mode, such as
+/// the code of a Heavy Secure payload.
+///
+/// Secure regions are delimited by `.secure name` and `.endsecure`, which pad
+/// the code to the boundaries of [`SECURE_PAGE_SIZE`]d pages.
+///
+/// [`SECURE_PAGE_SIZE`]: constant.SECURE_PAGE_SIZE.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecureRegion {
+    /// The name of the region.
+    pub name: String,
+    /// The name of the section that contains the region.
+    pub section: String,
+    /// The address of the first byte of the region.
+    pub start: u32,
+    /// The address behind the last byte of the region.
+    pub end: u32,
+}
+
+/// A block of zeros that is reserved through `.signature` for an external
+/// signing tool to fill in the signature of a [`SecureRegion`].
+///
+/// [`SecureRegion`]: struct.SecureRegion.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// The name of the secure region that is signed.
+    pub region: String,
+    /// The name of the section that contains the block.
+    pub section: String,
+    /// The address of the block.
+    pub address: u32,
+    /// The size of the block in bytes.
+    pub size: u32,
+}
+
+/// The size of the IMEM pages that are marked as secure as a whole.
+pub const SECURE_PAGE_SIZE: u32 = 0x100;
+
+/// The size of a signature block when no size is given, which fits an
+/// AES-CMAC signature.
+pub const SIGNATURE_SIZE: u32 = 0x10;
+
+/// A contiguous image of code or data that is loaded at a fixed address.
+///
+/// Every section has its own location counter, which starts at the origin of
+/// the section. By convention, the `code` section is loaded into IMEM and the
+/// `data` section into DMEM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    /// The name of the section.
+    pub name: String,
+    /// The address of the first byte of the section.
+    pub origin: u32,
+    /// The contents of the section.
+    pub data: Vec<u8>,
+}
+
+/// An assembler for Falcon source code along with its configuration.
+///
+/// For assembling code with the default configuration, [`assemble_str`] and
+/// [`assemble_file`] are more convenient.
+///
+/// ```
+/// use faucon_asm::assembler::Assembler;
+///
+/// let mut assembler = Assembler::new();
+/// assembler.include_paths.push("include".into());
+/// assembler.defines.insert("SECURE".into(), 1);
+///
+/// let source = ".if SECURE\nret\n.else\nexit\n.endif";
+/// assert_eq!(assembler.assemble_str(source).unwrap(), [0xF8, 0x00]);
+/// ```
+///
+/// [`assemble_str`]: fn.assemble_str.html
+/// [`assemble_file`]: fn.assemble_file.html
+#[derive(Clone, Default)]
+pub struct Assembler {
+    /// The directories to search for files that are pulled in through the
+    /// `.include` directive, in order.
+    pub include_paths: Vec<PathBuf>,
+    /// The constants that are predefined for the source code, by their names.
+    /// They may be used wherever a value is expected, most notably in the
+    /// conditions of `.if` blocks.
+    pub defines: HashMap<String, i64>,
+    /// Whether to optimize the code for size. Instructions are replaced by
+    /// equivalent ones with shorter encodings, such as `call` for `lcall` or
+    /// `clear` for moving zero into a register.
+    pub optimize: bool,
+    /// Whether values that don't fit into their fields are truncated, rather
+    /// than rejected. Every truncation is reported in [`Program::warnings`],
+    /// whereas relocatable objects always reject such values.
+    ///
+    /// [`Program::warnings`]: struct.Program.html#structfield.warnings
+    pub truncate_immediates: bool,
+    directives: parser::Directives,
+}
+
+impl Assembler {
+    /// Creates a new assembler with the default configuration.
+    pub fn new() -> Self {
+        Assembler::default()
+    }
+
+    /// Registers a handler for the custom directive `name`, which is given
+    /// without the leading `.`.
+    ///
+    /// A handler that was previously registered for the same name is
+    /// replaced. See [`DirectiveHandler`] for an example.
+    ///
+    /// [`DirectiveHandler`]: trait.DirectiveHandler.html
+    pub fn register_directive<H: DirectiveHandler + 'static>(&mut self, name: &str, handler: H) {
+        self.directives.insert(name.to_string(), Arc::new(handler));
+    }
+
+    /// Assembles Falcon assembly source code into machine code.
+    ///
+    /// Included files are searched relative to the current working directory
+    /// first. The code must not place any data outside of the `code` section.
+    pub fn assemble_str(&self, source: &str) -> Result<Vec<u8>, ParseErrors> {
+        Ok(self.assemble_program_str(source)?.into_flat()?)
+    }
+
+    /// Reads a file of Falcon assembly source code and assembles it into
+    /// machine code.
+    ///
+    /// The code must not place any data outside of the `code` section.
+    pub fn assemble_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, ParseErrors> {
+        let path = path.as_ref();
+        let code = self
+            .assemble_program_file(path)?
+            .into_flat()
+            .map_err(|e| ParseError {
+                file: Some(path.to_path_buf()),
+                ..e
+            })?;
+
+        Ok(code)
+    }
+
+    /// Assembles Falcon assembly source code into a [`Program`] that holds
+    /// all of its sections.
+    ///
+    /// [`Program`]: struct.Program.html
+    pub fn assemble_program_str(&self, source: &str) -> Result<Program, ParseErrors> {
+        self.assemble(source, None, codegen::assemble)
+            .map(with_sources)
+    }
+
+    /// Reads a file of Falcon assembly source code and assembles it into a
+    /// [`Program`] that holds all of its sections.
+    ///
+    /// [`Program`]: struct.Program.html
+    pub fn assemble_program_file<P: AsRef<Path>>(&self, path: P) -> Result<Program, ParseErrors> {
+        self.assemble_file_with(path.as_ref(), codegen::assemble)
+            .map(with_sources)
+    }
+
+    /// Assembles Falcon assembly source code into a relocatable [`Object`].
+    ///
+    /// Symbols that are declared through `.extern` are left for the linker
+    /// to resolve.
+    ///
+    /// [`Object`]: ../object/struct.Object.html
+    pub fn assemble_object_str(&self, source: &str) -> Result<Object, ParseErrors> {
+        self.assemble(source, None, codegen::assemble_object)
+            .map(|(object, _)| object)
+    }
+
+    /// Reads a file of Falcon assembly source code and assembles it into a
+    /// relocatable [`Object`].
+    ///
+    /// [`Object`]: ../object/struct.Object.html
+    pub fn assemble_object_file<P: AsRef<Path>>(&self, path: P) -> Result<Object, ParseErrors> {
+        self.assemble_file_with(path.as_ref(), codegen::assemble_object)
+            .map(|(object, _)| object)
+    }
+
+    /// Reads multiple files of Falcon assembly source code and assembles each
+    /// of them into a relocatable [`Object`] concurrently.
+    ///
+    /// Every file is tokenized, parsed and laid out on its own, which leaves
+    /// resolving the symbols between files to the [`Linker`]. The errors of
+    /// all files are reported at once, in the order of the files:
+    ///
+    /// ```
+    /// use faucon_asm::assembler::Assembler;
+    /// use faucon_asm::linker::link;
+    ///
+    /// let dir = std::env::temp_dir().join("faucon-asm-project");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("main.s"), ".extern exit\nlcall exit").unwrap();
+    /// std::fs::write(dir.join("exit.s"), ".global exit\nexit: ret").unwrap();
+    ///
+    /// let paths = [dir.join("main.s"), dir.join("exit.s")];
+    /// let objects = Assembler::new().assemble_object_files(&paths).unwrap();
+    /// let program = link(&objects).unwrap();
+    ///
+    /// assert_eq!(program.sections[0].data, [0x7E, 0x04, 0x00, 0x00, 0xF8, 0x00]);
+    /// ```
+    ///
+    /// This method is only available with the `parallel` feature enabled.
+    ///
+    /// [`Object`]: ../object/struct.Object.html
+    /// [`Linker`]: ../linker/struct.Linker.html
+    #[cfg(feature = "parallel")]
+    pub fn assemble_object_files<P: AsRef<Path> + Sync>(
+        &self,
+        paths: &[P],
+    ) -> Result<Vec<Object>, ParseErrors> {
+        let results = paths
+            .par_iter()
+            .map(|path| self.assemble_object_file(path))
+            .collect::<Vec<_>>();
+
+        let mut objects = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(object) => objects.push(object),
+                Err(e) => errors.extend(e.errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(objects)
+        } else {
+            Err(ParseErrors { errors })
+        }
+    }
+
+    fn assemble_file_with<T>(
+        &self,
+        path: &Path,
+        codegen: fn(&[parser::Statement], codegen::Options) -> Result<T, ParseErrors>,
+    ) -> Result<(T, include::Includer<'_>), ParseErrors> {
+        let source = fs::read_to_string(path).map_err(|e| ParseError {
+            file: Some(path.to_path_buf()),
+            ..ParseError::new(Span::default(), format!("failed to read file: {}", e))
+        })?;
+
+        self.assemble(&source, Some(path), codegen)
+    }
+
+    // Assembles source code through the given code generator and returns the
+    // result along with the includer that holds all source files that were
+    // read.
+    fn assemble<T>(
+        &self,
+        source: &str,
+        path: Option<&Path>,
+        codegen: fn(&[parser::Statement], codegen::Options) -> Result<T, ParseErrors>,
+    ) -> Result<(T, include::Includer<'_>), ParseErrors> {
+        let mut includer = include::Includer::new(&self.include_paths, path, &self.defines);
+
+        let result = includer
+            .tokenize(source)
+            .and_then(|tokens| macros::expand(&tokens))
+            .map_err(ParseErrors::from)
+            .and_then(|tokens| parser::parse(&tokens, &self.defines, &self.directives))
+            .and_then(|statements| {
+                let options = codegen::Options {
+                    optimize: self.optimize,
+                    truncate: self.truncate_immediates,
+                };
+                codegen(&statements, options)
+            });
+
+        // Point errors at the files that contain the offending code.
+        match result {
+            Ok(value) => Ok((value, includer)),
+            Err(mut e) => {
+                for error in &mut e.errors {
+                    locate(error, &includer);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Assembler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut directives = self.directives.keys().collect::<Vec<_>>();
+        directives.sort();
+
+        f.debug_struct("Assembler")
+            .field("include_paths", &self.include_paths)
+            .field("defines", &self.defines)
+            .field("optimize", &self.optimize)
+            .field("truncate_immediates", &self.truncate_immediates)
+            .field("directives", &directives)
+            .finish()
+    }
+}
+
+// Points an error at the file that contains the offending code.
+fn locate(error: &mut ParseError, includer: &include::Includer) {
+    let file = error.span.file;
+    error.file = includer.path(file).map(Path::to_path_buf);
+    error.source_line = includer
+        .source(file)
+        .and_then(|source| source_line(source, error.span.line));
+}
+
+// Points the warnings of a program at their source files and attaches the
+// paths to the files to its debug information.
+fn with_sources((mut program, includer): (Program, include::Includer)) -> Program {
+    for warning in &mut program.warnings {
+        locate(warning, &includer);
+    }
+    if let Some(debug_info) = &mut program.debug_info {
+        debug_info.files = includer.into_files();
+    }
+
+    program
+}
+
+/// Assembles Falcon assembly source code into machine code.
+///
+/// ```
+/// let code = faucon_asm::assembler::assemble_str("ld b32 $r15 D[$r1]\nret").unwrap();
+///
+/// assert_eq!(code, [0xBF, 0x1F, 0xF8, 0x00]);
+/// ```
+pub fn assemble_str(source: &str) -> Result<Vec<u8>, ParseErrors> {
+    Assembler::new().assemble_str(source)
+}
+
+/// Assembles Falcon assembly source code into a [`Program`] that holds all of
+/// its sections.
+///
+/// See [`Program`] for an example.
+///
+/// [`Program`]: struct.Program.html
+pub fn assemble_program_str(source: &str) -> Result<Program, ParseErrors> {
+    Assembler::new().assemble_program_str(source)
+}
+
+/// Reads a file of Falcon assembly source code and assembles it into a
+/// [`Program`] that holds all of its sections.
+///
+/// [`Program`]: struct.Program.html
+pub fn assemble_program_file<P: AsRef<Path>>(path: P) -> Result<Program, ParseErrors> {
+    Assembler::new().assemble_program_file(path)
+}
+
+/// Assembles Falcon assembly source code into a relocatable [`Object`].
+///
+/// See the [`object`] module for an example.
+///
+/// [`Object`]: ../object/struct.Object.html
+/// [`object`]: ../object/index.html
+pub fn assemble_object_str(source: &str) -> Result<Object, ParseErrors> {
+    Assembler::new().assemble_object_str(source)
+}
+
+/// Reads a file of Falcon assembly source code and assembles it into a
+/// relocatable [`Object`].
+///
+/// [`Object`]: ../object/struct.Object.html
+pub fn assemble_object_file<P: AsRef<Path>>(path: P) -> Result<Object, ParseErrors> {
+    Assembler::new().assemble_object_file(path)
+}
+
+/// Assembles exactly one instruction, located at address `pc`, into machine
+/// code.
+///
+/// This is useful for patching individual instructions in existing code, where
+/// PC-relative branch targets need to be encoded against the real address of
+/// the instruction.
+///
+/// ```
+/// let code = faucon_asm::assembler::assemble_instruction("bra 0x1234", 0x1200).unwrap();
+///
+/// assert_eq!(code, [0xF4, 0x0E, 0x34]);
+/// ```
+pub fn assemble_instruction(source: &str, pc: u32) -> Result<Vec<u8>, ParseError> {
+    let assemble = || {
+        let tokens = lexer::tokenize(source, 0)?;
+        let statements = parser::parse(&tokens, &HashMap::new(), &HashMap::new())
+            .map_err(|e| e.first().clone())?;
+
+        match statements.as_slice() {
+            [statement @ parser::Statement {
+                kind: parser::StatementKind::Instruction { .. },
+                ..
+            }] => codegen::assemble_instruction(statement, pc),
+            [] => Err(ParseError::new(Span::default(), "expected an instruction")),
+            [_, statement, ..] | [statement] => Err(ParseError::new(
+                statement.span,
+                "expected exactly one instruction",
+            )),
+        }
+    };
+
+    assemble().map_err(|e| ParseError {
+        source_line: source_line(source, e.span.line),
+        ..e
+    })
+}
+
+// Gets the line with the given number, counting from 1, from source code.
+fn source_line(source: &str, line: usize) -> Option<Box<str>> {
+    source.lines().nth(line.checked_sub(1)?).map(Box::from)
+}
+
+/// Reads a file of Falcon assembly source code and assembles it into machine
+/// code.
+///
+/// See [`assemble_str`] for details.
+///
+/// [`assemble_str`]: fn.assemble_str.html
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, ParseErrors> {
+    Assembler::new().assemble_file(path)
+}
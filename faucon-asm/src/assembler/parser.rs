@@ -0,0 +1,1312 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::opcode::OperandSize;
+use crate::operands::{MemorySpace, Register};
+
+use super::directive::{DirectiveContext, DirectiveHandler};
+use super::lexer::{line_end, Token, TokenKind};
+use super::{report, ParseError, ParseErrors, Span};
+
+/// A statement in assembly source code, which spans a single line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statement {
+    /// The kind of statement.
+    pub kind: StatementKind,
+    /// The location of the statement in the source code.
+    pub span: Span,
+}
+
+/// The kinds of [`Statement`]s that make up assembly source code.
+///
+/// [`Statement`]: struct.Statement.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatementKind {
+    /// A label definition that names the current address: `name:`
+    Label(String),
+    /// A Falcon instruction with its operands: `mnemonic [size] operands...`
+    Instruction {
+        /// The mnemonic of the instruction.
+        mnemonic: String,
+        /// The operand size of the instruction, if specified.
+        size: Option<OperandSize>,
+        /// The operands of the instruction.
+        operands: Vec<Operand>,
+    },
+    /// Integer values of a given width in bytes: `.byte`, `.word`, `.dword`
+    Data {
+        /// The width of every value in bytes.
+        width: usize,
+        /// The values to emit, along with their locations.
+        values: Vec<(Expr, Span)>,
+    },
+    /// The raw bytes of a string literal: `.str "text"`
+    Bytes(Vec<u8>),
+    /// Zero padding up to the next multiple of an alignment: `.align 4`
+    Align(Expr),
+    /// A move of the location counter to an absolute address: `.org 0x100`
+    Org(Expr),
+    /// A number of reserved bytes with a fill value: `.skip 16, 0xFF`
+    Skip {
+        /// The number of bytes to reserve.
+        count: Expr,
+        /// The value of the reserved bytes, zero if omitted.
+        fill: Option<Expr>,
+    },
+    /// A number of repetitions of a value: `.fill 16, 4, handler`
+    Fill {
+        /// The number of repetitions.
+        count: Expr,
+        /// The width of the value in bytes, one if omitted.
+        size: Option<Expr>,
+        /// The value to repeat, zero if omitted.
+        value: Option<Expr>,
+    },
+    /// Padding of the section up to a multiple of a size, counted from the
+    /// start of the section: `.pad 0x100, 0xFF`
+    Pad {
+        /// The size to pad the section to a multiple of.
+        size: Expr,
+        /// The value of the padding bytes, zero if omitted.
+        fill: Option<Expr>,
+    },
+    /// A 32-bit checksum over the bytes of an address range in the current
+    /// section: `.sum32 start, end` or `.crc32 start, end`
+    Checksum {
+        /// The algorithm to compute the checksum with.
+        kind: ChecksumKind,
+        /// The address of the first byte of the range.
+        start: Expr,
+        /// The address behind the last byte of the range.
+        end: Expr,
+    },
+    /// The start of a named region of code that runs in secure mode:
+    /// `.secure name`
+    SecureStart(String),
+    /// The end of the current secure region: `.endsecure`
+    SecureEnd,
+    /// A block that is reserved for the signature of a secure region:
+    /// `.signature name[, size]`
+    Signature {
+        /// The name of the secure region.
+        region: String,
+        /// The size of the block in bytes, 16 if omitted.
+        size: Option<Expr>,
+    },
+    /// A named constant: `.equ NAME, value` or `.set NAME, value`
+    Constant {
+        /// The name of the constant.
+        name: String,
+        /// The value of the constant.
+        value: Expr,
+        /// Whether the constant may be redefined, which is the case for `.set`.
+        redefinable: bool,
+    },
+    /// Labels that are visible to other objects: `.global name, ...`
+    Global(Vec<String>),
+    /// Symbols that are defined by other objects: `.extern name, ...`
+    Extern(Vec<String>),
+    /// A switch to the section that subsequent code is placed in:
+    /// `.section name[, origin]`, `.code [origin]` or `.data [origin]`
+    Section {
+        /// The name of the section.
+        name: String,
+        /// The address at which the section starts, if specified.
+        origin: Option<Expr>,
+    },
+}
+
+/// The algorithms that checksums are computed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// The wrapping sum of all little-endian 32-bit words in the range.
+    Sum32,
+    /// The CRC-32 of all bytes in the range, as used by zlib.
+    Crc32,
+}
+
+/// An instruction operand in assembly source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operand {
+    /// The kind of operand.
+    pub kind: OperandKind,
+    /// The location of the operand in the source code.
+    pub span: Span,
+}
+
+/// The kinds of [`Operand`]s in assembly source code.
+///
+/// [`Operand`]: struct.Operand.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    /// A CPU register: `$r0`, `$sp`, ...
+    Register(Register),
+    /// An immediate, flag bit or symbol value: `0x10`, `c`, `label`, ...
+    Immediate(Expr),
+    /// A direct memory access: `D[$r0]`, `D[$sp + 0x10]`, `I[$r1 + $r2 * 4]`, ...
+    Memory {
+        /// The memory space to access.
+        space: MemorySpace,
+        /// The register that holds the base address.
+        base: Register,
+        /// The offset to the base address, if any.
+        offset: Option<MemoryOffset>,
+    },
+}
+
+/// The offset to the base address of a direct memory access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemoryOffset {
+    /// An offset in a register, along with a scale.
+    Register(Register, u8),
+    /// An immediate offset.
+    Immediate(Expr),
+}
+
+/// An expression that evaluates to an integer value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    /// An integer literal.
+    Number(i64),
+    /// A reference to a symbol, such as a label.
+    Symbol(String),
+    /// The negation of another expression.
+    Neg(Box<Expr>),
+    /// The bitwise complement of another expression.
+    Not(Box<Expr>),
+    /// A binary operation on two expressions.
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// The binary operators that may be used in [`Expr`]s.
+///
+/// [`Expr`]: enum.Expr.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// Addition: `a + b`
+    Add,
+    /// Subtraction: `a - b`
+    Sub,
+    /// Multiplication: `a * b`
+    Mul,
+    /// Division: `a / b`
+    Div,
+    /// Remainder of a division: `a % b`
+    Rem,
+    /// Left shift: `a << b`
+    Shl,
+    /// Arithmetic right shift: `a >> b`
+    Shr,
+    /// Bitwise AND: `a & b`
+    And,
+    /// Bitwise OR: `a | b`
+    Or,
+    /// Bitwise XOR: `a ^ b`
+    Xor,
+}
+
+impl BinaryOp {
+    fn from_token(kind: &TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::Plus => Some(BinaryOp::Add),
+            TokenKind::Minus => Some(BinaryOp::Sub),
+            TokenKind::Star => Some(BinaryOp::Mul),
+            TokenKind::Slash => Some(BinaryOp::Div),
+            TokenKind::Percent => Some(BinaryOp::Rem),
+            TokenKind::ShiftLeft => Some(BinaryOp::Shl),
+            TokenKind::ShiftRight => Some(BinaryOp::Shr),
+            TokenKind::Ampersand => Some(BinaryOp::And),
+            TokenKind::Pipe => Some(BinaryOp::Or),
+            TokenKind::Caret => Some(BinaryOp::Xor),
+            _ => None,
+        }
+    }
+
+    /// Gets the precedence of the operator, where operators with higher
+    /// values bind more tightly. These follow the rules of C.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Shl | BinaryOp::Shr => 3,
+            BinaryOp::And => 2,
+            BinaryOp::Xor => 1,
+            BinaryOp::Or => 0,
+        }
+    }
+
+    /// Applies the operator to two values.
+    ///
+    /// Shifts by amounts outside of the range of 64 bits shift out all bits of
+    /// a value. `None` is returned for divisions by zero.
+    pub fn apply(self, lhs: i64, rhs: i64) -> Option<i64> {
+        let shift = if (0..64).contains(&rhs) {
+            Some(rhs as u32)
+        } else {
+            None
+        };
+
+        Some(match self {
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return None,
+            BinaryOp::Div => lhs.wrapping_div(rhs),
+            BinaryOp::Rem => lhs.wrapping_rem(rhs),
+            BinaryOp::Shl => shift.map_or(0, |shift| lhs << shift),
+            BinaryOp::Shr => shift.map_or(lhs >> 63, |shift| lhs >> shift),
+            BinaryOp::And => lhs & rhs,
+            BinaryOp::Or => lhs | rhs,
+            BinaryOp::Xor => lhs ^ rhs,
+        })
+    }
+}
+
+/// The value of an [`Expr`], which may be relative to a location that is only
+/// known once the code is linked.
+///
+/// [`Expr`]: enum.Expr.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocatable<'a> {
+    /// The location that the value is relative to, or `None` for absolute
+    /// values.
+    pub base: Option<Base<'a>>,
+    /// The value relative to the base.
+    pub offset: i64,
+}
+
+/// The locations that [`Relocatable`] values can be relative to.
+///
+/// [`Relocatable`]: struct.Relocatable.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base<'a> {
+    /// The start of a section, by index.
+    Section(usize),
+    /// A symbol that is defined elsewhere.
+    Symbol(&'a str),
+}
+
+impl<'a> Relocatable<'a> {
+    /// Creates an absolute value.
+    pub fn absolute(value: i64) -> Self {
+        Relocatable {
+            base: None,
+            offset: value,
+        }
+    }
+
+    /// Gets the value if it is absolute.
+    pub fn value(self) -> Option<i64> {
+        match self.base {
+            None => Some(self.offset),
+            Some(_) => None,
+        }
+    }
+}
+
+/// An error that occurs when evaluating an [`Expr`].
+///
+/// [`Expr`]: enum.Expr.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EvalError {
+    /// A symbol that is referenced by the expression could not be resolved.
+    UndefinedSymbol(String),
+    /// The expression divides by zero.
+    DivisionByZero,
+    /// The definition of a constant refers to itself, directly or through
+    /// other constants.
+    CircularDefinition(String),
+    /// The expression combines relocatable values in a way that cannot be
+    /// expressed by a relocation.
+    NotRelocatable,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UndefinedSymbol(name) => write!(f, "undefined symbol `{}`", name),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::CircularDefinition(name) => {
+                write!(f, "circular definition of `{}`", name)
+            }
+            EvalError::NotRelocatable => write!(f, "expression cannot be relocated"),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression, using `resolver` to look up the values of
+    /// symbols.
+    ///
+    /// Relocatable values may only be offset by absolute values or subtracted
+    /// from values with the same base. All arithmetic wraps around on overflow.
+    pub fn evaluate<'a>(
+        &'a self,
+        resolver: &dyn Fn(&'a str) -> Result<Relocatable<'a>, EvalError>,
+    ) -> Result<Relocatable<'a>, EvalError> {
+        let absolute = |expr: &'a Expr| {
+            expr.evaluate(resolver)?
+                .value()
+                .ok_or(EvalError::NotRelocatable)
+        };
+
+        match self {
+            Expr::Number(value) => Ok(Relocatable::absolute(*value)),
+            Expr::Symbol(name) => resolver(name),
+            Expr::Neg(expr) => Ok(Relocatable::absolute(absolute(expr)?.wrapping_neg())),
+            Expr::Not(expr) => Ok(Relocatable::absolute(!absolute(expr)?)),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(resolver)?, rhs.evaluate(resolver)?);
+                let base = match (op, lhs.base, rhs.base) {
+                    (_, None, None) => None,
+                    (BinaryOp::Add, base, None)
+                    | (BinaryOp::Add, None, base)
+                    | (BinaryOp::Sub, base, None) => base,
+                    // The distance between values with the same base is absolute.
+                    (BinaryOp::Sub, Some(a), Some(b)) if a == b => None,
+                    _ => return Err(EvalError::NotRelocatable),
+                };
+
+                Ok(Relocatable {
+                    base,
+                    offset: op
+                        .apply(lhs.offset, rhs.offset)
+                        .ok_or(EvalError::DivisionByZero)?,
+                })
+            }
+        }
+    }
+}
+
+/// Parses a sequence of [`Token`]s into [`Statement`]s.
+///
+/// Conditional blocks that were not resolved ahead of time, like those that
+/// stem from macros, are resolved while parsing, so that only the statements
+/// of active branches are returned. Conditions may refer to the given
+/// `defines` and to the constants that were defined in front of them. Every
+/// define is also returned as a constant statement, ahead of the source code.
+///
+/// Parsing continues behind statements that contain errors, so that all of
+/// them are reported at once.
+///
+/// [`Token`]: ../lexer/struct.Token.html
+/// [`Statement`]: struct.Statement.html
+pub fn parse(
+    tokens: &[Token],
+    defines: &HashMap<String, i64>,
+    directives: &Directives,
+) -> Result<Vec<Statement>, ParseErrors> {
+    let mut parser = Parser::new(tokens, defines, directives);
+    let mut statements = defines
+        .iter()
+        .map(|(name, value)| Statement {
+            kind: StatementKind::Constant {
+                name: name.clone(),
+                value: Expr::Number(*value),
+                redefinable: false,
+            },
+            span: Span::default(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut errors = Vec::new();
+    while let Some(token) = parser.peek() {
+        // Errors in the structure of conditional and repeated blocks cannot be
+        // recovered from, as it is unclear which code they apply to.
+        if let TokenKind::Directive(name) = &token.kind {
+            match parser.control(name, token.span) {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => {
+                    report(&mut errors, e);
+                    return Err(ParseErrors { errors });
+                }
+            }
+        }
+        if !parser.is_active() {
+            parser.position = line_end(tokens, parser.position) + 1;
+            continue;
+        }
+
+        // Errors in a statement only affect its own line.
+        match parser.statement(token) {
+            Ok(parsed) => {
+                for statement in parsed {
+                    parser.define(&statement);
+                    statements.push(statement);
+                }
+            }
+            Err(e) => {
+                report(&mut errors, e);
+                parser.position = line_end(tokens, parser.position) + 1;
+            }
+        }
+    }
+
+    if let Some(repetition) = parser.repetitions.last() {
+        report(
+            &mut errors,
+            ParseError::new(repetition.span, "repetition block is missing its `.endr`"),
+        );
+    }
+    if let Some(condition) = parser.conditions.last() {
+        report(
+            &mut errors,
+            ParseError::new(condition.span, "conditional block is missing its `.endif`"),
+        );
+    }
+
+    // Make sure that all forward references to local labels were defined.
+    for (number, index, span) in parser.forward {
+        if parser.locals.get(&number).copied().unwrap_or(0) < index {
+            report(
+                &mut errors,
+                ParseError::new(
+                    span,
+                    format!("no following definition of local label `{}`", number),
+                ),
+            );
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(ParseErrors { errors });
+    }
+
+    Ok(statements)
+}
+
+/// Parses a sequence of tokens as a single expression, where `span` is
+/// reported for a missing expression.
+pub(super) fn parse_expression(tokens: &[Token], span: Span) -> Result<Expr, ParseError> {
+    if tokens.is_empty() {
+        return Err(ParseError::new(span, "expected an expression"));
+    }
+
+    let directives = HashMap::new();
+    let mut parser = Parser::new(tokens, &HashMap::new(), &directives);
+    let expr = parser.expression()?;
+    if !parser.at_end_of_statement() {
+        return Err(parser.unexpected());
+    }
+
+    Ok(expr)
+}
+
+/// The handlers of custom directives, by the names of the directives.
+pub type Directives = HashMap<String, Arc<dyn DirectiveHandler>>;
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    directives: &'a Directives,
+    // The number of definitions of every local label so far.
+    locals: HashMap<i64, usize>,
+    // The forward references to local labels, which must be checked at the end.
+    forward: Vec<(i64, usize, Span)>,
+    // The conditional blocks that enclose the current position.
+    conditions: Vec<Condition>,
+    // The values of the constants that were defined so far, for evaluating
+    // conditions.
+    constants: HashMap<String, i64>,
+    // The names of all symbols that were defined or declared so far.
+    symbols: HashSet<String>,
+    // The repetition blocks that enclose the current position.
+    repetitions: Vec<Repetition>,
+}
+
+/// The maximum number of times the contents of nested repetition blocks are
+/// repeated in total, which guards against runaway repetition counts.
+pub const MAX_REPETITIONS: i64 = 0x10000;
+
+// A block that is repeated through `.rept`.
+struct Repetition {
+    span: Span,
+    // The position of the first token of the block.
+    start: usize,
+    // The number of repetitions that are left after the current one.
+    remaining: i64,
+    // The number of times the contents are repeated, including the
+    // repetitions of all enclosing blocks.
+    total: i64,
+    // The number of conditional blocks that enclose the `.rept`.
+    depth: usize,
+}
+
+// A conditional block that is opened through `.if`, `.ifdef` or `.ifndef`.
+struct Condition {
+    span: Span,
+    // Whether the statements of the current branch are assembled.
+    active: bool,
+    // Whether a branch of the block was already taken, or the whole block is
+    // nested inside an inactive branch.
+    taken: bool,
+    // Whether the block already has an `.else` branch.
+    has_else: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(
+        tokens: &'a [Token],
+        defines: &HashMap<String, i64>,
+        directives: &'a Directives,
+    ) -> Self {
+        Parser {
+            tokens,
+            position: 0,
+            directives,
+            locals: HashMap::new(),
+            forward: Vec::new(),
+            conditions: Vec::new(),
+            constants: defines.clone(),
+            symbols: defines.keys().cloned().collect(),
+            repetitions: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_kind(&self) -> Option<&'a TokenKind> {
+        self.peek().map(|t| &t.kind)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek_kind() == Some(kind) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_end_of_statement(&self) -> bool {
+        matches!(self.peek_kind(), None | Some(TokenKind::Newline))
+    }
+
+    fn previous_span(&self) -> Span {
+        self.tokens[self.position - 1].span
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) if token.kind != TokenKind::Newline => {
+                ParseError::new(token.span, "unexpected token")
+            }
+            _ => ParseError::new(self.previous_span(), "unexpected end of statement"),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Span, ParseError> {
+        if self.eat(&kind) {
+            Ok(self.previous_span())
+        } else {
+            let span = self.peek().map_or_else(|| self.previous_span(), |t| t.span);
+            Err(ParseError::new(span, format!("expected {}", what)))
+        }
+    }
+
+    // Parses the statements that start with `token`, which are none for blank
+    // lines and possibly many for custom directives.
+    fn statement(&mut self, token: &'a Token) -> Result<Vec<Statement>, ParseError> {
+        let statement = match &token.kind {
+            TokenKind::Newline => {
+                self.position += 1;
+                return Ok(Vec::new());
+            }
+            TokenKind::Identifier(name) => {
+                self.position += 1;
+
+                if self.eat(&TokenKind::Colon) {
+                    Statement {
+                        kind: StatementKind::Label(name.clone()),
+                        span: token.span,
+                    }
+                } else {
+                    self.instruction(name.clone(), token.span)?
+                }
+            }
+            TokenKind::Number(number)
+                if self.tokens.get(self.position + 1).map(|t| &t.kind)
+                    == Some(&TokenKind::Colon) =>
+            {
+                self.position += 2;
+
+                let count = self.locals.entry(*number).or_insert(0);
+                *count += 1;
+                Statement {
+                    kind: StatementKind::Label(local_label_name(*number, *count)),
+                    span: token.span,
+                }
+            }
+            TokenKind::Directive(name) => {
+                self.position += 1;
+                match self.directives.get(name) {
+                    Some(handler) => return self.custom_directive(handler.as_ref(), token.span),
+                    None => self.directive(name, token.span)?,
+                }
+            }
+            _ => return Err(self.unexpected()),
+        };
+
+        Ok(vec![statement])
+    }
+
+    // Invokes the handler of a custom directive with the tokens of its
+    // arguments, up to the end of the line.
+    fn custom_directive(
+        &mut self,
+        handler: &dyn DirectiveHandler,
+        span: Span,
+    ) -> Result<Vec<Statement>, ParseError> {
+        let end = line_end(self.tokens, self.position);
+        let arguments = &self.tokens[self.position..end];
+        self.position = end;
+
+        let span = arguments.iter().fold(span, |span, t| span.merge(t.span));
+        let mut context = DirectiveContext::new(span);
+        handler.handle(arguments, &mut context)?;
+
+        Ok(context.into_statements())
+    }
+
+    // Handles the directive `name` if it controls conditional or repeated
+    // blocks, and returns whether it does.
+    fn control(&mut self, name: &str, span: Span) -> Result<bool, ParseError> {
+        if self.conditional(name, span)? {
+            return Ok(true);
+        }
+
+        Ok(self.is_active() && self.repetition(name, span)?)
+    }
+
+    fn is_active(&self) -> bool {
+        !matches!(self.conditions.last(), Some(condition) if !condition.active)
+    }
+
+    // Evaluates an expression over the constants that were defined so far.
+    fn evaluate(&self, expr: &Expr) -> Result<i64, EvalError> {
+        let resolver = |name: &str| match self.constants.get(name) {
+            Some(&value) => Ok(Relocatable::absolute(value)),
+            None => Err(EvalError::UndefinedSymbol(name.to_string())),
+        };
+        Ok(expr.evaluate(&resolver)?.offset)
+    }
+
+    // Parses an expression that must evaluate to a constant while parsing.
+    fn constant_expression(&mut self, span: Span) -> Result<i64, ParseError> {
+        let start = self.peek().map_or(span, |t| t.span);
+        let expr = self.expression()?;
+        let span = start.merge(self.previous_span());
+
+        match self.evaluate(&expr) {
+            Ok(value) => Ok(value),
+            // Labels and values that depend on them are unknown yet.
+            Err(EvalError::UndefinedSymbol(symbol)) if self.symbols.contains(&symbol) => {
+                Err(ParseError::new(span, "expected a constant expression")
+                    .with_note("only constants that are defined in front of it are known"))
+            }
+            Err(e) => Err(ParseError::new(span, e.to_string())),
+        }
+    }
+
+    // Handles the repetition directive `name`, if it is one, and returns
+    // whether it was.
+    fn repetition(&mut self, name: &str, span: Span) -> Result<bool, ParseError> {
+        match name {
+            "rept" => {
+                self.position += 1;
+                let count = self.constant_expression(span)?;
+                if count < 0 {
+                    return Err(ParseError::new(span, "count must not be negative"));
+                }
+                if !self.at_end_of_statement() {
+                    return Err(self.unexpected());
+                }
+
+                let total = self
+                    .repetitions
+                    .last()
+                    .map_or(1, |outer| outer.total)
+                    .saturating_mul(count);
+                if total > MAX_REPETITIONS {
+                    let note = format!("blocks may be repeated {} times in total", MAX_REPETITIONS);
+                    return Err(ParseError::new(span, "too many repetitions").with_note(note));
+                }
+
+                if count == 0 {
+                    self.position = self.repetition_end(span)?;
+                } else {
+                    self.repetitions.push(Repetition {
+                        span,
+                        start: self.position,
+                        remaining: count - 1,
+                        total,
+                        depth: self.conditions.len(),
+                    });
+                }
+            }
+            "endr" => {
+                self.position += 1;
+                if !self.at_end_of_statement() {
+                    return Err(self.unexpected());
+                }
+
+                let repetition = match self.repetitions.last_mut() {
+                    Some(repetition) if repetition.depth == self.conditions.len() => repetition,
+                    Some(_) => {
+                        return Err(ParseError::new(
+                            span,
+                            "repetition block overlaps a conditional block",
+                        ))
+                    }
+                    None => return Err(ParseError::new(span, "`.endr` without `.rept`")),
+                };
+
+                if repetition.remaining > 0 {
+                    repetition.remaining -= 1;
+                    self.position = repetition.start;
+                } else {
+                    self.repetitions.pop();
+                }
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    // Finds the end of the repetition block that starts at the current
+    // position, skipping nested blocks, and returns the position behind its
+    // `.endr`.
+    fn repetition_end(&self, span: Span) -> Result<usize, ParseError> {
+        let mut depth = 0;
+        let mut position = self.position;
+        while position < self.tokens.len() {
+            match &self.tokens[position].kind {
+                TokenKind::Directive(name) if name == "rept" => depth += 1,
+                TokenKind::Directive(name) if name == "endr" && depth == 0 => {
+                    return Ok(line_end(self.tokens, position));
+                }
+                TokenKind::Directive(name) if name == "endr" => depth -= 1,
+                _ => {}
+            }
+            position = line_end(self.tokens, position) + 1;
+        }
+
+        Err(ParseError::new(
+            span,
+            "repetition block is missing its `.endr`",
+        ))
+    }
+
+    // Records the symbols that are defined by a parsed statement.
+    fn define(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Label(name) => {
+                self.symbols.insert(name.clone());
+            }
+            StatementKind::Constant { name, value, .. } => {
+                match self.evaluate(value).ok() {
+                    Some(value) => self.constants.insert(name.clone(), value),
+                    None => self.constants.remove(name),
+                };
+                self.symbols.insert(name.clone());
+            }
+            StatementKind::Extern(names) => self.symbols.extend(names.iter().cloned()),
+            _ => {}
+        }
+    }
+
+    // Handles the conditional assembly directive `name`, if it is one, and
+    // returns whether it was.
+    fn conditional(&mut self, name: &str, span: Span) -> Result<bool, ParseError> {
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                self.position += 1;
+
+                // Conditions inside of inactive branches are not evaluated.
+                let enclosing = self.is_active();
+                let active = if !enclosing {
+                    self.position = line_end(self.tokens, self.position);
+                    false
+                } else if name == "if" {
+                    self.constant_expression(span)? != 0
+                } else {
+                    let defined = match self.peek_kind() {
+                        Some(TokenKind::Identifier(symbol)) => self.symbols.contains(symbol),
+                        _ => {
+                            let span = self.peek().map_or(span, |t| t.span);
+                            return Err(ParseError::new(span, "expected a symbol name"));
+                        }
+                    };
+                    self.position += 1;
+                    defined == (name == "ifdef")
+                };
+
+                self.conditions.push(Condition {
+                    span,
+                    active,
+                    taken: active || !enclosing,
+                    has_else: false,
+                });
+            }
+            "else" => {
+                self.position += 1;
+                let condition = match self.conditions.last_mut() {
+                    Some(condition) if !condition.has_else => condition,
+                    Some(_) => {
+                        return Err(ParseError::new(
+                            span,
+                            "conditional block has multiple `.else` branches",
+                        ))
+                    }
+                    None => return Err(ParseError::new(span, "`.else` without `.if`")),
+                };
+
+                condition.active = !condition.taken;
+                condition.taken = true;
+                condition.has_else = true;
+            }
+            "endif" => {
+                self.position += 1;
+                if self.conditions.pop().is_none() {
+                    return Err(ParseError::new(span, "`.endif` without `.if`"));
+                }
+            }
+            _ => return Ok(false),
+        }
+
+        if !self.at_end_of_statement() {
+            return Err(self.unexpected());
+        }
+
+        Ok(true)
+    }
+
+    fn instruction(&mut self, mnemonic: String, span: Span) -> Result<Statement, ParseError> {
+        let mut span = span;
+
+        // Parse the optional operand size suffix.
+        let mut size = None;
+        if let Some(TokenKind::Identifier(name)) = self.peek_kind() {
+            size = match name.as_str() {
+                "b8" => Some(OperandSize::EightBit),
+                "b16" => Some(OperandSize::SixteenBit),
+                "b32" => Some(OperandSize::ThirtyTwoBit),
+                _ => None,
+            };
+            if size.is_some() {
+                self.position += 1;
+                span = span.merge(self.previous_span());
+            }
+        }
+
+        // Parse the operands, which may optionally be separated by commas.
+        let mut operands = Vec::new();
+        while !self.at_end_of_statement() {
+            if !operands.is_empty() {
+                self.eat(&TokenKind::Comma);
+            }
+
+            let operand = self.operand()?;
+            span = span.merge(operand.span);
+            operands.push(operand);
+        }
+
+        let (mnemonic, size, operands) = pseudo_instruction(mnemonic, size, operands, span)?;
+        Ok(Statement {
+            kind: StatementKind::Instruction {
+                mnemonic,
+                size,
+                operands,
+            },
+            span,
+        })
+    }
+
+    fn directive(&mut self, name: &str, span: Span) -> Result<Statement, ParseError> {
+        let kind = match name {
+            "byte" | "word" | "dword" => {
+                let width = match name {
+                    "byte" => 1,
+                    "word" => 2,
+                    _ => 4,
+                };
+
+                let mut values = Vec::new();
+                loop {
+                    let start = self.peek().map_or_else(|| self.previous_span(), |t| t.span);
+                    let expr = self.expression()?;
+                    values.push((expr, start.merge(self.previous_span())));
+
+                    if !self.eat(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+
+                StatementKind::Data { width, values }
+            }
+            "str" => match self.peek_kind() {
+                Some(TokenKind::String(string)) => {
+                    self.position += 1;
+                    StatementKind::Bytes(string.clone())
+                }
+                _ => {
+                    let span = self.peek().map_or(span, |t| t.span);
+                    return Err(ParseError::new(span, "expected a string literal"));
+                }
+            },
+            "equ" | "set" => {
+                let redefinable = name == "set";
+                let name = match self.peek_kind() {
+                    Some(TokenKind::Identifier(name)) => {
+                        self.position += 1;
+                        name.clone()
+                    }
+                    _ => {
+                        let span = self.peek().map_or(span, |t| t.span);
+                        return Err(ParseError::new(span, "expected a symbol name"));
+                    }
+                };
+                self.expect(TokenKind::Comma, "`,`")?;
+
+                StatementKind::Constant {
+                    name,
+                    value: self.expression()?,
+                    redefinable,
+                }
+            }
+            "align" => StatementKind::Align(self.expression()?),
+            "org" => StatementKind::Org(self.expression()?),
+            "skip" => {
+                let count = self.expression()?;
+                let fill = if self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                StatementKind::Skip { count, fill }
+            }
+            "fill" => {
+                let count = self.expression()?;
+                let size = if self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+                let value = if size.is_some() && self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                StatementKind::Fill { count, size, value }
+            }
+            "pad" => {
+                let size = self.expression()?;
+                let fill = if self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                StatementKind::Pad { size, fill }
+            }
+            "sum32" | "crc32" => {
+                let kind = match name {
+                    "sum32" => ChecksumKind::Sum32,
+                    _ => ChecksumKind::Crc32,
+                };
+                let start = self.expression()?;
+                self.expect(TokenKind::Comma, "`,`")?;
+
+                StatementKind::Checksum {
+                    kind,
+                    start,
+                    end: self.expression()?,
+                }
+            }
+            "secure" => StatementKind::SecureStart(self.symbol(span)?),
+            "endsecure" => StatementKind::SecureEnd,
+            "signature" => {
+                let region = self.symbol(span)?;
+                let size = if self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                StatementKind::Signature { region, size }
+            }
+            "global" => StatementKind::Global(self.symbol_list(span)?),
+            "extern" => StatementKind::Extern(self.symbol_list(span)?),
+            "section" => {
+                let name = match self.peek_kind() {
+                    Some(TokenKind::Identifier(name)) => {
+                        self.position += 1;
+                        name.clone()
+                    }
+                    _ => {
+                        let span = self.peek().map_or(span, |t| t.span);
+                        return Err(ParseError::new(span, "expected a section name"));
+                    }
+                };
+                let origin = if self.eat(&TokenKind::Comma) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                StatementKind::Section { name, origin }
+            }
+            "code" | "data" => StatementKind::Section {
+                name: name.to_string(),
+                origin: if self.at_end_of_statement() {
+                    None
+                } else {
+                    Some(self.expression()?)
+                },
+            },
+            _ => {
+                return Err(ParseError::new(
+                    span,
+                    format!("unknown directive `.{}`", name),
+                ))
+            }
+        };
+
+        if !self.at_end_of_statement() {
+            return Err(self.unexpected());
+        }
+
+        Ok(Statement {
+            kind,
+            span: span.merge(self.previous_span()),
+        })
+    }
+
+    // Parses a comma-separated list of symbol names.
+    fn symbol_list(&mut self, span: Span) -> Result<Vec<String>, ParseError> {
+        let mut names = Vec::new();
+        loop {
+            names.push(self.symbol(span)?);
+
+            if !self.eat(&TokenKind::Comma) {
+                return Ok(names);
+            }
+        }
+    }
+
+    fn symbol(&mut self, span: Span) -> Result<String, ParseError> {
+        match self.peek_kind() {
+            Some(TokenKind::Identifier(name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => {
+                let span = self.peek().map_or(span, |t| t.span);
+                Err(ParseError::new(span, "expected a symbol name"))
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        let token = self.peek().ok_or_else(|| self.unexpected())?;
+
+        match &token.kind {
+            TokenKind::Register(_) => {
+                let register = self.register()?;
+                Ok(Operand {
+                    kind: OperandKind::Register(register),
+                    span: token.span,
+                })
+            }
+            TokenKind::Identifier(name)
+                if (name == "D" || name == "I")
+                    && self.tokens.get(self.position + 1).map(|t| &t.kind)
+                        == Some(&TokenKind::LBracket) =>
+            {
+                let space = if name == "D" {
+                    MemorySpace::DMem
+                } else {
+                    MemorySpace::IMem
+                };
+                self.position += 2;
+
+                let base = self.register()?;
+                let offset = if self.eat(&TokenKind::Plus) {
+                    if let Some(TokenKind::Register(_)) = self.peek_kind() {
+                        let offset = self.register()?;
+                        let scale = if self.eat(&TokenKind::Star) {
+                            match self.peek_kind() {
+                                Some(TokenKind::Number(scale @ 1..=4)) => {
+                                    self.position += 1;
+                                    *scale as u8
+                                }
+                                _ => return Err(self.unexpected()),
+                            }
+                        } else {
+                            1
+                        };
+
+                        Some(MemoryOffset::Register(offset, scale))
+                    } else {
+                        Some(MemoryOffset::Immediate(self.expression()?))
+                    }
+                } else {
+                    None
+                };
+                let end = self.expect(TokenKind::RBracket, "`]`")?;
+
+                Ok(Operand {
+                    kind: OperandKind::Memory {
+                        space,
+                        base,
+                        offset,
+                    },
+                    span: token.span.merge(end),
+                })
+            }
+            _ => {
+                let expr = self.expression()?;
+                Ok(Operand {
+                    kind: OperandKind::Immediate(expr),
+                    span: token.span.merge(self.previous_span()),
+                })
+            }
+        }
+    }
+
+    fn register(&mut self) -> Result<Register, ParseError> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Register(name),
+                span,
+            }) => {
+                self.position += 1;
+                Register::from_name(name)
+                    .ok_or_else(|| ParseError::new(*span, format!("unknown register `${}`", name)))
+            }
+            _ => Err(ParseError::new(
+                self.peek().map_or_else(|| self.previous_span(), |t| t.span),
+                "expected a register",
+            )),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.binary(0)
+    }
+
+    // Parses binary operations through precedence climbing, where all
+    // operators bind at least as tightly as `precedence`.
+    fn binary(&mut self, precedence: u8) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+
+        loop {
+            let op = match self.peek_kind().and_then(BinaryOp::from_token) {
+                Some(op) if op.precedence() >= precedence => op,
+                _ => return Ok(expr),
+            };
+            self.position += 1;
+
+            let rhs = self.binary(op.precedence() + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&TokenKind::Minus) {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat(&TokenKind::Tilde) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&TokenKind::LParen) {
+            let expr = self.expression()?;
+            self.expect(TokenKind::RParen, "`)`")?;
+            return Ok(expr);
+        }
+
+        match self.peek_kind() {
+            Some(TokenKind::Number(value)) => {
+                self.position += 1;
+                Ok(Expr::Number(*value))
+            }
+            Some(TokenKind::Identifier(name)) => {
+                self.position += 1;
+                Ok(Expr::Symbol(name.clone()))
+            }
+            Some(TokenKind::LocalLabel(number, forward)) => {
+                let span = self.peek().unwrap().span;
+                self.position += 1;
+
+                // Refer to the next or the latest definition, respectively.
+                let count = self.locals.get(number).copied().unwrap_or(0);
+                let index = if *forward {
+                    self.forward.push((*number, count + 1, span));
+                    count + 1
+                } else if count > 0 {
+                    count
+                } else {
+                    return Err(ParseError::new(
+                        span,
+                        format!("no preceding definition of local label `{}`", number),
+                    ));
+                };
+
+                Ok(Expr::Symbol(local_label_name(*number, index)))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+// Generates the symbol name for a definition of a local label. These names
+// cannot collide with identifiers in source code.
+fn local_label_name(number: i64, index: usize) -> String {
+    format!("{}@{}", number, index)
+}
+
+// Rewrites pseudo-instructions into the real instructions they stand for.
+// Other instructions are passed through unchanged.
+fn pseudo_instruction(
+    mnemonic: String,
+    size: Option<OperandSize>,
+    operands: Vec<Operand>,
+    span: Span,
+) -> Result<(String, Option<OperandSize>, Vec<Operand>), ParseError> {
+    match mnemonic.as_str() {
+        // Adjusting the stack pointer doesn't affect the flags, so adding zero
+        // to it has no effect at all.
+        "nop" => {
+            if size.is_some() || !operands.is_empty() {
+                return Err(ParseError::new(span, "`nop` takes no operands"));
+            }
+
+            let operand = |kind| Operand { kind, span };
+            let sp = Register::SP;
+            Ok((
+                "add".to_string(),
+                None,
+                vec![
+                    operand(OperandKind::Register(sp)),
+                    operand(OperandKind::Register(sp)),
+                    operand(OperandKind::Immediate(Expr::Number(0))),
+                ],
+            ))
+        }
+        // Registers are cleared as a whole unless a size is given.
+        "clr" => Ok((
+            "clear".to_string(),
+            size.or(Some(OperandSize::ThirtyTwoBit)),
+            operands,
+        )),
+        _ => Ok((mnemonic, size, operands)),
+    }
+}
@@ -0,0 +1,198 @@
+//! Debug information that maps machine code back to its assembly source.
+//!
+//! When assembling a [`Program`], the assembler records the source line of
+//! every statement that emits code into the `code` section, along with the
+//! addresses of its labels. The resulting [`DebugInfo`] can be stored next to
+//! the binary image through [`DebugInfo::write`], so that debuggers can show
+//! the assembly that is being executed:
+//!
+//! ```
+//! use faucon_asm::debug_info::DebugInfo;
+//!
+//! let program = faucon_asm::assembler::assemble_program_str("entry:\nmov $r1 0x10\nret").unwrap();
+//! let info = program.debug_info.unwrap();
+//!
+//! // `ret` is located behind the two bytes of `mov`, on the third line.
+//! assert_eq!(info.line(0x2).map(|l| l.line), Some(3));
+//! let (symbol, offset) = info.symbol(0x2).unwrap();
+//! assert_eq!((symbol.name.as_str(), offset), ("entry", 2));
+//!
+//! let mut bytes = Vec::new();
+//! info.write(&mut bytes).unwrap();
+//! assert_eq!(DebugInfo::read(&mut &bytes[..]).unwrap(), info);
+//! ```
+//!
+//! [`Program`]: ../assembler/struct.Program.html
+//! [`DebugInfo`]: struct.DebugInfo.html
+//! [`DebugInfo::write`]: struct.DebugInfo.html#method.write
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::object::{invalid_data, read_string, write_bytes};
+
+/// The magic bytes that every serialized [`DebugInfo`] starts with.
+///
+/// [`DebugInfo`]: struct.DebugInfo.html
+pub const MAGIC: &[u8; 4] = b"FDBG";
+
+/// The version of the serialized debug information format.
+pub const VERSION: u32 = 1;
+
+/// The source locations and symbols of the code in a [`Program`].
+///
+/// [`Program`]: ../assembler/struct.Program.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// The paths to the source files, where the main source code comes first.
+    /// Files that were not read from disk have no path.
+    pub files: Vec<Option<PathBuf>>,
+    /// The source lines of the statements that emit code, sorted by address.
+    pub lines: Vec<Line>,
+    /// The labels of the code, sorted by address.
+    pub symbols: Vec<Symbol>,
+}
+
+/// The source location of a statement that emits code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The address of the first byte that is emitted by the statement.
+    pub address: u32,
+    /// The index of the source file in [`DebugInfo::files`].
+    ///
+    /// [`DebugInfo::files`]: struct.DebugInfo.html#structfield.files
+    pub file: usize,
+    /// The line of the statement, counting from 1.
+    pub line: usize,
+}
+
+/// A label in the code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the label.
+    pub name: String,
+    /// The address of the label.
+    pub address: u32,
+}
+
+impl DebugInfo {
+    /// Gets the source location of the statement that starts at `address`.
+    pub fn line(&self, address: u32) -> Option<&Line> {
+        self.lines
+            .binary_search_by_key(&address, |l| l.address)
+            .ok()
+            .map(|index| &self.lines[index])
+    }
+
+    /// Gets the closest label at or in front of `address`, along with the
+    /// offset of the address to it.
+    pub fn symbol(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let index = match self.symbols.binary_search_by_key(&address, |s| s.address) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let symbol = &self.symbols[index];
+        Some((symbol, address - symbol.address))
+    }
+
+    /// Serializes the debug information into its binary representation.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+
+        writer.write_u32::<LittleEndian>(self.files.len() as u32)?;
+        for file in &self.files {
+            // Files without a path are stored with an empty one.
+            let path = file.as_ref().map(|p| p.to_string_lossy().into_owned());
+            write_bytes(writer, path.unwrap_or_default().as_bytes())?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.lines.len() as u32)?;
+        for line in &self.lines {
+            writer.write_u32::<LittleEndian>(line.address)?;
+            writer.write_u32::<LittleEndian>(line.file as u32)?;
+            writer.write_u32::<LittleEndian>(line.line as u32)?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.symbols.len() as u32)?;
+        for symbol in &self.symbols {
+            write_bytes(writer, symbol.name.as_bytes())?;
+            writer.write_u32::<LittleEndian>(symbol.address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deserializes debug information from its binary representation.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] when the data is not well-formed
+    /// debug information.
+    ///
+    /// [`io::ErrorKind::InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a Falcon debug information file"));
+        }
+        if reader.read_u32::<LittleEndian>()? != VERSION {
+            return Err(invalid_data("unsupported debug information version"));
+        }
+
+        let mut info = DebugInfo::default();
+
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let path = read_string(reader)?;
+            info.files
+                .push(Some(path).filter(|p| !p.is_empty()).map(PathBuf::from));
+        }
+
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let address = reader.read_u32::<LittleEndian>()?;
+            let file = reader.read_u32::<LittleEndian>()? as usize;
+            let line = reader.read_u32::<LittleEndian>()? as usize;
+            if file >= info.files.len() {
+                return Err(invalid_data("line refers to a missing file"));
+            }
+
+            info.lines.push(Line {
+                address,
+                file,
+                line,
+            });
+        }
+
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let name = read_string(reader)?;
+            let address = reader.read_u32::<LittleEndian>()?;
+
+            info.symbols.push(Symbol { name, address });
+        }
+
+        // Lookups rely on the entries being sorted.
+        if !is_sorted(info.lines.iter().map(|l| l.address))
+            || !is_sorted(info.symbols.iter().map(|s| s.address))
+        {
+            return Err(invalid_data("entries are not sorted by address"));
+        }
+
+        Ok(info)
+    }
+}
+
+fn is_sorted<I: Iterator<Item = u32>>(mut addresses: I) -> bool {
+    let mut previous = match addresses.next() {
+        Some(address) => address,
+        None => return true,
+    };
+
+    addresses.all(|address| {
+        let sorted = previous <= address;
+        previous = address;
+        sorted
+    })
+}
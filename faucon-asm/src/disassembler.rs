@@ -1,11 +1,14 @@
 //! Disassembler for the Falcon ISA.
 
-use std::io::Read;
+use std::io::{ErrorKind, Read};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::arguments::Argument;
 use crate::isa::*;
 use crate::opcode;
-use crate::{Error, Instruction, Result};
+use crate::{Error, Instruction, Result, MAX_INSTRUCTION_LENGTH};
 
 /// Reads an instruction from a given [`Read`]er and attempts to parse it into an
 /// [`Instruction`] object.
@@ -13,7 +16,7 @@ use crate::{Error, Instruction, Result};
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 /// [`Instruction`]: ../struct.Instruction.html
 pub fn read_instruction<R: Read>(reader: &mut R) -> Result<Instruction> {
-    let mut insn = Vec::new();
+    let mut insn = InstructionBuffer::new();
 
     // First, read the opcode of the next instruction and parse it.
     read_bytes(&mut insn, reader, 1)?;
@@ -23,11 +26,12 @@ pub fn read_instruction<R: Read>(reader: &mut R) -> Result<Instruction> {
     // Read the subopcode that is necessary to look up the instruction.
     let subopcode_location = opcode::get_subopcode_location(operand_size.value(), a, b)
         .ok_or(Error::UnknownInstruction(insn[0]))?;
-    read_bytes(&mut insn, reader, subopcode_location.get())?;
-    let subopcode = subopcode_location.parse(&insn);
+    read_bytes(&mut insn, reader, subopcode_location.get() as usize)?;
+    let subopcode = subopcode_location.parse(insn.as_slice());
 
     // Now do the actual instruction lookup and read the remaining bytes.
-    let mut instruction_meta = lookup_instruction(operand_size.sized(), a, b, subopcode)
+    let mut instruction_meta = lookup_instruction(insn[0], subopcode)
+        .cloned()
         .ok_or(Error::UnknownInstruction(insn[0]))?;
     read_operands(
         &mut insn,
@@ -36,27 +40,318 @@ pub fn read_instruction<R: Read>(reader: &mut R) -> Result<Instruction> {
         &mut instruction_meta.operands,
     )?;
 
-    Ok(Instruction::new(insn, operand_size, instruction_meta))
+    Ok(Instruction::from_slice(
+        insn.as_slice(),
+        operand_size,
+        instruction_meta,
+    ))
+}
+
+/// Reads an instruction at a given offset into a buffer of code and attempts to
+/// parse it into an [`Instruction`] object.
+///
+/// `base_pc` denotes the address where the start of `code` is mapped, so that
+/// the program counter of the resulting instruction is set to `base_pc + offset`.
+/// On success, the instruction is returned along with the offset of the next
+/// instruction in the buffer, so this function can be called repeatedly.
+///
+/// ```
+/// let code = [0xBFu8, 0x1Fu8, 0xBFu8, 0x1Fu8];
+///
+/// let (first, offset) = faucon_asm::read_instruction_at(&code, 0, 0x100).unwrap();
+/// let (second, _) = faucon_asm::read_instruction_at(&code, offset, 0x100).unwrap();
+///
+/// assert_eq!(first.program_counter(), 0x100);
+/// assert_eq!(second.program_counter(), 0x102);
+/// ```
+///
+/// [`Instruction`]: ../struct.Instruction.html
+pub fn read_instruction_at(
+    code: &[u8],
+    offset: usize,
+    base_pc: u32,
+) -> Result<(Instruction, usize)> {
+    let mut reader = code.get(offset..).ok_or(Error::Eof)?;
+
+    let mut insn = read_instruction(&mut reader)?;
+    insn.pc = base_pc.wrapping_add(offset as u32);
+
+    let next_offset = offset + insn.len();
+    Ok((insn, next_offset))
 }
 
-fn lookup_instruction(sized: bool, a: u8, b: u8, subopcode: u8) -> Option<InstructionMeta> {
-    if sized {
-        if a == 3 {
-            InstructionKind::parse_sized_form_2(b, subopcode)
-        } else {
-            InstructionKind::parse_sized_form_1(a, b, subopcode)
+/// Determines the length of the instruction at the start of a buffer of code
+/// without fully decoding it.
+///
+/// Only the opcode and the subopcode bytes are inspected to identify the
+/// instruction form, no operands are extracted. This makes it a cheap way to
+/// step over instructions when their semantics are not of interest.
+///
+/// An instruction that is cut off by the end of the buffer yields
+/// [`Error::Eof`], just like reading it would.
+///
+/// ```
+/// let code = [0xBFu8, 0x1Fu8, 0xF8u8, 0x00u8];
+///
+/// assert_eq!(faucon_asm::peek_instruction_length(&code).unwrap(), 2);
+/// assert_eq!(faucon_asm::peek_instruction_length(&code[2..]).unwrap(), 2);
+///
+/// // `lcall 0x4` takes 4 bytes.
+/// let code = [0x7Eu8, 0x04u8, 0x00u8];
+/// assert_eq!(faucon_asm::peek_instruction_length(&code), Err(faucon_asm::Error::Eof));
+/// ```
+///
+/// [`Error::Eof`]: ../enum.Error.html#variant.Eof
+pub fn peek_instruction_length(code: &[u8]) -> Result<usize> {
+    let opcode = *code.first().ok_or(Error::Eof)?;
+    let operand_size = opcode::OperandSize::from(opcode);
+    let (a, b) = opcode::get_opcode_form(opcode);
+
+    // Look up the instruction through its subopcode.
+    let subopcode_location = opcode::get_subopcode_location(operand_size.value(), a, b)
+        .ok_or(Error::UnknownInstruction(opcode))?;
+    let mut length = subopcode_location.get() as usize + 1;
+    if code.len() < length {
+        return Err(Error::Eof);
+    }
+    let subopcode = subopcode_location.parse(code);
+    let instruction_meta =
+        lookup_instruction(opcode, subopcode).ok_or(Error::UnknownInstruction(opcode))?;
+
+    // The instruction spans up to the end of its last operand.
+    for operand in instruction_meta.operands.iter() {
+        let operand = match operand {
+            Argument::Nop => continue,
+            Argument::SizeConverter(c) => c(operand_size.value()),
+            _ => operand.clone(),
+        };
+
+        length = length.max(operand.position() + operand.width());
+    }
+    if code.len() < length {
+        return Err(Error::Eof);
+    }
+
+    Ok(length)
+}
+
+/// An iterator over the instructions in a buffer of code.
+///
+/// Every item holds the absolute address of an [`Instruction`] along with the
+/// decoded instruction itself, whose encoded length can be obtained through
+/// [`Instruction::len`]. Iteration stops at the end of the buffer or after the
+/// first error has been yielded. An instruction that is cut off by the end of
+/// the buffer yields [`Error::Eof`], so that it can be told apart from the end
+/// of the buffer.
+///
+/// ```
+/// let code = [0xF8u8, 0x00u8, 0xBFu8];
+///
+/// let mut instructions = faucon_asm::disassemble(&code, 0);
+/// assert!(instructions.next().unwrap().is_ok());
+/// assert_eq!(instructions.next().unwrap().err(), Some(faucon_asm::Error::Eof));
+/// assert!(instructions.next().is_none());
+/// ```
+///
+/// This iterator is created by the [`disassemble`] function.
+///
+/// [`Instruction`]: ../struct.Instruction.html
+/// [`Instruction::len`]: ../struct.Instruction.html#method.len
+/// [`disassemble`]: fn.disassemble.html
+/// [`Error::Eof`]: ../enum.Error.html#variant.Eof
+pub struct Instructions<'a> {
+    code: &'a [u8],
+    offset: usize,
+    base_pc: u32,
+    done: bool,
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<(u32, Instruction)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.offset >= self.code.len() {
+            return None;
         }
-    } else {
-        if a == 3 {
-            InstructionKind::parse_unsized_form_2(b, subopcode)
-        } else {
-            InstructionKind::parse_unsized_form_1(a, b)
+
+        match read_instruction_at(self.code, self.offset, self.base_pc) {
+            Ok((insn, next_offset)) => {
+                self.offset = next_offset;
+                Some(Ok((insn.program_counter(), insn)))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Creates an iterator over all instructions in a buffer of code, where `base_pc`
+/// denotes the address at which the start of `code` is mapped.
+///
+/// ```
+/// let code = [0xBFu8, 0x1Fu8, 0xF8u8, 0x00u8];
+///
+/// let listing = faucon_asm::disassemble(&code, 0x100)
+///     .map(|result| result.map(|(address, insn)| format!("{:#x}: {}", address, insn)))
+///     .collect::<faucon_asm::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert_eq!(listing, ["0x100: ld b32 $r15 D[$r1]", "0x102: ret"]);
+/// ```
+pub fn disassemble(code: &[u8], base_pc: u32) -> Instructions<'_> {
+    Instructions {
+        code,
+        offset: 0,
+        base_pc,
+        done: false,
+    }
+}
+
+/// Disassembles a buffer of code concurrently and merges the results into a
+/// single listing that is ordered by instruction addresses.
+///
+/// `base_pc` denotes the address at which the start of `code` is mapped, and
+/// `boundaries` is a list of absolute addresses where functions are likely to
+/// start. The buffer is split into chunks at these boundaries, which are then
+/// decoded in parallel, each one up to the first instruction that reaches into
+/// the next chunk. Boundaries outside of the buffer are ignored.
+///
+/// A boundary that does not fall on the start of an instruction is detected
+/// when the chunks are merged, as the previous chunk ends elsewhere. Decoding
+/// is then resynchronised at the first instruction of the chunk that starts
+/// where the previous one ended, or the chunk is decoded again from there, so
+/// that the listing is always the same as the one of [`disassemble`].
+///
+/// If any of the chunks fails to decode, the error at the lowest address is
+/// returned.
+///
+/// ```
+/// let code = [0xBFu8, 0x1Fu8, 0xF8u8, 0x00u8, 0xF8u8, 0x00u8];
+///
+/// let listing = faucon_asm::disassemble_parallel(&code, 0x100, &[0x104]).unwrap();
+/// let addresses = listing.iter().map(|(address, _)| *address).collect::<Vec<_>>();
+///
+/// assert_eq!(addresses, [0x100, 0x102, 0x104]);
+///
+/// // A boundary inside of an instruction does not split the buffer.
+/// let listing = faucon_asm::disassemble_parallel(&code, 0x100, &[0x101]).unwrap();
+/// assert_eq!(listing.len(), 3);
+/// ```
+///
+/// This function is only available with the `parallel` feature enabled.
+///
+/// [`disassemble`]: fn.disassemble.html
+#[cfg(feature = "parallel")]
+pub fn disassemble_parallel(
+    code: &[u8],
+    base_pc: u32,
+    boundaries: &[u32],
+) -> Result<Vec<(u32, Instruction)>> {
+    // Translate the function boundaries into sorted chunk offsets.
+    let mut offsets = boundaries
+        .iter()
+        .map(|&address| address.wrapping_sub(base_pc) as usize)
+        .filter(|&offset| offset < code.len())
+        .collect::<Vec<_>>();
+    offsets.push(0);
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    let chunks = offsets
+        .par_iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = offsets.get(i + 1).copied().unwrap_or(code.len());
+            decode_chunk(code, start, end, base_pc)
+        })
+        .collect::<Vec<_>>();
+
+    let mut listing = Vec::new();
+    let mut expected = 0;
+    for (i, (start, mut chunk)) in offsets.iter().zip(chunks).enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(code.len());
+        if expected >= end {
+            // An instruction of a previous chunk spans the whole chunk.
+            continue;
+        }
+        if *start != expected {
+            // The chunk started in the middle of an instruction, so only the
+            // part behind the end of the previous chunk is of use.
+            chunk = match chunk.iter().position(|(offset, _)| *offset == expected) {
+                Some(position) => chunk.split_off(position),
+                None => decode_chunk(code, expected, end, base_pc),
+            };
+        }
+
+        for (offset, result) in chunk {
+            let insn = result?;
+            expected = offset + insn.len();
+            listing.push((insn.program_counter(), insn));
         }
     }
+
+    Ok(listing)
+}
+
+// Decodes the instructions that start in the given range of a buffer of code
+// along with their offsets, up to and including the first error.
+#[cfg(feature = "parallel")]
+fn decode_chunk(
+    code: &[u8],
+    start: usize,
+    end: usize,
+    base_pc: u32,
+) -> Vec<(usize, Result<Instruction>)> {
+    let mut chunk = Vec::new();
+    let mut offset = start;
+    while offset < end {
+        match read_instruction_at(code, offset, base_pc) {
+            Ok((insn, next_offset)) => {
+                chunk.push((offset, Ok(insn)));
+                offset = next_offset;
+            }
+            Err(e) => {
+                chunk.push((offset, Err(e)));
+                break;
+            }
+        }
+    }
+
+    chunk
+}
+
+// A fixed-size buffer for collecting the bytes of an instruction without
+// allocating heap memory.
+struct InstructionBuffer {
+    bytes: [u8; MAX_INSTRUCTION_LENGTH],
+    length: usize,
+}
+
+impl InstructionBuffer {
+    fn new() -> Self {
+        InstructionBuffer {
+            bytes: [0; MAX_INSTRUCTION_LENGTH],
+            length: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+}
+
+impl std::ops::Index<usize> for InstructionBuffer {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &u8 {
+        &self.as_slice()[index]
+    }
 }
 
 fn read_operands<R: Read>(
-    buffer: &mut Vec<u8>,
+    buffer: &mut InstructionBuffer,
     reader: &mut R,
     operand_size: u8,
     operands: &mut [Argument],
@@ -77,8 +372,8 @@ fn read_operands<R: Read>(
         // into the buffer.
         let mut bytes_to_read = 0;
         let operand_width = operand.position() + operand.width();
-        if buffer.len() < operand_width {
-            bytes_to_read += (operand_width - buffer.len()) as u64;
+        if buffer.length < operand_width {
+            bytes_to_read += operand_width - buffer.length;
         }
 
         // Read the operand bytes.
@@ -88,15 +383,34 @@ fn read_operands<R: Read>(
     Ok(())
 }
 
-fn read_bytes<R: Read>(buffer: &mut Vec<u8>, reader: &mut R, amount: u64) -> Result<usize> {
-    if let Ok(amount_read) = reader.take(amount).read_to_end(buffer) {
-        // If no bytes were read at all purposefully, it shouldn't count as an EOF.
-        if amount != 0 && amount_read == 0 {
-            Err(Error::Eof)
-        } else {
-            Ok(amount_read)
+fn read_bytes<R: Read>(
+    buffer: &mut InstructionBuffer,
+    reader: &mut R,
+    amount: usize,
+) -> Result<usize> {
+    let start = buffer.length;
+    let end = start + amount;
+    // The opcode is always read first, so an encoding that doesn't fit into
+    // the buffer can be attributed to it.
+    if end > MAX_INSTRUCTION_LENGTH {
+        return Err(Error::UnknownInstruction(buffer.bytes[0]));
+    }
+
+    let mut amount_read = 0;
+    while start + amount_read < end {
+        match reader.read(&mut buffer.bytes[start + amount_read..end]) {
+            Ok(0) => break,
+            Ok(n) => amount_read += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return Err(Error::IoError),
         }
+    }
+    buffer.length += amount_read;
+
+    // An instruction that is cut off counts as an EOF just like a missing one.
+    if amount_read < amount {
+        Err(Error::Eof)
     } else {
-        Err(Error::IoError)
+        Ok(amount_read)
     }
 }
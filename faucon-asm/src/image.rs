@@ -0,0 +1,158 @@
+//! Output formats for the images of assembled sections.
+//!
+//! Besides raw binaries, sections can be written as Intel HEX or Motorola
+//! S-record files, which carry the load address of the data and are
+//! understood by most flashing and upload tools:
+//!
+//! ```
+//! use faucon_asm::image::Format;
+//!
+//! let program = faucon_asm::assembler::assemble_program_str(".org 0x100\nret").unwrap();
+//! let code = &program.sections[0];
+//!
+//! let mut ihex = Vec::new();
+//! Format::IntelHex.write(code, &mut ihex).unwrap();
+//! assert_eq!(String::from_utf8(ihex).unwrap(), ":02010000F80005\n:00000001FF\n");
+//!
+//! let mut srec = Vec::new();
+//! Format::SRecord.write(code, &mut srec).unwrap();
+//! assert_eq!(
+//!     String::from_utf8(srec).unwrap(),
+//!     "S0070000636F64655D\nS1050100F80001\nS9030100FB\n",
+//! );
+//! ```
+
+use std::io::{self, Write};
+
+use crate::assembler::Section;
+
+/// The maximum number of data bytes in a single record of the text formats.
+pub const RECORD_SIZE: usize = 16;
+
+/// The formats that section images can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The raw bytes of the section, without its address.
+    Binary,
+    /// Intel HEX records, using extended linear addresses above 64 KiB.
+    IntelHex,
+    /// Motorola S-records, using the narrowest address width that fits.
+    SRecord,
+}
+
+impl Format {
+    /// Gets the format with the given name, which is one of `bin`, `ihex` and
+    /// `srec`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bin" => Some(Format::Binary),
+            "ihex" => Some(Format::IntelHex),
+            "srec" => Some(Format::SRecord),
+            _ => None,
+        }
+    }
+
+    /// Gets the file extension that is commonly used for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Binary => "bin",
+            Format::IntelHex => "hex",
+            Format::SRecord => "srec",
+        }
+    }
+
+    /// Writes the image of a section in this format.
+    pub fn write<W: Write>(self, section: &Section, writer: &mut W) -> io::Result<()> {
+        match self {
+            Format::Binary => writer.write_all(&section.data),
+            Format::IntelHex => write_ihex(section, writer),
+            Format::SRecord => write_srec(section, writer),
+        }
+    }
+}
+
+fn write_ihex<W: Write>(section: &Section, writer: &mut W) -> io::Result<()> {
+    let mut upper = 0;
+    let mut offset = 0;
+    while offset < section.data.len() {
+        let address = section.origin.wrapping_add(offset as u32);
+
+        // Records must not cross the boundaries of 64 KiB segments, whose base
+        // is set through extended linear address records.
+        if address >> 16 != upper {
+            upper = address >> 16;
+            write_ihex_record(writer, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        let length = RECORD_SIZE
+            .min(section.data.len() - offset)
+            .min(0x10000 - (address & 0xFFFF) as usize);
+
+        let data = &section.data[offset..offset + length];
+        write_ihex_record(writer, address as u16, 0x00, data)?;
+        offset += length;
+    }
+
+    write_ihex_record(writer, 0, 0x01, &[])
+}
+
+fn write_ihex_record<W: Write>(
+    writer: &mut W,
+    address: u16,
+    kind: u8,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+
+    // The checksum makes all bytes of the record sum up to zero.
+    let sum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    record.push(sum.wrapping_neg());
+
+    writeln!(writer, ":{}", hex(&record))
+}
+
+fn write_srec<W: Write>(section: &Section, writer: &mut W) -> io::Result<()> {
+    // Pick the narrowest of the 16-, 24- and 32-bit address records that can
+    // hold the addresses of all data.
+    let end = u64::from(section.origin) + section.data.len() as u64;
+    let (data_kind, end_kind, width) = match end {
+        0..=0x1_0000 => (1, 9, 2),
+        0x1_0001..=0x100_0000 => (2, 8, 3),
+        _ => (3, 7, 4),
+    };
+
+    // The header names the section, which is cut off to fit into a record.
+    let name = section.name.as_bytes();
+    write_srec_record(writer, 0, 0, 2, &name[..name.len().min(RECORD_SIZE)])?;
+    for (index, data) in section.data.chunks(RECORD_SIZE).enumerate() {
+        let address = section.origin.wrapping_add((index * RECORD_SIZE) as u32);
+        write_srec_record(writer, data_kind, address, width, data)?;
+    }
+
+    write_srec_record(writer, end_kind, section.origin, width, &[])
+}
+
+fn write_srec_record<W: Write>(
+    writer: &mut W,
+    kind: u8,
+    address: u32,
+    width: usize,
+    data: &[u8],
+) -> io::Result<()> {
+    // The count covers the address, the data and the checksum.
+    let mut record = vec![(width + data.len() + 1) as u8];
+    record.extend_from_slice(&address.to_be_bytes()[4 - width..]);
+    record.extend_from_slice(data);
+
+    // The checksum is the complement of the low byte of the sum of all bytes.
+    let sum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    record.push(!sum);
+
+    writeln!(writer, "S{}{}", kind, hex(&record))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
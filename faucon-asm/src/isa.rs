@@ -1,16 +1,31 @@
 //! Falcon ISA definitions to be used by the assembler and the disassembler.
 
+use std::collections::HashMap;
+use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
 
 use faucon_asm_derive::Instruction;
+use lazy_static::lazy_static;
 
 use crate::arguments::*;
 use crate::opcode::*;
+use crate::operands::Flag;
+use crate::MAX_INSTRUCTION_LENGTH;
 
 // Helper macro that is used by faucon-asm-derive codegen.
 macro_rules! instruction_meta {
-    ($kind:ident, $op:tt, $subop:tt, $operands:expr) => {
-        InstructionMeta::new(InstructionKind::$kind, $op as u8, $subop as u8, $operands)
+    ($kind:ident, $op:tt, $subop:tt, $operands:expr, $cycles:tt, $flags:expr) => {
+        InstructionMeta::new(
+            InstructionKind::$kind,
+            $op as u8,
+            $subop as u8,
+            $operands,
+            $cycles as usize,
+            $flags,
+        )
     };
 }
 
@@ -43,6 +58,16 @@ pub struct InstructionMeta {
     /// A vector of Arguments which work as a parser layer of packing or unpacking
     /// several instruction operands in the underlying raw bytes.
     pub operands: [Argument; 3],
+    /// The number of CPU cycles that it takes to execute the instruction.
+    ///
+    /// This is specified through the `cycles` argument of the `#[insn]`
+    /// attribute, and defaults to a single cycle.
+    pub cycles: usize,
+    /// The ALU flags that are updated by the instruction.
+    ///
+    /// This is specified through the `flags` argument of the `#[insn]`
+    /// attribute, as a comma-separated list of flag names.
+    pub flags: &'static [Flag],
 }
 
 impl InstructionMeta {
@@ -55,6 +80,8 @@ impl InstructionMeta {
         opcode: u8,
         subopcode: u8,
         operands: [Argument; 3],
+        cycles: usize,
+        flags: &'static [Flag],
     ) -> Self {
         let (a, b) = get_opcode_form(opcode);
 
@@ -64,529 +91,254 @@ impl InstructionMeta {
             b,
             subopcode,
             operands,
+            cycles,
+            flags,
         }
     }
 }
 
-/// Assembly instruction kinds within the Falcon ISA.
+/// Looks up the [`InstructionMeta`] that corresponds to a given opcode and
+/// subopcode in the [`OPCODE_TABLE`].
 ///
-/// Through internal implementation details, this enum is responsible for
-/// generating opcode lookup tables that can be used to identify instructions
-/// and their variants.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Instruction)]
-pub enum InstructionKind {
-    /// The CMPU instruction.
-    ///
-    /// Compares two unsigned values and sets ALU flags based on the result.
-    #[insn(opcode = 0x30, subopcode = 0x04, operands(R2, I8ZXS))]
-    #[insn(opcode = 0x31, subopcode = 0x04, operands(R2, I16ZXS))]
-    #[insn(opcode = 0x24, subopcode = 0x04, operands(R2, R1))]
-    CMPU,
-
-    /// The CMPS instruction.
-    ///
-    /// Compares two signed values and sets ALU flags based on the result.
-    #[insn(opcode = 0x30, subopcode = 0x05, operands(R2, I8SXS))]
-    #[insn(opcode = 0x31, subopcode = 0x05, operands(R2, I16SXS))]
-    #[insn(opcode = 0x25, subopcode = 0x05, operands(R2, R1))]
-    CMPS,
-
-    /// The CMP instruction.
-    ///
-    /// Compares two values and sets ALU flags based on the result.
-    #[insn(opcode = 0x30, subopcode = 0x06, operands(R2, I8SXS))]
-    #[insn(opcode = 0x31, subopcode = 0x06, operands(R2, I16SXS))]
-    #[insn(opcode = 0x26, subopcode = 0x06, operands(R2, R1))]
-    CMP,
-
-    /// The ADD instruction.
-    ///
-    /// Computes the sum of two operands and stores the result.
-    #[insn(opcode = 0x10, subopcode = 0x00, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x00, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x37, subopcode = 0x00, operands(R2, R2, I16ZXS))]
-    #[insn(opcode = 0x38, subopcode = 0x00, operands(R1, R2, I16ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x00, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x00, operands(R3, R2, R1))]
-    #[insn(opcode = 0xF5, subopcode = 0x30, operands(SP, SP, I16SX32))]
-    #[insn(opcode = 0xF9, subopcode = 0x01, operands(SP, SP, R2))]
-    ADD,
-
-    /// The ADC instruction.
-    ///
-    /// Computes the sum of two operands with a carry and stores the result.
-    #[insn(opcode = 0x11, subopcode = 0x01, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x01, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x37, subopcode = 0x01, operands(R2, R2, I16ZXS))]
-    #[insn(opcode = 0x38, subopcode = 0x01, operands(R1, R2, I16ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x01, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x01, operands(R3, R2, R1))]
-    ADC,
-
-    /// The SUB instruction.
-    ///
-    /// Subtracts two operands and stores the result.
-    #[insn(opcode = 0x12, subopcode = 0x02, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x02, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x37, subopcode = 0x02, operands(R2, R2, I16ZXS))]
-    #[insn(opcode = 0x38, subopcode = 0x02, operands(R1, R2, I16ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x02, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x02, operands(R3, R2, R1))]
-    SUB,
-
-    /// The SBB instruction.
-    ///
-    /// Subtracts two operands with borrow and stores the result.
-    #[insn(opcode = 0x13, subopcode = 0x03, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x03, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x37, subopcode = 0x03, operands(R2, R2, I16ZXS))]
-    #[insn(opcode = 0x38, subopcode = 0x03, operands(R1, R2, I16ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x03, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x03, operands(R3, R2, R1))]
-    SBB,
-
-    /// The SHL instruction.
-    ///
-    /// Shifts a value left and stores the result.
-    #[insn(opcode = 0x14, subopcode = 0x04, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x04, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x04, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x04, operands(R3, R2, R1))]
-    SHL,
-
-    /// The SHR instruction.
-    ///
-    /// Shifts a value right and stores the result.
-    #[insn(opcode = 0x15, subopcode = 0x05, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x05, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x05, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x05, operands(R3, R2, R1))]
-    SHR,
-
-    /// The SAR instruction.
-    ///
-    /// Shifts a value right with sign bit and stores the result.
-    #[insn(opcode = 0x17, subopcode = 0x07, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x07, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x07, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x07, operands(R3, R2, R1))]
-    SAR,
-
-    /// The SHLC instruction.
-    ///
-    /// Shifts a value left with carry in and stores the result.
-    #[insn(opcode = 0x1C, subopcode = 0x0C, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x0C, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x0C, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x0C, operands(R3, R2, R1))]
-    SHLC,
-
-    /// The SHRC instruction.
-    ///
-    /// Shifts a value right with carry in and stores the result.
-    #[insn(opcode = 0x1D, subopcode = 0x0D, operands(R1, R2, I8ZXS))]
-    #[insn(opcode = 0x36, subopcode = 0x0D, operands(R2, R2, I8ZXS))]
-    #[insn(opcode = 0x3B, subopcode = 0x0D, operands(R2, R2, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x0D, operands(R3, R2, R1))]
-    SHRC,
-
-    /// The NOT instruction.
-    ///
-    /// Flips all bits in a value.
-    #[insn(opcode = 0x39, subopcode = 0x00, operands(R1, R2))]
-    #[insn(opcode = 0x3D, subopcode = 0x01, operands(R2, R2))]
-    NOT,
-
-    /// The NEG instruction.
-    ///
-    /// Negates a value
-    #[insn(opcode = 0x39, subopcode = 0x01, operands(R1, R2))]
-    #[insn(opcode = 0x3D, subopcode = 0x00, operands(R2, R2))]
-    NEG,
-
-    /// The HSWAP instruction.
-    ///
-    ///  Rotates a value by half it's size
-    #[insn(opcode = 0x39, subopcode = 0x03, operands(R1, R2))]
-    #[insn(opcode = 0x3D, subopcode = 0x03, operands(R2, R2))]
-    HSWAP,
-
-    /// The SETHI instruction.
-    ///
-    /// Sets the high 16 bits of a register to a value, without thouching
-    /// the low 16 bits.
-    #[insn(opcode = 0xF0, subopcode = 0x03, operands(R2, I8ZX32S16))]
-    SETHI,
-
-    /// The CLEAR instruction.
-    ///
-    /// Clears the contents of a register.
-    #[insn(opcode = 0x3D, subopcode = 0x04, operands(R2))]
-    CLEAR,
-
-    /// THE MULU instruction.
-    ///
-    /// Performs an unsigned multiplication and stores the result.
-    #[insn(opcode = 0xC0, subopcode = 0x00, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xE0, subopcode = 0x00, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xF0, subopcode = 0x00, operands(R2, R2, I8ZX32))]
-    #[insn(opcode = 0xFD, subopcode = 0x00, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x00, operands(R3, R2, R1))]
-    MULU,
-
-    /// The MULS instruction.
-    ///
-    /// Performs a signed multiplication and stores the result.
-    #[insn(opcode = 0xC1, subopcode = 0x01, operands(R1, R2, I8SX32))]
-    #[insn(opcode = 0xE1, subopcode = 0x01, operands(R1, R2, I16SX32))]
-    #[insn(opcode = 0xF0, subopcode = 0x01, operands(R2, R2, I8SX32))]
-    #[insn(opcode = 0xFD, subopcode = 0x01, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x01, operands(R3, R2, R1))]
-    MULS,
-
-    /// The SEXT instruction.
-    ///
-    /// Sign-extends a value and stores the result.
-    #[insn(opcode = 0xC2, subopcode = 0x02, operands(R1, R2, I8))]
-    #[insn(opcode = 0xF0, subopcode = 0x02, operands(R2, R2, I8))]
-    #[insn(opcode = 0xFD, subopcode = 0x02, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x02, operands(R3, R2, R1))]
-    SEXT,
-
-    /// The AND instruction.
-    ///
-    /// Performs a binary AND operation on two operands.
-    #[insn(opcode = 0xC4, subopcode = 0x04, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xE4, subopcode = 0x04, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xF0, subopcode = 0x04, operands(R2, R2, I8ZX32))]
-    #[insn(opcode = 0xF1, subopcode = 0x04, operands(R2, R2, I16ZX32))]
-    #[insn(opcode = 0xFD, subopcode = 0x04, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x04, operands(R3, R2, R1))]
-    AND,
-
-    /// The OR instruction.
-    ///
-    /// Performs a binary OR operation on two operands.
-    #[insn(opcode = 0xC5, subopcode = 0x05, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xE5, subopcode = 0x05, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xF0, subopcode = 0x05, operands(R2, R2, I8ZX32))]
-    #[insn(opcode = 0xF1, subopcode = 0x05, operands(R2, R2, I16ZX32))]
-    #[insn(opcode = 0xFD, subopcode = 0x05, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x05, operands(R3, R2, R1))]
-    OR,
-
-    /// The XOR instruction.
-    ///
-    /// Performs a binary XOR operation on two operands.
-    #[insn(opcode = 0xC6, subopcode = 0x06, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xE6, subopcode = 0x06, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xF0, subopcode = 0x06, operands(R2, R2, I8ZX32))]
-    #[insn(opcode = 0xF1, subopcode = 0x06, operands(R2, R2, I16ZX32))]
-    #[insn(opcode = 0xFD, subopcode = 0x06, operands(R2, R2, R1))]
-    #[insn(opcode = 0xFF, subopcode = 0x06, operands(R3, R2, R1))]
-    XOR,
-
-    /// The XBIT instruction.
-    ///
-    /// Extracts a bit from a specified register and stores it in the lowest
-    /// bit of the destination register, setting all other bits to 0.
-    #[insn(opcode = 0xC8, subopcode = 0x08, operands(R1, R2, I8))]
-    #[insn(opcode = 0xFF, subopcode = 0x08, operands(R3, R2, R1))]
-    #[insn(opcode = 0xF0, subopcode = 0x0C, operands(R2, FLAGS, FLAG))]
-    #[insn(opcode = 0xFE, subopcode = 0x0C, operands(R1, FLAGS, R2))]
-    XBIT,
-
-    /// The BSET instruction.
-    ///
-    /// Sets a specific bit in a given register.
-    #[insn(opcode = 0xF0, subopcode = 0x09, operands(R2, I8))]
-    #[insn(opcode = 0xFD, subopcode = 0x09, operands(R2, R1))]
-    #[insn(opcode = 0xF4, subopcode = 0x31, operands(FLAGS, FLAG))]
-    #[insn(opcode = 0xF9, subopcode = 0x09, operands(FLAGS, R2))]
-    BSET,
-
-    /// The BCLR instruction.
-    ///
-    /// Clears a specific bit in a given register.
-    #[insn(opcode = 0xF0, subopcode = 0x0A, operands(R2, I8))]
-    #[insn(opcode = 0xFD, subopcode = 0x0A, operands(R2, R1))]
-    #[insn(opcode = 0xF4, subopcode = 0x32, operands(FLAGS, FLAG))]
-    #[insn(opcode = 0xF9, subopcode = 0x0A, operands(FLAGS, R2))]
-    BCLR,
-
-    /// The BTGL instruction.
-    ///
-    /// Toggles (flips) a specific bit in a given register.
-    #[insn(opcode = 0xF0, subopcode = 0x0B, operands(R2, I8))]
-    #[insn(opcode = 0xFD, subopcode = 0x0B, operands(R2, R1))]
-    #[insn(opcode = 0xF4, subopcode = 0x33, operands(FLAGS, FLAG))]
-    #[insn(opcode = 0xF9, subopcode = 0x0B, operands(FLAGS, R2))]
-    BTGL,
-
-    /// The DIV instruction.
-    ///
-    /// Performs unsigned 32-bit division on two operands.
-    #[insn(opcode = 0xCC, subopcode = 0x0C, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xEC, subopcode = 0x0C, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xFF, subopcode = 0x0C, operands(R3, R2, R1))]
-    DIV,
-
-    /// The MOD instruction.
-    ///
-    /// Takes the modulus of two 32-bit unsigned operands.
-    #[insn(opcode = 0xCD, subopcode = 0x0D, operands(R1, R2, I8ZX32))]
-    #[insn(opcode = 0xED, subopcode = 0x0D, operands(R1, R2, I16ZX32))]
-    #[insn(opcode = 0xFF, subopcode = 0x0D, operands(R3, R2, R1))]
-    MOD,
-
-    /// The SETP instruction.
-    ///
-    /// Sets a given bit in the `$flags` register to the lowest bit of the
-    /// source register.
-    #[insn(opcode = 0xF2, subopcode = 0x08, operands(FLAG, R2))]
-    #[insn(opcode = 0xFA, subopcode = 0x08, operands(R1, R2))]
-    SETP,
-
-    /// The MOV instruction.
-    ///
-    /// Moves values of immediates or registers to other registers.
-    #[insn(opcode = 0x00, subopcode = 0x00, operands(R0, I8SX32P1))]
-    #[insn(opcode = 0x40, subopcode = 0x01, operands(R0, I16SX32P1))]
-    #[insn(opcode = 0x80, subopcode = 0x02, operands(R0, I24SX32))]
-    #[insn(opcode = 0x32, subopcode = 0x02, operands(R1, R2))]
-    #[insn(opcode = 0xD0, subopcode = 0x00, operands(R0, I32))]
-    #[insn(opcode = 0xFE, subopcode = 0x00, operands(SR2, R2))]
-    #[insn(opcode = 0xFE, subopcode = 0x01, operands(R1, SR1))]
-    MOV,
-
-    /// The LD instruction.
-    ///
-    /// Loads a value from Falcon DMem to a register.
-    #[insn(opcode = 0x18, subopcode = 0x08, operands(R1, MEMRI))]
-    #[insn(opcode = 0x34, subopcode = 0x00, operands(R2, MEMSPI))]
-    #[insn(opcode = 0x3A, subopcode = 0x00, operands(R2, MEMSPR))]
-    #[insn(opcode = 0x3C, subopcode = 0x08, operands(R3, MEMRR))]
-    #[insn(opcode = 0x3F, subopcode = 0x0F, operands(R1, MEMR))]
-    LD,
-
-    /// The ST instruction.
-    ///
-    /// Stores a value from a register to Falcon DMem.
-    #[insn(opcode = 0x20, subopcode = 0x00, operands(MEMR, R1))]
-    #[insn(opcode = 0x21, subopcode = 0x01, operands(MEMSPR, R2))]
-    #[insn(opcode = 0x30, subopcode = 0x01, operands(MEMSPI, R2))]
-    #[insn(opcode = 0x35, subopcode = 0x05, operands(MEMRI, R1))]
-    #[insn(opcode = 0x3C, subopcode = 0x09, operands(MEMRRALT, R1))]
-    ST,
-
-    /// The PUSH instruction.
-    ///
-    /// Pushes a value onto the stack and increments the stack pointer by four.
-    #[insn(opcode = 0xF9, subopcode = 0x00, operands(R2))]
-    PUSH,
-
-    /// THE POP instruction.
-    ///
-    /// Pops a value off the stack and increments the stack pointer by four.
-    #[insn(opcode = 0xFC, subopcode = 0x00, operands(R2))]
-    POP,
-
-    /// The CALL instruction.
-    ///
-    /// Performs an unconditional branch to an absolute address, pushing
-    /// the return address onto the stack.
-    #[insn(opcode = 0xF3, subopcode = 0x03, operands(I16ZX32P1))]
-    #[insn(opcode = 0xF4, subopcode = 0x21, operands(I8ZX32))]
-    #[insn(opcode = 0xF9, subopcode = 0x05, operands(R2))]
-    CALL,
-
-    /// The LCALL instruction.
-    ///
-    /// Performs an unconditional branch to an absolute address, pushing
-    /// the return address onto the stack.
-    // FIXME: This is effectively just a CALL. Why is that a dedicated instruction?
-    #[insn(opcode = 0x7E, subopcode = 0x01, operands(I24ZX32))]
-    LCALL,
-
-    /// The LJMP instruction.
-    ///
-    /// Performs an unconditional branch to an absolute address.
-    // FIXME: This is effectively just a JMP. Why is that a dedicated instruction?
-    #[insn(opcode = 0x3E, subopcode = 0x00, operands(I24ZX32))]
-    LJMP,
-
-    /// The RET instruction.
-    ///
-    /// Returns from a previous subroutine call.
-    #[insn(opcode = 0xF8, subopcode = 0x00, operands())]
-    RET,
-
-    /// The EXIT instruction.
-    ///
-    /// Halts microcode execution and triggers the EXIT interrupt so that the
-    /// processor can only be restarted by the host machine.
-    #[insn(opcode = 0xF8, subopcode = 0x02, operands())]
-    EXIT,
-
-    /// The SLEEP instruction.
-    ///
-    /// Puts the processor into sleep state until an unmasked interrupt is
-    /// received. Repeated until the given flag bit is cleared.
-    #[insn(opcode = 0xF4, subopcode = 0x28, operands(FLAG))]
-    SLEEP,
-
-    /// The PTLB instruction.
-    ///
-    /// Loads the TLB that covers a given physical page into a destination
-    /// register.
-    #[insn(opcode = 0xFE, subopcode = 0x02, operands(R1, R2))]
-    PTLB,
+/// [`InstructionMeta`]: struct.InstructionMeta.html
+/// [`OPCODE_TABLE`]: static.OPCODE_TABLE.html
+pub fn lookup_instruction(opcode: u8, subopcode: u8) -> Option<&'static InstructionMeta> {
+    OPCODE_TABLE[opcode as usize]
+        .get(subopcode as usize)
+        .copied()
+        .flatten()
+        .or_else(|| {
+            // Unknown encodings are common in data, so spare them the lock
+            // as long as the ISA was never extended.
+            if !HAS_EXTENSIONS.load(Ordering::Acquire) {
+                return None;
+            }
+
+            let extensions = EXTENSIONS.read().unwrap();
+            extensions.get(&(opcode, subopcode)).copied()
+        })
+}
 
-    /// The VTLB instruction.
-    ///
-    /// Loads the TLB that covers a given virtual address into a destination
-    /// register.
-    #[insn(opcode = 0xFE, subopcode = 0x03, operands(R1, R2))]
-    VTLB,
+// Whether any encoding was registered at runtime, which is only ever set
+// once an encoding was inserted into the `EXTENSIONS`.
+static HAS_EXTENSIONS: AtomicBool = AtomicBool::new(false);
 
-    /// The ITLB instruction.
-    ///
-    /// Clears a non-secret TLB entry corresponding to a specified physical
-    /// page.
-    #[insn(opcode = 0xF9, subopcode = 0x08, operands(R2))]
-    ITLB,
+lazy_static! {
+    // The instruction encodings that were registered at runtime, by the opcode
+    // and the subopcode they occupy.
+    static ref EXTENSIONS: RwLock<HashMap<(u8, u8), &'static InstructionMeta>> =
+        RwLock::new(HashMap::new());
+}
 
-    /// The IRET instruction.
-    ///
-    /// Returns from an interrupt handler.
-    #[insn(opcode = 0xF8, subopcode = 0x01, operands())]
-    IRET,
+/// An error that occurs when registering an instruction encoding through
+/// [`register_instruction`].
+///
+/// [`register_instruction`]: fn.register_instruction.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtensionError {
+    /// The opcode and subopcode can never be looked up, because the opcode has
+    /// no form with a subopcode or the subopcode doesn't fit into its field.
+    InvalidEncoding(u8, u8),
+    /// The opcode and subopcode already denote an instruction of the given
+    /// kind.
+    Conflict(u8, u8, InstructionKind),
+}
 
-    /// The TRAP instruction.
-    ///
-    /// Triggers a software trap.
-    #[insn(opcode = 0xF8, subopcode = 0x08, operands(TRAP))]
-    #[insn(opcode = 0xF8, subopcode = 0x09, operands(TRAP))]
-    #[insn(opcode = 0xF8, subopcode = 0x0A, operands(TRAP))]
-    #[insn(opcode = 0xF8, subopcode = 0x0B, operands(TRAP))]
-    TRAP,
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionError::InvalidEncoding(opcode, subopcode) => write!(
+                f,
+                "opcode {:#04x} with subopcode {:#04x} is not a valid encoding",
+                opcode, subopcode
+            ),
+            ExtensionError::Conflict(opcode, subopcode, kind) => write!(
+                f,
+                "opcode {:#04x} with subopcode {:#04x} is already taken by `{}`",
+                opcode, subopcode, kind
+            ),
+        }
+    }
+}
 
-    /// The XCLD instruction.
-    ///
-    /// Submits a DMA transfer request to load code from external memory.
-    #[insn(opcode = 0xFA, subopcode = 0x04, operands(R2, R1))]
-    XCLD,
+impl Error for ExtensionError {}
 
-    /// The XDLD instruction.
-    ///
-    /// Submits a DMA transfer request to load data from external memory.
-    #[insn(opcode = 0xFA, subopcode = 0x05, operands(R2, R1))]
-    XDLD,
+/// Registers an additional encoding of an instruction in the decode tables,
+/// under the given opcode and the subopcode of `meta`.
+///
+/// This extends the ISA with encodings that are not part of the built-in
+/// tables, such as undocumented forms or forms that only exist on particular
+/// engines. Registered encodings are known to the disassembler right away and
+/// to every [`Assembler`] that is created afterwards. Unlike the built-in
+/// tables, an encoding only occupies a single slot, so that sized forms must
+/// be registered for every operand size separately.
+///
+/// ```
+/// use faucon_asm::arguments::*;
+/// use faucon_asm::isa::{register_instruction, ExtensionError, InstructionKind, InstructionMeta};
+///
+/// // An encoding of `mov` that is not part of the built-in tables.
+/// let meta = InstructionMeta::new(InstructionKind::MOV, 0xFE, 0x0F, [R1, R2, NOP], 1, &[]);
+/// register_instruction(0xFE, meta.clone()).unwrap();
+///
+/// let insn = faucon_asm::read_instruction(&mut &[0xFEu8, 0x21, 0x0F][..]).unwrap();
+/// assert_eq!(insn.to_string(), "mov $r1 $r2");
+///
+/// assert_eq!(
+///     register_instruction(0xFE, meta),
+///     Err(ExtensionError::Conflict(0xFE, 0x0F, InstructionKind::MOV)),
+/// );
+/// ```
+///
+/// [`Assembler`]: ../assembler/struct.Assembler.html
+pub fn register_instruction(opcode: u8, meta: InstructionMeta) -> Result<(), ExtensionError> {
+    let subopcode = meta.subopcode;
+    let size = OperandSize::from(opcode);
+    let (a, b) = get_opcode_form(opcode);
+
+    // The subopcode must fit into its field in the instruction bytes.
+    let fits = match get_subopcode_location(size.value(), a, b) {
+        Some(location) => {
+            let mut insn = [opcode; MAX_INSTRUCTION_LENGTH];
+            location.write(&mut insn, subopcode);
+            location.parse(&insn) == subopcode
+        }
+        None => false,
+    };
+    if !fits {
+        return Err(ExtensionError::InvalidEncoding(opcode, subopcode));
+    }
 
-    /// The XDST instruction.
-    ///
-    /// Submits a DMA transfer request to store local Falcon data in external
-    /// memory.
-    #[insn(opcode = 0xFA, subopcode = 0x06, operands(R2, R1))]
-    XDST,
+    let mut extensions = EXTENSIONS.write().unwrap();
+    if let Some(existing) = OPCODE_TABLE[opcode as usize][subopcode as usize]
+        .or_else(|| extensions.get(&(opcode, subopcode)).copied())
+    {
+        return Err(ExtensionError::Conflict(opcode, subopcode, existing.kind));
+    }
 
-    /// The XCWAIT instruction.
-    ///
-    /// Waits for all DMA code load transfers to complete.
-    #[insn(opcode = 0xF8, subopcode = 0x07, operands())]
-    XCWAIT,
+    extensions.insert((opcode, subopcode), Box::leak(Box::new(meta)));
+    HAS_EXTENSIONS.store(true, Ordering::Release);
+    Ok(())
+}
 
-    /// The XDWAIT instruction.
-    ///
-    /// Waits for all DMA data load/store transfers to complete.
-    #[insn(opcode = 0xF8, subopcode = 0x03, operands())]
-    XDWAIT,
+/// Gets all instruction encodings in the decode tables as tuples of opcode,
+/// subopcode and the [`InstructionMeta`], including the encodings that were
+/// registered through [`register_instruction`].
+///
+/// Encodings that span multiple slots of the [`OPCODE_TABLE`] are listed once
+/// for every slot.
+///
+/// [`InstructionMeta`]: struct.InstructionMeta.html
+/// [`register_instruction`]: fn.register_instruction.html
+/// [`OPCODE_TABLE`]: static.OPCODE_TABLE.html
+pub fn instruction_encodings() -> Vec<(u8, u8, &'static InstructionMeta)> {
+    let mut encodings = Vec::new();
+    for (opcode, row) in OPCODE_TABLE.iter().enumerate() {
+        for (subopcode, meta) in row.iter().enumerate() {
+            if let Some(meta) = meta {
+                encodings.push((opcode as u8, subopcode as u8, *meta));
+            }
+        }
+    }
 
-    /// The IOWR instruction.
-    ///
-    /// Asynchronously writes a word to the I/O space of the microprocessor.
-    #[insn(opcode = 0xF6, subopcode = 0x06, operands(IORI, R1))]
-    #[insn(opcode = 0xFA, subopcode = 0x00, operands(IOR, R1))]
-    IOWR,
+    let extensions = EXTENSIONS.read().unwrap();
+    let mut extensions = extensions
+        .iter()
+        .map(|(&(opcode, subopcode), meta)| (opcode, subopcode, *meta))
+        .collect::<Vec<_>>();
+    extensions.sort_by_key(|&(opcode, subopcode, _)| (opcode, subopcode));
+    encodings.extend(extensions);
 
-    /// The IOWRS instruction.
-    ///
-    /// Synchronously writes a word to the I/O space of the microprocessor.
-    #[insn(opcode = 0xF7, subopcode = 0x07, operands(IORI, R1))]
-    #[insn(opcode = 0xFA, subopcode = 0x01, operands(IOR, R2))]
-    IOWRS,
+    encodings
+}
 
-    /// The IORD instruction.
-    ///
-    /// Reads a word from the I/O space of the processor.
-    #[insn(opcode = 0xCF, subopcode = 0x0F, operands(R1, IORI))]
-    #[insn(opcode = 0xFF, subopcode = 0x0F, operands(R3, IORR))]
-    IORD,
+/// The specification of the ISA that the instruction definitions of this crate
+/// are generated from.
+///
+/// It is a TOML document, whose format is described at its top. Tools that
+/// deal with Falcon instructions can consume it to stay in sync with this crate.
+pub const SPEC: &str = include_str!("../isa.toml");
+
+include!(concat!(env!("OUT_DIR"), "/isa.rs"));
+
+impl InstructionKind {
+    /// Checks whether instructions of this kind are branches, jumps or calls,
+    /// which may continue execution elsewhere than at the next instruction.
+    ///
+    /// Returns from subroutines and handlers are not considered branches.
+    ///
+    /// ```
+    /// use faucon_asm::InstructionKind;
+    ///
+    /// assert!(InstructionKind::BRA.is_branch());
+    /// assert!(InstructionKind::BNZ.is_branch());
+    /// assert!(InstructionKind::LCALL.is_branch());
+    /// assert!(!InstructionKind::RET.is_branch());
+    /// assert!(!InstructionKind::ADD.is_branch());
+    /// ```
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            InstructionKind::BP
+                | InstructionKind::BC
+                | InstructionKind::BO
+                | InstructionKind::BS
+                | InstructionKind::BZ
+                | InstructionKind::BA
+                | InstructionKind::BNA
+                | InstructionKind::BRA
+                | InstructionKind::BNP
+                | InstructionKind::BNC
+                | InstructionKind::BNO
+                | InstructionKind::BNS
+                | InstructionKind::BNZ
+                | InstructionKind::BG
+                | InstructionKind::BLE
+                | InstructionKind::BL
+                | InstructionKind::BGE
+                | InstructionKind::JMP
+                | InstructionKind::LJMP
+                | InstructionKind::CALL
+                | InstructionKind::LCALL
+        )
+    }
+}
 
-    /// An invalid or unknown instruction.
-    XXX,
+impl FromStr for InstructionKind {
+    type Err = UnknownMnemonic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        INSTRUCTIONS
+            .iter()
+            .map(|meta| meta.kind)
+            .find(|kind| !kind.invalid() && kind.mnemonic() == s)
+            .or_else(|| {
+                MNEMONIC_ALIASES
+                    .iter()
+                    .find(|&&(alias, _)| alias == s)
+                    .map(|&(_, kind)| kind)
+            })
+            .ok_or_else(|| UnknownMnemonic(s.to_string()))
+    }
 }
 
 impl fmt::Display for InstructionKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mnemonic = match self {
-            InstructionKind::CMPU => "cmpu",
-            InstructionKind::CMPS => "cmps",
-            InstructionKind::CMP => "cmp",
-            InstructionKind::ADD => "add",
-            InstructionKind::ADC => "adc",
-            InstructionKind::SUB => "sub",
-            InstructionKind::SBB => "sbb",
-            InstructionKind::SHL => "shl",
-            InstructionKind::SHR => "shr",
-            InstructionKind::SAR => "sar",
-            InstructionKind::SHLC => "shlc",
-            InstructionKind::SHRC => "shrc",
-            InstructionKind::NOT => "not",
-            InstructionKind::NEG => "neg",
-            InstructionKind::HSWAP => "hswap",
-            InstructionKind::SETHI => "sethi",
-            InstructionKind::CLEAR => "clear",
-            InstructionKind::MULU => "mulu",
-            InstructionKind::MULS => "muls",
-            InstructionKind::SEXT => "sext",
-            InstructionKind::AND => "and",
-            InstructionKind::OR => "or",
-            InstructionKind::XOR => "xor",
-            InstructionKind::XBIT => "xbit",
-            InstructionKind::BSET => "bset",
-            InstructionKind::BCLR => "bclr",
-            InstructionKind::BTGL => "btgl",
-            InstructionKind::DIV => "div",
-            InstructionKind::MOD => "mod",
-            InstructionKind::SETP => "setp",
-            InstructionKind::MOV => "mov",
-            InstructionKind::LD => "ld",
-            InstructionKind::ST => "st",
-            InstructionKind::PUSH => "push",
-            InstructionKind::POP => "pop",
-            InstructionKind::CALL => "call",
-            InstructionKind::LCALL => "lcall",
-            InstructionKind::LJMP => "ljmp",
-            InstructionKind::RET => "ret",
-            InstructionKind::EXIT => "exit",
-            InstructionKind::SLEEP => "sleep",
-            InstructionKind::PTLB => "ptlb",
-            InstructionKind::VTLB => "vtlb",
-            InstructionKind::ITLB => "itlb",
-            InstructionKind::IRET => "iret",
-            InstructionKind::TRAP => "trap",
-            InstructionKind::XCLD => "xcld",
-            InstructionKind::XDLD => "xdld",
-            InstructionKind::XDST => "xdst",
-            InstructionKind::XCWAIT => "xcwait",
-            InstructionKind::XDWAIT => "xdwait",
-            InstructionKind::IOWR => "iowr",
-            InstructionKind::IOWRS => "iowrs",
-            InstructionKind::IORD => "iord",
-            InstructionKind::XXX => "???",
-        };
+        write!(f, "{}", self.mnemonic())
+    }
+}
+
+/// An error that occurs when parsing an [`InstructionKind`] from a string that
+/// is not the mnemonic of any instruction.
+///
+/// [`InstructionKind`]: enum.InstructionKind.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownMnemonic(pub String);
 
-        write!(f, "{}", mnemonic)
+impl fmt::Display for UnknownMnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown mnemonic `{}`", self.0)
     }
 }
+
+impl Error for UnknownMnemonic {}
@@ -41,6 +41,16 @@
 //! assert_eq!(instruction.to_string(), "ld b32 $r15 D[$r1]");
 //! ```
 //!
+//! The alternate form (`{:#}`) prints the pseudo-instructions that are known
+//! to the assembler, such as `nop` or `clr`, where they apply:
+//!
+//! ```
+//! let instruction = faucon_asm::read_instruction(&mut &[0xBDu8, 0x14u8][..]).unwrap();
+//!
+//! assert_eq!(format!("{}", instruction), "clear b32 $r1");
+//! assert_eq!(format!("{:#}", instruction), "clr $r1");
+//! ```
+//!
 //! ## Instruction operands
 //!
 //! Of course, an [`Instruction`] object lets you access its operands which are used to
@@ -70,11 +80,16 @@
 //!
 //! # Assembling instructions
 //!
-//! Functionality for assembling intermediate representation to machine code is
-//! currently unsupported and planned for the future.
+//! Falcon assembly source code can be turned into machine code through the
+//! [`assembler`] module, which understands the same syntax that is emitted when
+//! pretty-printing instructions.
+//!
+//! ```
+//! let code = faucon_asm::assembler::assemble_str("ld b32 $r15 D[$r1]")
+//!     .expect("Failed to assemble the given source code");
 //!
-//! For the time being, it is advised to use `envyas` from the [envytools]
-//! collection.
+//! assert_eq!(code, [0xBF, 0x1F]);
+//! ```
 //!
 //! # Disassembling instructions
 //!
@@ -82,36 +97,66 @@
 //! raw instruction bytes into [`Instruction`] objects. The function can be called
 //! repeatedly on a buffer of code until an error or [`Error::Eof`] occurs.
 //!
+//! When disassembling a buffer of code which is mapped at a known address, the
+//! [`read_instruction_at`] function should be preferred as it keeps track of the
+//! program counter of every [`Instruction`] that is read.
+//!
+//! Large binary images can be disassembled concurrently with the
+//! [`disassemble_parallel`] function, given that the `parallel` feature of this
+//! crate is enabled.
+//!
+//! Binary images which mix code with data can be examined with the functions in
+//! the [`analysis`] module, which classify the image and produce a listing that
+//! emits data as `.byte` and `.word` directives.
+//!
+//! With the `roundtrip` feature enabled, the [`roundtrip`] module verifies that
+//! every encoding in the ISA tables survives disassembling and assembling it
+//! again, which is useful when adding instructions.
+//!
 //! It is within the user's responsibility to ensure that all possible exceptions
 //! are handled correctly. The validity of an [`Instruction`] can be ensured through
 //! [`Instruction::is_valid`].
 //!
 //! [`Instruction`]: struct.Instruction.html
 //! [`read_instruction`]: fn.read_instruction.html
+//! [`read_instruction_at`]: fn.read_instruction_at.html
+//! [`disassemble_parallel`]: fn.disassemble_parallel.html
+//! [`analysis`]: analysis/index.html
+//! [`roundtrip`]: roundtrip/index.html
 //! [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
 //! [`Operand`]: ./operands/enum.Operand.html
 //! [`Instruction::operands`]: struct.Instruction.html#method.operands
 //! [`Instruction::kind`]: struct.Instruction.html#method.kind
 //! [`InstructionKind`]: ./isa/enum.InstructionKind.html
-//! [envytools]: https://github.com/envytools/envytools
+//! [`assembler`]: assembler/index.html
 //! [`Error::Eof`]: enum.Error.html#variant.Eof
 //! [`Instruction::is_valid`]: struct.Instruction.html#method.is_valid
 
 use std::fmt;
 
+use smallvec::SmallVec;
+
 pub use disassembler::*;
-pub use isa::InstructionKind;
+pub use isa::{InstructionKind, UnknownMnemonic};
 pub use opcode::OperandSize;
 pub use operands::*;
 
 use arguments::Argument;
 use opcode::*;
 
-mod arguments;
+pub mod analysis;
+pub mod arguments;
+pub mod assembler;
+pub mod debug_info;
 pub mod disassembler;
+pub mod image;
 pub mod isa;
+pub mod linker;
+pub mod object;
 pub mod opcode;
 pub mod operands;
+#[cfg(feature = "roundtrip")]
+pub mod roundtrip;
 
 /// A result that is returned by the functions in this crate.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -132,24 +177,93 @@ pub enum Error {
     Eof,
 }
 
+/// How the hardware treats a decoded [`Instruction`].
+///
+/// Some encodings decode to instructions of the ISA, but refer to special-purpose
+/// registers or flag bits that are reserved. Their behavior on hardware is
+/// unpredictable, and the assembly syntax has no names for their operands.
+///
+/// [`Instruction`]: struct.Instruction.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodedAs {
+    /// The instruction has well-defined behavior.
+    Defined,
+    /// The instruction refers to reserved registers or flag bits and thus
+    /// behaves unpredictably.
+    Unpredictable,
+}
+
+/// The maximum length of a Falcon instruction in bytes.
+pub const MAX_INSTRUCTION_LENGTH: usize = 8;
+
 /// A Falcon processor instruction.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Instruction {
-    bytes: Vec<u8>,
+    bytes: InstructionBytes,
     /// The operand size of the instruction.
     pub operand_size: OperandSize,
     meta: isa::InstructionMeta,
+    pc: u32,
+}
+
+// The encoding of an instruction, which is stored inline unless it is longer
+// than any instruction of the ISA.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum InstructionBytes {
+    Inline([u8; MAX_INSTRUCTION_LENGTH], u8),
+    Heap(Vec<u8>),
+}
+
+impl InstructionBytes {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            InstructionBytes::Inline(bytes, length) => &bytes[..*length as usize],
+            InstructionBytes::Heap(bytes) => bytes,
+        }
+    }
 }
 
 impl Instruction {
     /// Constructs a new instruction from its byte representation and metadata.
-    pub fn new(bytes: Vec<u8>, mut operand_size: OperandSize, meta: isa::InstructionMeta) -> Self {
+    ///
+    /// Encodings of up to [`MAX_INSTRUCTION_LENGTH`] bytes, which covers all
+    /// instructions of the ISA, are stored inline within the object. Longer
+    /// ones are kept in the given buffer.
+    ///
+    /// [`MAX_INSTRUCTION_LENGTH`]: constant.MAX_INSTRUCTION_LENGTH.html
+    pub fn new(bytes: Vec<u8>, operand_size: OperandSize, meta: isa::InstructionMeta) -> Self {
+        if bytes.len() <= MAX_INSTRUCTION_LENGTH {
+            Instruction::from_slice(&bytes, operand_size, meta)
+        } else {
+            Instruction::with_bytes(InstructionBytes::Heap(bytes), operand_size, meta)
+        }
+    }
+
+    // Constructs a new instruction from an encoding of up to
+    // `MAX_INSTRUCTION_LENGTH` bytes without allocating heap memory.
+    pub(crate) fn from_slice(
+        bytes: &[u8],
+        operand_size: OperandSize,
+        meta: isa::InstructionMeta,
+    ) -> Self {
+        let mut inline = [0; MAX_INSTRUCTION_LENGTH];
+        inline[..bytes.len()].copy_from_slice(bytes);
+
+        let bytes = InstructionBytes::Inline(inline, bytes.len() as u8);
+        Instruction::with_bytes(bytes, operand_size, meta)
+    }
+
+    fn with_bytes(
+        bytes: InstructionBytes,
+        mut operand_size: OperandSize,
+        meta: isa::InstructionMeta,
+    ) -> Self {
         // TODO: InstructionKind::XXX?
 
         // Certain Falcon weirdos encode their subopcode in the high size bits and thus
         // making the instruction per se unsized. We need to make sure to not use a false
         // positive operand size.
-        let (a, b) = get_opcode_form(bytes[0]);
+        let (a, b) = get_opcode_form(bytes.as_slice()[0]);
         if get_subopcode_location(operand_size.value(), a, b) == Some(SubopcodeLocation::OH) {
             operand_size = OperandSize::Unsized;
         }
@@ -158,6 +272,7 @@ impl Instruction {
             bytes,
             operand_size,
             meta,
+            pc: 0,
         }
     }
 
@@ -186,9 +301,68 @@ impl Instruction {
         self.meta.kind
     }
 
+    /// Gets the program counter value the instruction is located at.
+    ///
+    /// Only instructions obtained through [`read_instruction_at`] carry their
+    /// real address, for all others this defaults to `0`.
+    ///
+    /// [`read_instruction_at`]: fn.read_instruction_at.html
+    pub fn program_counter(&self) -> u32 {
+        self.pc
+    }
+
+    /// Gets the number of CPU cycles that it takes to execute the instruction.
+    ///
+    /// ```
+    /// let instruction = faucon_asm::read_instruction(&mut &[0xF8u8, 0x00u8][..]).unwrap();
+    /// assert_eq!(instruction.cycles(), 5);
+    /// ```
+    pub fn cycles(&self) -> usize {
+        self.meta.cycles
+    }
+
+    /// Gets the ALU flags that are updated by the instruction.
+    ///
+    /// ```
+    /// use faucon_asm::Flag;
+    ///
+    /// // add b8 $r1 $r0 0x2
+    /// let instruction = faucon_asm::read_instruction(&mut &[0x10u8, 0x01, 0x02][..]).unwrap();
+    /// assert_eq!(instruction.flags(), &[Flag::C, Flag::O, Flag::S, Flag::Z]);
+    /// ```
+    pub fn flags(&self) -> &'static [Flag] {
+        self.meta.flags
+    }
+
+    /// Gets how the hardware treats the instruction.
+    ///
+    /// ```
+    /// use faucon_asm::DecodedAs;
+    ///
+    /// // mov $r1 $sp
+    /// let instruction = faucon_asm::read_instruction(&mut &[0xFEu8, 0x41, 0x01][..]).unwrap();
+    /// assert_eq!(instruction.decoded_as(), DecodedAs::Defined);
+    ///
+    /// // mov $r1 $unk
+    /// let instruction = faucon_asm::read_instruction(&mut &[0xFEu8, 0xD1, 0x01][..]).unwrap();
+    /// assert_eq!(instruction.decoded_as(), DecodedAs::Unpredictable);
+    /// ```
+    pub fn decoded_as(&self) -> DecodedAs {
+        if self.operands().iter().any(Operand::is_reserved) {
+            DecodedAs::Unpredictable
+        } else {
+            DecodedAs::Defined
+        }
+    }
+
     /// Gets the length of an instruction by counting its bytes.
     pub fn len(&self) -> usize {
-        self.bytes.len()
+        self.raw_bytes().len()
+    }
+
+    /// Gets the raw bytes that encode the instruction.
+    pub fn raw_bytes(&self) -> &[u8] {
+        self.bytes.as_slice()
     }
 
     /// Constructs the opcode of the instruction.
@@ -198,8 +372,8 @@ impl Instruction {
     /// masked out.
     pub fn opcode(&self) -> u8 {
         match self.operand_size {
-            OperandSize::Unsized => self.bytes[0],
-            _ => self.bytes[0] & !0xC0,
+            OperandSize::Unsized => self.raw_bytes()[0],
+            _ => self.raw_bytes()[0] & !0xC0,
         }
     }
 
@@ -213,9 +387,13 @@ impl Instruction {
 
     /// A vector of instruction [`Operand`]s.
     ///
+    /// As Falcon instructions take no more than three operands, they are stored
+    /// inline in a [`SmallVec`] without allocating heap memory.
+    ///
     /// [`Operand`]: ./operands/enum.Operand.html
-    pub fn operands(&self) -> Vec<Operand> {
-        let mut operands = Vec::new();
+    /// [`SmallVec`]: https://docs.rs/smallvec/1/smallvec/struct.SmallVec.html
+    pub fn operands(&self) -> SmallVec<[Operand; 3]> {
+        let mut operands = SmallVec::new();
 
         for arg in self.meta.operands.iter() {
             // If the argument is a dummy placeholder, purposefully ignore it.
@@ -224,7 +402,7 @@ impl Instruction {
             }
 
             // Extract the real value of the operand from the instruction bytes.
-            operands.push(Operand::read(arg, &self.bytes));
+            operands.push(Operand::read(arg, self.raw_bytes(), self.pc));
         }
 
         operands
@@ -233,6 +411,28 @@ impl Instruction {
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The alternate form prints the pseudo-instructions of the assembler
+        // in place of the instructions they stand for.
+        if f.alternate() {
+            let sp = Register::SP;
+            match (self.kind(), self.operand_size, &self.operands()[..]) {
+                (
+                    InstructionKind::ADD,
+                    OperandSize::Unsized,
+                    [Operand::Register(a), Operand::Register(b), Operand::I32(0)],
+                ) if *a == sp && *b == sp => {
+                    return write!(f, "nop");
+                }
+                (InstructionKind::CLEAR, OperandSize::ThirtyTwoBit, [register]) => {
+                    return write!(f, "clr {}", register);
+                }
+                (InstructionKind::CLEAR, size, [register]) => {
+                    return write!(f, "clr{} {}", size, register);
+                }
+                _ => {}
+            }
+        }
+
         write!(f, "{}{}", self.kind(), self.operand_size)?;
         for operand in self.operands() {
             write!(f, " {}", operand)?;
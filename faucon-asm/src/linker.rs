@@ -0,0 +1,229 @@
+//! Linker for relocatable Falcon objects.
+//!
+//! The linker combines [`Object`]s that were assembled separately into the
+//! final images of all their sections. Sections of the same name are placed
+//! one after another in the order of the objects, starting at the base that
+//! was configured for the section. External symbols of every object are
+//! bound to the global symbols of other objects and all relocations are
+//! applied.
+//!
+//! ```
+//! use faucon_asm::assembler::assemble_object_str;
+//! use faucon_asm::linker::Linker;
+//!
+//! let main = assemble_object_str(".extern function\nlcall function\nret").unwrap();
+//! let function = assemble_object_str(".global function\nfunction: ret").unwrap();
+//!
+//! let mut linker = Linker::new();
+//! linker.bases.insert("code".into(), 0x100);
+//!
+//! let program = linker.link(&[main, function]).unwrap();
+//! let code = program.section("code").unwrap();
+//! assert_eq!(code.origin, 0x100);
+//! assert_eq!(code.data, [0x7E, 0x06, 0x01, 0x00, 0xF8, 0x00, 0xF8, 0x00]);
+//! ```
+//!
+//! [`Object`]: ../object/struct.Object.html
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::assembler::{Program, Section};
+use crate::object::{Object, Target};
+
+/// An error that occurs when linking objects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkError {
+    /// An external symbol is not defined as a global symbol by any object.
+    UndefinedSymbol(String),
+    /// A global symbol is defined by multiple objects.
+    MultipleDefinitions(String),
+    /// A relocated value does not fit into its field, which is located at the
+    /// given address of the section.
+    RelocationOverflow {
+        /// The name of the section that contains the field.
+        section: String,
+        /// The address of the field.
+        address: u32,
+    },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::UndefinedSymbol(name) => write!(f, "undefined symbol `{}`", name),
+            LinkError::MultipleDefinitions(name) => {
+                write!(f, "symbol `{}` is defined multiple times", name)
+            }
+            LinkError::RelocationOverflow { section, address } => write!(
+                f,
+                "relocated value does not fit into its field at {}:{:#x}",
+                section, address
+            ),
+        }
+    }
+}
+
+impl Error for LinkError {}
+
+/// A linker for Falcon objects along with its configuration.
+///
+/// For linking with the default configuration, [`link`] is more convenient.
+///
+/// [`link`]: fn.link.html
+#[derive(Clone, Debug, Default)]
+pub struct Linker {
+    /// The addresses at which sections are placed, by their names. Sections
+    /// without a configured base start at address zero.
+    pub bases: HashMap<String, u32>,
+}
+
+impl Linker {
+    /// Creates a new linker with the default configuration.
+    pub fn new() -> Self {
+        Linker::default()
+    }
+
+    /// Links a sequence of objects into a [`Program`] with the final images of
+    /// all sections.
+    ///
+    /// [`Program`]: ../assembler/struct.Program.html
+    pub fn link(&self, objects: &[Object]) -> Result<Program, LinkError> {
+        self.link_with(objects, &HashMap::new())
+            .map(|(program, _)| program)
+    }
+
+    // Links a sequence of objects like `link`, binding the external symbols
+    // that no object defines to the given addresses. Also returns the global
+    // symbols that were placed, in the order of the objects.
+    pub(crate) fn link_with(
+        &self,
+        objects: &[Object],
+        external: &HashMap<String, u32>,
+    ) -> Result<(Program, Vec<PlacedSymbol>), LinkError> {
+        // Place the sections of all objects, respecting their alignments.
+        let mut sections: Vec<Section> = Vec::new();
+        let mut addresses = Vec::with_capacity(objects.len());
+        for object in objects {
+            let mut placement = Vec::with_capacity(object.sections.len());
+            for section in &object.sections {
+                let index = match sections.iter().position(|s| s.name == section.name) {
+                    Some(index) => index,
+                    None => {
+                        sections.push(Section {
+                            name: section.name.clone(),
+                            origin: self.bases.get(&section.name).copied().unwrap_or(0),
+                            data: Vec::new(),
+                        });
+                        sections.len() - 1
+                    }
+                };
+
+                let output = &mut sections[index];
+                let alignment = section.alignment.max(1);
+                let end = output.origin.wrapping_add(output.data.len() as u32);
+                let padding = (alignment - end % alignment) % alignment;
+                output.data.resize(output.data.len() + padding as usize, 0);
+
+                placement.push((index, end.wrapping_add(padding)));
+                output.data.extend_from_slice(&section.data);
+            }
+
+            addresses.push(placement);
+        }
+
+        // Collect the addresses of all global symbols.
+        let mut symbols = HashMap::new();
+        let mut placed = Vec::new();
+        for (object, placement) in objects.iter().zip(addresses.iter()) {
+            for symbol in &object.symbols {
+                if let (true, Some(section)) = (symbol.global, symbol.section) {
+                    let (index, start) = placement[section];
+                    let address = start.wrapping_add(symbol.offset);
+                    if symbols.insert(symbol.name.as_str(), address).is_some() {
+                        return Err(LinkError::MultipleDefinitions(symbol.name.clone()));
+                    }
+
+                    placed.push(PlacedSymbol {
+                        name: symbol.name.clone(),
+                        section: sections[index].name.clone(),
+                        address,
+                        limit: start.wrapping_add(object.sections[section].data.len() as u32),
+                    });
+                }
+            }
+        }
+
+        // Apply the relocations of all sections.
+        for (object, placement) in objects.iter().zip(addresses.iter()) {
+            for (section, &(index, start)) in object.sections.iter().zip(placement.iter()) {
+                for relocation in &section.relocations {
+                    let target = match relocation.target {
+                        Target::Absolute => 0,
+                        Target::Section(section) => placement[section].1,
+                        Target::Symbol(symbol) => {
+                            let symbol = &object.symbols[symbol];
+                            match symbol.section {
+                                Some(section) => placement[section].1.wrapping_add(symbol.offset),
+                                None => *symbols
+                                    .get(symbol.name.as_str())
+                                    .or_else(|| external.get(&symbol.name))
+                                    .ok_or_else(|| {
+                                        LinkError::UndefinedSymbol(symbol.name.clone())
+                                    })?,
+                            }
+                        }
+                    };
+
+                    let address = start.wrapping_add(relocation.offset);
+                    let mut value = i64::from(target) + relocation.addend;
+                    if relocation.kind.is_pc_relative() {
+                        value -= i64::from(address);
+                    }
+
+                    let output = &mut sections[index];
+                    let position = address.wrapping_sub(output.origin) as usize;
+                    if !relocation.kind.apply(&mut output.data[position..], value) {
+                        return Err(LinkError::RelocationOverflow {
+                            section: output.name.clone(),
+                            address,
+                        });
+                    }
+                }
+            }
+        }
+
+        let program = Program {
+            sections,
+            debug_info: None,
+            secure_regions: Vec::new(),
+            signatures: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        Ok((program, placed))
+    }
+}
+
+// A global symbol along with its final address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PlacedSymbol {
+    pub name: String,
+    // The name of the section that defines the symbol.
+    pub section: String,
+    pub address: u32,
+    // The address behind the section of the object that defines the symbol.
+    pub limit: u32,
+}
+
+/// Links a sequence of objects into a [`Program`], placing all sections at
+/// address zero.
+///
+/// See the [module documentation] for an example.
+///
+/// [`Program`]: ../assembler/struct.Program.html
+/// [module documentation]: index.html
+pub fn link(objects: &[Object]) -> Result<Program, LinkError> {
+    Linker::new().link(objects)
+}
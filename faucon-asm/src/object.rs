@@ -0,0 +1,411 @@
+//! Relocatable object files for separately assembled Falcon code.
+//!
+//! Unlike a flat binary, an [`Object`] does not commit to the final addresses
+//! of its code. It holds the contents of every section along with the symbols
+//! it defines or refers to, and [`Relocation`]s that describe the fields which
+//! must be patched once the sections have been placed in memory.
+//!
+//! Objects are produced by [`assemble_object_str`] and can be serialized into
+//! a compact binary representation through [`Object::write`]:
+//!
+//! ```
+//! use faucon_asm::object::Object;
+//!
+//! let object = faucon_asm::assembler::assemble_object_str(
+//!     ".extern function\nlcall function",
+//! )
+//! .unwrap();
+//! assert_eq!(object.symbols[0].name, "function");
+//! assert_eq!(object.symbols[0].section, None);
+//!
+//! let mut bytes = Vec::new();
+//! object.write(&mut bytes).unwrap();
+//! assert_eq!(Object::read(&mut &bytes[..]).unwrap(), object);
+//! ```
+//!
+//! [`Object`]: struct.Object.html
+//! [`Relocation`]: struct.Relocation.html
+//! [`assemble_object_str`]: ../assembler/fn.assemble_object_str.html
+//! [`Object::write`]: struct.Object.html#method.write
+
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// The magic bytes that every serialized [`Object`] starts with.
+///
+/// [`Object`]: struct.Object.html
+pub const MAGIC: &[u8; 4] = b"FOBJ";
+
+/// The version of the serialized object format.
+pub const VERSION: u32 = 2;
+
+/// A relocatable object that was produced from a single unit of assembly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+    /// The sections in order of their first appearance in the source code.
+    pub sections: Vec<Section>,
+    /// The symbols that are defined or referenced by the object.
+    pub symbols: Vec<Symbol>,
+}
+
+/// The contents of a section in an [`Object`], which starts at offset zero.
+///
+/// [`Object`]: struct.Object.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Section {
+    /// The name of the section.
+    pub name: String,
+    /// The alignment in bytes which the start of the section requires.
+    pub alignment: u32,
+    /// The contents of the section.
+    pub data: Vec<u8>,
+    /// The fields in the contents that must be patched when linking.
+    pub relocations: Vec<Relocation>,
+}
+
+/// A symbol that is either defined by an [`Object`] or referenced by it.
+///
+/// [`Object`]: struct.Object.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the symbol.
+    pub name: String,
+    /// The index of the section that defines the symbol, or `None` if it is
+    /// defined by another object.
+    pub section: Option<usize>,
+    /// The offset of the symbol in its section.
+    pub offset: u32,
+    /// Whether the symbol is visible to other objects. Symbols that are
+    /// defined elsewhere are always global.
+    pub global: bool,
+}
+
+/// A field in the contents of a [`Section`] whose value depends on the final
+/// addresses of code.
+///
+/// The value of the field is computed as `S + A` for absolute relocations and
+/// as `S + A - P` for PC-relative relocations, where `S` is the address of the
+/// [`Target`], `A` is the addend and `P` is the address of the field itself.
+///
+/// [`Section`]: struct.Section.html
+/// [`Target`]: enum.Target.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// The offset of the field in its section.
+    pub offset: u32,
+    /// The encoding of the field.
+    pub kind: RelocationKind,
+    /// The location that the field refers to.
+    pub target: Target,
+    /// A constant that is added to the address of the target.
+    pub addend: i64,
+}
+
+/// The location that a [`Relocation`] refers to.
+///
+/// [`Relocation`]: struct.Relocation.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// No location at all, which evaluates to zero.
+    Absolute,
+    /// The start of a section in the same object, by index.
+    Section(usize),
+    /// A symbol of the object, by index.
+    Symbol(usize),
+}
+
+/// The encodings of fields that can be relocated.
+///
+/// All fields are stored as little-endian integers in the code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RelocationKind {
+    /// An 8-bit zero-extended absolute value.
+    U8,
+    /// A 16-bit zero-extended absolute value.
+    U16,
+    /// A 24-bit zero-extended absolute value, like the targets of `lcall`.
+    U24,
+    /// A 32-bit absolute value.
+    U32,
+    /// An 8-bit sign-extended absolute value.
+    I8,
+    /// A 16-bit sign-extended absolute value.
+    I16,
+    /// A 24-bit sign-extended absolute value.
+    I24,
+    /// A 32-bit absolute value, which may be negative.
+    I32,
+    /// An 8-bit PC-relative branch target.
+    Pc8,
+    /// A 16-bit PC-relative branch target.
+    Pc16,
+}
+
+impl RelocationKind {
+    /// Gets the width of the field in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            RelocationKind::U8 | RelocationKind::I8 | RelocationKind::Pc8 => 1,
+            RelocationKind::U16 | RelocationKind::I16 | RelocationKind::Pc16 => 2,
+            RelocationKind::U24 | RelocationKind::I24 => 3,
+            RelocationKind::U32 | RelocationKind::I32 => 4,
+        }
+    }
+
+    /// Whether the field is sign-extended when the code is executed.
+    pub fn is_signed(self) -> bool {
+        !matches!(
+            self,
+            RelocationKind::U8 | RelocationKind::U16 | RelocationKind::U24 | RelocationKind::U32
+        )
+    }
+
+    /// Whether the value is relative to the address of the field.
+    pub fn is_pc_relative(self) -> bool {
+        matches!(self, RelocationKind::Pc8 | RelocationKind::Pc16)
+    }
+
+    /// Writes a value into the field at the start of `field`.
+    ///
+    /// Returns `false` if the value cannot be represented by the field, in
+    /// which case `field` is left unchanged.
+    ///
+    /// ```
+    /// use faucon_asm::object::RelocationKind;
+    ///
+    /// let mut field = [0; 2];
+    /// assert!(RelocationKind::I16.apply(&mut field, -2));
+    /// assert_eq!(field, [0xFE, 0xFF]);
+    /// assert!(!RelocationKind::Pc8.apply(&mut field, 0x80));
+    /// ```
+    pub fn apply(self, field: &mut [u8], value: i64) -> bool {
+        let bits = self.width() as u32 * 8;
+        let (min, max) = match (self.width(), self.is_signed()) {
+            // Full-width fields wrap around either way.
+            (4, _) => (i64::from(i32::MIN), i64::from(u32::MAX)),
+            (_, true) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            (_, false) => (0, (1 << bits) - 1),
+        };
+        if value < min || value > max || field.len() < self.width() {
+            return false;
+        }
+
+        field[..self.width()].copy_from_slice(&value.to_le_bytes()[..self.width()]);
+        true
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            0 => RelocationKind::U8,
+            1 => RelocationKind::U16,
+            2 => RelocationKind::U24,
+            3 => RelocationKind::U32,
+            4 => RelocationKind::I8,
+            5 => RelocationKind::I16,
+            6 => RelocationKind::I24,
+            7 => RelocationKind::I32,
+            8 => RelocationKind::Pc8,
+            9 => RelocationKind::Pc16,
+            _ => return None,
+        })
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            RelocationKind::U8 => 0,
+            RelocationKind::U16 => 1,
+            RelocationKind::U24 => 2,
+            RelocationKind::U32 => 3,
+            RelocationKind::I8 => 4,
+            RelocationKind::I16 => 5,
+            RelocationKind::I24 => 6,
+            RelocationKind::I32 => 7,
+            RelocationKind::Pc8 => 8,
+            RelocationKind::Pc16 => 9,
+        }
+    }
+}
+
+impl Object {
+    /// Gets the index of the symbol with the given name, if it exists.
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().position(|s| s.name == name)
+    }
+
+    /// Serializes the object into its binary representation.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+
+        writer.write_u32::<LittleEndian>(self.sections.len() as u32)?;
+        for section in &self.sections {
+            write_bytes(writer, section.name.as_bytes())?;
+            writer.write_u32::<LittleEndian>(section.alignment)?;
+            write_bytes(writer, &section.data)?;
+
+            writer.write_u32::<LittleEndian>(section.relocations.len() as u32)?;
+            for relocation in &section.relocations {
+                let (target, index) = match relocation.target {
+                    Target::Absolute => (0, 0),
+                    Target::Section(index) => (1, index),
+                    Target::Symbol(index) => (2, index),
+                };
+
+                writer.write_u32::<LittleEndian>(relocation.offset)?;
+                writer.write_u8(relocation.kind.code())?;
+                writer.write_u8(target)?;
+                writer.write_u32::<LittleEndian>(index as u32)?;
+                writer.write_i64::<LittleEndian>(relocation.addend)?;
+            }
+        }
+
+        writer.write_u32::<LittleEndian>(self.symbols.len() as u32)?;
+        for symbol in &self.symbols {
+            write_bytes(writer, symbol.name.as_bytes())?;
+            match symbol.section {
+                Some(section) => writer.write_u32::<LittleEndian>(section as u32)?,
+                None => writer.write_u32::<LittleEndian>(u32::MAX)?,
+            }
+            writer.write_u32::<LittleEndian>(symbol.offset)?;
+            writer.write_u8(symbol.global as u8)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deserializes an object from its binary representation.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] when the data is not a well-formed
+    /// object.
+    ///
+    /// [`io::ErrorKind::InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a Falcon object file"));
+        }
+        if reader.read_u32::<LittleEndian>()? != VERSION {
+            return Err(invalid_data("unsupported object file version"));
+        }
+
+        let mut object = Object::default();
+
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let name = read_string(reader)?;
+            let alignment = reader.read_u32::<LittleEndian>()?;
+            let data = read_bytes(reader)?;
+
+            let mut relocations = Vec::new();
+            for _ in 0..reader.read_u32::<LittleEndian>()? {
+                let offset = reader.read_u32::<LittleEndian>()?;
+                let kind = RelocationKind::from_code(reader.read_u8()?)
+                    .ok_or_else(|| invalid_data("invalid relocation kind"))?;
+                let target = match (reader.read_u8()?, reader.read_u32::<LittleEndian>()?) {
+                    (0, _) => Target::Absolute,
+                    (1, index) => Target::Section(index as usize),
+                    (2, index) => Target::Symbol(index as usize),
+                    _ => return Err(invalid_data("invalid relocation target")),
+                };
+                let addend = reader.read_i64::<LittleEndian>()?;
+
+                relocations.push(Relocation {
+                    offset,
+                    kind,
+                    target,
+                    addend,
+                });
+            }
+
+            object.sections.push(Section {
+                name,
+                alignment,
+                data,
+                relocations,
+            });
+        }
+
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let name = read_string(reader)?;
+            let section = match reader.read_u32::<LittleEndian>()? {
+                u32::MAX => None,
+                section => Some(section as usize),
+            };
+            let offset = reader.read_u32::<LittleEndian>()?;
+            let global = match reader.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid_data("invalid symbol visibility")),
+            };
+
+            object.symbols.push(Symbol {
+                name,
+                section,
+                offset,
+                global,
+            });
+        }
+
+        object.validate()?;
+        Ok(object)
+    }
+
+    // Checks that all indices and offsets in the object are in bounds.
+    fn validate(&self) -> io::Result<()> {
+        for symbol in &self.symbols {
+            if let Some(section) = symbol.section {
+                if section >= self.sections.len() {
+                    return Err(invalid_data("symbol refers to a missing section"));
+                }
+            }
+        }
+
+        for section in &self.sections {
+            for relocation in &section.relocations {
+                let end = relocation.offset as usize + relocation.kind.width();
+                if end > section.data.len() {
+                    return Err(invalid_data("relocation is out of bounds"));
+                }
+
+                match relocation.target {
+                    Target::Section(index) if index >= self.sections.len() => {
+                        return Err(invalid_data("relocation refers to a missing section"));
+                    }
+                    Target::Symbol(index) if index >= self.symbols.len() => {
+                        return Err(invalid_data("relocation refers to a missing symbol"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let length = reader.read_u32::<LittleEndian>()? as usize;
+
+    // Don't trust the length for the allocation, the data may be truncated.
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+pub(crate) fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|_| invalid_data("invalid UTF-8 in string"))
+}
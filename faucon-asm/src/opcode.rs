@@ -6,7 +6,7 @@ use std::fmt;
 ///
 /// The size is determined by the highest two bits of the first
 /// instruction byte.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum OperandSize {
     /// The instruction operates on operands of 8 bits in size.
     EightBit,
@@ -40,6 +40,88 @@ impl OperandSize {
             OperandSize::Unsized => 0b11,
         }
     }
+
+    /// Gets the operand size that denotes operands of the given number of
+    /// bits, if there is any.
+    ///
+    /// ```
+    /// use faucon_asm::OperandSize;
+    ///
+    /// assert_eq!(OperandSize::from_bits(16), Some(OperandSize::SixteenBit));
+    /// assert_eq!(OperandSize::from_bits(24), None);
+    /// ```
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(OperandSize::EightBit),
+            16 => Some(OperandSize::SixteenBit),
+            32 => Some(OperandSize::ThirtyTwoBit),
+            _ => None,
+        }
+    }
+
+    /// Gets the operand size that denotes operands of the given number of
+    /// bytes, if there is any.
+    pub fn from_bytes(bytes: usize) -> Option<Self> {
+        OperandSize::from_bits(bytes as u32 * 8)
+    }
+
+    /// Gets the number of bits in an operand.
+    ///
+    /// Unsized instructions operate on the full 32 bits.
+    pub fn bits(&self) -> u32 {
+        match self {
+            OperandSize::EightBit => 8,
+            OperandSize::SixteenBit => 16,
+            OperandSize::ThirtyTwoBit | OperandSize::Unsized => 32,
+        }
+    }
+
+    /// Gets the number of bytes in an operand.
+    pub fn bytes(&self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Gets a mask of all the bits in an operand.
+    ///
+    /// ```
+    /// use faucon_asm::OperandSize;
+    ///
+    /// assert_eq!(OperandSize::SixteenBit.mask(), 0xFFFF);
+    /// assert_eq!(OperandSize::Unsized.mask(), 0xFFFF_FFFF);
+    /// ```
+    pub fn mask(&self) -> u32 {
+        u32::MAX >> (32 - self.bits())
+    }
+
+    /// Checks whether the sign bit of an operand is set in `value`.
+    pub fn is_sign(&self, value: u32) -> bool {
+        value >> (self.bits() - 1) & 1 != 0
+    }
+
+    /// Checks whether `value` can be represented in an operand, either as a
+    /// signed or as an unsigned integer.
+    pub fn fits(&self, value: i64) -> bool {
+        value >= -(1 << (self.bits() - 1)) && value < 1 << self.bits()
+    }
+
+    /// Converts `value` into the bits of an operand, unless it doesn't
+    /// [`fit`] into one.
+    ///
+    /// ```
+    /// use faucon_asm::OperandSize;
+    ///
+    /// assert_eq!(OperandSize::EightBit.checked_convert(-1), Some(0xFF));
+    /// assert_eq!(OperandSize::EightBit.checked_convert(0x100), None);
+    /// ```
+    ///
+    /// [`fit`]: #method.fits
+    pub fn checked_convert(&self, value: i64) -> Option<u32> {
+        if self.fits(value) {
+            Some(value as u32 & self.mask())
+        } else {
+            None
+        }
+    }
 }
 
 impl From<u8> for OperandSize {
@@ -80,7 +162,7 @@ pub const fn get_opcode_form(opcode: u8) -> (u8, u8) {
 ///
 /// In Falcon assembly, opcodes generally span a variety of instructions, many
 /// cases require an additional subopcode to identify instructions uniquely.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SubopcodeLocation {
     /// The subopcode is encoded in the high 2 bits of byte 0.
     OH,
@@ -124,6 +206,18 @@ impl SubopcodeLocation {
             SubopcodeLocation::O5 => insn[4] & 0xF,
         }
     }
+
+    /// Encodes the subopcode value into the instruction bytes.
+    pub fn write(&self, insn: &mut [u8], subopcode: u8) {
+        match self {
+            SubopcodeLocation::OH => insn[0] = insn[0] & 0x3F | subopcode << 6,
+            SubopcodeLocation::O1 => insn[0] = insn[0] & 0xF0 | subopcode & 0xF,
+            SubopcodeLocation::O2 => insn[1] = insn[1] & 0xF0 | subopcode & 0xF,
+            SubopcodeLocation::OL => insn[1] = insn[1] & 0xC0 | subopcode & 0x3F,
+            SubopcodeLocation::O3 => insn[2] = insn[2] & 0xF0 | subopcode & 0xF,
+            SubopcodeLocation::O5 => insn[4] = insn[4] & 0xF0 | subopcode & 0xF,
+        }
+    }
 }
 
 /// Parses the [`SubopcodeLocation`] for the given opcode chunks.
@@ -151,6 +245,7 @@ pub fn get_subopcode_location(size: u8, a: u8, b: u8) -> Option<SubopcodeLocatio
         (0x3, 0x3, 0x6..=0x7) => Some(SubopcodeLocation::O1),
         (0x3, 0x3, 0x8..=0x9) => Some(SubopcodeLocation::O2),
         (0x3, 0x3, 0xA) => Some(SubopcodeLocation::O3),
+        (0x3, 0x3, 0xB) => Some(SubopcodeLocation::O2),
         (0x3, 0x3, 0xC) => Some(SubopcodeLocation::O2),
         (0x3, 0x3, 0xD..=0xF) => Some(SubopcodeLocation::O3),
 
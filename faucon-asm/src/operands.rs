@@ -1,16 +1,136 @@
 //! Representations of Falcon instruction operands.
 
+use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
 
 use crate::arguments::{Argument, MemoryAccess as ArgMemoryAccess};
 
 /// A Falcon CPU register.
 ///
 /// It is described by a tuple which holds the kind of register and its index
-/// which is required for addressing.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// which is required for addressing. The special-purpose registers with known
+/// names are available as constants.
+///
+/// Registers are written and parsed in the assembly syntax:
+///
+/// ```
+/// use faucon_asm::{Register, RegisterKind};
+///
+/// assert_eq!("$r7".parse(), Ok(Register(RegisterKind::Gpr, 7)));
+/// assert_eq!("$sp".parse(), Ok(Register::SP));
+/// assert_eq!(Register::XDBASE.to_string(), "$xdbase");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Register(pub RegisterKind, pub usize);
 
+impl Register {
+    /// The special-purpose register that holds the address of interrupt
+    /// vector 0.
+    pub const IV0: Register = Register(RegisterKind::Spr, 0);
+    /// The special-purpose register that holds the address of interrupt
+    /// vector 1.
+    pub const IV1: Register = Register(RegisterKind::Spr, 1);
+    /// The special-purpose register that holds the address of interrupt
+    /// vector 2.
+    pub const IV2: Register = Register(RegisterKind::Spr, 2);
+    /// The special-purpose register that holds the address of the trap vector.
+    pub const TV: Register = Register(RegisterKind::Spr, 3);
+    /// The special-purpose register that holds the stack pointer.
+    pub const SP: Register = Register(RegisterKind::Spr, 4);
+    /// The special-purpose register that holds the program counter.
+    pub const PC: Register = Register(RegisterKind::Spr, 5);
+    /// The special-purpose register that holds the external base address for
+    /// IMEM transfers.
+    pub const XCBASE: Register = Register(RegisterKind::Spr, 6);
+    /// The special-purpose register that holds the external base address for
+    /// DMEM transfers.
+    pub const XDBASE: Register = Register(RegisterKind::Spr, 7);
+    /// The special-purpose register that holds the CPU flag bits.
+    pub const FLAGS: Register = Register(RegisterKind::Spr, 8);
+    /// The special-purpose register that holds the configuration of the SCP
+    /// DMA functionality, also known as the crypto control register `$ccr`.
+    pub const CX: Register = Register(RegisterKind::Spr, 9);
+    /// The special-purpose register that holds the configuration of the SCP
+    /// authentication process.
+    pub const CAUTH: Register = Register(RegisterKind::Spr, 10);
+    /// The special-purpose register that holds the configuration of the
+    /// CTXDMA ports.
+    pub const XTARGETS: Register = Register(RegisterKind::Spr, 11);
+    /// The special-purpose register that holds details on triggered traps.
+    pub const TSTATUS: Register = Register(RegisterKind::Spr, 12);
+
+    /// The number of registers of each kind.
+    pub const COUNT: usize = 0x10;
+
+    /// Gets an iterator over all special-purpose registers that have names.
+    pub fn special() -> impl Iterator<Item = Register> {
+        (0..Register::COUNT)
+            .filter(|&i| get_spr_name(i).is_some())
+            .map(|i| Register(RegisterKind::Spr, i))
+    }
+
+    /// Looks up a register by its name in the assembly syntax, without the
+    /// leading `$`.
+    ///
+    /// `$flags` may also be referred to by its historical name `$csw` and
+    /// `$cx` by its name `$ccr`.
+    ///
+    /// ```
+    /// use faucon_asm::Register;
+    ///
+    /// assert_eq!(Register::from_name("csw"), Some(Register::FLAGS));
+    /// assert_eq!(Register::from_name("ccr"), Some(Register::CX));
+    /// ```
+    pub fn from_name(name: &str) -> Option<Register> {
+        if let Some(index) = name.strip_prefix('r') {
+            if let Ok(index) = index.parse::<usize>() {
+                return if index < Register::COUNT {
+                    Some(Register(RegisterKind::Gpr, index))
+                } else {
+                    None
+                };
+            }
+        }
+
+        match name {
+            "csw" => return Some(Register::FLAGS),
+            "ccr" => return Some(Register::CX),
+            _ => {}
+        }
+
+        Register::special().find(|reg| get_spr_name(reg.1) == Some(name))
+    }
+
+    /// Gets a unique index of the register among all registers, where
+    /// general-purpose registers come first.
+    ///
+    /// ```
+    /// use faucon_asm::{Register, RegisterKind};
+    ///
+    /// assert_eq!(Register(RegisterKind::Gpr, 3).index(), 3);
+    /// assert_eq!(Register::SP.index(), 0x14);
+    /// assert_eq!(Register::from_index(0x14), Some(Register::SP));
+    /// ```
+    pub fn index(&self) -> usize {
+        match self.0 {
+            RegisterKind::Gpr => self.1,
+            RegisterKind::Spr => Register::COUNT + self.1,
+        }
+    }
+
+    /// Gets the register with the given [`index`], if there is one.
+    ///
+    /// [`index`]: #method.index
+    pub fn from_index(index: usize) -> Option<Register> {
+        match index {
+            i if i < Register::COUNT => Some(Register(RegisterKind::Gpr, i)),
+            i if i < 2 * Register::COUNT => Some(Register(RegisterKind::Spr, i - Register::COUNT)),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Register {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.0 == RegisterKind::Gpr {
@@ -21,6 +141,31 @@ impl fmt::Display for Register {
     }
 }
 
+impl FromStr for Register {
+    type Err = UnknownRegister;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix('$')
+            .and_then(Register::from_name)
+            .ok_or_else(|| UnknownRegister(s.to_string()))
+    }
+}
+
+/// An error that occurs when parsing a [`Register`] from a string that names
+/// no register.
+///
+/// [`Register`]: struct.Register.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownRegister(pub String);
+
+impl fmt::Display for UnknownRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown register `{}`", self.0)
+    }
+}
+
+impl Error for UnknownRegister {}
+
 /// Gets the dedicated name of a special-purpose register based on the given register
 /// index.
 #[rustfmt::skip]
@@ -85,6 +230,140 @@ pub fn get_flag_name(value: usize) -> Option<&'static str> {
     ][value]
 }
 
+/// A named flag bit in the `$flags` register.
+///
+/// The flags are the operands of instructions like `bset`, and are written by
+/// their names in the assembly syntax:
+///
+/// ```
+/// use faucon_asm::Flag;
+///
+/// assert_eq!("ie0".parse(), Ok(Flag::IE0));
+/// assert_eq!(Flag::IE0.bit(), 0x10);
+/// assert_eq!(Flag::from_bit(0x10), Some(Flag::IE0));
+/// assert_eq!(Flag::IE0.to_string(), "ie0");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Flag {
+    /// General-purpose predicate 0.
+    P0 = 0x00,
+    /// General-purpose predicate 1.
+    P1 = 0x01,
+    /// General-purpose predicate 2.
+    P2 = 0x02,
+    /// General-purpose predicate 3.
+    P3 = 0x03,
+    /// General-purpose predicate 4.
+    P4 = 0x04,
+    /// General-purpose predicate 5.
+    P5 = 0x05,
+    /// General-purpose predicate 6.
+    P6 = 0x06,
+    /// General-purpose predicate 7.
+    P7 = 0x07,
+    /// The ALU carry flag.
+    C = 0x08,
+    /// The ALU signed overflow flag.
+    O = 0x09,
+    /// The ALU sign flag.
+    S = 0x0A,
+    /// The ALU zero flag.
+    Z = 0x0B,
+    /// The enable flag of interrupt 0.
+    IE0 = 0x10,
+    /// The enable flag of interrupt 1.
+    IE1 = 0x11,
+    /// The enable flag of interrupt 2.
+    IE2 = 0x12,
+    /// The saved enable flag of interrupt 0.
+    IS0 = 0x14,
+    /// The saved enable flag of interrupt 1.
+    IS1 = 0x15,
+    /// The saved enable flag of interrupt 2.
+    IS2 = 0x16,
+    /// The flag that is set while a trap handler is active.
+    TA = 0x18,
+}
+
+impl Flag {
+    /// All flags, in the order of their bits.
+    pub const ALL: [Flag; 19] = [
+        Flag::P0,
+        Flag::P1,
+        Flag::P2,
+        Flag::P3,
+        Flag::P4,
+        Flag::P5,
+        Flag::P6,
+        Flag::P7,
+        Flag::C,
+        Flag::O,
+        Flag::S,
+        Flag::Z,
+        Flag::IE0,
+        Flag::IE1,
+        Flag::IE2,
+        Flag::IS0,
+        Flag::IS1,
+        Flag::IS2,
+        Flag::TA,
+    ];
+
+    /// Gets the flag at the given bit index, if it has a name.
+    pub fn from_bit(bit: u8) -> Option<Flag> {
+        Flag::ALL.iter().copied().find(|flag| flag.bit() == bit)
+    }
+
+    /// Gets the index of the bit in the `$flags` register.
+    pub fn bit(self) -> u8 {
+        self as u8
+    }
+
+    /// Gets a mask of the bit in the `$flags` register.
+    pub fn mask(self) -> u32 {
+        1 << self.bit()
+    }
+
+    /// Gets the name of the flag in the assembly syntax.
+    pub fn name(self) -> &'static str {
+        get_flag_name(self.bit() as usize).unwrap()
+    }
+}
+
+impl FromStr for Flag {
+    type Err = UnknownFlag;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Flag::ALL
+            .iter()
+            .copied()
+            .find(|flag| flag.name() == s)
+            .ok_or_else(|| UnknownFlag(s.to_string()))
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// An error that occurs when parsing a [`Flag`] from a string that names no
+/// flag.
+///
+/// [`Flag`]: enum.Flag.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownFlag(pub String);
+
+impl fmt::Display for UnknownFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown flag `{}`", self.0)
+    }
+}
+
+impl Error for UnknownFlag {}
+
 /// The Falcon memory spaces.
 ///
 /// The Falcon utilizes separated memory spaces in SRAM that have special purposes
@@ -112,7 +391,7 @@ impl fmt::Display for MemorySpace {
 }
 
 /// The types of CPU registers that are utilized by the Falcon processor.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum RegisterKind {
     /// A general-purpose CPU register.
     Gpr,
@@ -196,6 +475,86 @@ impl fmt::Display for MemoryAccess {
     }
 }
 
+/// A bitfield that is operated on by the `extr`, `extrs` and `ins`
+/// instructions.
+///
+/// These instructions select the field through their last operand, whose low 5
+/// bits encode the position of the lowest bit of the field and the next 5 bits
+/// encode the size of the field minus one. Bits of a field that extends beyond
+/// bit 31 are read as zeroes and discarded on insertion.
+///
+/// ```
+/// use faucon_asm::Bitfield;
+///
+/// // The smallest field, with an encoded size of 0, holds a single bit.
+/// let bit = Bitfield::from_spec(4);
+/// assert_eq!(bit, Bitfield { position: 4, size: 1 });
+/// assert_eq!(bit.extract(0x10, false), 1);
+/// assert_eq!(bit.extract(0x10, true), 0xFFFF_FFFF);
+/// assert_eq!(bit.insert(0xFF, 0), 0xEF);
+///
+/// // The largest field, with an encoded size of 31, covers the whole word.
+/// let word = Bitfield::from_spec(31 << 5);
+/// assert_eq!(word, Bitfield { position: 0, size: 32 });
+/// assert_eq!(word.spec(), 0x3E0);
+/// assert_eq!(word.extract(0x8000_0001, true), 0x8000_0001);
+/// assert_eq!(word.insert(0x1234_5678, 0xCAFE_BABE), 0xCAFE_BABE);
+///
+/// let top = Bitfield::from_spec(31 << 5 | 28);
+/// assert_eq!(top.extract(0xF000_0000, true), 0xF);
+/// assert_eq!(top.insert(0, 0xFFFF_FFFF), 0xF000_0000);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bitfield {
+    /// The position of the lowest bit of the field.
+    pub position: u8,
+    /// The number of bits in the field, from 1 through 32.
+    pub size: u8,
+}
+
+impl Bitfield {
+    /// Decodes a bitfield from the operand of an instruction, ignoring all but
+    /// the low 10 bits.
+    pub fn from_spec(spec: u32) -> Self {
+        Bitfield {
+            position: (spec & 0x1F) as u8,
+            size: (spec >> 5 & 0x1F) as u8 + 1,
+        }
+    }
+
+    /// Encodes the bitfield into an operand of an instruction.
+    pub fn spec(&self) -> u32 {
+        (self.position as u32 & 0x1F) | ((self.size as u32).wrapping_sub(1) & 0x1F) << 5
+    }
+
+    /// Gets the mask of the bits of the field, relative to its lowest bit.
+    pub fn mask(&self) -> u32 {
+        ((1u64 << self.size) - 1) as u32
+    }
+
+    /// Extracts the field from a value and zero- or sign-extends it, based on
+    /// `signed`.
+    ///
+    /// The sign is taken from the highest bit of the field, which reads as zero
+    /// if it lies beyond bit 31.
+    pub fn extract(&self, value: u32, signed: bool) -> u32 {
+        let mask = self.mask();
+        let field = value >> self.position & mask;
+        if signed && field & (mask ^ mask >> 1) != 0 {
+            field | !mask
+        } else {
+            field
+        }
+    }
+
+    /// Inserts the low bits of `value` into the field of `destination` and
+    /// returns the result.
+    pub fn insert(&self, destination: u32, value: u32) -> u32 {
+        let mask = self.mask();
+        destination & !(mask << self.position) | (value & mask) << self.position
+    }
+}
+
 /// An operand in Falcon assembly that belongs to an [`Instruction`].
 ///
 /// Operands usually denote CPU registers, immediates, and memory addressing for
@@ -239,12 +598,69 @@ pub enum Operand {
 }
 
 impl Operand {
+    /// Gets the register that is denoted by the operand, if it is one.
+    ///
+    /// ```
+    /// use faucon_asm::{read_instruction, Register, RegisterKind};
+    ///
+    /// let insn = read_instruction(&mut &[0xBF, 0x1F][..]).unwrap();
+    /// let operands = insn.operands();
+    ///
+    /// assert_eq!(operands[0].as_register(), Some(Register(RegisterKind::Gpr, 15)));
+    /// assert_eq!(operands[1].as_register(), None);
+    /// assert!(operands[1].as_memory().is_some());
+    /// ```
+    pub fn as_register(&self) -> Option<Register> {
+        self.value()
+    }
+
+    /// Gets the index of the CPU flag that is denoted by the operand, if it is
+    /// one.
+    pub fn as_flag(&self) -> Option<u8> {
+        match self {
+            Operand::Flag(flag) => Some(*flag),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of an immediate of any size, if the operand is one.
+    pub fn as_imm(&self) -> Option<u32> {
+        self.value()
+    }
+
+    /// Gets the memory access that is denoted by the operand, if it is one.
+    pub fn as_memory(&self) -> Option<MemoryAccess> {
+        self.value()
+    }
+
+    /// Checks whether the operand refers to a special-purpose register or a
+    /// flag bit that is reserved, which is the case when it has no name.
+    pub fn is_reserved(&self) -> bool {
+        match self {
+            Operand::Register(Register(RegisterKind::Spr, index)) => get_spr_name(*index).is_none(),
+            Operand::Flag(flag) => get_flag_name(*flag as usize).is_none(),
+            _ => false,
+        }
+    }
+
+    /// Converts the operand into a value of type `T`, if it represents one.
+    ///
+    /// See [`OperandValue`] for the supported types.
+    ///
+    /// [`OperandValue`]: trait.OperandValue.html
+    pub fn value<T: OperandValue>(&self) -> Option<T> {
+        T::from_operand(self)
+    }
+
     /// Reads the value of an [`Argument`] from the instruction bytes and wraps it
     /// into a real [`Operand`].
     ///
+    /// PC-relative arguments are resolved to absolute addresses based on `pc`,
+    /// the program counter of the instruction.
+    ///
     /// [`Argument`]: ../argument/enum.Argument.html
     /// [`Operand`]: enum.Operand.html
-    pub(crate) fn read(arg: &Argument, insn: &[u8]) -> Self {
+    pub(crate) fn read(arg: &Argument, insn: &[u8], pc: u32) -> Self {
         match arg {
             // Already evaluated by the disassembler, unreachable at this point.
             Argument::SizeConverter(_) => unreachable!(),
@@ -258,6 +674,7 @@ impl Operand {
             Argument::I24(imm) => Operand::I24(imm.read(insn) as u32),
             Argument::U32(imm) => Operand::I32(imm.read(insn)),
             Argument::I32(imm) => Operand::I32(imm.read(insn) as u32),
+            Argument::PcRel(imm) => Operand::I32(pc.wrapping_add(imm.read(insn) as u32)),
 
             // Register forms.
             Argument::Register(reg) => {
@@ -304,6 +721,85 @@ impl Operand {
     }
 }
 
+/// A type that the values of certain [`Operand`]s can be converted into.
+///
+/// Immediates convert into all unsigned integer types that can hold them
+/// without truncation, registers into [`Register`], named flag bits into
+/// [`Flag`] and memory accesses into [`MemoryAccess`]:
+///
+/// ```
+/// use faucon_asm::Operand;
+///
+/// let operand = Operand::I16(0x1234);
+///
+/// assert_eq!(operand.value::<u32>(), Some(0x1234));
+/// assert_eq!(operand.value::<u8>(), None);
+/// ```
+///
+/// [`Operand`]: enum.Operand.html
+/// [`Register`]: struct.Register.html
+/// [`Flag`]: enum.Flag.html
+/// [`MemoryAccess`]: enum.MemoryAccess.html
+pub trait OperandValue: Sized {
+    /// Converts an operand into the value it represents, if it is of the
+    /// expected kind.
+    fn from_operand(operand: &Operand) -> Option<Self>;
+}
+
+impl OperandValue for Register {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match operand {
+            Operand::Register(reg) => Some(*reg),
+            _ => None,
+        }
+    }
+}
+
+impl OperandValue for Flag {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        operand.as_flag().and_then(Flag::from_bit)
+    }
+}
+
+impl OperandValue for MemoryAccess {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match operand {
+            Operand::Memory(mem) => Some(*mem),
+            _ => None,
+        }
+    }
+}
+
+impl OperandValue for u8 {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match operand {
+            Operand::I8(imm) => Some(*imm),
+            _ => None,
+        }
+    }
+}
+
+impl OperandValue for u16 {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match operand {
+            Operand::I8(imm) => Some(u16::from(*imm)),
+            Operand::I16(imm) => Some(*imm),
+            _ => None,
+        }
+    }
+}
+
+impl OperandValue for u32 {
+    fn from_operand(operand: &Operand) -> Option<Self> {
+        match operand {
+            Operand::I8(imm) => Some(u32::from(*imm)),
+            Operand::I16(imm) => Some(u32::from(*imm)),
+            Operand::I24(imm) | Operand::I32(imm) => Some(*imm),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Operand {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,220 @@
+//! Round-trip testing of the assembler against the disassembler.
+//!
+//! For every encoding of every instruction in the opcode table, the [`Harness`]
+//! generates instructions with random operands, disassembles them, assembles
+//! the resulting text again and verifies that the new machine code decodes to
+//! the same instruction. Forks that extend the ISA tables, as well as crates
+//! that register encodings at runtime, get the same checks for their additions
+//! without writing any tests:
+//!
+//! ```
+//! use faucon_asm::roundtrip::Harness;
+//! use faucon_asm::InstructionKind;
+//!
+//! let report = Harness::new().run_kind(InstructionKind::ADD);
+//! assert!(report.checked > 0);
+//! assert!(report.is_ok(), "{}", report.failures[0]);
+//! ```
+//!
+//! The operands are drawn from a seeded pseudo-random generator, so that
+//! failures are reproducible. This module is only available with the
+//! `roundtrip` feature enabled.
+//!
+//! [`Harness`]: struct.Harness.html
+
+use std::fmt;
+
+use crate::assembler::assemble_instruction;
+use crate::isa::{instruction_encodings, InstructionKind, InstructionMeta};
+use crate::opcode::{get_opcode_form, get_subopcode_location, OperandSize};
+use crate::{read_instruction_at, DecodedAs, Instruction, MAX_INSTRUCTION_LENGTH};
+
+/// An instruction that failed to round-trip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The kind of the instruction.
+    pub kind: InstructionKind,
+    /// The machine code of the instruction that was generated.
+    pub bytes: Vec<u8>,
+    /// The address at which the instruction was located.
+    pub pc: u32,
+    /// The disassembly of the instruction, which was fed to the assembler.
+    pub source: String,
+    /// A description of what went wrong.
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` ({:02X?} at {:#x}) failed to round-trip: {}",
+            self.source, self.bytes, self.pc, self.reason
+        )
+    }
+}
+
+/// The outcome of a round-trip test run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The number of instructions that were checked.
+    pub checked: usize,
+    /// The instructions that failed to round-trip, in order of generation.
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Checks whether all instructions round-tripped.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A round-trip test of the assembler along with its configuration.
+#[derive(Clone, Debug)]
+pub struct Harness {
+    /// The seed of the pseudo-random generator for operands.
+    pub seed: u64,
+    /// The number of instructions that are generated for every encoding.
+    pub iterations: usize,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Harness {
+            seed: 0x5EED_FA1C_0000_0001,
+            iterations: 64,
+        }
+    }
+}
+
+impl Harness {
+    /// Creates a new harness with the default configuration.
+    pub fn new() -> Self {
+        Harness::default()
+    }
+
+    /// Tests the encodings of all valid instructions.
+    pub fn run(&self) -> Report {
+        self.run_filtered(|_| true)
+    }
+
+    /// Tests the encodings of instructions of the given kind only.
+    pub fn run_kind(&self, kind: InstructionKind) -> Report {
+        self.run_filtered(|k| k == kind)
+    }
+
+    fn run_filtered<F: Fn(InstructionKind) -> bool>(&self, filter: F) -> Report {
+        let mut rng = Rng::new(self.seed);
+        let mut report = Report::default();
+
+        // Some encodings are replicated over multiple slots of the opcode table,
+        // which only differ in bits that are overwritten by operands anyway.
+        let mut seen = Vec::new();
+        for (opcode, subopcode, meta) in instruction_encodings() {
+            if meta.kind.invalid() || !filter(meta.kind) {
+                continue;
+            }
+            let key = (meta as *const InstructionMeta, opcode >> 6);
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+
+            for _ in 0..self.iterations {
+                let (bytes, pc) = generate(&mut rng, opcode, subopcode);
+                match check(meta.kind, &bytes, pc) {
+                    Ok(true) => report.checked += 1,
+                    Ok(false) => {}
+                    Err(failure) => {
+                        report.checked += 1;
+                        report.failures.push(failure);
+                    }
+                }
+            }
+        }
+
+        report
+    }
+}
+
+// Generates the machine code of an instruction with the given opcode and
+// subopcode, whose operand fields are filled with random bits, along with a
+// random address for it.
+fn generate(rng: &mut Rng, opcode: u8, subopcode: u8) -> ([u8; MAX_INSTRUCTION_LENGTH], u32) {
+    let mut bytes = rng.next().to_le_bytes();
+    bytes[0] = opcode;
+
+    let (a, b) = get_opcode_form(opcode);
+    if let Some(location) = get_subopcode_location(OperandSize::from(opcode).value(), a, b) {
+        location.write(&mut bytes, subopcode);
+    }
+
+    (bytes, rng.next() as u32 & 0xFFFF)
+}
+
+// Round-trips a single instruction through the disassembler and the
+// assembler. Returns whether the instruction was checked at all, which is not
+// the case for unpredictable instructions, whose operands have no names in the
+// assembly syntax.
+fn check(kind: InstructionKind, bytes: &[u8], pc: u32) -> Result<bool, Failure> {
+    let (original, length) = read_instruction_at(bytes, 0, pc).map_err(|e| Failure {
+        kind,
+        bytes: bytes.to_vec(),
+        pc,
+        source: String::new(),
+        reason: format!("failed to disassemble: {:?}", e),
+    })?;
+    if original.decoded_as() == DecodedAs::Unpredictable {
+        return Ok(false);
+    }
+
+    let bytes = &bytes[..length];
+    let source = original.to_string();
+    let fail = |reason: String| Failure {
+        kind,
+        bytes: bytes.to_vec(),
+        pc,
+        source: source.clone(),
+        reason,
+    };
+
+    let code = assemble_instruction(&source, pc)
+        .map_err(|e| fail(format!("failed to assemble: {}", e.message)))?;
+    let assembled = read_instruction_at(&code, 0, pc)
+        .map_err(|e| fail(format!("failed to disassemble {:02X?}: {:?}", code, e)))?
+        .0;
+
+    if !equivalent(&original, &assembled) {
+        return Err(fail(format!(
+            "assembled to {:02X?}, which is `{}`",
+            code, assembled
+        )));
+    }
+
+    Ok(true)
+}
+
+// Checks whether two instructions have the same effect, regardless of how
+// they are encoded. Some operands have multiple representations that are
+// written the same, like memory accesses with and without a zero offset.
+fn equivalent(a: &Instruction, b: &Instruction) -> bool {
+    a.kind() == b.kind() && a.operand_size == b.operand_size && a.to_string() == b.to_string()
+}
+
+// A xorshift64* generator, which is good enough for picking operands.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Rng(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
@@ -0,0 +1,85 @@
+//! Tracking of the call stack of the executed code.
+
+use super::Trap;
+
+/// The ways in which a [`Frame`] on the call stack can be entered.
+///
+/// [`Frame`]: struct.Frame.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    /// A subroutine that was entered through a `call` instruction.
+    Call,
+    /// An interrupt handler that was entered for the given line.
+    Interrupt(u8),
+    /// A trap handler that was entered for the given trap.
+    Trap(Trap),
+}
+
+/// A frame on the call stack, which describes a subroutine or handler that
+/// has been entered and did not return yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The way in which the frame was entered.
+    pub kind: FrameKind,
+    /// The address of the code that was entered.
+    pub entry: u32,
+    /// The address at which execution continues after the frame returns.
+    pub return_address: u32,
+    /// The value of `$sp` after the return address was pushed, which is the
+    /// location of the return address in DMEM.
+    pub stack_pointer: u32,
+}
+
+/// A shadow call stack that is maintained alongside the stack in DMEM.
+///
+/// As the return addresses are recorded by the emulator itself, the call
+/// stack remains accurate even when code overwrites its stack in DMEM.
+/// Frames are matched by their stack pointers when returning, so code that
+/// unwinds the stack manually by adjusting `$sp` doesn't confuse it.
+#[derive(Clone, Debug)]
+pub(crate) struct CallStack {
+    /// The frames on the call stack, the outermost one first.
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    /// Creates an empty call stack.
+    pub fn new() -> Self {
+        CallStack { frames: Vec::new() }
+    }
+
+    /// Gets the frames on the call stack, the outermost one first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Records a frame that was just entered.
+    pub fn enter(&mut self, frame: Frame) {
+        // Frames below the new one were abandoned without returning.
+        self.unwind(frame.stack_pointer);
+        self.frames.push(frame);
+    }
+
+    /// Removes the frames that are left by popping a return address off the
+    /// stack at the given stack pointer.
+    pub fn leave(&mut self, stack_pointer: u32) {
+        self.unwind(stack_pointer.wrapping_add(4));
+    }
+
+    /// Removes all frames from the call stack.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // Removes the frames whose return addresses are stored below the given
+    // stack pointer and are thereby no longer part of the stack.
+    fn unwind(&mut self, stack_pointer: u32) {
+        while let Some(frame) = self.frames.last() {
+            if frame.stack_pointer >= stack_pointer {
+                break;
+            }
+
+            self.frames.pop();
+        }
+    }
+}
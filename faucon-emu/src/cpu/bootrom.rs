@@ -0,0 +1,52 @@
+//! Emulation of the boot ROM that authenticates secret code before it is
+//! executed in Heavy Secure mode.
+
+use crate::memory::{PageFlag, PAGE_SIZE};
+
+use super::{Cpu, SecureFault, CAUTH};
+
+/// Authenticates the secret code that is entered at the given address, as
+/// described by `$cauth`, and returns whether it may be executed.
+///
+/// The low 16 bits of `$cauth` hold the index of the first virtual page of the
+/// code, which is also its only entry point, and the next 8 bits hold the
+/// number of pages. All of them must be mapped to complete secret pages and
+/// match the signature in the SCP, otherwise a [`SecureFault`] is raised.
+///
+/// [`SecureFault`]: enum.SecureFault.html
+pub(crate) fn authenticate(cpu: &mut Cpu, address: u32) -> bool {
+    let cauth = cpu.registers[CAUTH];
+    let start = (cauth & 0xFFFF) << 8;
+    let pages = (cauth >> 16 & 0xFF) as usize;
+    if address != start || pages == 0 {
+        cpu.raise_secure_fault(SecureFault::InvalidEntry);
+        return false;
+    }
+
+    // Gather the code of the signed pages through the TLB.
+    let mut code = Vec::with_capacity(pages * PAGE_SIZE);
+    for page in 0..pages {
+        let (physical, entry) = match cpu.memory.tlb.lookup(start + (page * PAGE_SIZE) as u32) {
+            Ok(found) => found,
+            Err(_) => {
+                cpu.raise_secure_fault(SecureFault::InvalidEntry);
+                return false;
+            }
+        };
+        if !entry.get_flag(PageFlag::Secret) || !entry.get_flag(PageFlag::Usable) {
+            cpu.raise_secure_fault(SecureFault::InvalidEntry);
+            return false;
+        }
+
+        let physical = physical as usize * PAGE_SIZE;
+        code.extend_from_slice(&cpu.memory.code[physical..physical + PAGE_SIZE]);
+    }
+
+    match cpu.scp.authenticate(&code) {
+        Ok(()) => true,
+        Err(fault) => {
+            cpu.raise_secure_fault(fault);
+            false
+        }
+    }
+}
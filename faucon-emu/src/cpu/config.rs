@@ -0,0 +1,261 @@
+//! Configuration of the hardware that is emulated.
+
+use std::fmt;
+
+use crate::host::{DMEM_PORTS, IMEM_PORTS};
+use crate::io::Peripheral;
+
+/// The address of the UC_CAPS register in the I/O space, which describes the
+/// sizes of IMEM and DMEM.
+///
+/// Bits 0 through 8 hold the number of IMEM pages, bits 9 through 17 the
+/// number of 0x100 byte blocks of DMEM.
+pub const UC_CAPS: u32 = 0x108;
+
+/// The address of the UC_CAPS2 register in the I/O space, which identifies
+/// the processor.
+///
+/// Bits 0 through 3 hold the revision of the ISA, bits 4 and 5 the security
+/// model, bits 8 through 11 the number of IMEM access ports and bits 12
+/// through 15 the number of DMEM access ports.
+pub const UC_CAPS2: u32 = 0x12C;
+
+/// The address in the I/O space at which the FIFO of engines with a
+/// [`CpuConfig::fifo`] is registered.
+///
+/// [`CpuConfig::fifo`]: struct.CpuConfig.html#structfield.fifo
+pub const FIFO_BASE: u32 = 0x400;
+
+/// The number of entries in the FIFO of engines with a [`CpuConfig::fifo`].
+///
+/// [`CpuConfig::fifo`]: struct.CpuConfig.html#structfield.fifo
+pub const FIFO_ENTRIES: usize = 0x10;
+
+/// The configuration of a Falcon engine, which is passed to [`Cpu::new`].
+///
+/// Engines such as the PMU, SEC or CTXCTL are built around the same processor,
+/// but differ in their memory sizes, the revision of the ISA and the blocks
+/// they are equipped with. Instructions that operate on absent blocks trigger
+/// an invalid opcode trap.
+///
+/// ```
+/// use faucon_emu::cpu::{Cpu, CpuConfig};
+///
+/// let cpu = Cpu::new(CpuConfig {
+///     imem_size: 0x4000,
+///     dmem_size: 0x2000,
+///     crypto: false,
+///     ..CpuConfig::new()
+/// })
+/// .unwrap();
+/// assert_eq!(cpu.imem_size(), 0x4000);
+/// assert_eq!(cpu.dmem_size(), 0x2000);
+/// assert!(cpu.fifo().is_none());
+/// ```
+///
+/// [`Cpu::new`]: struct.Cpu.html#method.new
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuConfig {
+    /// The size of IMEM in bytes, which must be a multiple of the page size
+    /// and hold no more than 0x100 pages.
+    pub imem_size: usize,
+    /// The size of DMEM in bytes, which must be a multiple of 0x100.
+    pub dmem_size: usize,
+    /// The revision of the ISA that is implemented by the processor.
+    pub version: u8,
+    /// Whether the engine is equipped with a Secure Co-Processor.
+    pub crypto: bool,
+    /// Whether the engine is equipped with a DMA engine.
+    pub dma: bool,
+    /// Whether the engine is equipped with a FIFO for methods submitted by the
+    /// host, which is registered at [`FIFO_BASE`] in the I/O space and asserts
+    /// interrupt line 0.
+    ///
+    /// [`FIFO_BASE`]: constant.FIFO_BASE.html
+    pub fifo: bool,
+    /// The address at which the processor starts executing code after it was
+    /// created or reset.
+    ///
+    /// The hardware starts at the address in the BOOTVEC register of the host
+    /// interface, which is cleared on reset, so this is 0 for code that is
+    /// booted the regular way.
+    pub boot_vector: u32,
+    /// The address in DMEM from which the stack grows down, which the stack
+    /// pointer is set to after the processor was created or reset. When unset,
+    /// the stack starts at the top of DMEM.
+    pub stack_top: Option<u32>,
+    /// The maximum size of the stack in bytes, if it is bounded.
+    ///
+    /// Pushes beyond the bottom of a bounded stack and pops beyond its top raise
+    /// a [`DebugStop`] instead of overwriting the data below the stack. The
+    /// words that the instruction pushed before are discarded along with it.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_asm::{Register, RegisterKind};
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, DebugStop, PC, SP};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     stack_size: Some(8),
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// cpu.load_code(&assemble_str("mpush $r3").unwrap());
+    /// for index in 0..4 {
+    ///     cpu.registers[Register(RegisterKind::Gpr, index)] = 0xFF;
+    /// }
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// assert!(matches!(cpu.debug_stop(), Some(DebugStop::StackOverflow(_))));
+    /// assert_eq!((cpu.registers[PC], cpu.registers[SP]), (0, 0x4000));
+    /// assert_eq!(cpu.memory.read_data_word(0x3FFC), 0);
+    /// assert_eq!(cpu.memory.read_data_word(0x3FF8), 0);
+    /// ```
+    ///
+    /// [`DebugStop`]: enum.DebugStop.html
+    pub stack_size: Option<u32>,
+    /// Whether unaligned accesses to DMEM and the I/O space as well as accesses
+    /// beyond the end of DMEM raise a [`DebugStop`] instead of being carried
+    /// out like by the hardware, which rounds unaligned addresses down and,
+    /// for stores, shifts the value, and wraps around at the end of DMEM.
+    ///
+    /// [`DebugStop`]: enum.DebugStop.html
+    pub strict_access: bool,
+    /// Whether DMEM is shadowed to detect reads of bytes that were never
+    /// written, which are reported as [`UninitializedRead`]s.
+    ///
+    /// [`UninitializedRead`]: struct.UninitializedRead.html
+    pub track_uninitialized: bool,
+    /// Whether secret code is authenticated by the boot ROM when it is
+    /// entered, which verifies the signature of the pages described by
+    /// `$cauth` against the one in [`SIGNATURE_REGISTER`] before the processor
+    /// enters Heavy Secure mode at the first of them.
+    ///
+    /// Without the boot ROM, secret code is entered without verification.
+    ///
+    /// [`SIGNATURE_REGISTER`]: ../scp/constant.SIGNATURE_REGISTER.html
+    pub boot_rom: bool,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            imem_size: 0x8000,
+            dmem_size: 0x4000,
+            version: 5,
+            crypto: true,
+            dma: true,
+            fifo: false,
+            boot_vector: 0,
+            stack_top: None,
+            stack_size: None,
+            strict_access: false,
+            track_uninitialized: false,
+            boot_rom: false,
+        }
+    }
+}
+
+impl CpuConfig {
+    /// Creates the configuration of a Falcon v5 engine with 32KiB of IMEM,
+    /// 16KiB of DMEM, a Secure Co-Processor and a DMA engine.
+    pub fn new() -> Self {
+        CpuConfig::default()
+    }
+
+    /// Computes the value of the [`UC_CAPS`] register.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::CpuConfig;
+    ///
+    /// assert_eq!(CpuConfig::new().uc_caps(), 0x80 | 0x40 << 9);
+    /// ```
+    ///
+    /// [`UC_CAPS`]: constant.UC_CAPS.html
+    pub fn uc_caps(&self) -> u32 {
+        let imem_pages = (self.imem_size >> 8) as u32 & 0x1FF;
+        let dmem_blocks = (self.dmem_size >> 8) as u32 & 0x1FF;
+
+        imem_pages | dmem_blocks << 9
+    }
+
+    /// Computes the value of the [`UC_CAPS2`] register.
+    ///
+    /// The security model is reported as heavy secure for engines with a
+    /// Secure Co-Processor and as none otherwise.
+    ///
+    /// [`UC_CAPS2`]: constant.UC_CAPS2.html
+    pub fn uc_caps2(&self) -> u32 {
+        let security_model = if self.crypto { 2 } else { 0 };
+
+        (self.version & 0xF) as u32
+            | security_model << 4
+            | (IMEM_PORTS as u32 & 0xF) << 8
+            | (DMEM_PORTS as u32 & 0xF) << 12
+    }
+}
+
+/// An error that occurs when a [`CpuConfig`] that is passed to [`Cpu::new`]
+/// describes hardware that cannot exist.
+///
+/// ```
+/// use faucon_emu::cpu::{ConfigError, Cpu, CpuConfig};
+///
+/// let config = CpuConfig {
+///     imem_size: 0x8080,
+///     ..CpuConfig::new()
+/// };
+/// assert_eq!(Cpu::new(config).err(), Some(ConfigError::ImemSize(0x8080)));
+///
+/// let config = CpuConfig {
+///     stack_top: Some(0x3FFE),
+///     ..CpuConfig::new()
+/// };
+/// assert_eq!(Cpu::new(config).err(), Some(ConfigError::StackTop(0x3FFE)));
+/// ```
+///
+/// [`CpuConfig`]: struct.CpuConfig.html
+/// [`Cpu::new`]: struct.Cpu.html#method.new
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The IMEM size is not a multiple of the page size or exceeds 0x100
+    /// pages.
+    ImemSize(usize),
+    /// The DMEM size is not a multiple of 0x100 bytes.
+    DmemSize(usize),
+    /// The top of the stack is not word-aligned.
+    StackTop(u32),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::ImemSize(size) => write!(
+                f,
+                "IMEM of {:#x} bytes does not consist of up to 0x100 whole pages",
+                size
+            ),
+            ConfigError::DmemSize(size) => write!(
+                f,
+                "DMEM of {:#x} bytes is not a multiple of 0x100 bytes",
+                size
+            ),
+            ConfigError::StackTop(address) => {
+                write!(f, "the stack top {:#x} is not word-aligned", address)
+            }
+        }
+    }
+}
+
+/// A read-only identification register, as registered at [`UC_CAPS`] and
+/// [`UC_CAPS2`] in the I/O space of every processor.
+pub(crate) struct Capabilities(pub u32);
+
+impl Peripheral for Capabilities {
+    fn read32(&mut self, _: u32) -> u32 {
+        self.0
+    }
+
+    fn write32(&mut self, _: u32, _: u32) {}
+}
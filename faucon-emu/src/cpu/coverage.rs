@@ -0,0 +1,132 @@
+//! Tracking of the code in IMEM that was executed by the processor.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// The code coverage of a [`Cpu`], which records the instructions that were
+/// executed by their physical addresses in IMEM.
+///
+/// Coverage is tracked once it was started through [`Cpu::start_coverage`].
+/// Adjacent instructions are merged into ranges of covered code, which can be
+/// exported in the drcov format understood by coverage tools such as
+/// Lighthouse, or as JSON.
+///
+/// ```
+/// use faucon_asm::assembler::assemble_str;
+/// use faucon_emu::cpu::{Cpu, CpuConfig};
+///
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let code = assemble_str("mov $r1 0x1\nbra 0x7\nexit\nexit").unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.start_coverage();
+/// cpu.start();
+/// cpu.step();
+/// cpu.step();
+/// cpu.step();
+///
+/// let coverage = cpu.stop_coverage().unwrap();
+/// assert!(coverage.is_covered(0x2));
+/// assert!(!coverage.is_covered(0x5));
+/// assert_eq!(coverage.ranges(), vec![0x0..0x5, 0x7..0x9]);
+///
+/// let mut json = Vec::new();
+/// coverage.write_json(&mut json).unwrap();
+/// assert_eq!(
+///     String::from_utf8(json).unwrap(),
+///     "{\"imem_size\":32768,\"ranges\":[[0,5],[7,9]]}\n"
+/// );
+/// ```
+///
+/// [`Cpu`]: struct.Cpu.html
+/// [`Cpu::start_coverage`]: struct.Cpu.html#method.start_coverage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    /// The size of IMEM in bytes.
+    imem_size: usize,
+    /// The lengths of the executed instructions, by their physical addresses.
+    insns: BTreeMap<u16, usize>,
+}
+
+impl Coverage {
+    /// Creates an empty coverage map for an IMEM of the given size.
+    pub(crate) fn new(imem_size: usize) -> Self {
+        Coverage {
+            imem_size,
+            insns: BTreeMap::new(),
+        }
+    }
+
+    /// Records that an instruction of the given length was executed at the
+    /// given physical address.
+    pub(crate) fn record(&mut self, address: u16, len: usize) {
+        self.insns.insert(address, len);
+    }
+
+    /// Gets the number of distinct instructions that were executed.
+    pub fn instructions(&self) -> usize {
+        self.insns.len()
+    }
+
+    /// Checks whether an executed instruction starts at the given physical
+    /// address.
+    pub fn is_covered(&self, address: u16) -> bool {
+        self.insns.contains_key(&address)
+    }
+
+    /// Gets the ranges of physical addresses that hold executed code, with
+    /// adjacent instructions merged into a single range.
+    pub fn ranges(&self) -> Vec<Range<u16>> {
+        let mut ranges: Vec<Range<u16>> = Vec::new();
+        for (&address, &len) in self.insns.iter() {
+            let end = address.saturating_add(len as u16);
+            match ranges.last_mut() {
+                Some(range) if range.end >= address => range.end = range.end.max(end),
+                _ => ranges.push(address..end),
+            }
+        }
+
+        ranges
+    }
+
+    /// Writes the coverage in version 2 of the drcov format, which describes
+    /// IMEM as a single module with the given name and every range of covered
+    /// code as a basic block.
+    pub fn write_drcov<W: Write>(&self, writer: &mut W, module: &str) -> io::Result<()> {
+        let ranges = self.ranges();
+
+        writeln!(writer, "DRCOV VERSION: 2")?;
+        writeln!(writer, "DRCOV FLAVOR: drcov")?;
+        writeln!(writer, "Module Table: version 2, count 1")?;
+        writeln!(
+            writer,
+            "Columns: id, base, end, entry, checksum, timestamp, path"
+        )?;
+        writeln!(
+            writer,
+            " 0, 0x0, {:#x}, 0x0, 0x0, 0x0, {}",
+            self.imem_size, module
+        )?;
+        writeln!(writer, "BB Table: {} bbs", ranges.len())?;
+        for range in ranges {
+            // Every entry holds the start offset, the size and the module id.
+            writer.write_all(&(range.start as u32).to_le_bytes())?;
+            writer.write_all(&(range.end - range.start).to_le_bytes())?;
+            writer.write_all(&0u16.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the coverage as a JSON object, which holds the `imem_size` and
+    /// the covered `ranges` as pairs of their start and end addresses.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{{\"imem_size\":{},\"ranges\":[", self.imem_size)?;
+        for (i, range) in self.ranges().into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(writer, "{}[{},{}]", separator, range.start, range.end)?;
+        }
+        writeln!(writer, "]}}")
+    }
+}
@@ -0,0 +1,149 @@
+//! Callbacks that observe the execution of code.
+
+use std::ops::Range;
+
+use faucon_asm::Instruction;
+
+use super::{Cpu, FrameKind};
+
+/// A callback that observes an instruction.
+pub type InstructionCallback = Box<dyn FnMut(&Cpu, &Instruction)>;
+
+/// A callback that observes a transfer of control from one address to
+/// another.
+pub type BranchCallback = Box<dyn FnMut(&Cpu, u32, u32)>;
+
+/// A callback that observes the entry into a handler.
+pub type InterruptCallback = Box<dyn FnMut(&Cpu, FrameKind)>;
+
+/// A callback that observes an access to memory, which may replace the
+/// value that is read or written.
+pub type MemoryCallback = Box<dyn FnMut(&Cpu, &mut MemoryAccess)>;
+
+/// The address spaces in which [`Hook::Memory`] observes accesses.
+///
+/// [`Hook::Memory`]: enum.Hook.html#variant.Memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressSpace {
+    /// The code space.
+    IMem,
+    /// The data space, including the stack.
+    DMem,
+    /// The I/O space, where peripherals are mapped.
+    Io,
+}
+
+/// The kinds of accesses to memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    /// Memory is read.
+    Read,
+    /// Memory is written.
+    Write,
+}
+
+/// An access to memory that is performed by the executed code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// The address space that is accessed.
+    pub space: AddressSpace,
+    /// Whether memory is read or written.
+    pub kind: AccessKind,
+    /// The address of the first byte that is accessed.
+    pub address: u32,
+    /// The number of bytes that are accessed.
+    pub size: u32,
+    /// The value that was read or is to be written.
+    ///
+    /// Hooks may replace the value to mock the contents of memory.
+    pub value: u32,
+}
+
+impl MemoryAccess {
+    /// Checks whether any of the accessed bytes is within the given range.
+    pub fn overlaps(&self, range: &Range<u32>) -> bool {
+        self.address < range.end && range.start < self.address.saturating_add(self.size)
+    }
+}
+
+/// A callback that is invoked by the [`Cpu`] when a certain event occurs
+/// during execution.
+///
+/// Hooks observe the state of the processor, but cannot modify it. They are
+/// registered through [`Cpu::add_hook`].
+///
+/// [`Cpu`]: struct.Cpu.html
+/// [`Cpu::add_hook`]: struct.Cpu.html#method.add_hook
+pub enum Hook {
+    /// Invoked with each instruction before it is executed.
+    PreInstruction(InstructionCallback),
+    /// Invoked with each instruction after it was executed.
+    PostInstruction(InstructionCallback),
+    /// Invoked with the address of an instruction and the address it
+    /// transferred control to, whenever an instruction doesn't continue with
+    /// the next one.
+    Branch(BranchCallback),
+    /// Invoked whenever an interrupt or trap handler is entered, after the
+    /// processor jumped into it.
+    Interrupt(InterruptCallback),
+    /// Invoked whenever the executed code accesses any of the bytes in a
+    /// range of addresses in an address space.
+    ///
+    /// Reads are observed after the value was read, whereas writes are
+    /// observed before the value is written.
+    Memory {
+        /// The address space to observe.
+        space: AddressSpace,
+        /// The range of addresses to observe.
+        range: Range<u32>,
+        /// The callback to invoke.
+        callback: MemoryCallback,
+    },
+}
+
+/// A handle to a [`Hook`] that was registered, which can be used to remove it
+/// again.
+///
+/// [`Hook`]: enum.Hook.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
+/// The hooks that are registered with a processor.
+pub(crate) struct Hooks {
+    /// The registered hooks, in the order in which they are invoked.
+    hooks: Vec<(HookId, Hook)>,
+    /// The identifier of the hook that is registered next.
+    next_id: usize,
+}
+
+impl Hooks {
+    /// Creates an empty set of hooks.
+    pub fn new() -> Self {
+        Hooks {
+            hooks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Registers a hook, which is invoked after all the previously registered
+    /// ones.
+    pub fn add(&mut self, hook: Hook) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push((id, hook));
+
+        id
+    }
+
+    /// Removes a hook, if it is still registered, and returns it.
+    pub fn remove(&mut self, id: HookId) -> Option<Hook> {
+        let index = self.hooks.iter().position(|(hook_id, _)| *hook_id == id)?;
+
+        Some(self.hooks.remove(index).1)
+    }
+
+    /// Gets an iterator over the registered hooks.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Hook> {
+        self.hooks.iter_mut().map(|(_, hook)| hook)
+    }
+}
@@ -1,12 +1,17 @@
 //! Arithmetic Falcon instructions.
 
 use enum_primitive::FromPrimitive;
-use faucon_asm::{Instruction, InstructionKind, Operand, OperandSize};
+use faucon_asm::{Bitfield, Instruction, InstructionKind, Operand, OperandSize};
 
 use super::{utils, Cpu, CpuFlag};
 
 fn sign(x: u32, size: OperandSize) -> bool {
-    (x >> (size.value() as u32 - 1) & 1) != 0
+    size.is_sign(x)
+}
+
+fn sign_extend(x: u32, size: OperandSize) -> i32 {
+    let shift = 32 - size.bits();
+    (x << shift) as i32 >> shift
 }
 
 fn carry(a: bool, b: bool, c: bool) -> bool {
@@ -16,8 +21,8 @@ fn carry(a: bool, b: bool, c: bool) -> bool {
     }
 
     // One of a and b is set. In this case, there is carry out if
-    // the result has bit 0 set.
-    if a || b && !c {
+    // the result has the bit clear.
+    if (a || b) && !c {
         return true;
     }
 
@@ -30,68 +35,89 @@ fn overflow(a: bool, b: bool, c: bool) -> bool {
 }
 
 /// Compares two operands and stores ALU flags based on the result.
-pub fn cmp(cpu: &mut Cpu, insn: &Instruction) -> usize {
+///
+/// `cmpu` and `cmps` set the carry flag if the first operand is below the
+/// second one in an unsigned or signed comparison, respectively, and leave
+/// the overflow and sign flags untouched. `cmp` sets all ALU flags the way a
+/// subtraction of the operands does.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let r = |index| Register(RegisterKind::Gpr, index);
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let source = "cmpu b8 $r1 $r2\ncmps b8 $r1 $r2\n\
+///               cmpu b16 $r1 $r2\ncmps b16 $r1 $r2\n\
+///               cmpu b32 $r1 $r2\ncmps b32 $r1 $r2\n\
+///               cmp b8 $r1 $r2\ncmp b8 $r3 $r1";
+/// let code = assemble_str(source).unwrap();
+/// cpu.load_code(&code);
+///
+/// // The first operand is negative at every size, whereas the second one is
+/// // positive.
+/// cpu.registers[r(1)] = 0x8000_8080;
+/// cpu.registers[r(2)] = 0x1;
+/// cpu.registers[r(3)] = 0x180;
+/// cpu.start();
+///
+/// for _ in 0..3 {
+///     cpu.step();
+///     assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+///     cpu.step();
+///     assert!(cpu.registers.get_flag(CpuFlag::CARRY));
+///     assert!(!cpu.registers.get_flag(CpuFlag::ZERO));
+/// }
+///
+/// cpu.step();
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+/// assert!(cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// // Bits beyond the operand size are not compared.
+/// cpu.step();
+/// assert!(cpu.registers.get_flag(CpuFlag::ZERO));
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+/// assert!(!cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// ```
+pub fn cmp(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register and register or immediate).
-    let source1 = utils::get_value(cpu, insn.operand_size, operands[0]);
-    let source2 = utils::get_value(cpu, insn.operand_size, operands[1]);
+    let size = insn.operand_size;
+    let source1 = utils::get_value(cpu, size, operands[0]) & size.mask();
+    let source2 = utils::get_value(cpu, size, operands[1]) & size.mask();
 
     // Subtract the operands and set ALU flags based on the result.
-    let diff = source1.wrapping_sub(source2);
+    let diff = source1.wrapping_sub(source2) & size.mask();
     cpu.registers.set_flag(CpuFlag::ZERO, diff == 0);
     match insn.kind() {
         InstructionKind::CMPS => {
             cpu.registers.set_flag(
                 CpuFlag::CARRY,
-                overflow(
-                    sign(source1, insn.operand_size),
-                    !sign(source2, insn.operand_size),
-                    sign(diff, insn.operand_size),
-                ) ^ sign(diff, insn.operand_size),
+                sign_extend(source1, size) < sign_extend(source2, size),
             );
         }
         InstructionKind::CMPU => {
-            cpu.registers.set_flag(
-                CpuFlag::CARRY,
-                !carry(
-                    sign(source1, insn.operand_size),
-                    !sign(source2, insn.operand_size),
-                    sign(diff, insn.operand_size),
-                ),
-            );
+            cpu.registers.set_flag(CpuFlag::CARRY, source1 < source2);
         }
         InstructionKind::CMP => {
-            cpu.registers.set_flag(
-                CpuFlag::CARRY,
-                !carry(
-                    sign(source1, insn.operand_size),
-                    !sign(source2, insn.operand_size),
-                    sign(diff, insn.operand_size),
-                ),
-            );
+            cpu.registers.set_flag(CpuFlag::CARRY, source1 < source2);
             cpu.registers.set_flag(
                 CpuFlag::OVERFLOW,
-                overflow(
-                    sign(source1, insn.operand_size),
-                    !sign(source2, insn.operand_size),
-                    sign(diff, insn.operand_size),
-                ),
+                overflow(sign(source1, size), !sign(source2, size), sign(diff, size)),
             );
-            cpu.registers
-                .set_flag(CpuFlag::NEGATIVE, sign(diff, insn.operand_size));
+            cpu.registers.set_flag(CpuFlag::NEGATIVE, sign(diff, size));
         }
         _ => unreachable!(),
     };
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Performs an additional or subtraction, based on the instruction, and stores the result.
-pub fn addsub(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn addsub(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -163,12 +189,61 @@ pub fn addsub(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Carries out a bitwise shift and stores the result.
-pub fn shift(cpu: &mut Cpu, insn: &Instruction) -> usize {
+///
+/// The shift count is taken modulo the operand size. The carry flag receives
+/// the last bit that was shifted out and is cleared for a shift count of 0,
+/// while `shlc` and `shrc` shift the previous carry flag in.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let r = |index| Register(RegisterKind::Gpr, index);
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let source = "shl b32 $r1 $r2 0\n\
+///               shr b8 $r3 $r2 8\n\
+///               shlc b16 $r4 $r5 4\n\
+///               shrc b32 $r6 $r2 1\n\
+///               shrc b32 $r7 $r5 4\n\
+///               shlc b32 $r8 $r5 1";
+/// let code = assemble_str(source).unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.registers[r(2)] = 0x8000_0001;
+/// cpu.registers[r(5)] = 0x1234;
+/// cpu.registers.set_flag(CpuFlag::CARRY, true);
+/// cpu.start();
+///
+/// // Shifts by 0 and by the operand size shift nothing out.
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(1)], 0x8000_0001);
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(3)], 0x01);
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+///
+/// // A set carry flag is shifted in.
+/// cpu.registers.set_flag(CpuFlag::CARRY, true);
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(4)], 0x2348);
+/// assert!(cpu.registers.get_flag(CpuFlag::CARRY));
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(6)], 0xC000_0000);
+/// assert!(cpu.registers.get_flag(CpuFlag::CARRY));
+///
+/// // A cleared carry flag is shifted in.
+/// cpu.registers.set_flag(CpuFlag::CARRY, false);
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(7)], 0x123);
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(8)], 0x2468);
+/// assert!(!cpu.registers.get_flag(CpuFlag::CARRY));
+/// ```
+pub fn shift(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -177,29 +252,26 @@ pub fn shift(cpu: &mut Cpu, insn: &Instruction) -> usize {
     let mut source2 = utils::get_value(cpu, insn.operand_size, operands[2]);
 
     // Truncate source2 accordingly, depending on the operand size.
-    match insn.operand_size {
-        OperandSize::EightBit => source2 &= 0x7,
-        OperandSize::SixteenBit => source2 &= 0xF,
-        OperandSize::ThirtyTwoBit => source2 &= 0x1F,
-        _ => unreachable!(),
-    };
+    let bits = insn.operand_size.bits();
+    source2 &= bits - 1;
+
+    // A shift by 0 leaves the value untouched and shifts nothing out.
+    let carry_in = cpu.registers.get_flag(CpuFlag::CARRY) as u32;
+    if source2 == 0 {
+        cpu.registers.set_flag(CpuFlag::CARRY, false);
+    }
 
     // Carry out the operation and store the result.
     let res = match insn.kind() {
         InstructionKind::SHL | InstructionKind::SHLC => {
             let mut result = source1.wrapping_shl(source2);
 
-            if insn.kind() == InstructionKind::SHLC && source2 != 0 {
-                result |= (cpu.registers.get_flag(CpuFlag::CARRY) as u32) << (source2 - 1);
-            }
-
-            if source2 == 0 {
-                cpu.registers.set_flag(CpuFlag::CARRY, false);
-            } else {
-                cpu.registers.set_flag(
-                    CpuFlag::CARRY,
-                    (source1 >> (insn.operand_size.value() as u32 - source2) & 1) != 0,
-                );
+            if source2 != 0 {
+                if insn.kind() == InstructionKind::SHLC {
+                    result |= carry_in << (source2 - 1);
+                }
+                cpu.registers
+                    .set_flag(CpuFlag::CARRY, source1 >> (bits - source2) & 1 != 0);
             }
 
             result
@@ -207,18 +279,14 @@ pub fn shift(cpu: &mut Cpu, insn: &Instruction) -> usize {
         InstructionKind::SHR | InstructionKind::SAR | InstructionKind::SHRC => {
             let mut result = source1.wrapping_shr(source2);
 
-            if insn.kind() == InstructionKind::SHRC && source2 != 0 {
-                result |= (cpu.registers.get_flag(CpuFlag::CARRY) as u32)
-                    << (insn.operand_size.value() as u32 - source2);
-            } else if insn.kind() == InstructionKind::SAR && sign(source1, insn.operand_size) {
-                result |= !0 << (insn.operand_size.value() as u32 - source2);
-            }
-
-            if source2 == 0 {
-                cpu.registers.set_flag(CpuFlag::CARRY, false);
-            } else {
+            if source2 != 0 {
+                if insn.kind() == InstructionKind::SHRC {
+                    result |= carry_in << (bits - source2);
+                } else if insn.kind() == InstructionKind::SAR && sign(source1, insn.operand_size) {
+                    result |= !0 << (bits - source2);
+                }
                 cpu.registers
-                    .set_flag(CpuFlag::CARRY, ((source2 - 1) & 1) != 0);
+                    .set_flag(CpuFlag::CARRY, source1 >> (source2 - 1) & 1 != 0);
             }
 
             result
@@ -240,51 +308,120 @@ pub fn shift(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
-/// Performs a unary binary operation.
-pub fn unary(cpu: &mut Cpu, insn: &Instruction) -> usize {
+/// Performs a unary operation.
+///
+/// Only the low bits of the destination register that are covered by the
+/// operand size are written. The overflow flag is set by `neg` when negating
+/// the most negative value of the size and cleared by `not`, `movf` and
+/// `hswap`, whereas the carry flag is left untouched.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let r = |index| Register(RegisterKind::Gpr, index);
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let source = "not b8 $r1 $r2\nneg b8 $r3 $r2\nneg b16 $r4 $r5\nhswap b16 $r6 $r5";
+/// let code = assemble_str(source).unwrap();
+/// cpu.load_code(&code);
+///
+/// for &(register, value) in &[(1, 0x1234_5678), (2, 0xAA80), (3, 0xFFFF_FF00), (5, 0x8000)] {
+///     cpu.registers[r(register)] = value;
+/// }
+/// cpu.registers[r(4)] = 0x1234_0000;
+/// cpu.registers[r(6)] = 0x1234_0000;
+/// cpu.start();
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(1)], 0x1234_567F);
+/// assert!(!cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(3)], 0xFFFF_FF80);
+/// assert!(cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// assert!(cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(4)], 0x1234_8000);
+/// assert!(cpu.registers.get_flag(CpuFlag::OVERFLOW));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(6)], 0x1234_0080);
+/// assert!(!cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+/// assert!(!cpu.registers.get_flag(CpuFlag::ZERO));
+/// ```
+pub fn unary(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
-    
-    // Extract the instruction operands (register and immediate).
+    let size = insn.operand_size;
+
+    // Extract the instruction operands (register and register).
     let destination = operands[0];
-    let source = utils::get_value(cpu, insn.operand_size, operands[1]);
-    
-    // Carry out the operation and store the result.
-    match insn.kind() {
+    let source = utils::get_value(cpu, size, operands[1]);
+
+    // Carry out the operation.
+    let half = size.bits() / 2;
+    let result = match insn.kind() {
+        InstructionKind::NOT => !source,
+        InstructionKind::NEG => source.wrapping_neg(),
+        InstructionKind::MOVF => source,
+        InstructionKind::HSWAP => source >> half | source << half,
+        _ => unreachable!(),
+    } & size.mask();
 
-        InstructionKind::NOT => {
-            cpu.registers[destination] = !source;
-            cpu.registers.set_flag(CpuFlag::OVERFLOW, false);
-        }
-        InstructionKind::NEG => {
-            cpu.registers[destination] = source.wrapping_neg();
-            cpu.registers.set_flag(CpuFlag::OVERFLOW, 
-                                   cpu.registers[destination] == 1 << (insn.operand_size.value() - 1));
-        }
-        InstructionKind::HSWAP => {
-            cpu.registers[destination] = source >> (insn.operand_size.value() / 2) | source << (insn.operand_size.value() / 2);
-            cpu.registers.set_flag(CpuFlag::OVERFLOW, cpu.registers[destination] == 0);
-        }
+    // Store the result and set the ALU flags accordingly.
+    utils::write_value_to_reg(cpu, size, destination, result);
+    let overflow = insn.kind() == InstructionKind::NEG && result == 1 << (size.bits() - 1);
+    cpu.registers.set_flag(CpuFlag::OVERFLOW, overflow);
+    cpu.registers.set_flag(CpuFlag::NEGATIVE, sign(result, size));
+    cpu.registers.set_flag(CpuFlag::ZERO, result == 0);
 
-        _ => unreachable!(),
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
 
-    };
+/// Sets ALU flags based on the value of a register.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let code = assemble_str("setf b8 $r1\nsetf b16 $r1").unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.registers[Register(RegisterKind::Gpr, 1)] = 0x8000;
+/// cpu.start();
+///
+/// cpu.step();
+/// assert!(cpu.registers.get_flag(CpuFlag::ZERO));
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert!(!cpu.registers.get_flag(CpuFlag::ZERO));
+/// assert!(cpu.registers.get_flag(CpuFlag::NEGATIVE));
+/// assert!(!cpu.registers.get_flag(CpuFlag::OVERFLOW));
+/// ```
+pub fn setf(cpu: &mut Cpu, insn: &Instruction) {
+    // Extract the instruction operands (a single register).
+    let source = utils::get_value(cpu, insn.operand_size, insn.operands()[0]);
 
-    // Set the remaining ALU flags.
-    cpu.registers.set_flag(CpuFlag::NEGATIVE, sign(cpu.registers[destination], insn.operand_size));
-    cpu.registers.set_flag(CpuFlag::ZERO, cpu.registers[destination] == 0);
+    // Set the ALU flags accordingly.
+    cpu.registers.set_flag(CpuFlag::OVERFLOW, false);
+    cpu.registers
+        .set_flag(CpuFlag::NEGATIVE, sign(source, insn.operand_size));
+    cpu.registers
+        .set_flag(CpuFlag::ZERO, source & insn.operand_size.mask() == 0);
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Sets the high 16 bits of a register ot a given value.
-pub fn sethi(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn sethi(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register and immediate).
@@ -296,12 +433,10 @@ pub fn sethi(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Clears a given CPU register.
-pub fn clear(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn clear(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operands (a single register).
     let destination = insn.operands()[0];
 
@@ -310,12 +445,10 @@ pub fn clear(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Multiplies two operands and stores the result.
-pub fn mul(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn mul(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -338,12 +471,39 @@ pub fn mul(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Performs a sign-extension of the given operand.
-pub fn sext(cpu: &mut Cpu, insn: &Instruction) -> usize {
+///
+/// The second operand selects the sign bit, which is copied into all bits
+/// above it.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let r = |index| Register(RegisterKind::Gpr, index);
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let code = assemble_str("sext $r1 $r2 31\nsext $r3 $r4 7\nsext $r5 $r4 8").unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.registers[r(2)] = 0x8000_0000;
+/// cpu.registers[r(4)] = 0x1280;
+/// cpu.start();
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(1)], 0x8000_0000);
+/// assert!(cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(3)], 0xFFFF_FF80);
+/// assert!(cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(5)], 0x80);
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+/// ```
+pub fn sext(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -354,7 +514,7 @@ pub fn sext(cpu: &mut Cpu, insn: &Instruction) -> usize {
     // Perform the sign-extension and store the result.
     let bit = source2 & 0x1F;
     if source1 & 1 << bit != 0 {
-        cpu.registers[destination] = source1 & ((1 << bit) - 1) | (-(1 << bit as i32)) as u32;
+        cpu.registers[destination] = source1 & ((1 << bit) - 1) | u32::MAX << bit;
     } else {
         cpu.registers[destination] = source1 & ((1 << bit) - 1);
     }
@@ -369,12 +529,10 @@ pub fn sext(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Performs a bitwise operation on two operands and stores the result.
-pub fn bitwise(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn bitwise(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -402,12 +560,10 @@ pub fn bitwise(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Modifies a bit in a register.
-pub fn xbit(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn xbit(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register, immediate/register/flag).
@@ -431,12 +587,78 @@ pub fn xbit(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
+}
+
+/// Extracts a bitfield from a register and zero- or sign-extends it, based on
+/// the instruction.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, Register, RegisterKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag};
+///
+/// let r = |index| Register(RegisterKind::Gpr, index);
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let source = "extrs $r1 $r2 0x64\nextr $r3 $r2 0x64\nins $r4 $r5 0x64";
+/// let code = assemble_str(source).unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.registers[r(2)] = 0x80;
+/// cpu.registers[r(4)] = 0xFFFF_FFFF;
+/// cpu.registers[r(5)] = 0x3;
+/// cpu.start();
+///
+/// // The field spans 4 bits, starting at bit 4.
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(1)], 0xFFFF_FFF8);
+/// assert!(cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(3)], 0x8);
+/// assert!(!cpu.registers.get_flag(CpuFlag::NEGATIVE));
+///
+/// cpu.step();
+/// assert_eq!(cpu.registers[r(4)], 0xFFFF_FF3F);
+/// ```
+pub fn extr(cpu: &mut Cpu, insn: &Instruction) {
+    let operands = insn.operands();
+
+    // Extract the instruction operands (register, register and register or immediate).
+    let destination = operands[0];
+    let source1 = utils::get_value(cpu, insn.operand_size, operands[1]);
+    let source2 = utils::get_value(cpu, insn.operand_size, operands[2]);
+
+    // Extract the bitfield and sign-extend it, if necessary.
+    let signed = insn.kind() == InstructionKind::EXTRS;
+    let result = Bitfield::from_spec(source2).extract(source1, signed);
+    cpu.registers[destination] = result;
+
+    // Set the ALU flags accordingly.
+    cpu.registers.set_flag(CpuFlag::NEGATIVE, result >> 31 != 0);
+    cpu.registers.set_flag(CpuFlag::ZERO, result == 0);
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
+
+/// Inserts the low bits of a register into a bitfield of another register.
+pub fn ins(cpu: &mut Cpu, insn: &Instruction) {
+    let operands = insn.operands();
+
+    // Extract the instruction operands (register, register and immediate).
+    let destination = operands[0];
+    let source1 = utils::get_value(cpu, insn.operand_size, operands[1]);
+    let source2 = utils::get_value(cpu, insn.operand_size, operands[2]);
 
-    1
+    // Replace the bitfield in the destination register.
+    cpu.registers[destination] =
+        Bitfield::from_spec(source2).insert(cpu.registers[destination], source1);
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
 }
 
 /// Modifies a given bit in a register.
-pub fn bitop(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn bitop(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register and register or immediate).
@@ -445,27 +667,26 @@ pub fn bitop(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Extract the bit and perform the operation.
     let bit = match source {
-        Operand::Register(reg) => cpu.registers[reg] & 0x1FF,
+        Operand::Register(reg) => cpu.registers[reg] & 0x1F,
         Operand::Flag(flag) => flag as u32,
-        Operand::I8(imm) => imm as u32 & 0x1FF,
+        Operand::I8(imm) => imm as u32 & 0x1F,
         _ => unreachable!(),
     };
 
-    match insn.kind() {
-        InstructionKind::BSET => cpu.registers[destination] |= 1 << bit,
-        InstructionKind::BCLR => cpu.registers[destination] &= !(1 << bit),
-        InstructionKind::BTGL => cpu.registers[destination] ^= 1 << bit,
+    let value = match insn.kind() {
+        InstructionKind::BSET => cpu.registers[destination] | 1 << bit,
+        InstructionKind::BCLR => cpu.registers[destination] & !(1 << bit),
+        InstructionKind::BTGL => cpu.registers[destination] ^ 1 << bit,
         _ => unreachable!(),
     };
+    utils::write_value_to_reg(cpu, OperandSize::ThirtyTwoBit, destination, value);
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Performs a division or takes the modulus of two operands.
-pub fn divmod(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn divmod(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register, register and register or immediate).
@@ -489,12 +710,10 @@ pub fn divmod(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    30
 }
 
 /// Sets a specific CPU flag to a given value.
-pub fn setp(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn setp(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register or flag and register).
@@ -504,16 +723,16 @@ pub fn setp(cpu: &mut Cpu, insn: &Instruction) -> usize {
     // Get the bit in question and determine the value to set it to.
     let value = cpu.registers[source2] & 1 != 0;
     let flag = if insn.opcode() == 0xF2 {
-        utils::parse_flag(source1).unwrap()
+        utils::parse_flag(source1)
     } else {
-        CpuFlag::from_u32(cpu.registers[source1] & 0x1F).unwrap()
+        CpuFlag::from_u32(1 << (cpu.registers[source1] & 0x1F))
     };
 
-    // Set the bit accordingly.
-    cpu.registers.set_flag(flag, value);
+    // Set the bit accordingly, unless it isn't architected.
+    if let Some(flag) = flag {
+        cpu.registers.set_flag(flag, value);
+    }
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
@@ -1,28 +1,82 @@
 //! Instructions related to Falcon code branching.
 
-use faucon_asm::Instruction;
+use faucon_asm::{Instruction, InstructionKind};
 
-use super::{utils, Cpu, PC};
+use super::{utils, Cpu, CpuFlag, Frame, FrameKind, PC, SP};
+
+/// Performs a conditional or unconditional branch to a PC-relative target address.
+pub fn bra(cpu: &mut Cpu, insn: &Instruction) {
+    let operands = insn.operands();
+
+    // Evaluate the branch condition based on the CPU flags.
+    let flag = |flag| cpu.registers.get_flag(flag);
+    let condition = match insn.kind() {
+        InstructionKind::BP => flag(utils::parse_flag(operands[0]).unwrap()),
+        InstructionKind::BNP => !flag(utils::parse_flag(operands[0]).unwrap()),
+        InstructionKind::BC => flag(CpuFlag::CARRY),
+        InstructionKind::BNC => !flag(CpuFlag::CARRY),
+        InstructionKind::BO => flag(CpuFlag::OVERFLOW),
+        InstructionKind::BNO => !flag(CpuFlag::OVERFLOW),
+        InstructionKind::BS => flag(CpuFlag::NEGATIVE),
+        InstructionKind::BNS => !flag(CpuFlag::NEGATIVE),
+        InstructionKind::BZ => flag(CpuFlag::ZERO),
+        InstructionKind::BNZ => !flag(CpuFlag::ZERO),
+        InstructionKind::BA => !flag(CpuFlag::CARRY) && !flag(CpuFlag::ZERO),
+        InstructionKind::BNA => flag(CpuFlag::CARRY) || flag(CpuFlag::ZERO),
+        InstructionKind::BG => {
+            !flag(CpuFlag::ZERO) && flag(CpuFlag::OVERFLOW) == flag(CpuFlag::NEGATIVE)
+        }
+        InstructionKind::BLE => {
+            flag(CpuFlag::ZERO) || flag(CpuFlag::OVERFLOW) != flag(CpuFlag::NEGATIVE)
+        }
+        InstructionKind::BL => flag(CpuFlag::OVERFLOW) != flag(CpuFlag::NEGATIVE),
+        InstructionKind::BGE => flag(CpuFlag::OVERFLOW) == flag(CpuFlag::NEGATIVE),
+        InstructionKind::BRA => true,
+        _ => unreachable!(),
+    };
+
+    if condition {
+        // The target operand was resolved against the address the instruction was
+        // decoded at, which is not necessarily the address it is executed at.
+        let target = operands[operands.len() - 1].as_imm().unwrap();
+        let offset = target.wrapping_sub(insn.program_counter());
+        cpu.registers[PC] = cpu.registers[PC].wrapping_add(offset);
+
+        // Signal irregular PC increment to the CPU.
+        cpu.increment_pc = false;
+    } else {
+        // Signal regular PC increment to the CPU.
+        cpu.increment_pc = true;
+    }
+}
 
 /// Performs a (long) subroutine call to an absolute target address.
-pub fn call(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn call(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operands (single register or immediate).
     let target = insn.operands()[0];
 
     // Push return address onto the stack.
-    cpu.stack_push(cpu.registers[PC] + insn.len() as u32);
+    let return_address = cpu.registers[PC] + insn.len() as u32;
+    cpu.stack_push(return_address);
 
     // Branch to the absolute address.
-    cpu.registers[PC] = utils::get_value(cpu, insn.operand_size, target);
+    let entry = utils::get_value(cpu, insn.operand_size, target);
+    cpu.registers[PC] = entry;
+
+    // Record the subroutine in the call stack.
+    cpu.call_stack.enter(Frame {
+        kind: FrameKind::Call,
+        entry,
+        return_address,
+        stack_pointer: cpu.registers[SP],
+    });
 
     // Signal irregular PC increment to the CPU.
     cpu.increment_pc = false;
-
-    4
 }
 
 /// Performs a (long) unconditional branch to an absolute target address.
-pub fn jmp(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn jmp(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operands (single register or immediate).
     let target = insn.operands()[0];
 
@@ -31,17 +85,14 @@ pub fn jmp(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal irregular PC increment to the CPU.
     cpu.increment_pc = false;
-
-    4
 }
 
 /// Returns from a previous (long) call.
-pub fn ret(cpu: &mut Cpu, _: &Instruction) -> usize {
+pub fn ret(cpu: &mut Cpu, _: &Instruction) {
     // Restore the return address from the stack.
+    cpu.call_stack.leave(cpu.registers[SP]);
     cpu.registers[PC] = cpu.stack_pop();
 
     // Signal irregular PC increment to the CPU.
     cpu.increment_pc = false;
-
-    5
 }
@@ -1,49 +1,81 @@
 //! Instructions related to controlling the state of the processor.
 
-use faucon_asm::Instruction;
+use faucon_asm::{get_spr_name, Instruction, Operand, Register, RegisterKind};
 
-use super::{utils, Cpu, ExecutionState};
+use super::{
+    utils, Cpu, ExecutionState, SecureFault, SecurityMode, CAUTH, CX, CX_MASK, FLAGS, FLAGS_MASK,
+    PC, SP, TSTATUS, XTARGETS, XTARGETS_MASK,
+};
 
 /// Halts the microcode execution and triggers the EXIT interrupt.
-pub fn exit(cpu: &mut Cpu, _: &Instruction) -> usize {
+pub fn exit(cpu: &mut Cpu, _: &Instruction) {
     // Modify the execution state of the processor.
     cpu.state = ExecutionState::Stopped;
 
     // TODO: Trigger EXIT interrupt.
-
-    1
 }
 
 /// Halts the microcode execution until an interrupt is received.
-pub fn sleep(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn sleep(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operands (a flag bit).
     let flag = insn.operands()[0];
 
-    // If the flag bit is set, put the processor into sleeping state.
+    // If the flag bit is set, put the processor into sleeping state. The PC stays
+    // at this instruction, so that the flag is checked again after waking up.
     let flag = utils::parse_flag(flag).unwrap();
-    if cpu.registers.get_flag(flag) {
+    let sleep = cpu.registers.get_flag(flag);
+    if sleep {
         cpu.state = ExecutionState::Sleeping;
     }
 
-    // Signal irregular PC increment to the CPU.
-    cpu.increment_pc = false;
-
-    1
+    // Signal regular PC increment to the CPU, unless the processor sleeps.
+    cpu.increment_pc = !sleep;
 }
 
 /// Copies a value into another register.
-pub fn mov(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn mov(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register and register or immediate).
     let destination = operands[0];
     let source = operands[1];
 
-    // Copy the source value to the destination.
-    utils::write_reg(cpu, insn.operand_size, destination, source);
+    // `$cauth` is locked while authenticated code is executed.
+    if destination == Operand::Register(CAUTH) && cpu.security == SecurityMode::HeavySecure {
+        cpu.raise_secure_fault(SecureFault::LockedRegister);
+        return;
+    }
 
-    // Signal regular PC increment to the CPU.
+    // Signal regular PC increment to the CPU, unless `$pc` is overwritten below.
     cpu.increment_pc = true;
 
-    1
+    // Copy the source value to the destination.
+    match destination {
+        Operand::Register(reg) if reg.0 == RegisterKind::Spr => {
+            let value = utils::get_value(cpu, insn.operand_size, source);
+            write_spr(cpu, reg, value);
+        }
+        _ => utils::write_reg(cpu, insn.operand_size, destination, source),
+    }
+}
+
+// Writes a value to a special-purpose register and emulates the side effects.
+fn write_spr(cpu: &mut Cpu, reg: Register, value: u32) {
+    match reg {
+        // Writing the PC is a jump to the given address.
+        PC => {
+            cpu.registers[PC] = value;
+            cpu.increment_pc = false;
+        }
+        // The stack is always word-aligned.
+        SP => cpu.registers[SP] = value & !0x3,
+        FLAGS => cpu.registers[FLAGS] = value & FLAGS_MASK,
+        CX => cpu.registers[CX] = value & CX_MASK,
+        XTARGETS => cpu.registers[XTARGETS] = value & XTARGETS_MASK,
+        // The trap status is only ever written by the processor itself.
+        TSTATUS => {}
+        // Registers without a name are not implemented and read as zero.
+        _ if get_spr_name(reg.1).is_none() => {}
+        _ => cpu.registers[reg] = value,
+    }
 }
@@ -0,0 +1,49 @@
+//! Instructions related to the Secure Co-Processor.
+
+use faucon_asm::Instruction;
+
+use super::{utils, Cpu, SecurityMode, Trap, CX, CX_MASK};
+use crate::scp::Command;
+
+/// Redirects a number of subsequent DMA data transfers to the SCP.
+pub fn cxset(cpu: &mut Cpu, insn: &Instruction) {
+    if !utils::require_block(cpu, cpu.config.crypto) {
+        return;
+    }
+
+    // Extract the instruction operand (an immediate).
+    let count = insn.operands()[0].as_imm().unwrap();
+
+    // The count of transfers to redirect is tracked in `$cx`.
+    cpu.registers[CX] = count & CX_MASK;
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
+
+/// Issues a command to the SCP.
+pub fn ccmd(cpu: &mut Cpu, insn: &Instruction) {
+    if !utils::require_block(cpu, cpu.config.crypto) {
+        return;
+    }
+
+    // Decode the command from the instruction operand (an immediate).
+    let command = match Command::from_instruction(insn) {
+        Some(command) => command,
+        None => {
+            cpu.trigger_trap(Trap::InvalidOpcode);
+            cpu.increment_pc = false;
+            return;
+        }
+    };
+
+    let secure = cpu.security == SecurityMode::HeavySecure;
+    if let Err(fault) = cpu.scp.execute(command, secure) {
+        cpu.raise_secure_fault(fault);
+        cpu.increment_pc = false;
+        return;
+    }
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
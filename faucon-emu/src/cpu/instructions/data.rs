@@ -1,11 +1,11 @@
 //! Instructions related to interfacing with the Falcon data segment.
 
-use faucon_asm::Instruction;
+use faucon_asm::{Instruction, InstructionKind, Register, RegisterKind};
 
-use super::{utils, Cpu};
+use super::{branch, utils, Cpu, SP};
 
 /// Loads a value from data segment to a register.
-pub fn ld(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn ld(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (register and memory access descriptor).
@@ -17,29 +17,23 @@ pub fn ld(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Stores a value from a register to data segment.
-pub fn st(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn st(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (memory access descriptor and register).
     let destination = operands[0];
     let source = operands[1];
 
-    // Write the value in the source register to DMem.
-    utils::write_mem(cpu, insn.operand_size, source, destination);
-
-    // Signal regular PC increment to the CPU.
-    cpu.increment_pc = true;
-
-    1
+    // Write the value in the source register to DMem and signal regular PC
+    // increment to the CPU, unless the store trapped.
+    cpu.increment_pc = utils::write_mem(cpu, insn.operand_size, source, destination);
 }
 
 /// Pushes a given register onto the stack.
-pub fn push(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn push(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operand (a single register).
     let source = insn.operands()[0];
 
@@ -48,12 +42,10 @@ pub fn push(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
 /// Pops a value off the stack and stores the result in a register.
-pub fn pop(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn pop(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operand (a single register).
     let destination = insn.operands()[0];
 
@@ -62,6 +54,45 @@ pub fn pop(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
+}
+
+/// Pushes the registers from `$r0` up to a given register onto the stack.
+pub fn mpush(cpu: &mut Cpu, insn: &Instruction) {
+    // Extract the instruction operand (the last register to push).
+    let last = insn.operands()[0].as_register().unwrap().1;
+
+    // Push the registers in ascending order, so that the last one ends up on top.
+    for index in 0..=last {
+        cpu.stack_push(cpu.registers[Register(RegisterKind::Gpr, index)]);
+    }
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
+
+/// Pops the registers from a given register down to `$r0` off the stack,
+/// optionally freeing stack space beforehand and returning afterwards.
+pub fn mpop(cpu: &mut Cpu, insn: &Instruction) {
+    let operands = insn.operands();
 
-    1
+    // Extract the instruction operands (the last register that was pushed and
+    // an optional immediate).
+    let last = operands[0].as_register().unwrap().1;
+
+    // Free the stack space on top of the pushed registers.
+    if let InstructionKind::MPOPADD | InstructionKind::MPOPADDRET = insn.kind() {
+        let value = utils::get_value(cpu, insn.operand_size, operands[1]);
+        cpu.registers[SP] = cpu.registers[SP].wrapping_add(value);
+    }
+
+    // Pop the registers in the reverse order of a preceding mpush.
+    for index in (0..=last).rev() {
+        cpu.registers[Register(RegisterKind::Gpr, index)] = cpu.stack_pop();
+    }
+
+    match insn.kind() {
+        InstructionKind::MPOPRET | InstructionKind::MPOPADDRET => branch::ret(cpu, insn),
+        // Signal regular PC increment to the CPU.
+        _ => cpu.increment_pc = true,
+    }
 }
@@ -1,13 +1,14 @@
 //! Instructions related to processor interrupts and traps.
 
 use enum_primitive::FromPrimitive;
-use faucon_asm::{Instruction, Operand};
+use faucon_asm::Instruction;
 
-use super::{Cpu, CpuFlag, Trap, PC};
+use super::{Cpu, CpuFlag, Trap, PC, SP};
 
 /// Returns from an interrupt handler.
-pub fn iret(cpu: &mut Cpu, _: &Instruction) -> usize {
+pub fn iret(cpu: &mut Cpu, _: &Instruction) {
     // Restore return address from the stack.
+    cpu.call_stack.leave(cpu.registers[SP]);
     cpu.registers[PC] = cpu.stack_pop();
 
     // Restore the interrupt state.
@@ -18,26 +19,20 @@ pub fn iret(cpu: &mut Cpu, _: &Instruction) -> usize {
     cpu.registers
         .set_flag(CpuFlag::IE2, cpu.registers.get_flag(CpuFlag::IS2));
 
-    // Signal regular PC increment to the CPU.
-    cpu.increment_pc = true;
-
-    1
+    // Signal irregular PC modification to the CPU.
+    cpu.increment_pc = false;
 }
 
 /// Triggers a software trap.
-pub fn trap(cpu: &mut Cpu, insn: &Instruction) -> usize {
+pub fn trap(cpu: &mut Cpu, insn: &Instruction) {
     // Extract the instruction operands (trap value).
     let trap = insn.operands()[0];
 
-    // Trigger the software trap.
-    if let Operand::I8(imm) = trap {
-        cpu.trigger_trap(Trap::from_u8(imm).unwrap());
-    } else {
-        unreachable!();
-    }
+    // Trigger the software trap, which returns to the next instruction.
+    let imm = trap.value::<u8>().unwrap();
+    let return_address = cpu.registers[PC] + insn.len() as u32;
+    cpu.deliver_trap(Trap::from_u8(imm).unwrap(), return_address);
 
     // Signal irregular PC modification to the CPU.
     cpu.increment_pc = false;
-
-    1
 }
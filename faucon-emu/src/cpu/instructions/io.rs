@@ -0,0 +1,90 @@
+//! Instructions related to the I/O space of the processor.
+
+use faucon_asm::Instruction;
+
+use super::{utils, AccessKind, AddressSpace, Cpu};
+
+/// Reads a word from the I/O space.
+pub fn iord(cpu: &mut Cpu, insn: &Instruction) {
+    read(cpu, insn, false);
+}
+
+/// Reads a word from the I/O space and stalls the processor for the wait
+/// states of the accessed address.
+pub fn iords(cpu: &mut Cpu, insn: &Instruction) {
+    read(cpu, insn, true);
+}
+
+/// Writes a word to the I/O space.
+pub fn iowr(cpu: &mut Cpu, insn: &Instruction) {
+    write(cpu, insn, false);
+}
+
+/// Writes a word to the I/O space and stalls the processor until the write
+/// completed after the wait states of the accessed address.
+pub fn iowrs(cpu: &mut Cpu, insn: &Instruction) {
+    write(cpu, insn, true);
+}
+
+// Reads a word from the I/O space into a register, synchronously if requested.
+fn read(cpu: &mut Cpu, insn: &Instruction, sync: bool) {
+    let operands = insn.operands();
+
+    // Extract the instruction operands (register and I/O memory access).
+    let destination = operands[0];
+    let (_, address) = utils::parse_memory_access(cpu, operands[1]).unwrap();
+    if !cpu.check_access(AddressSpace::Io, AccessKind::Read, address, 4, 0) {
+        return;
+    }
+
+    // Read the word from the I/O bus into the destination register. The I/O
+    // space is accessed in words, so the low bits of the address are ignored.
+    let address = address & !3;
+    let value = cpu.io.read32(address);
+    cpu.registers[destination] =
+        cpu.observe_access(AddressSpace::Io, AccessKind::Read, address, 4, value);
+    if sync {
+        let wait_states = cpu.io.wait_states(address);
+        cpu.stall(wait_states);
+    }
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
+
+// Writes a word from a register to the I/O space, synchronously if requested.
+fn write(cpu: &mut Cpu, insn: &Instruction, sync: bool) {
+    let operands = insn.operands();
+
+    // Extract the instruction operands (I/O memory access and register).
+    let (_, address) = utils::parse_memory_access(cpu, operands[0]).unwrap();
+    let source = operands[1];
+    if !cpu.check_access(
+        AddressSpace::Io,
+        AccessKind::Write,
+        address,
+        4,
+        cpu.registers[source],
+    ) {
+        return;
+    }
+
+    // Write the word from the source register to the I/O bus. The I/O space is
+    // accessed in words, so the low bits of the address are ignored.
+    let address = address & !3;
+    let value = cpu.observe_access(
+        AddressSpace::Io,
+        AccessKind::Write,
+        address,
+        4,
+        cpu.registers[source],
+    );
+    cpu.io.write32(address, value);
+    if sync {
+        let wait_states = cpu.io.wait_states(address);
+        cpu.stall(wait_states);
+    }
+
+    // Signal regular PC increment to the CPU.
+    cpu.increment_pc = true;
+}
@@ -5,19 +5,24 @@ use super::*;
 mod alu;
 mod branch;
 mod control;
+mod crypto;
 mod data;
 mod intr;
+mod io;
 mod utils;
 mod vm;
+mod xfer;
 
 /// Processes the given instruction on the microprocessor and returns the amount
 /// of CPU cycles the operation took.
 pub fn process_instruction(cpu: &mut Cpu, insn: &Instruction) -> usize {
     let handler = get_handler(insn);
-    handler(cpu, insn)
+    handler(cpu, insn);
+
+    insn.cycles()
 }
 
-fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) -> usize {
+fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) {
     match insn.kind() {
         InstructionKind::CMPU => alu::cmp,
         InstructionKind::CMPS => alu::cmp,
@@ -34,6 +39,8 @@ fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) -> usi
         InstructionKind::NOT => alu::unary,
         InstructionKind::NEG => alu::unary,
         InstructionKind::HSWAP => alu::unary,
+        InstructionKind::MOVF => alu::unary,
+        InstructionKind::SETF => alu::setf,
         InstructionKind::SETHI => alu::sethi,
         InstructionKind::CLEAR => alu::clear,
         InstructionKind::MULU => alu::mul,
@@ -43,6 +50,9 @@ fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) -> usi
         InstructionKind::OR => alu::bitwise,
         InstructionKind::XOR => alu::bitwise,
         InstructionKind::XBIT => alu::xbit,
+        InstructionKind::EXTRS => alu::extr,
+        InstructionKind::EXTR => alu::extr,
+        InstructionKind::INS => alu::ins,
         InstructionKind::BSET => alu::bitop,
         InstructionKind::BCLR => alu::bitop,
         InstructionKind::BTGL => alu::bitop,
@@ -50,6 +60,24 @@ fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) -> usi
         InstructionKind::MOD => alu::divmod,
         InstructionKind::SETP => alu::setp,
         InstructionKind::MOV => control::mov,
+        InstructionKind::BP => branch::bra,
+        InstructionKind::BC => branch::bra,
+        InstructionKind::BO => branch::bra,
+        InstructionKind::BS => branch::bra,
+        InstructionKind::BZ => branch::bra,
+        InstructionKind::BA => branch::bra,
+        InstructionKind::BNA => branch::bra,
+        InstructionKind::BRA => branch::bra,
+        InstructionKind::BNP => branch::bra,
+        InstructionKind::BNC => branch::bra,
+        InstructionKind::BNO => branch::bra,
+        InstructionKind::BNS => branch::bra,
+        InstructionKind::BNZ => branch::bra,
+        InstructionKind::BG => branch::bra,
+        InstructionKind::BLE => branch::bra,
+        InstructionKind::BL => branch::bra,
+        InstructionKind::BGE => branch::bra,
+        InstructionKind::JMP => branch::jmp,
         InstructionKind::CALL => branch::call,
         InstructionKind::LCALL => branch::call,
         InstructionKind::LJMP => branch::jmp,
@@ -58,13 +86,29 @@ fn get_handler(insn: &Instruction) -> impl FnOnce(&mut Cpu, &Instruction) -> usi
         InstructionKind::ST => data::st,
         InstructionKind::PUSH => data::push,
         InstructionKind::POP => data::pop,
+        InstructionKind::MPUSH => data::mpush,
+        InstructionKind::MPOP => data::mpop,
+        InstructionKind::MPOPRET => data::mpop,
+        InstructionKind::MPOPADD => data::mpop,
+        InstructionKind::MPOPADDRET => data::mpop,
         InstructionKind::EXIT => control::exit,
         InstructionKind::SLEEP => control::sleep,
-        InstructionKind::PTLB => vm::ptlb,
-        InstructionKind::VTLB => vm::vtlb,
-        InstructionKind::ITLB => vm::itlb,
+        InstructionKind::IMBLK => vm::imblk,
+        InstructionKind::IMTAG => vm::imtag,
+        InstructionKind::IMINV => vm::iminv,
         InstructionKind::IRET => intr::iret,
         InstructionKind::TRAP => intr::trap,
+        InstructionKind::XCLD => xfer::xfer,
+        InstructionKind::XDLD => xfer::xfer,
+        InstructionKind::XDST => xfer::xfer,
+        InstructionKind::XCWAIT => xfer::xcwait,
+        InstructionKind::XDWAIT => xfer::xdwait,
+        InstructionKind::IORD => io::iord,
+        InstructionKind::IORDS => io::iords,
+        InstructionKind::IOWR => io::iowr,
+        InstructionKind::IOWRS => io::iowrs,
+        InstructionKind::CXSET => crypto::cxset,
+        InstructionKind::CCMD => crypto::ccmd,
         _ => unimplemented!(),
     }
 }
@@ -1,9 +1,9 @@
 //! Helpers for frequently used instruction parsing routines to reduce boilerplate.
 
 use enum_primitive::FromPrimitive;
-use faucon_asm::{opcode::OperandSize, MemoryAccess, MemorySpace, Operand};
+use faucon_asm::{opcode::OperandSize, Flag, MemoryAccess, MemorySpace, Operand};
 
-use super::{Cpu, CpuFlag};
+use super::{AccessKind, AddressSpace, Cpu, CpuFlag, Trap, FLAGS, FLAGS_MASK};
 
 /// Parses a [`MemoryAccess`] descriptor by composing the memory address in question and
 /// extracting the corresponding [`MemorySpace`].
@@ -13,75 +13,69 @@ use super::{Cpu, CpuFlag};
 /// [`MemoryAccess`]: /faucon-asm/operands/enum.MemoryAccess.html
 /// [`MemorySpace`]: /faucon-asm/operands/enum.MemorySpace.html
 pub fn parse_memory_access(cpu: &Cpu, mem: Operand) -> Option<(MemorySpace, u32)> {
-    if let Operand::Memory(mem) = mem {
-        match mem {
-            MemoryAccess::Reg { space, base } => Some((space, cpu.registers[base])),
-            MemoryAccess::RegReg {
-                space,
-                base,
-                offset,
-                scale,
-            } => Some((
-                space,
-                cpu.registers[base] + cpu.registers[offset] * scale as u32,
-            )),
-            MemoryAccess::RegImm {
-                space,
-                base,
-                offset,
-            } => Some((space, cpu.registers[base] + offset)),
-        }
-    } else {
-        None
+    match mem.as_memory()? {
+        MemoryAccess::Reg { space, base } => Some((space, cpu.registers[base])),
+        MemoryAccess::RegReg {
+            space,
+            base,
+            offset,
+            scale,
+        } => Some((
+            space,
+            cpu.registers[base].wrapping_add(cpu.registers[offset].wrapping_mul(scale as u32)),
+        )),
+        MemoryAccess::RegImm {
+            space,
+            base,
+            offset,
+        } => Some((space, cpu.registers[base].wrapping_add(offset))),
+    }
+}
+
+/// Checks whether the engine is equipped with the block that executes an
+/// instruction, and triggers an invalid opcode trap if it isn't.
+pub fn require_block(cpu: &mut Cpu, present: bool) -> bool {
+    if !present {
+        cpu.trigger_trap(Trap::InvalidOpcode);
+        cpu.increment_pc = false;
     }
+
+    present
 }
 
 /// Parses a CPU flag that is encoded in an operand.
 pub fn parse_flag(flag: Operand) -> Option<CpuFlag> {
-    if let Operand::Flag(imm) = flag {
-        Some(CpuFlag::from_u8(imm).unwrap())
-    } else {
-        None
-    }
+    flag.value::<Flag>()
+        .map(|flag| CpuFlag::from_u32(flag.mask()).unwrap())
 }
 
 /// Reads the value that is represented by an operand.
 pub fn get_value(cpu: &Cpu, size: OperandSize, source: Operand) -> u32 {
     match source {
-        Operand::Register(reg) => match size {
-            OperandSize::EightBit => cpu.registers[reg] & 0xFF,
-            OperandSize::SixteenBit => cpu.registers[reg] & 0xFFFF,
-            OperandSize::ThirtyTwoBit | OperandSize::Unsized => cpu.registers[reg],
-        },
-        Operand::I8(imm) => imm as u32,
-        Operand::I16(imm) => imm as u32,
-        Operand::I24(imm) | Operand::I32(imm) => imm,
+        Operand::Register(reg) => cpu.registers[reg] & size.mask(),
         Operand::Memory(_) => read_mem(cpu, size, source),
-        _ => panic!("The operand doesn't represent an extractable value"),
+        _ => source
+            .as_imm()
+            .expect("The operand doesn't represent an extractable value"),
     }
 }
 
 /// Writes the value of a given source operand to a destination register.
 pub fn write_reg(cpu: &mut Cpu, size: OperandSize, destination: Operand, source: Operand) {
     let value = get_value(cpu, size, source);
-    match size {
-        OperandSize::EightBit => cpu.registers[destination] &= !0xFF | value,
-        OperandSize::SixteenBit => cpu.registers[destination] &= !0xFFFF | value,
-        OperandSize::ThirtyTwoBit | OperandSize::Unsized => {
-            cpu.registers[destination] &= !0xFFFFFFFF | value;
-        }
-    }
+    write_value_to_reg(cpu, size, destination, value);
 }
 
 /// Writes a given value to a destination register.
+///
+/// Bits of the `$flags` register that are not architected are left clear.
 pub fn write_value_to_reg(cpu: &mut Cpu, size: OperandSize, destination: Operand, source: u32) {
-    match size {
-        OperandSize::EightBit => cpu.registers[destination] &= !0xFF | (source & 0xFF),
-        OperandSize::SixteenBit => cpu.registers[destination] &= !0xFFFF | (source & 0xFFFF),
-        OperandSize::ThirtyTwoBit | OperandSize::Unsized => {
-            cpu.registers[destination] &= !0xFFFFFFFF | source
-        }
+    let mut value = cpu.registers[destination] & !size.mask() | source & size.mask();
+    if destination == Operand::Register(FLAGS) {
+        value &= FLAGS_MASK;
     }
+
+    cpu.registers[destination] = value;
 }
 
 /// Reads a value from the given [`MemoryAccess`] descriptor.
@@ -100,27 +94,56 @@ pub fn read_mem(cpu: &Cpu, size: OperandSize, source: Operand) -> u32 {
 }
 
 /// Writes a given operand to a location in memory that is encoded in the destination operand.
-pub fn write_mem(cpu: &mut Cpu, size: OperandSize, source: Operand, destination: Operand) {
+///
+/// Code space cannot be written by the executed code, so stores to it trigger an
+/// invalid opcode trap instead. Returns whether the store was carried out.
+pub fn write_mem(cpu: &mut Cpu, size: OperandSize, source: Operand, destination: Operand) -> bool {
     let (space, address) = parse_memory_access(cpu, destination).unwrap();
     match space {
-        MemorySpace::IMem => panic!("Unsupported IMem write access attempted"),
+        MemorySpace::IMem => {
+            cpu.trigger_trap(Trap::InvalidOpcode);
+            return false;
+        }
         MemorySpace::DMem => write_dmem(cpu, size, address, get_value(cpu, size, source)),
     };
+
+    true
 }
 
 fn read_imem(cpu: &Cpu, address: u32) -> u32 {
-    cpu.memory.read_code_addr(address as u16)
+    let value = if cpu.permissions(AddressSpace::IMem, address).read {
+        cpu.memory.read_code_addr(address as u16)
+    } else {
+        cpu.memory.read_code_unprivileged(address as u16)
+    };
+
+    cpu.observe_access(AddressSpace::IMem, AccessKind::Read, address, 4, value)
 }
 
 fn read_dmem(cpu: &Cpu, size: OperandSize, address: u32) -> u32 {
-    match size {
+    let bytes = size.bytes() as u32;
+    if !cpu.check_access(AddressSpace::DMem, AccessKind::Read, address, bytes, 0) {
+        return 0;
+    }
+    cpu.check_initialized(address & !(bytes - 1), bytes);
+
+    let value = match size {
         OperandSize::EightBit => cpu.memory.read_data_byte(address) as u32,
         OperandSize::SixteenBit => cpu.memory.read_data_halfword(address) as u32,
         OperandSize::ThirtyTwoBit | OperandSize::Unsized => cpu.memory.read_data_word(address),
-    }
+    };
+
+    cpu.observe_access(AddressSpace::DMem, AccessKind::Read, address, bytes, value) & size.mask()
 }
 
 fn write_dmem(cpu: &mut Cpu, size: OperandSize, address: u32, value: u32) {
+    let bytes = size.bytes() as u32;
+    if !cpu.check_access(AddressSpace::DMem, AccessKind::Write, address, bytes, value) {
+        return;
+    }
+
+    let value = cpu.observe_access(AddressSpace::DMem, AccessKind::Write, address, bytes, value);
+
     match size {
         OperandSize::EightBit => cpu.memory.write_data_byte(address, value as u8),
         OperandSize::SixteenBit => cpu.memory.write_data_halfword(address, value as u16),
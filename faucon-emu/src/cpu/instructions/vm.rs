@@ -4,29 +4,24 @@ use faucon_asm::Instruction;
 
 use super::Cpu;
 
-/// Reads the TLB corresponding to a given physical address.
-pub fn ptlb(cpu: &mut Cpu, insn: &Instruction) -> usize {
+/// Reads the status of the TLB entry corresponding to a given physical page.
+pub fn imblk(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (two registers).
     let destination = operands[0];
     let source = operands[1];
 
-    // Look up the TLB.
-    let physical_index = ((cpu.registers[source] & 0xFF) << 8) as u16;
-    let tlb = cpu.memory.tlb.get_physical_entry(physical_index);
-
-    // Build and write the result value.
-    cpu.registers[destination] = (tlb.flags as u32) << 24 | (tlb.virtual_page_number as u32) << 8;
+    // Look up the TLB to get the result value and write it to the destination.
+    let physical_page = (cpu.registers[source] & 0xFF) as u8;
+    cpu.registers[destination] = cpu.memory.tlb.lookup_block_raw(physical_page);
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
-/// Reads the TLB corresponding to a given virtual address.
-pub fn vtlb(cpu: &mut Cpu, insn: &Instruction) -> usize {
+/// Reads the status of the TLB entries corresponding to a given virtual address.
+pub fn imtag(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operands (two registers).
@@ -39,26 +34,22 @@ pub fn vtlb(cpu: &mut Cpu, insn: &Instruction) -> usize {
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
 
-/// Invalidates a TLB entry corresponding to a physical address.
-pub fn itlb(cpu: &mut Cpu, insn: &Instruction) -> usize {
+/// Invalidates a non-secret TLB entry corresponding to a physical page.
+pub fn iminv(cpu: &mut Cpu, insn: &Instruction) {
     let operands = insn.operands();
 
     // Extract the instruction operand (one register).
     let source = operands[0];
 
-    // Look up the TLB.
-    let physical_index = ((cpu.registers[source] & 0xFF) << 8) as u16;
-    let tlb = cpu.memory.tlb.get_physical_entry(physical_index);
-
-    // Clear the entry.
-    tlb.clear();
+    // Clear the entry, which is refused for secret pages. The code of an
+    // invalidated page is about to be replaced, so it is evicted from the cache.
+    let physical_page = (cpu.registers[source] & 0xFF) as u8;
+    if cpu.memory.tlb.invalidate(physical_page) {
+        cpu.memory.insn_cache.invalidate_page(physical_page);
+    }
 
     // Signal regular PC increment to the CPU.
     cpu.increment_pc = true;
-
-    1
 }
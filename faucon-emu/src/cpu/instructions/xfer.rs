@@ -0,0 +1,69 @@
+//! Instructions related to DMA transfers between Falcon and external memory.
+
+use faucon_asm::Instruction;
+
+use super::{utils, Cpu, Trap, CX};
+use crate::dma::{Request, RequestMode};
+
+/// Submits a DMA transfer request to the DMA engine.
+pub fn xfer(cpu: &mut Cpu, insn: &Instruction) {
+    if !utils::require_block(cpu, cpu.config.dma) {
+        return;
+    }
+
+    // Build the request from the instruction operands (two registers).
+    let request = Request::from_instruction(insn, &cpu.registers).unwrap();
+    if !request.is_valid(cpu.imem_size() as u32) {
+        cpu.trigger_trap(Trap::InvalidOpcode);
+        cpu.increment_pc = false;
+        return;
+    }
+    let code_page = if request.mode == RequestMode::CodeLoad {
+        Some((request.local_party(), request.vaddr(), request.secret()))
+    } else {
+        None
+    };
+
+    let crypto_override = request.crypto_override();
+
+    // If the queue is full, the instruction is retried until a request completed.
+    let submitted = cpu.dma_engine.enqueue(request).is_ok();
+
+    // Every data transfer that targets the SCP consumes one of the transfers
+    // that were redirected through `$cx`.
+    if submitted && crypto_override {
+        cpu.registers[CX] -= 1;
+    }
+
+    // The page that receives code is mapped right away, but it is unusable
+    // until the load completes.
+    if let (true, Some((address, vaddress, secret))) = (submitted, code_page) {
+        cpu.memory
+            .tlb
+            .get_physical_entry(address)
+            .map(vaddress, secret);
+    }
+
+    // Signal regular PC increment to the CPU, unless the request must be retried.
+    cpu.increment_pc = submitted;
+}
+
+/// Waits for all DMA code loads to complete.
+pub fn xcwait(cpu: &mut Cpu, _: &Instruction) {
+    if !utils::require_block(cpu, cpu.config.dma) {
+        return;
+    }
+
+    // Signal regular PC increment to the CPU, unless the wait must be repeated.
+    cpu.increment_pc = !cpu.dma_engine.is_pending(RequestMode::CodeLoad);
+}
+
+/// Waits for all DMA data loads and stores to complete.
+pub fn xdwait(cpu: &mut Cpu, _: &Instruction) {
+    if !utils::require_block(cpu, cpu.config.dma) {
+        return;
+    }
+
+    // Signal regular PC increment to the CPU, unless the wait must be repeated.
+    cpu.increment_pc = !cpu.dma_engine.is_data_pending();
+}
@@ -1,15 +1,40 @@
 //! Falcon microprocessor abstractions.
 
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+
 use faucon_asm::{disassembler, Instruction};
 
 use crate::dma;
-use crate::memory::{LookupError, Memory, PageFlag};
+use crate::io::{Fifo, IoBus, FIFO_SIZE};
+use crate::memory::{LookupError, Memory, PageFlag, Permissions, PAGE_SIZE};
+use crate::scp::Scp;
 
+use backtrace::CallStack;
+pub use backtrace::{Frame, FrameKind};
+pub use config::*;
+pub use coverage::Coverage;
+pub use hooks::*;
 use instructions::process_instruction;
 pub use registers::*;
+pub use replay::StepBackError;
+use replay::{Checkpoint, Journal};
+#[cfg(feature = "stats")]
+pub use stats::Statistics;
+pub use trace::*;
 
+mod backtrace;
+mod bootrom;
+mod config;
+mod coverage;
+mod hooks;
 mod instructions;
 mod registers;
+mod replay;
+#[cfg(feature = "stats")]
+mod stats;
+mod trace;
 
 /// Representation of the Falcon processor.
 pub struct Cpu {
@@ -18,7 +43,11 @@ pub struct Cpu {
     /// The Falcon SRAM for code and data.
     pub memory: Memory,
     /// The Falcon DMA engine.
-    dma_engine: dma::Engine,
+    pub dma_engine: dma::Engine,
+    /// The bus that connects peripherals to the Falcon I/O space.
+    pub io: IoBus,
+    /// The Falcon Secure Co-Processor for cryptographic operations.
+    pub scp: Scp,
     /// The current execution state of the processor that controls the way
     /// the CPU executes code.
     state: ExecutionState,
@@ -32,6 +61,48 @@ pub struct Cpu {
     /// whether the PC should be regularly incremented or to indicate that
     /// the instruction itself does that.
     increment_pc: bool,
+    /// The security mode the processor currently executes code in.
+    security: SecurityMode,
+    /// The violation of the authentication model that stopped the processor,
+    /// if any.
+    secure_fault: Option<SecureFault>,
+    /// The number of cycles that passed since the processor was created.
+    cycles: u64,
+    /// The cycles that the current instruction stalls the processor for in
+    /// addition to its own.
+    stall_cycles: u32,
+    /// The number of cycles that the processor was stalled for by accesses to
+    /// peripherals since it was created.
+    stalled_cycles: u64,
+    /// The counts of the executed instructions.
+    #[cfg(feature = "stats")]
+    stats: Statistics,
+    /// The code coverage, while it is tracked.
+    coverage: Option<Coverage>,
+    /// The trace of the executed instructions, while it is recorded.
+    trace: Option<Vec<TraceEntry>>,
+    /// The reference trace that executed instructions are compared against,
+    /// if any.
+    golden_trace: Option<GoldenTrace>,
+    /// The addresses of instructions at which headless runs are interrupted.
+    breakpoints: HashSet<u32>,
+    /// The trap that was delivered during the most recent step, if any.
+    delivered_trap: Option<Trap>,
+    /// The shadow call stack of the executed code.
+    call_stack: CallStack,
+    /// The callbacks that observe the execution of code.
+    hooks: RefCell<Hooks>,
+    /// The journal of executed steps, while they are recorded.
+    journal: Option<Journal>,
+    /// The condition that stopped the most recent step in the debugger, if
+    /// any.
+    debug_stop: Cell<Option<DebugStop>>,
+    /// The reads of uninitialized DMEM that were not yet taken.
+    uninitialized_reads: RefCell<Vec<UninitializedRead>>,
+    /// The configuration of the emulated engine.
+    config: CpuConfig,
+    /// The FIFO of the engine, if it is equipped with one.
+    fifo: Option<Fifo>,
 }
 
 /// The execution state of the Falcon processor which controls its behavior.
@@ -39,6 +110,7 @@ pub struct Cpu {
 /// The execution states influence code execution and how interrupts are
 /// being handled. There are different ways to change the processor state,
 /// including resets, instructions, interrupts, and host interaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExecutionState {
     /// The processor is actively running and executes instructions.
     Running,
@@ -54,6 +126,203 @@ pub enum ExecutionState {
     Sleeping,
 }
 
+/// The security mode of the Falcon processor, which depends on the code page
+/// that is being executed.
+///
+/// Pages that were uploaded as secret can only be executed in Heavy Secure
+/// mode. The processor authenticates the code and enters this mode when a
+/// branch lands at the start of a secret page, and falls back to Non-Secure
+/// mode as soon as code outside of secret pages is executed again. Unless the
+/// boot ROM is emulated through [`CpuConfig::boot_rom`], the emulator doesn't
+/// verify code signatures and authentication always succeeds.
+///
+/// [`CpuConfig::boot_rom`]: struct.CpuConfig.html#structfield.boot_rom
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityMode {
+    /// The processor executes regular, unauthenticated code.
+    NonSecure,
+    /// The processor executes authenticated secret code.
+    ///
+    /// In this mode, the `$cauth` register is locked.
+    HeavySecure,
+}
+
+/// Violations of the Falcon authentication model.
+///
+/// Secure faults cannot be handled by the code that caused them. Instead, the
+/// processor is stopped immediately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureFault {
+    /// Secret code was entered elsewhere than at the start of a page, where
+    /// it would have been authenticated.
+    InvalidEntry,
+    /// The locked `$cauth` register was written in Heavy Secure mode.
+    LockedRegister,
+    /// The SCP failed to verify the signature of the authenticated code.
+    SignatureMismatch,
+}
+
+/// Conditions that stop execution in the debugger rather than being handled
+/// like the hardware would.
+///
+/// Unless noted otherwise, the instruction that raised the condition is not
+/// executed, so that the PC remains at it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugStop {
+    /// An access to DMEM or the I/O space was not aligned to its size while
+    /// [`CpuConfig::strict_access`] was enabled.
+    ///
+    /// [`CpuConfig::strict_access`]: struct.CpuConfig.html#structfield.strict_access
+    UnalignedAccess(MemoryAccess),
+    /// An access to DMEM exceeded its bounds while
+    /// [`CpuConfig::strict_access`] was enabled, which the hardware would wrap
+    /// around to the start of DMEM.
+    ///
+    /// [`CpuConfig::strict_access`]: struct.CpuConfig.html#structfield.strict_access
+    OutOfBoundsAccess(MemoryAccess),
+    /// The executed instruction deviated from the entry at the given index of
+    /// the [`GoldenTrace`].
+    ///
+    /// The instruction was executed, so that its effects can be inspected.
+    ///
+    /// [`GoldenTrace`]: struct.GoldenTrace.html
+    TraceDivergence(usize),
+    /// A push to the stack at the given address exceeded the
+    /// [`CpuConfig::stack_size`].
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::*;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     stack_size: Some(8),
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// let code = assemble_str("push $r1\npush $r1\npush $r1").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// let reason = cpu.run_until(|_| false);
+    /// assert_eq!(reason, ExitReason::DebugStop(DebugStop::StackOverflow(0x3FF4)));
+    /// assert_eq!(cpu.registers[SP], 0x3FF8);
+    /// ```
+    ///
+    /// [`CpuConfig::stack_size`]: struct.CpuConfig.html#structfield.stack_size
+    StackOverflow(u32),
+    /// A pop from the stack at the given address reached beyond the
+    /// [`CpuConfig::stack_top`] of a stack with a [`CpuConfig::stack_size`].
+    ///
+    /// [`CpuConfig::stack_top`]: struct.CpuConfig.html#structfield.stack_top
+    /// [`CpuConfig::stack_size`]: struct.CpuConfig.html#structfield.stack_size
+    StackUnderflow(u32),
+    /// The code at the given virtual address could not be translated through
+    /// the TLB while a trap was already being handled, which would stop the
+    /// processor on the hardware.
+    ///
+    /// Page faults that occur outside of trap handlers are delivered to the
+    /// firmware as [`Trap::VmNoHit`] or [`Trap::VmMultiHit`].
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::*;
+    /// use faucon_emu::memory::LookupError;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("jmp 0x1000").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// // The trap handler isn't mapped either.
+    /// cpu.registers[SP] = 0x100;
+    /// cpu.registers[TV] = 0x2000;
+    /// cpu.start();
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Trap(Trap::VmNoHit));
+    /// assert_eq!(cpu.registers[TSTATUS], 0x1000 | 0xA << 20);
+    ///
+    /// let error = LookupError::NoPageHits;
+    /// let stop = DebugStop::PageFault { address: 0x2000, error };
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::DebugStop(stop));
+    /// ```
+    ///
+    /// [`Trap::VmNoHit`]: enum.Trap.html#variant.VmNoHit
+    /// [`Trap::VmMultiHit`]: enum.Trap.html#variant.VmMultiHit
+    PageFault {
+        /// The virtual address that could not be translated.
+        address: u32,
+        /// The reason why the translation failed.
+        error: LookupError,
+    },
+}
+
+/// The reasons for which a headless run of the processor through
+/// [`Cpu::run_until`] or [`Cpu::run_for_cycles`] returns.
+///
+/// [`Cpu::run_until`]: struct.Cpu.html#method.run_until
+/// [`Cpu::run_for_cycles`]: struct.Cpu.html#method.run_for_cycles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The processor is stopped, which happens when it executes `exit`, on a
+    /// secure fault or on a trap while another one is being handled.
+    Halted,
+    /// The processor is about to execute the instruction at a breakpoint
+    /// that was added through [`Cpu::add_breakpoint`].
+    ///
+    /// [`Cpu::add_breakpoint`]: struct.Cpu.html#method.add_breakpoint
+    Breakpoint(u32),
+    /// A trap was delivered, so that the processor is about to execute its
+    /// handler.
+    Trap(Trap),
+    /// A condition that stops execution in the debugger was raised.
+    DebugStop(DebugStop),
+    /// The condition passed to [`Cpu::run_until`] was met.
+    ///
+    /// [`Cpu::run_until`]: struct.Cpu.html#method.run_until
+    Condition,
+    /// The number of cycles passed to [`Cpu::run_for_cycles`] has passed.
+    ///
+    /// [`Cpu::run_for_cycles`]: struct.Cpu.html#method.run_for_cycles
+    CycleBudgetExhausted,
+    /// The processor waits for an event that cannot occur anymore, so that it
+    /// would never make progress again.
+    ///
+    /// This is the case when it sleeps with all interrupt lines disabled, or
+    /// when it executes code from a page in upload mode while no DMA request
+    /// is pending that could complete the upload.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag, ExitReason};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("sleep 0x0").unwrap();
+    /// cpu.load_code(&code);
+    /// cpu.registers.set_flag(CpuFlag::P0, true);
+    /// cpu.start();
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Stalled);
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.load_code(&assemble_str("bra 0x100").unwrap());
+    /// cpu.upload_code(0x100, 0x100, 0, false);
+    /// cpu.start();
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Stalled);
+    /// ```
+    Stalled,
+}
+
+/// A read of DMEM by the executed code that covered bytes which were never
+/// written, as detected while [`CpuConfig::track_uninitialized`] is enabled.
+///
+/// [`CpuConfig::track_uninitialized`]: struct.CpuConfig.html#structfield.track_uninitialized
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UninitializedRead {
+    /// The address of the instruction that performed the read.
+    pub pc: u32,
+    /// The address of the first byte that was read.
+    pub address: u32,
+    /// The number of bytes that were read.
+    pub size: u32,
+}
+
 enum_from_primitive! {
     /// Falcon trap kinds that can be delivered to the microprocessor.
     ///
@@ -82,18 +351,744 @@ enum_from_primitive! {
     }
 }
 
+impl From<LookupError> for Trap {
+    fn from(error: LookupError) -> Self {
+        match error {
+            LookupError::NoPageHits => Trap::VmNoHit,
+            LookupError::MultiplePageHits => Trap::VmMultiHit,
+        }
+    }
+}
+
 impl Cpu {
-    /// Creates a new instance of the CPU.
-    pub fn new() -> Self {
-        Cpu {
-            registers: CpuRegisters::new(),
-            memory: Memory::new(),
+    /// Creates a new instance of the CPU that emulates the engine described by
+    /// the given [`CpuConfig`].
+    ///
+    /// Returns a [`ConfigError`] if the configuration describes hardware that
+    /// cannot exist.
+    ///
+    /// [`CpuConfig`]: struct.CpuConfig.html
+    /// [`ConfigError`]: enum.ConfigError.html
+    pub fn new(config: CpuConfig) -> Result<Self, ConfigError> {
+        if config.imem_size & (PAGE_SIZE - 1) != 0 || config.imem_size > PAGE_SIZE * 0x100 {
+            return Err(ConfigError::ImemSize(config.imem_size));
+        }
+        if config.dmem_size & 0xFF != 0 {
+            return Err(ConfigError::DmemSize(config.dmem_size));
+        }
+        match config.stack_top {
+            Some(address) if address & 3 != 0 => return Err(ConfigError::StackTop(address)),
+            _ => {}
+        }
+
+        let mut io = IoBus::new();
+        for &(address, value) in &[(UC_CAPS, config.uc_caps()), (UC_CAPS2, config.uc_caps2())] {
+            io.register(address..address + 4, Box::new(Capabilities(value)))
+                .unwrap();
+        }
+        let fifo = if config.fifo {
+            let fifo = Fifo::new(FIFO_ENTRIES, 0);
+            io.register(FIFO_BASE..FIFO_BASE + FIFO_SIZE, Box::new(fifo.clone()))
+                .unwrap();
+            Some(fifo)
+        } else {
+            None
+        };
+
+        let mut memory = Memory::new(config.imem_size, config.dmem_size);
+        if config.track_uninitialized {
+            memory.track_initialization();
+        }
+
+        let mut registers = CpuRegisters::new();
+        registers[PC] = config.boot_vector;
+        registers[SP] = config.stack_top.unwrap_or(config.dmem_size as u32);
+
+        Ok(Cpu {
+            registers,
+            memory,
             dma_engine: dma::Engine::new(),
+            io,
+            scp: Scp::new(),
             state: ExecutionState::Stopped,
             increment_pc: false,
+            security: SecurityMode::NonSecure,
+            secure_fault: None,
+            cycles: 0,
+            stall_cycles: 0,
+            stalled_cycles: 0,
+            #[cfg(feature = "stats")]
+            stats: Statistics::new(),
+            coverage: None,
+            trace: None,
+            golden_trace: None,
+            breakpoints: HashSet::new(),
+            delivered_trap: None,
+            call_stack: CallStack::new(),
+            hooks: RefCell::new(Hooks::new()),
+            journal: None,
+            debug_stop: Cell::new(None),
+            uninitialized_reads: RefCell::new(Vec::new()),
+            config,
+            fifo,
+        })
+    }
+
+    /// Gets the current [`ExecutionState`] of the processor.
+    ///
+    /// [`ExecutionState`]: enum.ExecutionState.html
+    pub fn state(&self) -> ExecutionState {
+        self.state
+    }
+
+    /// Gets the configuration of the emulated engine.
+    pub fn config(&self) -> &CpuConfig {
+        &self.config
+    }
+
+    /// Gets the FIFO through which the host submits methods, if the engine is
+    /// equipped with one.
+    pub fn fifo(&self) -> Option<&Fifo> {
+        self.fifo.as_ref()
+    }
+
+    /// Gets the number of cycles that passed since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Gets the number of cycles that the processor was stalled for by the
+    /// wait states of peripherals since it was created, which are included in
+    /// [`Cpu::cycles`].
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    /// use faucon_emu::io::Peripheral;
+    ///
+    /// struct Slow;
+    ///
+    /// impl Peripheral for Slow {
+    ///     fn read32(&mut self, _: u32) -> u32 {
+    ///         0
+    ///     }
+    ///
+    ///     fn write32(&mut self, _: u32, _: u32) {}
+    ///
+    ///     fn wait_states(&self, _: u32) -> u32 {
+    ///         20
+    ///     }
+    /// }
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.io.register(0x2000..0x2004, Box::new(Slow)).unwrap();
+    /// let code = assemble_str("mov $r1 0x2000\niord $r2 I[$r1]\niords $r2 I[$r1]").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    /// assert_eq!(cpu.stalled_cycles(), 0);
+    /// cpu.step();
+    /// assert_eq!(cpu.stalled_cycles(), 20);
+    /// ```
+    ///
+    /// [`Cpu::cycles`]: struct.Cpu.html#method.cycles
+    pub fn stalled_cycles(&self) -> u64 {
+        self.stalled_cycles
+    }
+
+    /// Gets the counts of the instructions that were executed since the
+    /// processor was created or the statistics were cleared.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> &Statistics {
+        &self.stats
+    }
+
+    /// Discards the counts of the instructions that were executed so far.
+    #[cfg(feature = "stats")]
+    pub fn clear_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    /// Resets the processor, which clears all registers and stops it. The PC
+    /// is set to the [`CpuConfig::boot_vector`], from which the processor
+    /// executes once it is started again, and the stack pointer to the
+    /// [`stack_top`].
+    ///
+    /// The contents of IMEM and DMEM are preserved.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, PC};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     boot_vector: 0x200,
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// assert_eq!(cpu.registers[PC], 0x200);
+    ///
+    /// cpu.registers[PC] = 0x1234;
+    /// cpu.reset();
+    /// assert_eq!(cpu.registers[PC], 0x200);
+    /// ```
+    ///
+    /// [`CpuConfig::boot_vector`]: struct.CpuConfig.html#structfield.boot_vector
+    /// [`stack_top`]: struct.Cpu.html#method.stack_top
+    pub fn reset(&mut self) {
+        self.registers = CpuRegisters::new();
+        self.registers[PC] = self.config.boot_vector;
+        self.registers[SP] = self.stack_top();
+        self.state = ExecutionState::Stopped;
+        self.increment_pc = false;
+        self.security = SecurityMode::NonSecure;
+        self.secure_fault = None;
+        self.call_stack.clear();
+    }
+
+    /// Gets the subroutines and handlers that were entered and did not return
+    /// yet, the outermost one first.
+    ///
+    /// The frames are tracked by the emulator itself, so they remain accurate
+    /// when the code overwrites its stack in DMEM.
+    pub fn backtrace(&self) -> &[Frame] {
+        self.call_stack.frames()
+    }
+
+    /// Registers a [`Hook`] that observes the execution of code from now on.
+    ///
+    /// Hooks are invoked in the order in which they were added.
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, Hook};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("nop\nexit").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// let executed = Rc::new(Cell::new(0));
+    /// let counter = executed.clone();
+    /// let id = cpu.add_hook(Hook::PreInstruction(Box::new(move |_, _| {
+    ///     counter.set(counter.get() + 1)
+    /// })));
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    /// assert_eq!(executed.get(), 2);
+    /// assert!(cpu.remove_hook(id).is_some());
+    /// ```
+    ///
+    /// [`Hook`]: enum.Hook.html
+    pub fn add_hook(&mut self, hook: Hook) -> HookId {
+        self.hooks.get_mut().add(hook)
+    }
+
+    /// Removes a previously added [`Hook`] and returns it, unless it was
+    /// already removed.
+    ///
+    /// [`Hook`]: enum.Hook.html
+    pub fn remove_hook(&mut self, id: HookId) -> Option<Hook> {
+        self.hooks.get_mut().remove(id)
+    }
+
+    // Invokes the given function on all hooks, which get to observe the
+    // processor in the meantime.
+    fn call_hooks<F: FnMut(&mut Hook, &Cpu)>(&self, mut call: F) {
+        // Hooks only get shared access to the processor, through which no
+        // further hooks can be invoked.
+        let mut hooks = self.hooks.borrow_mut();
+        for hook in hooks.iter_mut() {
+            call(hook, self);
+        }
+    }
+
+    /// Gets the [`DebugStop`] that interrupted the most recent step, if any.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, DebugStop, PC};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     strict_access: true,
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// let code = assemble_str("mov $r1 0x102\nld b32 $r2 D[$r1]").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// let pc = cpu.registers[PC];
+    /// cpu.step();
+    /// match cpu.debug_stop() {
+    ///     Some(DebugStop::UnalignedAccess(access)) => assert_eq!(access.address, 0x102),
+    ///     _ => panic!("the unaligned load was executed"),
+    /// }
+    /// assert_eq!(cpu.registers[PC], pc);
+    /// ```
+    ///
+    /// [`DebugStop`]: enum.DebugStop.html
+    pub fn debug_stop(&self) -> Option<DebugStop> {
+        self.debug_stop.get()
+    }
+
+    /// Takes the reads of uninitialized DMEM that were detected since they were
+    /// last taken, the oldest one first.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     track_uninitialized: true,
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// let code = assemble_str(
+    ///     "mov $r1 0x100\n\
+    ///      st b16 D[$r1] $r1\n\
+    ///      ld b16 $r2 D[$r1]\n\
+    ///      ld b32 $r2 D[$r1]",
+    /// )
+    /// .unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// for _ in 0..4 {
+    ///     cpu.step();
+    /// }
+    /// let reads = cpu.take_uninitialized_reads();
+    /// assert_eq!(reads.len(), 1);
+    /// assert_eq!((reads[0].address, reads[0].size), (0x100, 4));
+    /// assert!(cpu.take_uninitialized_reads().is_empty());
+    /// ```
+    pub fn take_uninitialized_reads(&mut self) -> Vec<UninitializedRead> {
+        self.uninitialized_reads.get_mut().split_off(0)
+    }
+
+    // Records a read of uninitialized DMEM if any of the `size` bytes starting
+    // from the given address were never written.
+    pub(crate) fn check_initialized(&self, address: u32, size: u32) {
+        if !self.memory.is_initialized(address, size) {
+            self.uninitialized_reads
+                .borrow_mut()
+                .push(UninitializedRead {
+                    pc: self.registers[PC],
+                    address,
+                    size,
+                });
+        }
+    }
+
+    // Checks whether the executed code may perform an access to memory, which
+    // is not the case if it raises a debug stop.
+    pub(crate) fn check_access(
+        &self,
+        space: AddressSpace,
+        kind: AccessKind,
+        address: u32,
+        size: u32,
+        value: u32,
+    ) -> bool {
+        if !self.config.strict_access {
+            return true;
+        }
+
+        let access = MemoryAccess {
+            space,
+            kind,
+            address,
+            size,
+            value,
+        };
+        if address & (size - 1) != 0 {
+            self.debug_stop.set(Some(DebugStop::UnalignedAccess(access)));
+            false
+        } else if space == AddressSpace::DMem && !self.memory.data.contains(address, size) {
+            self.debug_stop.set(Some(DebugStop::OutOfBoundsAccess(access)));
+            false
+        } else {
+            true
+        }
+    }
+
+    // Checks whether a push or pop at the given address stays within the
+    // bounds of the stack, which is not the case if it raises a debug stop.
+    fn check_stack(&self, kind: AccessKind, address: u32) -> bool {
+        let size = match self.config.stack_size {
+            Some(size) => size,
+            None => return true,
+        };
+
+        let top = self.stack_top();
+        if address >= top {
+            self.debug_stop.set(Some(match kind {
+                AccessKind::Read => DebugStop::StackUnderflow(address),
+                AccessKind::Write => DebugStop::StackOverflow(address),
+            }));
+            false
+        } else if address < top.saturating_sub(size) {
+            self.debug_stop.set(Some(DebugStop::StackOverflow(address)));
+            false
+        } else {
+            true
+        }
+    }
+
+    // Lets the memory hooks observe an access by the executed code, and
+    // returns the value that was read or is to be written, as the hooks may
+    // replace it.
+    pub(crate) fn observe_access(
+        &self,
+        space: AddressSpace,
+        kind: AccessKind,
+        address: u32,
+        size: u32,
+        value: u32,
+    ) -> u32 {
+        let mut access = MemoryAccess {
+            space,
+            kind,
+            address,
+            size,
+            value,
+        };
+        self.call_hooks(|hook, cpu| {
+            if let Hook::Memory {
+                space,
+                range,
+                callback,
+            } = hook
+            {
+                if access.space == *space && access.overlaps(range) {
+                    callback(cpu, &mut access);
+                }
+            }
+        });
+
+        access.value
+    }
+
+    // Records the trace entry of an executed instruction and compares it
+    // against the reference trace.
+    fn record_trace(&mut self, pc: u32, insn: &Instruction, before: &CpuRegisters) {
+        let entry = TraceEntry::new(pc, insn.raw_bytes(), before, &self.registers);
+        if let Some(ref mut trace) = self.trace {
+            trace.push(entry.clone());
+        }
+        if let Some(ref mut golden_trace) = self.golden_trace {
+            if let Some(index) = golden_trace.check(entry) {
+                self.debug_stop.set(Some(DebugStop::TraceDivergence(index)));
+            }
         }
     }
 
+    // Stalls the processor for the given number of cycles in addition to the
+    // cycles of the executed instruction.
+    pub(crate) fn stall(&mut self, cycles: u32) {
+        self.stall_cycles += cycles;
+    }
+
+    /// Starts tracking the code coverage, unless it is already tracked.
+    pub fn start_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new(self.config.imem_size));
+        }
+    }
+
+    /// Gets the code coverage that was tracked so far, if it is tracked.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Stops tracking the code coverage and returns what was tracked so far.
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Starts recording a canonical trace of the instructions that are executed
+    /// from now on, unless it is already recorded.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("mov $r1 0x3\nmov $r2 0x4").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start_tracing();
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    ///
+    /// let trace = cpu.take_trace();
+    /// assert_eq!(trace.len(), 2);
+    /// assert_eq!(trace[1].to_string(), "00000002 0204 $r2=00000004");
+    /// ```
+    pub fn start_tracing(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    /// Takes the entries of the instructions that were traced since the trace
+    /// was last taken, the oldest one first.
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        match self.trace {
+            Some(ref mut trace) => std::mem::take(trace),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stops recording the trace and discards the entries that were not taken.
+    pub fn stop_tracing(&mut self) {
+        self.trace = None;
+    }
+
+    /// Compares the instructions that are executed from now on against the
+    /// given reference trace, replacing any previous one.
+    pub fn set_golden_trace(&mut self, reference: Vec<TraceEntry>) {
+        self.golden_trace = Some(GoldenTrace::new(reference));
+    }
+
+    /// Gets the reference trace that instructions are compared against, if
+    /// any.
+    pub fn golden_trace(&self) -> Option<&GoldenTrace> {
+        self.golden_trace.as_ref()
+    }
+
+    /// Stops comparing instructions against the reference trace and returns
+    /// it.
+    pub fn clear_golden_trace(&mut self) -> Option<GoldenTrace> {
+        self.golden_trace.take()
+    }
+
+    /// Starts recording the steps that are executed from now on, so that the
+    /// most recent `capacity` of them can be undone by [`Cpu::step_back`].
+    ///
+    /// Registers, IMEM, DMEM along with its initialization, the TLB, the queue
+    /// of the DMA engine and the SCP are rewound. Peripherals and external
+    /// memory are not recorded, so steps that access them cannot be undone,
+    /// and the cycles that peripherals were advanced by are not rewound.
+    ///
+    /// [`Cpu::step_back`]: struct.Cpu.html#method.step_back
+    pub fn start_recording(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(self, capacity));
+    }
+
+    /// Stops recording steps and discards the recorded ones.
+    pub fn stop_recording(&mut self) {
+        self.journal = None;
+    }
+
+    /// Gets the number of recorded steps that can be undone.
+    pub fn recorded_steps(&self) -> usize {
+        self.journal.as_ref().map_or(0, Journal::len)
+    }
+
+    /// Undoes the most recently executed step, if it was recorded.
+    ///
+    /// Steps that accessed peripherals or wrote external memory cannot be
+    /// undone, and neither can the steps that were executed before them.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_asm::{Register, RegisterKind};
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, StepBackError, PC};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("add b32 $r0 $r0 1\nexit").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start_recording(16);
+    /// cpu.start();
+    /// cpu.step();
+    /// assert_eq!(cpu.registers[Register(RegisterKind::Gpr, 0)], 1);
+    ///
+    /// assert_eq!(cpu.step_back(), Ok(()));
+    /// assert_eq!(cpu.registers[Register(RegisterKind::Gpr, 0)], 0);
+    /// assert_eq!(cpu.registers[PC], 0);
+    /// assert_eq!(cpu.step_back(), Err(StepBackError::Exhausted));
+    /// ```
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, StepBackError};
+    /// use faucon_emu::host::Host;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     track_uninitialized: true,
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// Host::new().attach(&mut cpu.io, 0).unwrap();
+    /// let code = assemble_str("mov $r1 0x40\niowr I[$r1] $r1\nst b32 D[$r1] $r1").unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start_recording(16);
+    /// cpu.start();
+    /// for _ in 0..3 {
+    ///     cpu.step();
+    /// }
+    ///
+    /// assert_eq!(cpu.step_back(), Ok(()));
+    /// assert_eq!(cpu.memory.read_data_word(0x40), 0);
+    /// assert!(!cpu.memory.is_initialized(0x40, 4));
+    ///
+    /// // The write to the mailbox of the host cannot be undone.
+    /// assert_eq!(cpu.step_back(), Err(StepBackError::Irreversible));
+    /// ```
+    pub fn step_back(&mut self) -> Result<(), StepBackError> {
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return Err(StepBackError::Exhausted),
+        };
+        let result = journal.undo(self);
+        self.journal = Some(journal);
+
+        result
+    }
+
+    /// Starts the processor, which then executes instructions from the current PC.
+    pub fn start(&mut self) {
+        self.state = ExecutionState::Running;
+        self.secure_fault = None;
+    }
+
+    /// Gets the [`SecurityMode`] the processor currently executes code in.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, SecureFault, SecurityMode, CAUTH};
+    /// use faucon_emu::host::Host;
+    /// use faucon_emu::scp::SIGNATURE_REGISTER;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig {
+    ///     boot_rom: true,
+    ///     ..CpuConfig::new()
+    /// })
+    /// .unwrap();
+    /// cpu.load_code(&assemble_str("jmp 0x100").unwrap());
+    /// let mut secret = assemble_str("nop\nexit").unwrap();
+    /// secret.resize(0x100, 0);
+    /// Host::new()
+    ///     .upload_code(&mut cpu, 0, 0x100, 0x100, &secret, true)
+    ///     .unwrap();
+    ///
+    /// // The boot ROM refuses to enter code that doesn't match its signature.
+    /// cpu.registers[CAUTH] = 0x1 | 1 << 16;
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    /// assert_eq!(cpu.secure_fault(), Some(SecureFault::SignatureMismatch));
+    ///
+    /// cpu.scp.registers[SIGNATURE_REGISTER] = cpu.scp.sign_code(&secret);
+    /// cpu.start();
+    /// cpu.step();
+    /// assert_eq!(cpu.secure_fault(), None);
+    /// assert_eq!(cpu.security_mode(), SecurityMode::HeavySecure);
+    /// ```
+    ///
+    /// [`SecurityMode`]: enum.SecurityMode.html
+    pub fn security_mode(&self) -> SecurityMode {
+        self.security
+    }
+
+    /// Gets the accesses that may currently be performed at the given address,
+    /// which is a physical one in code space.
+    ///
+    /// A code page is in upload mode from the upload of its first word until
+    /// the upload of its last one. Only then it is writable, and only by the
+    /// upload itself, while stores of the executed code to code space always
+    /// trap. Once the upload completed, the page is executable and readable
+    /// unless it holds secret code, which only secret code itself may read.
+    /// Instructions are only ever fetched from code space, so data and I/O
+    /// are not executable.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{AddressSpace, Cpu, CpuConfig};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// for address in (0x100..0x180).step_by(4) {
+    ///     cpu.upload_code(address, address as u32, 0, true);
+    /// }
+    /// let code = cpu.permissions(AddressSpace::IMem, 0x180);
+    /// assert!(code.write && !code.execute);
+    ///
+    /// for address in (0x180..0x200).step_by(4) {
+    ///     cpu.upload_code(address, address as u32, 0, true);
+    /// }
+    /// let code = cpu.permissions(AddressSpace::IMem, 0x180);
+    /// assert!(code.execute && !code.read && !code.write);
+    ///
+    /// let data = cpu.permissions(AddressSpace::DMem, 0x180);
+    /// assert!(data.read && data.write && !data.execute);
+    /// ```
+    pub fn permissions(&self, space: AddressSpace, address: u32) -> Permissions {
+        match space {
+            AddressSpace::IMem => match self.memory.tlb.physical_entry(address as u16) {
+                Some(entry) if address < self.imem_size() as u32 => Permissions {
+                    read: !entry.is_secret() || self.security == SecurityMode::HeavySecure,
+                    write: entry.get_flag(PageFlag::Busy),
+                    execute: entry.get_flag(PageFlag::Usable),
+                },
+                _ => Permissions {
+                    read: false,
+                    write: false,
+                    execute: false,
+                },
+            },
+            AddressSpace::DMem | AddressSpace::Io => Permissions {
+                read: true,
+                write: true,
+                execute: false,
+            },
+        }
+    }
+
+    /// Gets the [`SecureFault`] that stopped the processor, if any.
+    ///
+    /// [`SecureFault`]: enum.SecureFault.html
+    pub fn secure_fault(&self) -> Option<SecureFault> {
+        self.secure_fault
+    }
+
+    // Stops the processor because of a violation of the authentication model.
+    pub(crate) fn raise_secure_fault(&mut self, fault: SecureFault) {
+        self.state = ExecutionState::Stopped;
+        self.secure_fault = Some(fault);
+    }
+
+    // Switches the security mode when execution crosses the boundary of secret
+    // code and returns whether the code at the given address may be executed.
+    fn switch_security_mode(&mut self, address: u32, secret: bool) -> bool {
+        match (self.security, secret) {
+            (SecurityMode::NonSecure, true) => {
+                // Secret code is authenticated when it is entered, which is only
+                // possible at the start of a page.
+                if (address & 0xFF) != 0 {
+                    self.raise_secure_fault(SecureFault::InvalidEntry);
+                    return false;
+                }
+                if self.config.boot_rom && !bootrom::authenticate(self, address) {
+                    return false;
+                }
+
+                self.security = SecurityMode::HeavySecure;
+            }
+            (SecurityMode::HeavySecure, false) => self.security = SecurityMode::NonSecure,
+            _ => {}
+        }
+
+        true
+    }
+
     /// Returns the length of the Falcon code segment.
     pub fn imem_size(&self) -> usize {
         self.memory.code.len()
@@ -104,30 +1099,169 @@ impl Cpu {
         self.memory.data.len()
     }
 
+    /// Gets the address in DMEM from which the stack grows down, as configured
+    /// through [`CpuConfig::stack_top`].
+    ///
+    /// [`CpuConfig::stack_top`]: struct.CpuConfig.html#structfield.stack_top
+    pub fn stack_top(&self) -> u32 {
+        self.config
+            .stack_top
+            .unwrap_or(self.memory.data.len() as u32)
+    }
+
+    /// Gets the number of bytes on the stack, between the stack pointer and
+    /// the [`stack_top`].
+    ///
+    /// [`stack_top`]: struct.Cpu.html#method.stack_top
+    pub fn stack_depth(&self) -> u32 {
+        self.stack_top().saturating_sub(self.registers[SP])
+    }
+
+    /// Gets the words on the stack along with their addresses, starting from
+    /// the most recently pushed one.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, SP};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// assert_eq!(cpu.registers[SP], 0x4000);
+    ///
+    /// cpu.stack_push(0x1234);
+    /// cpu.stack_push(0x5678);
+    /// assert_eq!(cpu.stack_depth(), 8);
+    /// assert_eq!(cpu.stack(), vec![(0x3FF8, 0x5678), (0x3FFC, 0x1234)]);
+    ///
+    /// assert_eq!(cpu.stack_pop(), 0x5678);
+    /// assert_eq!(cpu.stack(), vec![(0x3FFC, 0x1234)]);
+    /// ```
+    pub fn stack(&self) -> Vec<(u32, u32)> {
+        let sp = self.registers[SP];
+
+        (0..self.stack_depth() / 4)
+            .map(|i| sp + i * 4)
+            .map(|address| (address, self.memory.data.read32(address)))
+            .collect()
+    }
+
     /// Pushes a word onto the stack and decrements the stack pointer by 4.
+    ///
+    /// The stack pointer wraps around at the start of DMEM. If the push is
+    /// rejected by the stack or access checks, the stack pointer is left
+    /// unchanged.
     pub fn stack_push(&mut self, word: u32) {
-        self.registers[SP] -= 4;
-        self.memory.write_data_word(self.registers[SP], word);
+        let address = self.memory.data.wrap(self.registers[SP].wrapping_sub(4));
+        if !self.check_stack(AccessKind::Write, address)
+            || !self.check_access(AddressSpace::DMem, AccessKind::Write, address, 4, word)
+        {
+            return;
+        }
+        self.registers[SP] = address;
+
+        let word = self.observe_access(AddressSpace::DMem, AccessKind::Write, address, 4, word);
+        self.memory.write_data_word(address, word);
     }
 
     /// Pops a word off the stack and increments the stack pointer by 4.
     pub fn stack_pop(&mut self) -> u32 {
-        let word = self.memory.read_data_word(self.registers[SP]);
-        self.registers[SP] += 4;
+        let address = self.registers[SP];
+        if !self.check_stack(AccessKind::Read, address)
+            || !self.check_access(AddressSpace::DMem, AccessKind::Read, address, 4, 0)
+        {
+            return 0;
+        }
+        self.check_initialized(address & !3, 4);
+
+        let word = self.observe_access(
+            AddressSpace::DMem,
+            AccessKind::Read,
+            self.registers[SP],
+            4,
+            self.memory.read_data_word(self.registers[SP]),
+        );
+        self.registers[SP] = self.registers[SP].wrapping_add(4);
 
         word
     }
 
     /// Triggers a [`Trap`] that should be delivered to the processor.
     ///
+    /// The current PC is recorded in `$tstatus` and pushed onto the stack as the
+    /// return address, so that the faulting instruction is retried after the trap
+    /// handler returns. A trap that occurs while another one is still being handled
+    /// stops the processor.
+    ///
     /// [`Trap`]: enum.Trap.html
     pub fn trigger_trap(&mut self, trap: Trap) {
+        self.deliver_trap(trap, self.registers[PC]);
+    }
+
+    // Delivers the trap for a failed translation of the given virtual address,
+    // unless it would stop the processor during a trap handler, in which case
+    // the fault is raised as a debug stop instead.
+    fn page_fault(&mut self, address: u32, error: LookupError) {
+        if self.registers.get_flag(CpuFlag::TA) {
+            self.debug_stop
+                .set(Some(DebugStop::PageFault { address, error }));
+        } else {
+            self.trigger_trap(error.into());
+        }
+    }
+
+    // Delivers a trap to the processor, which returns to the given address after
+    // handling it.
+    pub(crate) fn deliver_trap(&mut self, trap: Trap, return_address: u32) {
+        self.delivered_trap = Some(trap);
+
+        // A trap during a trap handler is fatal.
+        if self.registers.get_flag(CpuFlag::TA) {
+            self.state = ExecutionState::Stopped;
+            return;
+        }
+
         // Set the Trap Active bit in the flags register.
-        self.registers[FLAGS] |= 1 << 24;
+        self.registers.set_flag(CpuFlag::TA, true);
 
         // Store the trap status, composed of the current PC and the trap reason.
         self.registers[TSTATUS] = self.registers[PC] | ((trap as u8 & 0xF) as u32) << 20;
 
+        // Jump into the trap vector.
+        self.enter_handler(FrameKind::Trap(trap), return_address, self.registers[TV]);
+    }
+
+    /// Triggers an interrupt on the given line, from 0 through 2.
+    ///
+    /// Interrupts are only delivered if they are enabled through the respective
+    /// `ie` flag. In that case, the current PC is pushed onto the stack as the
+    /// return address and a sleeping processor is woken up. As a `sleep`
+    /// instruction doesn't advance the PC, it is executed again after the
+    /// interrupt handler returns and re-checks its flag. Returns whether the
+    /// interrupt was delivered.
+    pub fn trigger_interrupt(&mut self, line: u8) -> bool {
+        let (enable, vector) = match line {
+            0 => (CpuFlag::IE0, IV0),
+            1 => (CpuFlag::IE1, IV1),
+            2 => (CpuFlag::IE2, IV2),
+            _ => return false,
+        };
+        if !self.registers.get_flag(enable) {
+            return false;
+        }
+
+        if self.state == ExecutionState::Sleeping {
+            self.state = ExecutionState::Running;
+        }
+        self.enter_handler(
+            FrameKind::Interrupt(line),
+            self.registers[PC],
+            self.registers[vector],
+        );
+
+        true
+    }
+
+    // Enters a trap or interrupt handler at the given address, saving the
+    // interrupt state and the return address.
+    fn enter_handler(&mut self, kind: FrameKind, return_address: u32, handler: u32) {
         // Store the interrupt state.
         self.registers
             .set_flag(CpuFlag::IS0, self.registers.get_flag(CpuFlag::IE0));
@@ -140,23 +1274,79 @@ impl Cpu {
         self.registers.set_flag(CpuFlag::IE2, false);
 
         // Push the return address onto the stack.
-        self.stack_push(self.registers[PC]);
+        self.stack_push(return_address);
+        self.call_stack.enter(Frame {
+            kind,
+            entry: handler,
+            return_address,
+            stack_pointer: self.registers[SP],
+        });
 
-        // Jump into the trap vector.
-        self.registers[PC] = self.registers[TV];
+        // Jump into the handler.
+        self.registers[PC] = handler;
+
+        self.call_hooks(|hook, cpu| {
+            if let Hook::Interrupt(callback) = hook {
+                callback(cpu, kind);
+            }
+        });
     }
 
     /// Uploads a code word to IMEM at a given physical and virtual address.
-    pub fn upload_code(&mut self, address: u16, vaddress: u32, value: u32) {
-        // TODO: Add support for all the secret stuff.
+    ///
+    /// When `secret` is set, the page is marked as secret once its first word
+    /// is uploaded, so that its code is executed in Heavy Secure mode. When a
+    /// secret page is re-uploaded as non-secret code, its previous contents
+    /// are scrubbed, so that they cannot be read once the page is no longer
+    /// secret.
+    ///
+    /// Words that are not the first one of a page are only written while the
+    /// page is in upload mode, as reported by [`Cpu::permissions`]. Uploads to
+    /// the middle of a page whose upload already completed are discarded, so
+    /// that the code of usable pages cannot be patched behind the back of the
+    /// instruction cache.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    /// use faucon_emu::memory::SECRET_CODE_PATTERN;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// for address in (0..0x100).step_by(4) {
+    ///     cpu.upload_code(address, address as u32, 0xAAAA_AAAA, true);
+    /// }
+    /// assert_eq!(cpu.memory.read_code_unprivileged(0x80), SECRET_CODE_PATTERN);
+    ///
+    /// cpu.upload_code(0, 0, 0, false);
+    /// assert_eq!(cpu.memory.read_code_unprivileged(0x80), 0);
+    ///
+    /// for address in (4..0x100).step_by(4) {
+    ///     cpu.upload_code(address, address as u32, 0x1234, false);
+    /// }
+    /// cpu.upload_code(0x80, 0x80, 0x5678, false);
+    /// assert_eq!(cpu.memory.read_code_addr(0x80), 0x1234);
+    /// ```
+    ///
+    /// [`Cpu::permissions`]: struct.Cpu.html#method.permissions
+    pub fn upload_code(&mut self, address: u16, vaddress: u32, value: u32, secret: bool) {
         // TODO: Nicer way to access TLB without making the borrow checker scream?
 
-        // If the first word is being uploaded, map the page.
+        // If the first word is being uploaded, map the page and evict its old
+        // code from the instruction cache.
         if (address & 0xFC) == 0 {
-            self.memory
-                .tlb
-                .get_physical_entry(address)
-                .map(vaddress, false);
+            self.memory.insn_cache.invalidate_page((address >> 8) as u8);
+
+            let entry = self.memory.tlb.get_physical_entry(address);
+            let was_secret = entry.is_secret();
+            entry.map(vaddress, secret);
+
+            // Secret code must not become readable through a non-secret page.
+            if was_secret && !secret {
+                for offset in (0..PAGE_SIZE as u16).step_by(4) {
+                    self.memory.write_code_addr(address & !0xFF | offset, 0);
+                }
+            }
+        } else if !self.permissions(AddressSpace::IMem, address as u32).write {
+            return;
         }
 
         // Write word to the code segment.
@@ -175,17 +1365,53 @@ impl Cpu {
         }
     }
 
-    fn fetch_insn(&mut self, address: u32) -> Option<Instruction> {
+    /// Loads non-secret code to the start of IMEM and maps it to the same
+    /// virtual addresses.
+    ///
+    /// The code is padded with zeroes to whole pages, so that all of its pages
+    /// become usable. This is a shorthand for [`Cpu::upload_code`], whereas a
+    /// [`Host`] uploads code through its IMEM access ports.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the code does not fit into IMEM.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, ExecutionState};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.load_code(&assemble_str("nop\nexit").unwrap());
+    /// assert_eq!(cpu.memory.tlb.lookup(0x10).unwrap().0, 0);
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    /// assert_eq!(cpu.state(), ExecutionState::Stopped);
+    /// ```
+    ///
+    /// [`Cpu::upload_code`]: struct.Cpu.html#method.upload_code
+    /// [`Host`]: ../host/struct.Host.html
+    pub fn load_code(&mut self, code: &[u8]) {
+        let size = (code.len() + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        assert!(size <= self.imem_size(), "the code does not fit into IMEM");
+
+        for address in (0..size).step_by(4) {
+            let mut word = [0; 4];
+            for (byte, value) in word.iter_mut().zip(code.iter().skip(address)) {
+                *byte = *value;
+            }
+            let word = u32::from_le_bytes(word);
+            self.upload_code(address as u16, address as u32, word, false);
+        }
+    }
+
+    fn fetch_insn(&mut self, address: u32) -> Option<Rc<Instruction>> {
         // Look up the TLB to get the physical code page.
         let result = match self.memory.tlb.lookup(address) {
             Ok((page, tlb)) => Some((page, tlb)),
-            Err(LookupError::NoPageHits) => {
-                self.trigger_trap(Trap::VmNoHit);
-
-                None
-            }
-            Err(LookupError::MultiplePageHits) => {
-                self.trigger_trap(Trap::VmMultiHit);
+            Err(error) => {
+                self.page_fault(address, error);
 
                 None
             }
@@ -196,24 +1422,42 @@ impl Cpu {
             let page_offset = (address & 0xFF) as u16;
             let code_address = ((page_index as u16) << 8) | page_offset;
 
-            // If the page is marked usable, complete the access using the physical page.
-            if tlb.get_flag(PageFlag::Usable) {
+            // If the page is executable, complete the access using the physical page.
+            let permissions = self.permissions(AddressSpace::IMem, code_address as u32);
+            if permissions.execute {
+                if !self.switch_security_mode(address, tlb.get_flag(PageFlag::Secret)) {
+                    return None;
+                }
+
+                // Prefer an already decoded instruction from the cache, if any.
+                if let Some(insn) = self.memory.insn_cache.get(code_address) {
+                    return Some(insn.clone());
+                }
+
                 let mut code = &self.memory.code[code_address as usize..];
                 match disassembler::read_instruction(&mut code) {
-                    Ok(insn) => Some(insn),
+                    Ok(insn) => Some(self.memory.insn_cache.insert(code_address, insn)),
                     Err(faucon_asm::Error::UnknownInstruction(_)) => {
                         self.trigger_trap(Trap::InvalidOpcode);
 
                         None
                     }
-                    Err(faucon_asm::Error::IoError) => panic!("Rust exploded"),
-                    Err(faucon_asm::Error::Eof) => None,
+                    // The instruction reaches beyond the end of IMEM.
+                    Err(faucon_asm::Error::IoError) | Err(faucon_asm::Error::Eof) => {
+                        self.trigger_trap(Trap::InvalidOpcode);
+
+                        None
+                    }
                 }
-            } else if tlb.get_flag(PageFlag::Busy) {
-                // The page is marked busy, the access must be completed when possible.
-                todo!("Wait for the page to be marked as usable");
+            } else if permissions.write {
+                // The page is in upload mode, the access is retried once a
+                // pending code load marks it usable.
+                None
             } else {
-                unreachable!()
+                // The page is neither uploaded nor being uploaded.
+                self.page_fault(address, LookupError::NoPageHits);
+
+                None
             }
         } else {
             None
@@ -221,18 +1465,294 @@ impl Cpu {
     }
 
     /// Executes the next instruction at the address held by the PC register.
+    ///
+    /// Nothing is executed unless the processor is running. Every step also
+    /// completes one pending DMA request, even while the processor sleeps.
+    /// Interrupts that are asserted by peripherals are delivered beforehand,
+    /// which may wake up the processor.
+    ///
+    /// The cycles that the step took are passed on to the peripherals, where
+    /// a step of a sleeping processor takes a single cycle.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, CpuFlag, ExecutionState};
+    /// use faucon_emu::dma::{Request, RequestMode};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.dma_engine.attach(0, Box::new(vec![0xAAu8; 0x100])).unwrap();
+    /// cpu.load_code(&assemble_str("sleep 0x0").unwrap());
+    /// cpu.registers.set_flag(CpuFlag::P0, true);
+    /// cpu.start();
+    /// cpu.step();
+    /// assert_eq!(cpu.state(), ExecutionState::Sleeping);
+    ///
+    /// let request = Request::new(RequestMode::DataLoad, 0, 0, 0, 0x10, Some(0), None);
+    /// cpu.dma_engine.enqueue(request).unwrap();
+    /// cpu.step();
+    /// assert!(!cpu.dma_engine.is_busy());
+    /// assert_eq!(cpu.memory.read_data_word(0x10), 0xAAAA_AAAA);
+    /// ```
     pub fn step(&mut self) {
-        match self.fetch_insn(self.registers[PC]) {
+        if self.state == ExecutionState::Stopped {
+            return;
+        }
+
+        match self.journal.take() {
+            Some(mut journal) => {
+                let checkpoint = Checkpoint::new(self);
+                self.advance();
+                journal.record(checkpoint, self);
+                self.journal = Some(journal);
+            }
+            None => self.advance(),
+        }
+    }
+
+    /// Adds a breakpoint at the instruction at the given virtual address, at
+    /// which [`Cpu::run_until`] and [`Cpu::run_for_cycles`] return.
+    ///
+    /// [`Cpu::run_until`]: struct.Cpu.html#method.run_until
+    /// [`Cpu::run_for_cycles`]: struct.Cpu.html#method.run_for_cycles
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes the breakpoint at the given virtual address and returns whether
+    /// there was one.
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Steps through instructions until the given condition holds for the
+    /// processor or execution is interrupted for another [`ExitReason`].
+    ///
+    /// The condition is checked before every step, whereas breakpoints are
+    /// checked before every step except the first, so that a run can resume
+    /// from a breakpoint. A sleeping processor keeps stepping until one of
+    /// its peripherals wakes it up, unless it is [`ExitReason::Stalled`].
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_asm::{Register, RegisterKind};
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, ExitReason, Trap, PC, SP, TV};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let source = "mov $r1 0x3\nsub b32 $r1 $r1 0x1\nbnz 0x2\ntrap 0x1\nexit";
+    /// let code = assemble_str(source).unwrap();
+    /// cpu.load_code(&code);
+    /// cpu.registers[SP] = 0x100;
+    /// cpu.registers[TV] = 0xA;
+    /// cpu.start();
+    ///
+    /// let r1 = Register(RegisterKind::Gpr, 1);
+    /// assert_eq!(cpu.run_until(|cpu| cpu.registers[r1] == 1), ExitReason::Condition);
+    /// assert_eq!(cpu.registers[PC], 0x5);
+    ///
+    /// cpu.add_breakpoint(0x2);
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Breakpoint(0x2));
+    /// assert!(cpu.remove_breakpoint(0x2));
+    ///
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Trap(Trap::Software1));
+    /// assert_eq!(cpu.registers[PC], 0xA);
+    /// assert_eq!(cpu.run_until(|_| false), ExitReason::Halted);
+    /// ```
+    ///
+    /// [`ExitReason`]: enum.ExitReason.html
+    /// [`ExitReason::Stalled`]: enum.ExitReason.html#variant.Stalled
+    pub fn run_until<F: FnMut(&Cpu) -> bool>(&mut self, mut condition: F) -> ExitReason {
+        self.run(|cpu| {
+            if condition(cpu) {
+                Some(ExitReason::Condition)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Steps through instructions until the given number of cycles has passed
+    /// or execution is interrupted for another [`ExitReason`].
+    ///
+    /// The last instruction may take longer than the remaining cycles, in
+    /// which case it is executed regardless.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, ExitReason};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let code = assemble_str("bra 0x0").unwrap();
+    /// cpu.load_code(&code);
+    /// cpu.start();
+    ///
+    /// assert_eq!(cpu.run_for_cycles(1000), ExitReason::CycleBudgetExhausted);
+    /// assert!(cpu.cycles() >= 1000);
+    /// ```
+    ///
+    /// [`ExitReason`]: enum.ExitReason.html
+    pub fn run_for_cycles(&mut self, cycles: u64) -> ExitReason {
+        let end = self.cycles.saturating_add(cycles);
+        self.run(|cpu| {
+            if cpu.cycles >= end {
+                Some(ExitReason::CycleBudgetExhausted)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Steps through instructions until the given check or any of the other
+    // exit conditions interrupts execution.
+    fn run<F: FnMut(&Cpu) -> Option<ExitReason>>(&mut self, mut check: F) -> ExitReason {
+        let mut first = true;
+        loop {
+            if self.state == ExecutionState::Stopped {
+                return ExitReason::Halted;
+            }
+            if let Some(reason) = check(self) {
+                return reason;
+            }
+            let pc = self.registers[PC];
+            if !first && self.state == ExecutionState::Running && self.breakpoints.contains(&pc) {
+                return ExitReason::Breakpoint(pc);
+            }
+            first = false;
+
+            self.step();
+            if self.state == ExecutionState::Stopped {
+                return ExitReason::Halted;
+            }
+            if let Some(stop) = self.debug_stop() {
+                return ExitReason::DebugStop(stop);
+            }
+            if let Some(trap) = self.delivered_trap {
+                return ExitReason::Trap(trap);
+            }
+            if self.is_stalled() {
+                return ExitReason::Stalled;
+            }
+        }
+    }
+
+    // Checks whether the processor waits for an event that cannot occur
+    // anymore, in which case further steps would not change its state.
+    fn is_stalled(&self) -> bool {
+        match self.state {
+            ExecutionState::Sleeping => {
+                !self.registers.get_flag(CpuFlag::IE0)
+                    && !self.registers.get_flag(CpuFlag::IE1)
+                    && !self.registers.get_flag(CpuFlag::IE2)
+            }
+            ExecutionState::Running if self.dma_engine.is_idle() => {
+                match self.memory.tlb.translate_addr(self.registers[PC]) {
+                    Ok(address) => {
+                        let permissions = self.permissions(AddressSpace::IMem, address as u32);
+                        !permissions.execute && permissions.write
+                    }
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // Delivers interrupts, completes a pending DMA request, executes the next
+    // instruction if the processor is running and advances the peripherals.
+    fn advance(&mut self) {
+        self.debug_stop.set(None);
+        self.delivered_trap = None;
+        for line in self.io.interrupts() {
+            self.trigger_interrupt(line);
+        }
+        dma::process_next_request(self);
+        let cycles = if self.state == ExecutionState::Running {
+            self.execute_next()
+        } else {
+            1
+        };
+
+        self.cycles += cycles as u64;
+        self.io.tick(cycles as u32);
+        if self.io.take_reset() {
+            self.reset();
+        }
+    }
+
+    // Executes the next instruction and returns the number of cycles it took.
+    fn execute_next(&mut self) -> usize {
+        self.stall_cycles = 0;
+
+        let pc = self.registers[PC];
+        match self.fetch_insn(pc) {
             Some(insn) => {
-                process_instruction(self, &insn);
+                self.call_hooks(|hook, cpu| {
+                    if let Hook::PreInstruction(callback) = hook {
+                        callback(cpu, &insn);
+                    }
+                });
+
+                // The handler may remap the code, so it is located beforehand.
+                let physical = match self.coverage {
+                    Some(_) => self.memory.tlb.translate_addr(pc).ok(),
+                    None => None,
+                };
+
+                // Traced instructions are compared by the registers they modify.
+                let before = if self.trace.is_some() || self.golden_trace.is_some() {
+                    Some(self.registers.clone())
+                } else {
+                    None
+                };
+
+                // In strict mode and with a bounded stack, instructions that
+                // raise a debug stop are undone, along with the writes that
+                // they made to memory beforehand.
+                let checkpoint = if self.config.strict_access || self.config.stack_size.is_some() {
+                    Some(Checkpoint::new(self))
+                } else {
+                    None
+                };
+                let cycles = process_instruction(self, &insn);
+                if let Some(checkpoint) = checkpoint {
+                    if self.debug_stop.get().is_some() {
+                        checkpoint.restore(self);
+                        return 1;
+                    }
+                    checkpoint.release(self);
+                }
+                if let Some(before) = before {
+                    self.record_trace(pc, &insn, &before);
+                }
+                let cycles = cycles + self.stall_cycles as usize;
+                self.stalled_cycles += self.stall_cycles as u64;
+                #[cfg(feature = "stats")]
+                self.stats.record(pc, insn.kind());
+                if let (Some(coverage), Some(physical)) = (&mut self.coverage, physical) {
+                    coverage.record(physical, insn.len());
+                }
 
                 // Check if it is necessary to increment the PC.
                 // If not, this has already been done by the instruction itself.
                 if self.increment_pc {
                     self.registers[PC] += insn.len() as u32;
+                } else if self.registers[PC] != pc {
+                    let target = self.registers[PC];
+                    self.call_hooks(|hook, cpu| {
+                        if let Hook::Branch(callback) = hook {
+                            callback(cpu, pc, target);
+                        }
+                    });
                 }
+
+                self.call_hooks(|hook, cpu| {
+                    if let Hook::PostInstruction(callback) = hook {
+                        callback(cpu, &insn);
+                    }
+                });
+
+                cycles
             }
-            None => return,
+            None => 1,
         }
     }
 }
@@ -3,48 +3,75 @@ use std::ops::{Index, IndexMut};
 use faucon_asm::{Operand, Register, RegisterKind};
 
 /// A special-purpose register that holds the address for Interrupt Vector 0.
-pub const IV0: Register = Register(RegisterKind::Spr, 0);
+pub const IV0: Register = Register::IV0;
 
 /// A special-purpose register that holds the address for Interrupt Vector 1.
-pub const IV1: Register = Register(RegisterKind::Spr, 1);
+pub const IV1: Register = Register::IV1;
 
 /// A special-purpose register that holds the address for Interrupt Vector 2.
-pub const IV2: Register = Register(RegisterKind::Spr, 2);
+pub const IV2: Register = Register::IV2;
 
 /// A special-purpose register that holds the address for the Trap Vector.
-pub const TV: Register = Register(RegisterKind::Spr, 3);
+pub const TV: Register = Register::TV;
 
 /// A special-purpose register that holds the current stack pointer.
-pub const SP: Register = Register(RegisterKind::Spr, 4);
+pub const SP: Register = Register::SP;
 
 /// A special-purpose register that holds the current program counter.
-pub const PC: Register = Register(RegisterKind::Spr, 5);
+pub const PC: Register = Register::PC;
 
 /// A special-purpose register that holds the external base address for IMEM
 /// transfers.
-pub const XCBASE: Register = Register(RegisterKind::Spr, 6);
+pub const XCBASE: Register = Register::XCBASE;
 
 /// A special-purpose register that holds the external base address for DMEM
 /// transfers.
-pub const XDBASE: Register = Register(RegisterKind::Spr, 7);
+pub const XDBASE: Register = Register::XDBASE;
 
 /// A special-purpose register that holds various CPU flag bits.
-pub const FLAGS: Register = Register(RegisterKind::Spr, 8);
+pub const FLAGS: Register = Register::FLAGS;
 
 /// A special-purpose register that holds the configuration bits for the SCP DMA
 /// functionality.
-pub const CX: Register = Register(RegisterKind::Spr, 9);
+///
+/// Also known as `$ccr`, it holds the number of subsequent DMA data transfers
+/// that target the SCP rather than external memory, as set by `cxset`.
+pub const CX: Register = Register::CX;
 
 /// A special-purpose register that holds the configuration bits for the SCP
 /// authentication process.
-pub const CAUTH: Register = Register(RegisterKind::Spr, 10);
+pub const CAUTH: Register = Register::CAUTH;
 
 /// A special-purpose register that holds the configuration bits for the CTXDMA
 /// ports.
-pub const XTARGETS: Register = Register(RegisterKind::Spr, 11);
+pub const XTARGETS: Register = Register::XTARGETS;
 
 /// A special-purpose register that holds details on triggered traps.
-pub const TSTATUS: Register = Register(RegisterKind::Spr, 12);
+pub const TSTATUS: Register = Register::TSTATUS;
+
+/// A mask of the bits in the `$flags` register that are architected.
+///
+/// The remaining bits are hardwired to zero, so writes to them are ignored.
+///
+/// ```
+/// use faucon_emu::cpu::{CpuFlag, FLAGS_MASK};
+///
+/// assert_ne!(FLAGS_MASK & CpuFlag::TA as u32, 0);
+/// assert_eq!(FLAGS_MASK & 1 << 12, 0);
+/// ```
+pub const FLAGS_MASK: u32 = 0x0177_0FFF;
+
+/// A mask of the bits in the `$cx` register that are architected.
+///
+/// These hold the number of DMA data transfers that are still to be
+/// redirected to the SCP.
+pub const CX_MASK: u32 = 0x1F;
+
+/// A mask of the bits in the `$xtargets` register that are architected.
+///
+/// These are the DMA ports for code loads, data loads and data stores, in
+/// this order, at bits 0, 8 and 12.
+pub const XTARGETS_MASK: u32 = 0x7707;
 
 enum_from_primitive! {
     /// Flag bits for the `flags` special-purpose register.
@@ -93,6 +120,7 @@ enum_from_primitive! {
 }
 
 /// Representation of all Falcon CPU registers.
+#[derive(Clone)]
 pub struct CpuRegisters {
     /// The general-purpose CPU registers of the Falcon.
     gpr: [u32; 0x10],
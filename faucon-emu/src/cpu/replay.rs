@@ -0,0 +1,213 @@
+//! Recording of executed steps, so that they can be undone again.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+
+use crate::dma::Request;
+use crate::memory::{Overwritten, Savepoint, Tlb};
+use crate::scp::Scp;
+
+use super::{CallStack, Cpu, CpuRegisters, ExecutionState, SecureFault, SecurityMode, Trap};
+
+/// An error that occurs when a step cannot be undone through
+/// [`Cpu::step_back`].
+///
+/// [`Cpu::step_back`]: struct.Cpu.html#method.step_back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepBackError {
+    /// No recorded step is left to undo.
+    Exhausted,
+    /// The step accessed peripherals or wrote external memory, whose state is
+    /// not recorded.
+    Irreversible,
+}
+
+impl fmt::Display for StepBackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepBackError::Exhausted => write!(f, "no recorded step is left to undo"),
+            StepBackError::Irreversible => write!(
+                f,
+                "the step accessed peripherals or external memory, which cannot be undone"
+            ),
+        }
+    }
+}
+
+/// The state of the processor before a step or an instruction was executed.
+///
+/// The writes to memory from then on are logged until the checkpoint is
+/// either restored or released.
+pub(crate) struct Checkpoint {
+    /// The captured state of everything but memory.
+    snapshot: Snapshot,
+    /// The position in the undo log of the memory at the checkpoint.
+    savepoint: Savepoint,
+    /// The number of accesses to peripherals at the checkpoint.
+    io_accesses: u64,
+    /// The number of writes to external memory at the checkpoint.
+    external_writes: u64,
+}
+
+impl Checkpoint {
+    /// Captures the current state of the processor.
+    pub fn new(cpu: &mut Cpu) -> Self {
+        Checkpoint {
+            snapshot: Snapshot::new(cpu),
+            savepoint: cpu.memory.savepoint(),
+            io_accesses: cpu.io.accesses(),
+            external_writes: cpu.dma_engine.writes(),
+        }
+    }
+
+    /// Keeps the current state of the processor, including the writes to
+    /// memory since the checkpoint was captured.
+    pub fn release(self, cpu: &mut Cpu) {
+        cpu.memory.release(self.savepoint);
+    }
+
+    /// Restores the captured state of the processor, including memory.
+    pub fn restore(self, cpu: &mut Cpu) {
+        cpu.memory.rollback(self.savepoint);
+        self.snapshot.restore(cpu);
+    }
+}
+
+// The state of the processor apart from memory, the TLB, peripherals and
+// external memory.
+struct Snapshot {
+    registers: CpuRegisters,
+    state: ExecutionState,
+    increment_pc: bool,
+    security: SecurityMode,
+    secure_fault: Option<SecureFault>,
+    cycles: u64,
+    stalled_cycles: u64,
+    call_stack: CallStack,
+    delivered_trap: Option<Trap>,
+    uninitialized_reads: usize,
+    dma_queue: VecDeque<Request>,
+    scp: Scp,
+}
+
+impl Snapshot {
+    fn new(cpu: &mut Cpu) -> Self {
+        Snapshot {
+            registers: cpu.registers.clone(),
+            state: cpu.state,
+            increment_pc: cpu.increment_pc,
+            security: cpu.security,
+            secure_fault: cpu.secure_fault,
+            cycles: cpu.cycles,
+            stalled_cycles: cpu.stalled_cycles,
+            call_stack: cpu.call_stack.clone(),
+            delivered_trap: cpu.delivered_trap,
+            uninitialized_reads: cpu.uninitialized_reads.get_mut().len(),
+            dma_queue: cpu.dma_engine.pending(),
+            scp: cpu.scp.clone(),
+        }
+    }
+
+    fn restore(self, cpu: &mut Cpu) {
+        cpu.registers = self.registers;
+        cpu.state = self.state;
+        cpu.increment_pc = self.increment_pc;
+        cpu.security = self.security;
+        cpu.secure_fault = self.secure_fault;
+        cpu.cycles = self.cycles;
+        cpu.stalled_cycles = self.stalled_cycles;
+        cpu.call_stack = self.call_stack;
+        cpu.delivered_trap = self.delivered_trap;
+        cpu.uninitialized_reads
+            .get_mut()
+            .truncate(self.uninitialized_reads);
+        cpu.dma_engine.set_pending(self.dma_queue);
+        cpu.scp = self.scp;
+    }
+}
+
+/// A step that was executed, with everything that is needed to undo it.
+struct Step {
+    /// The state of the processor before the step.
+    snapshot: Snapshot,
+    /// The words in memory that were overwritten by the step.
+    memory: Vec<Overwritten>,
+    /// The TLB before the step, if the step modified it.
+    tlb: Option<Tlb>,
+    /// Whether the step accessed peripherals or wrote external memory, which
+    /// cannot be undone.
+    irreversible: bool,
+}
+
+/// A journal of the most recently executed steps of a processor.
+pub(crate) struct Journal {
+    /// The recorded steps, the oldest one first.
+    steps: VecDeque<Step>,
+    /// The maximum number of steps that are kept.
+    capacity: usize,
+    /// The TLB after the most recent step, to detect modifications.
+    tlb: Tlb,
+}
+
+impl Journal {
+    /// Creates an empty journal for the given processor, which keeps up to
+    /// `capacity` steps.
+    pub fn new(cpu: &Cpu, capacity: usize) -> Self {
+        Journal {
+            steps: VecDeque::new(),
+            capacity,
+            tlb: cpu.memory.tlb.clone(),
+        }
+    }
+
+    /// Gets the number of steps that can be undone.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Records a step that was executed from the given checkpoint.
+    pub fn record(&mut self, checkpoint: Checkpoint, cpu: &mut Cpu) {
+        let irreversible = cpu.io.accesses() != checkpoint.io_accesses
+            || cpu.dma_engine.writes() != checkpoint.external_writes;
+        let memory = cpu.memory.release(checkpoint.savepoint);
+        let tlb = if cpu.memory.tlb != self.tlb {
+            Some(mem::replace(&mut self.tlb, cpu.memory.tlb.clone()))
+        } else {
+            None
+        };
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(Step {
+            snapshot: checkpoint.snapshot,
+            memory,
+            tlb,
+            irreversible,
+        });
+    }
+
+    /// Undoes the most recently recorded step, unless there is none or it
+    /// cannot be undone.
+    pub fn undo(&mut self, cpu: &mut Cpu) -> Result<(), StepBackError> {
+        match self.steps.back() {
+            Some(step) if step.irreversible => return Err(StepBackError::Irreversible),
+            Some(_) => {}
+            None => return Err(StepBackError::Exhausted),
+        }
+        let step = self.steps.pop_back().unwrap();
+
+        cpu.memory.undo(step.memory);
+        if let Some(tlb) = step.tlb {
+            cpu.memory.tlb = tlb.clone();
+            self.tlb = tlb;
+        }
+        step.snapshot.restore(cpu);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Statistics about the instructions that are executed by the processor.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use faucon_asm::InstructionKind;
+
+/// Counts of the instructions that were executed by a [`Cpu`], by their kinds
+/// and by the addresses they were executed at.
+///
+/// Only instructions that completed are counted, so steps that were stalled
+/// by traps or undone in strict mode are not. Statistics are not rewound by
+/// [`Cpu::step_back`] either.
+///
+/// ```
+/// use faucon_asm::{assembler::assemble_str, InstructionKind};
+/// use faucon_emu::cpu::{Cpu, CpuConfig};
+///
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let code = assemble_str("mov $r1 0x3\nsub b32 $r1 $r1 0x1\nbnz 0x2").unwrap();
+/// cpu.load_code(&code);
+///
+/// cpu.start();
+/// for _ in 0..7 {
+///     cpu.step();
+/// }
+///
+/// let stats = cpu.stats();
+/// assert_eq!(stats.instructions(), 7);
+/// assert_eq!(stats.kind_count(InstructionKind::SUB), 3);
+/// assert_eq!(stats.pc_count(0x2), 3);
+/// assert_eq!(stats.pc_count(0x0), 1);
+///
+/// let mut csv = Vec::new();
+/// stats.write_csv(&mut csv).unwrap();
+/// assert!(String::from_utf8(csv).unwrap().contains("pc,0x2,3\n"));
+/// ```
+///
+/// [`Cpu`]: struct.Cpu.html
+/// [`Cpu::step_back`]: struct.Cpu.html#method.step_back
+#[derive(Clone, Debug)]
+pub struct Statistics {
+    /// The number of executed instructions, by their kinds.
+    kinds: HashMap<InstructionKind, u64>,
+    /// The number of executed instructions, by their virtual addresses.
+    pcs: HashMap<u32, u64>,
+}
+
+impl Statistics {
+    /// Creates empty statistics.
+    pub(crate) fn new() -> Self {
+        Statistics {
+            kinds: HashMap::new(),
+            pcs: HashMap::new(),
+        }
+    }
+
+    /// Counts an instruction of the given kind that was executed at the given
+    /// virtual address.
+    pub(crate) fn record(&mut self, pc: u32, kind: InstructionKind) {
+        *self.kinds.entry(kind).or_insert(0) += 1;
+        *self.pcs.entry(pc).or_insert(0) += 1;
+    }
+
+    /// Discards all counts.
+    pub fn clear(&mut self) {
+        self.kinds.clear();
+        self.pcs.clear();
+    }
+
+    /// Gets the total number of executed instructions.
+    pub fn instructions(&self) -> u64 {
+        self.kinds.values().sum()
+    }
+
+    /// Gets the number of executed instructions of the given kind.
+    pub fn kind_count(&self, kind: InstructionKind) -> u64 {
+        self.kinds.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Gets the number of instructions that were executed at the given virtual
+    /// address.
+    pub fn pc_count(&self, pc: u32) -> u64 {
+        self.pcs.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Gets the kinds of the executed instructions along with their counts,
+    /// the most frequently executed ones first.
+    pub fn kinds(&self) -> Vec<(InstructionKind, u64)> {
+        let mut kinds: Vec<_> = self.kinds.iter().map(|(&k, &c)| (k, c)).collect();
+        kinds.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| a.0.mnemonic().cmp(b.0.mnemonic()))
+        });
+
+        kinds
+    }
+
+    /// Gets the virtual addresses of the executed instructions along with
+    /// their counts, the most frequently executed ones first.
+    pub fn pcs(&self) -> Vec<(u32, u64)> {
+        let mut pcs: Vec<_> = self.pcs.iter().map(|(&pc, &c)| (pc, c)).collect();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        pcs
+    }
+
+    /// Writes the statistics as CSV with a `category,key,count` header, where
+    /// every row either counts the instructions of a `kind` by its mnemonic or
+    /// the instructions at a `pc`.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "category,key,count")?;
+        for (kind, count) in self.kinds() {
+            writeln!(writer, "kind,{},{}", kind, count)?;
+        }
+        for (pc, count) in self.pcs() {
+            writeln!(writer, "pc,{:#x},{}", pc, count)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the statistics as a JSON object, which holds the total number of
+    /// `instructions` and objects mapping the mnemonics of the `kinds` and the
+    /// hexadecimal `pcs` to their counts.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "{{\"instructions\":{},\"kinds\":{{",
+            self.instructions()
+        )?;
+        for (i, (kind, count)) in self.kinds().into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(writer, "{}\"{}\":{}", separator, kind, count)?;
+        }
+        write!(writer, "}},\"pcs\":{{")?;
+        for (i, (pc, count)) in self.pcs().into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(writer, "{}\"{:#x}\":{}", separator, pc, count)?;
+        }
+        writeln!(writer, "}}}}")
+    }
+}
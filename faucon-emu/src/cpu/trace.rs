@@ -0,0 +1,247 @@
+//! Canonical instruction traces and their comparison against reference traces.
+
+use std::fmt;
+use std::str::FromStr;
+
+use faucon_asm::{Register, RegisterKind};
+
+use super::{CpuRegisters, PC};
+
+/// An instruction that was executed, as a line of a canonical trace.
+///
+/// A trace entry consists of the address of the instruction, its encoding and
+/// the values of the registers other than the PC that were modified by it. It
+/// is formatted as a line of whitespace-separated fields, which can be
+/// captured from hardware or other emulators and parsed again:
+///
+/// ```
+/// use faucon_asm::Register;
+/// use faucon_emu::cpu::TraceEntry;
+///
+/// let entry: TraceEntry = "00000002 921101 $r1=00000002".parse().unwrap();
+/// assert_eq!(entry.pc, 0x2);
+/// assert_eq!(entry.opcode, vec![0x92, 0x11, 0x01]);
+/// assert_eq!(entry.deltas, vec![("$r1".parse::<Register>().unwrap(), 0x2)]);
+/// assert_eq!(entry.to_string(), "00000002 921101 $r1=00000002");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The virtual address of the instruction.
+    pub pc: u32,
+    /// The bytes that encode the instruction.
+    pub opcode: Vec<u8>,
+    /// The registers that were modified by the instruction along with their
+    /// new values, ordered by their [`Register::index`].
+    ///
+    /// [`Register::index`]: ../../faucon_asm/operands/struct.Register.html#method.index
+    pub deltas: Vec<(Register, u32)>,
+}
+
+impl TraceEntry {
+    /// Creates the trace entry of an instruction from the registers before and
+    /// after it was executed.
+    pub(crate) fn new(pc: u32, opcode: &[u8], before: &CpuRegisters, after: &CpuRegisters) -> Self {
+        let deltas = (0..Register::COUNT)
+            .map(|i| Register(RegisterKind::Gpr, i))
+            .chain(Register::special())
+            .filter(|&reg| reg != PC && before[reg] != after[reg])
+            .map(|reg| (reg, after[reg]))
+            .collect();
+
+        TraceEntry {
+            pc,
+            opcode: opcode.to_vec(),
+            deltas,
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x} ", self.pc)?;
+        for byte in &self.opcode {
+            write!(f, "{:02x}", byte)?;
+        }
+        for (reg, value) in &self.deltas {
+            write!(f, " {}={:08x}", reg, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TraceEntry {
+    type Err = ParseTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseTraceError(s.to_string());
+        let mut fields = s.split_whitespace();
+
+        let pc = fields
+            .next()
+            .and_then(|pc| u32::from_str_radix(pc, 16).ok())
+            .ok_or_else(error)?;
+        let opcode = fields.next().ok_or_else(error)?;
+        if opcode.len() & 1 != 0 {
+            return Err(error());
+        }
+        let opcode = (0..opcode.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(opcode.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+
+        let mut deltas = Vec::new();
+        for field in fields {
+            let mut parts = field.splitn(2, '=');
+            let reg = parts.next().and_then(|reg| reg.parse::<Register>().ok());
+            let value = parts
+                .next()
+                .and_then(|value| u32::from_str_radix(value, 16).ok());
+            match (reg, value) {
+                (Some(reg), Some(value)) => deltas.push((reg, value)),
+                _ => return Err(error()),
+            }
+        }
+        deltas.sort_by_key(|(reg, _)| reg.index());
+
+        Ok(TraceEntry { pc, opcode, deltas })
+    }
+}
+
+/// An error that occurs when parsing a line of a trace that is not a valid
+/// [`TraceEntry`].
+///
+/// [`TraceEntry`]: struct.TraceEntry.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseTraceError(pub String);
+
+impl fmt::Display for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid trace entry `{}`", self.0)
+    }
+}
+
+/// Parses a trace with one [`TraceEntry`] per line, skipping empty lines and
+/// comments that start with `#`.
+///
+/// [`TraceEntry`]: struct.TraceEntry.html
+pub fn parse_trace(trace: &str) -> Result<Vec<TraceEntry>, ParseTraceError> {
+    trace
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// The first instruction of a trace that deviated from the reference trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the instruction in the trace.
+    pub index: usize,
+    /// The entry of the reference trace.
+    pub expected: TraceEntry,
+    /// The entry of the instruction that was executed instead.
+    pub actual: TraceEntry,
+}
+
+/// A reference trace that the executed instructions are compared against,
+/// which is installed through [`Cpu::set_golden_trace`].
+///
+/// Execution stops in the debugger with a [`DebugStop::TraceDivergence`] at
+/// the first instruction that deviates from the reference, after which no
+/// further instructions are compared. The comparison is not rewound by
+/// [`Cpu::step_back`].
+///
+/// ```
+/// use faucon_asm::assembler::assemble_str;
+/// use faucon_emu::cpu::{parse_trace, Cpu, CpuConfig, DebugStop, PC};
+///
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let code = assemble_str("mov $r1 0x3\nsub b32 $r1 $r1 0x1\nbnz 0x2").unwrap();
+/// cpu.load_code(&code);
+///
+/// let reference = "
+///     00000000 0103 $r1=00000003
+///     00000002 921101 $r1=00000002
+///     00000005 f41bfd
+///     00000002 921101 $r1=00000000
+/// ";
+/// cpu.set_golden_trace(parse_trace(reference).unwrap());
+/// cpu.start();
+/// while cpu.debug_stop().is_none() {
+///     cpu.step();
+/// }
+///
+/// assert_eq!(cpu.debug_stop(), Some(DebugStop::TraceDivergence(3)));
+/// let divergence = cpu.golden_trace().unwrap().divergence().unwrap();
+/// assert_eq!(divergence.actual.to_string(), "00000002 921101 $r1=00000001");
+/// assert_eq!(cpu.registers[PC], 0x5);
+/// ```
+///
+/// [`Cpu::set_golden_trace`]: struct.Cpu.html#method.set_golden_trace
+/// [`Cpu::step_back`]: struct.Cpu.html#method.step_back
+/// [`DebugStop::TraceDivergence`]: enum.DebugStop.html#variant.TraceDivergence
+#[derive(Clone, Debug)]
+pub struct GoldenTrace {
+    /// The entries of the reference trace.
+    reference: Vec<TraceEntry>,
+    /// The index of the entry that is compared next.
+    position: usize,
+    /// The first divergence from the reference, if any.
+    divergence: Option<Divergence>,
+}
+
+impl GoldenTrace {
+    /// Creates a comparator for the given reference trace.
+    pub(crate) fn new(reference: Vec<TraceEntry>) -> Self {
+        GoldenTrace {
+            reference,
+            position: 0,
+            divergence: None,
+        }
+    }
+
+    /// Compares the entry of an executed instruction against the reference,
+    /// returning the index of the entry if this is the first divergence.
+    pub(crate) fn check(&mut self, actual: TraceEntry) -> Option<usize> {
+        if self.divergence.is_some() {
+            return None;
+        }
+        let index = self.position;
+        let expected = self.reference.get(index)?;
+        self.position += 1;
+
+        if *expected == actual {
+            None
+        } else {
+            self.divergence = Some(Divergence {
+                index,
+                expected: expected.clone(),
+                actual,
+            });
+
+            Some(index)
+        }
+    }
+
+    /// Gets the number of instructions that matched the reference so far.
+    pub fn matched(&self) -> usize {
+        match self.divergence {
+            Some(ref divergence) => divergence.index,
+            None => self.position,
+        }
+    }
+
+    /// Checks whether all instructions of the reference were executed without
+    /// a divergence.
+    pub fn is_complete(&self) -> bool {
+        self.divergence.is_none() && self.position == self.reference.len()
+    }
+
+    /// Gets the first divergence from the reference, if any.
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+}
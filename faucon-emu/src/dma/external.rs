@@ -0,0 +1,330 @@
+//! Backends for the memory that is attached to the ports of the DMA engine.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+/// Memory outside of the Falcon that is accessed through a DMA port.
+///
+/// Backends decide how much of the address space they cover. Reads from
+/// addresses that are not covered return zeroes and writes to them are
+/// discarded, so that transfers never fail.
+pub trait ExternalMemory {
+    /// Reads `buffer.len()` bytes of memory, starting at the given address.
+    fn read(&self, address: u64, buffer: &mut [u8]);
+
+    /// Writes `data` to memory, starting at the given address.
+    fn write(&mut self, address: u64, data: &[u8]);
+}
+
+/// Memory that is backed by a vector. Reads past its end return zeroes and
+/// writes past its end are discarded.
+impl ExternalMemory for Vec<u8> {
+    fn read(&self, address: u64, buffer: &mut [u8]) {
+        read_slice(self, address, buffer);
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) {
+        write_slice(self, address, data);
+    }
+}
+
+/// The size of the pages in which [`SparseMemory`] is allocated.
+///
+/// [`SparseMemory`]: struct.SparseMemory.html
+pub const SPARSE_PAGE_SIZE: usize = 0x1000;
+
+/// Memory of a fixed size that is allocated in pages of [`SPARSE_PAGE_SIZE`]
+/// bytes as they are written, which suits large address spaces like a
+/// framebuffer that are only touched in a few places.
+///
+/// Pages that were never written read as zeroes. Reads past the end of the
+/// memory return zeroes and writes past its end are discarded.
+///
+/// ```
+/// use faucon_emu::dma::{ExternalMemory, SparseMemory};
+///
+/// let mut memory = SparseMemory::new(512 << 20);
+/// memory.write(0x1234_5FFE, &[1, 2, 3, 4]);
+/// assert_eq!(memory.allocated_pages(), 2);
+///
+/// let mut buffer = [0xFF; 6];
+/// memory.read(0x1234_5FFD, &mut buffer);
+/// assert_eq!(buffer, [0, 1, 2, 3, 4, 0]);
+/// ```
+///
+/// [`SPARSE_PAGE_SIZE`]: constant.SPARSE_PAGE_SIZE.html
+#[derive(Clone, Debug)]
+pub struct SparseMemory {
+    /// The size of the memory in bytes.
+    size: u64,
+    /// The pages that were written, by their indices.
+    pages: HashMap<u64, Box<[u8]>>,
+}
+
+impl SparseMemory {
+    /// Creates memory of the given size in bytes without allocating any of it.
+    pub fn new(size: u64) -> Self {
+        SparseMemory {
+            size,
+            pages: HashMap::new(),
+        }
+    }
+
+    /// Gets the size of the memory in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Gets the number of pages that were allocated so far.
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl ExternalMemory for SparseMemory {
+    fn read(&self, address: u64, buffer: &mut [u8]) {
+        // Writes never reach past the end, so neither do the pages.
+        for_each_page(address, buffer.len(), |index, offset, range| {
+            let chunk = &mut buffer[range];
+            match self.pages.get(&index) {
+                Some(page) => read_slice(page, offset as u64, chunk),
+                None => chunk.iter_mut().for_each(|byte| *byte = 0),
+            }
+        });
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) {
+        let data = &data[..covered(self.size, address, data.len())];
+        let pages = &mut self.pages;
+        for_each_page(address, data.len(), |index, offset, range| {
+            let page = pages
+                .entry(index)
+                .or_insert_with(|| vec![0; SPARSE_PAGE_SIZE].into_boxed_slice());
+            page[offset..offset + range.len()].copy_from_slice(&data[range]);
+        });
+    }
+}
+
+/// Memory that is backed by a file, which is accessed on every transfer
+/// rather than being loaded up front.
+///
+/// The memory covers the length of the file at the time it was opened. Reads
+/// past its end return zeroes and writes past its end are discarded. As
+/// transfers cannot fail, the first I/O error that occurs is kept until it
+/// is taken through [`FileMemory::take_error`].
+///
+/// ```
+/// use std::fs::OpenOptions;
+///
+/// use faucon_emu::dma::{ExternalMemory, FileMemory};
+///
+/// let path = std::env::temp_dir().join("faucon-file-memory");
+/// let file = OpenOptions::new()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .truncate(true)
+///     .open(&path)
+///     .unwrap();
+/// file.set_len(0x1000).unwrap();
+///
+/// let mut memory = FileMemory::new(file).unwrap();
+/// memory.write(0xFFE, &[1, 2, 3, 4]);
+///
+/// let mut buffer = [0xFF; 4];
+/// memory.read(0xFFE, &mut buffer);
+/// assert_eq!(buffer, [1, 2, 0, 0]);
+/// assert!(memory.take_error().is_none());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+///
+/// [`FileMemory::take_error`]: struct.FileMemory.html#method.take_error
+#[derive(Debug)]
+pub struct FileMemory {
+    /// The file that backs the memory.
+    file: File,
+    /// The size of the memory in bytes.
+    size: u64,
+    /// The first I/O error that occurred during a transfer, if any.
+    error: RefCell<Option<io::Error>>,
+}
+
+impl FileMemory {
+    /// Creates memory that is backed by the given file, which must be opened
+    /// for writing if data should be stored in it.
+    pub fn new(file: File) -> io::Result<Self> {
+        let size = file.metadata()?.len();
+
+        Ok(FileMemory {
+            file,
+            size,
+            error: RefCell::new(None),
+        })
+    }
+
+    /// Gets the size of the memory in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Takes the first I/O error that occurred during a transfer since the
+    /// last call, if any.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.get_mut().take()
+    }
+
+    // Keeps the given error, unless an earlier one wasn't taken yet.
+    fn record_error(&self, error: io::Error) {
+        self.error.borrow_mut().get_or_insert(error);
+    }
+
+    // Reads from the file at the given address, which must be covered.
+    fn read_at(&self, address: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(address))?;
+        file.read_exact(buffer)
+    }
+
+    // Writes to the file at the given address, which must be covered.
+    fn write_at(&self, address: u64, data: &[u8]) -> io::Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(address))?;
+        file.write_all(data)
+    }
+}
+
+impl ExternalMemory for FileMemory {
+    fn read(&self, address: u64, buffer: &mut [u8]) {
+        let len = covered(self.size, address, buffer.len());
+        if len != 0 {
+            if let Err(e) = self.read_at(address, &mut buffer[..len]) {
+                self.record_error(e);
+                buffer[..len].iter_mut().for_each(|byte| *byte = 0);
+            }
+        }
+        buffer[len..].iter_mut().for_each(|byte| *byte = 0);
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) {
+        let len = covered(self.size, address, data.len());
+        if len == 0 {
+            return;
+        }
+
+        if let Err(e) = self.write_at(address, &data[..len]) {
+            self.record_error(e);
+        }
+    }
+}
+
+/// Memory that is backed by a memory mapping, either of a file or of
+/// anonymous memory that the operating system only allocates as it is
+/// touched.
+///
+/// Reads past the end of the mapping return zeroes and writes past its end
+/// are discarded.
+///
+/// ```
+/// use faucon_emu::dma::{ExternalMemory, MappedMemory};
+///
+/// let mut memory = MappedMemory::anonymous(256 << 20).unwrap();
+/// memory.write(0x0FFF_FFFE, &[1, 2, 3, 4]);
+///
+/// let mut buffer = [0xFF; 4];
+/// memory.read(0x0FFF_FFFE, &mut buffer);
+/// assert_eq!(buffer, [1, 2, 0, 0]);
+/// ```
+#[cfg(feature = "mmap")]
+pub struct MappedMemory {
+    /// The mapping that backs the memory.
+    map: memmap::MmapMut,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedMemory {
+    /// Maps the given file, which must be opened for reading and writing, as
+    /// memory. Data that is stored in the memory is written back to it.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified, neither by this process nor
+    /// by any other one, as long as it is mapped. Otherwise, accesses to the
+    /// memory are undefined behavior.
+    pub unsafe fn new(file: &File) -> io::Result<Self> {
+        let map = memmap::MmapMut::map_mut(file)?;
+
+        Ok(MappedMemory { map })
+    }
+
+    /// Maps anonymous memory of the given size in bytes, which reads as
+    /// zeroes until it is written.
+    pub fn anonymous(size: usize) -> io::Result<Self> {
+        let map = memmap::MmapMut::map_anon(size)?;
+
+        Ok(MappedMemory { map })
+    }
+
+    /// Gets the size of the memory in bytes.
+    pub fn size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    /// Writes the data that was stored in the memory back to the mapped file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ExternalMemory for MappedMemory {
+    fn read(&self, address: u64, buffer: &mut [u8]) {
+        read_slice(&self.map, address, buffer);
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) {
+        write_slice(&mut self.map, address, data);
+    }
+}
+
+// Gets the number of bytes of an access of the given length that are covered
+// by memory of the given size.
+fn covered(size: u64, address: u64, len: usize) -> usize {
+    size.saturating_sub(address).min(len as u64) as usize
+}
+
+// Reads from a slice of memory, reading zeroes past its end.
+fn read_slice(memory: &[u8], address: u64, buffer: &mut [u8]) {
+    let len = covered(memory.len() as u64, address, buffer.len());
+    if len != 0 {
+        let start = address as usize;
+        buffer[..len].copy_from_slice(&memory[start..start + len]);
+    }
+    buffer[len..].iter_mut().for_each(|byte| *byte = 0);
+}
+
+// Writes to a slice of memory, discarding the data past its end.
+fn write_slice(memory: &mut [u8], address: u64, data: &[u8]) {
+    let len = covered(memory.len() as u64, address, data.len());
+    if len != 0 {
+        let start = address as usize;
+        memory[start..start + len].copy_from_slice(&data[..len]);
+    }
+}
+
+// Splits an access into the sparse pages it touches, passing the index of
+// every page, the offset into it and the range of the access that it holds.
+fn for_each_page<F: FnMut(u64, usize, Range<usize>)>(address: u64, len: usize, mut f: F) {
+    let mut done = 0;
+    while done < len {
+        let current = address.wrapping_add(done as u64);
+        let index = current / SPARSE_PAGE_SIZE as u64;
+        let offset = (current % SPARSE_PAGE_SIZE as u64) as usize;
+        let chunk = (SPARSE_PAGE_SIZE - offset).min(len - done);
+
+        f(index, offset, done..done + chunk);
+        done += chunk;
+    }
+}
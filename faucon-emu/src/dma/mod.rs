@@ -1,12 +1,19 @@
 //! Implementation of the Falcon DMA engine.
 
+use std::collections::VecDeque;
 use std::convert::TryInto;
-use std::ptr;
+use std::fmt;
 
-use crate::cpu::Cpu;
+use faucon_asm::{Instruction, InstructionKind};
+
+use crate::cpu::{Cpu, CpuRegisters, CX, CX_MASK, XCBASE, XDBASE, XTARGETS};
+
+pub use external::*;
+
+mod external;
 
 /// Supported request modes that the DMA engine can process.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RequestMode {
     /// A DMA request to load Falcon code from external memory.
     CodeLoad,
@@ -18,7 +25,7 @@ pub enum RequestMode {
 
 /// A Falcon DMA request to perform a code/data transfer.
 // TODO: Figure out the missing secret flag.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Request {
     pub mode: RequestMode,
     external_port: u8,
@@ -27,6 +34,7 @@ pub struct Request {
     local_address: u16,
     size: Option<u8>,
     secret: Option<bool>,
+    crypto_override: bool,
 }
 
 impl Request {
@@ -48,36 +56,144 @@ impl Request {
             local_address,
             size,
             secret,
+            crypto_override: false,
+        }
+    }
+
+    /// Constructs the DMA request that is issued by an `xcld`, `xdld` or `xdst`
+    /// instruction, given the current state of the CPU registers.
+    ///
+    /// The first register operand of these instructions holds the external
+    /// offset, while the second one holds the local address in its low 16 bits
+    /// and the xfer size in the 3 bits above. The port is selected through the
+    /// `$xtargets` register and the external base through `$xcbase` or `$xdbase`,
+    /// respectively. Data transfers target the SCP instead of external memory
+    /// while `$cx` holds a count of transfers to redirect. Other instructions
+    /// don't issue any request.
+    pub fn from_instruction(insn: &Instruction, registers: &CpuRegisters) -> Option<Self> {
+        let (mode, port_shift, base) = match insn.kind() {
+            InstructionKind::XCLD => (RequestMode::CodeLoad, 0, XCBASE),
+            InstructionKind::XDLD => (RequestMode::DataLoad, 8, XDBASE),
+            InstructionKind::XDST => (RequestMode::DataStore, 12, XDBASE),
+            _ => return None,
+        };
+
+        // Code loads issued by instructions are never secret.
+        let operands = insn.operands();
+        let local = registers[operands[1]];
+        let (size, secret) = match mode {
+            RequestMode::CodeLoad => (None, Some(false)),
+            _ => (Some((local >> 16 & 0x7) as u8), None),
+        };
+
+        let mut request = Request::new(
+            mode,
+            (registers[XTARGETS] >> port_shift & 0x7) as u8,
+            registers[base],
+            registers[operands[0]],
+            local as u16,
+            size,
+            secret,
+        );
+        request.set_crypto_override(mode != RequestMode::CodeLoad && registers[CX] & CX_MASK != 0);
+
+        Some(request)
+    }
+
+    /// Checks whether the data transfer targets the SCP instead of external
+    /// memory.
+    ///
+    /// Redirected stores append their data to the input stream of the SCP,
+    /// while redirected loads take their data from its output stream.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig, CX};
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.dma_engine.attach(0, Box::new(vec![0u8; 0x100])).unwrap();
+    /// cpu.memory.write_data(0, &[0xAA; 0x20]);
+    ///
+    /// let source = "mov $r1 0x0\nmov $r2 0x20000\ncxset 0x1\nxdst $r1 $r2\n\
+    ///               mov $r1 0x10\nmov $r2 0x20010\nxdst $r1 $r2\nxdwait";
+    /// let code = assemble_str(source).unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// for _ in 0..4 {
+    ///     cpu.step();
+    /// }
+    /// assert_eq!(cpu.registers[CX], 0);
+    /// for _ in 0..6 {
+    ///     cpu.step();
+    /// }
+    ///
+    /// // Only the second store reached external memory.
+    /// let mut buffer = [0; 0x20];
+    /// cpu.dma_engine.read(0, 0, &mut buffer);
+    /// assert_eq!(buffer[..0x10], [0; 0x10]);
+    /// assert_eq!(buffer[0x10..], [0xAA; 0x10]);
+    /// ```
+    pub fn crypto_override(&self) -> bool {
+        self.crypto_override
+    }
+
+    /// Sets whether the data transfer targets the SCP instead of external
+    /// memory. Code loads are never redirected.
+    pub fn set_crypto_override(&mut self, crypto_override: bool) {
+        self.crypto_override = crypto_override && self.mode != RequestMode::CodeLoad;
+    }
+
+    /// Checks whether the request can be carried out by the DMA engine of a
+    /// processor with the given IMEM size in bytes.
+    ///
+    /// Data transfers must use one of the xfer sizes 0 through 6 and code loads
+    /// must target a page within IMEM. Addresses that are not aligned to the
+    /// xfer size are valid, as their low bits are ignored.
+    ///
+    /// ```
+    /// use faucon_emu::dma::{Request, RequestMode};
+    ///
+    /// let load = Request::new(RequestMode::DataLoad, 0, 0, 0x4, 0x2, Some(7), None);
+    /// assert!(!load.is_valid(0x8000));
+    ///
+    /// let load = Request::new(RequestMode::CodeLoad, 0, 0, 0x80, 0xF080, None, Some(false));
+    /// assert!(!load.is_valid(0x8000));
+    /// assert!(load.is_valid(0x10000));
+    /// assert_eq!((load.local_party(), load.vaddr()), (0xF000, 0x0));
+    /// ```
+    pub fn is_valid(&self, imem_size: u32) -> bool {
+        match self.mode {
+            RequestMode::CodeLoad => u32::from(self.local_party()) < imem_size,
+            _ => matches!(self.size, Some(size) if size <= 6),
         }
     }
+
     /// Gets the port and the start address of the external party for the xfer
     /// operation.
-    pub fn external_party(&self) -> (u8, usize) {
-        // The external offset always has to be aligned to the xfer size.
-        assert_eq!(self.external_offset % self.xfer_size() as u32, 0);
-
+    ///
+    /// The low bits of the external offset that are not aligned to the xfer
+    /// size are ignored.
+    pub fn external_party(&self) -> (u8, u64) {
         (
             self.external_port,
-            ((self.external_base << 8) + self.external_offset) as usize,
+            (u64::from(self.external_base) << 8) + u64::from(self.vaddr()),
         )
     }
 
     /// Gets the virtual destination address for code xfers.
     pub fn vaddr(&self) -> u32 {
-        // The external offset always has to be aligned to the xfer size.
-        assert_eq!(self.external_offset % self.xfer_size() as u32, 0);
-
         // Since the external offset also represents the virtual address
         // to be used in Falcon IMEM, return it as such.
-        self.external_offset
+        self.external_offset & !(self.xfer_data_size() as u32 - 1)
     }
 
     /// The physical start address of the local party for the xfer operation.
+    ///
+    /// The low bits of the local address that are not aligned to the xfer size
+    /// are ignored.
     pub fn local_party(&self) -> u16 {
-        // The local address always has to be aligned to the xfer size.
-        assert_eq!(self.local_address % self.xfer_size() as u16, 0);
-
-        self.local_address
+        self.local_address & !(self.xfer_data_size() as u16 - 1)
     }
 
     /// Gets the xfer size that indicates how much data to transfer.
@@ -85,7 +201,13 @@ impl Request {
     /// The actual amount of bytes to copy can be obtained through
     /// [`Request::xfer_data_size`].
     ///
+    /// # Panics
+    ///
+    /// Panics if a data transfer has no valid xfer size, which can be checked
+    /// through [`Request::is_valid`].
+    ///
     /// [`Request::xfer_data_size`]: struct.Request.html#method.xfer_data_size
+    /// [`Request::is_valid`]: struct.Request.html#method.is_valid
     pub fn xfer_size(&self) -> u8 {
         if self.mode == RequestMode::CodeLoad {
             // For code xfers, the size is effectively always 6.
@@ -118,76 +240,293 @@ impl Request {
     }
 }
 
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (operation, memory) = match self.mode {
+            RequestMode::CodeLoad => ("load code", "IMEM"),
+            RequestMode::DataLoad => ("load data", "DMEM"),
+            RequestMode::DataStore => ("store data", "DMEM"),
+        };
+
+        // Unlike `xfer_size`, this must not panic on invalid sizes.
+        match self.mode {
+            RequestMode::CodeLoad => write!(f, "{} {:#x} bytes", operation, 4 << 6)?,
+            _ => match self.size {
+                Some(size) if size <= 6 => write!(f, "{} {:#x} bytes", operation, 4 << size)?,
+                Some(size) => write!(f, "{} with invalid xfer size {}", operation, size)?,
+                None => write!(f, "{} with unknown xfer size", operation)?,
+            },
+        }
+
+        let external = (self.external_base << 8).wrapping_add(self.external_offset);
+        match self.mode {
+            RequestMode::DataStore => write!(
+                f,
+                " from {} {:#x} to port {} at {:#x}",
+                memory, self.local_address, self.external_port, external
+            ),
+            _ => write!(
+                f,
+                " from port {} at {:#x} to {} {:#x}",
+                self.external_port, external, memory, self.local_address
+            ),
+        }
+    }
+}
+
+/// The maximum number of pending [`Request`]s in the DMA queue.
+///
+/// [`Request`]: struct.Request.html
+pub const QUEUE_DEPTH: usize = 8;
+
+/// The number of ports through which external memory can be accessed.
+pub const PORTS: usize = 8;
+
+/// An error that occurs when external memory is attached to a port that the
+/// DMA [`Engine`] doesn't have.
+///
+/// [`Engine`]: struct.Engine.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidPortError {
+    /// The port that was requested.
+    pub port: u8,
+}
+
+impl fmt::Display for InvalidPortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "port {} does not exist, the DMA engine has {} ports",
+            self.port, PORTS
+        )
+    }
+}
+
 /// Representation of the Falcon DMA engine.
 ///
 /// The internal controller allows for asynchronous copies between Falcon DMEM/IMEM
-/// and external memory, issued through DMA [`Request`]s.
+/// and [`ExternalMemory`] that is attached to its ports, issued through DMA
+/// [`Request`]s. Requests are queued up to a depth of [`QUEUE_DEPTH`] and the
+/// processor completes one of them in every step.
+///
+/// ```
+/// use faucon_emu::dma::Engine;
+///
+/// let mut engine = Engine::new();
+/// engine.attach(1, Box::new(vec![0u8; 0x1000])).unwrap();
+///
+/// let mut buffer = [0xFF; 4];
+/// engine.write(1, 0x800, &[1, 2, 3, 4]);
+/// engine.read(1, 0x800, &mut buffer);
+/// assert_eq!(buffer, [1, 2, 3, 4]);
+/// ```
 ///
+/// [`ExternalMemory`]: trait.ExternalMemory.html
 /// [`Request`]: struct.Request.html
-// TODO: Make DMA engine capable of processing request asynchronously in separate threads.
-#[derive(Debug)]
+/// [`QUEUE_DEPTH`]: constant.QUEUE_DEPTH.html
 pub struct Engine {
     /// A queue of DMA [`Request`]s to be processed by the engine.
     ///
     /// [`Request`]: struct.Request.html
-    queue: Vec<Request>,
+    queue: VecDeque<Request>,
+    /// The external memory that is attached to each of the ports.
+    ports: Vec<Option<Box<dyn ExternalMemory>>>,
+    /// The number of writes to external memory.
+    writes: u64,
 }
 
 impl Engine {
-    /// Creates a new instance of the DMA engine.
+    /// Creates a new instance of the DMA engine without any memory attached.
     pub fn new() -> Self {
-        Engine { queue: Vec::new() }
+        Engine {
+            queue: VecDeque::with_capacity(QUEUE_DEPTH),
+            ports: (0..PORTS).map(|_| None).collect(),
+            writes: 0,
+        }
+    }
+
+    /// Attaches external memory to the given port and returns the memory that
+    /// was previously attached, if any.
+    ///
+    /// Fails if the engine has no such port, in which case the memory is
+    /// dropped.
+    ///
+    /// ```
+    /// use faucon_emu::dma::{Engine, PORTS};
+    ///
+    /// let mut engine = Engine::new();
+    /// assert!(engine.attach(1, Box::new(vec![0u8; 0x100])).unwrap().is_none());
+    /// assert!(engine.attach(1, Box::new(vec![0u8; 0x100])).unwrap().is_some());
+    /// let error = engine.attach(PORTS as u8, Box::new(vec![0u8; 0x100])).err();
+    /// assert_eq!(error.map(|e| e.port), Some(PORTS as u8));
+    ///
+    /// assert!(engine.detach(1).is_some());
+    /// assert!(engine.detach(PORTS as u8).is_none());
+    /// ```
+    pub fn attach(
+        &mut self,
+        port: u8,
+        memory: Box<dyn ExternalMemory>,
+    ) -> Result<Option<Box<dyn ExternalMemory>>, InvalidPortError> {
+        match self.ports.get_mut(port as usize) {
+            Some(slot) => Ok(slot.replace(memory)),
+            None => Err(InvalidPortError { port }),
+        }
+    }
+
+    /// Detaches the external memory from the given port, if there is such a
+    /// port and memory is attached to it.
+    pub fn detach(&mut self, port: u8) -> Option<Box<dyn ExternalMemory>> {
+        self.ports.get_mut(port as usize)?.take()
     }
 
     /// Checks whether the DMA engine is currently busy processing
     /// requests.
     pub fn is_busy(&self) -> bool {
-        // TODO
-        false
+        !self.queue.is_empty()
+    }
+
+    /// Checks whether the DMA queue is full, so that no further requests
+    /// can be submitted.
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= QUEUE_DEPTH
+    }
+
+    /// Checks whether any requests of the given mode are still pending.
+    pub fn is_pending(&self, mode: RequestMode) -> bool {
+        self.queue.iter().any(|request| request.mode == mode)
+    }
+
+    /// Checks whether any data loads or stores are still pending.
+    pub fn is_data_pending(&self) -> bool {
+        self.is_pending(RequestMode::DataLoad) || self.is_pending(RequestMode::DataStore)
     }
 
     /// Enqueues a new [`Request`] in the DMA queue.
     ///
-    /// # Safety
+    /// If the queue is full, the request is handed back.
+    ///
+    /// [`Request`]: struct.Request.html
+    pub fn enqueue(&mut self, request: Request) -> Result<(), Request> {
+        if self.is_full() {
+            return Err(request);
+        }
+
+        self.queue.push_back(request);
+        Ok(())
+    }
+
+    /// Takes the oldest [`Request`] out of the DMA queue for processing.
+    ///
+    /// [`Request`]: struct.Request.html
+    pub(crate) fn dequeue(&mut self) -> Option<Request> {
+        self.queue.pop_front()
+    }
+
+    /// Checks whether no [`Request`] is pending.
+    ///
+    /// [`Request`]: struct.Request.html
+    pub(crate) fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Gets a copy of the pending [`Request`]s, the oldest one first.
     ///
-    /// Due to raw pointer arithmetic used when processing a request,
-    /// the user must ensure that all the memory addresses and offsets
-    /// denoted in a request are valid and aligned, otherwise undefined
-    /// behavior will be triggered.
+    /// [`Request`]: struct.Request.html
+    pub(crate) fn pending(&self) -> VecDeque<Request> {
+        self.queue.clone()
+    }
+
+    /// Replaces the pending [`Request`]s.
     ///
     /// [`Request`]: struct.Request.html
-    pub unsafe fn enqueue(&mut self, request: Request, cpu: &mut Cpu) {
-        self.queue.push(request);
-
-        // TODO
-        self.process_request(cpu);
-    }
-
-    /// Executes a DMA request.
-    unsafe fn process_request(&mut self, cpu: &mut Cpu) {
-        if let Some(request) = self.queue.pop() {
-            match request.mode {
-                RequestMode::CodeLoad => {
-                    let destination = request.local_party();
-                    let (_, source) = request.external_party();
-                    let size = request.xfer_data_size();
-
-                    // TODO: Add support for secret xfers.
-
-                    // Copy the code to a vector for more idiomatic interaction with it.
-                    let mut data = Vec::with_capacity(size);
-                    ptr::copy_nonoverlapping(source as *const u8, data.as_mut_ptr(), size);
-
-                    for (index, chunk) in data.chunks(4).enumerate() {
-                        cpu.upload_code(
-                            destination + (index << 2) as u16,
-                            request.vaddr(),
-                            u32::from_le_bytes(chunk.try_into().unwrap()),
-                        )
-                    }
+    pub(crate) fn set_pending(&mut self, queue: VecDeque<Request>) {
+        self.queue = queue;
+    }
+
+    /// Gets the number of writes to external memory, which cannot be undone.
+    pub(crate) fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Reads from the external memory at the given port. Without any memory
+    /// attached or without such a port, zeroes are read.
+    pub fn read(&self, port: u8, address: u64, buffer: &mut [u8]) {
+        match self.ports.get(port as usize) {
+            Some(Some(memory)) => memory.read(address, buffer),
+            _ => buffer.iter_mut().for_each(|byte| *byte = 0),
+        }
+    }
+
+    /// Writes to the external memory at the given port. Without any memory
+    /// attached or without such a port, the data is discarded.
+    pub fn write(&mut self, port: u8, address: u64, data: &[u8]) {
+        if let Some(Some(memory)) = self.ports.get_mut(port as usize) {
+            self.writes += 1;
+            memory.write(address, data);
+        }
+    }
+}
+
+/// Completes the oldest pending [`Request`] of the processor's DMA engine, if
+/// any.
+///
+/// Code loads fill a physical IMEM page, which becomes usable when the load
+/// completes. Data transfers that were redirected to the SCP through `$cx`
+/// exchange their data with its streams rather than with external memory.
+///
+/// [`Request`]: struct.Request.html
+pub(crate) fn process_next_request(cpu: &mut Cpu) {
+    let request = match cpu.dma_engine.dequeue() {
+        Some(request) => request,
+        None => return,
+    };
+
+    // Invalid requests can only be enqueued by the host, as the processor
+    // traps on them, and are dropped.
+    if !request.is_valid(cpu.imem_size() as u32) {
+        return;
+    }
+
+    let (port, external) = request.external_party();
+    let local = request.local_party() as usize;
+    let mut data = vec![0; request.xfer_data_size()];
+    match request.mode {
+        RequestMode::CodeLoad => {
+            cpu.dma_engine.read(port, external, &mut data);
+
+            for (index, chunk) in data.chunks(4).enumerate() {
+                cpu.upload_code(
+                    (local + (index << 2)) as u16,
+                    request.vaddr(),
+                    u32::from_le_bytes(chunk.try_into().unwrap()),
+                    request.secret(),
+                )
+            }
+        }
+        RequestMode::DataLoad => {
+            if request.crypto_override() {
+                for chunk in data.chunks_mut(16) {
+                    let block = cpu.scp.pop_output().unwrap_or([0; 16]);
+                    chunk.copy_from_slice(&block[..chunk.len()]);
+                }
+            } else {
+                cpu.dma_engine.read(port, external, &mut data);
+            }
+
+            cpu.memory.write_data(local as u32, &data);
+        }
+        RequestMode::DataStore => {
+            cpu.memory.data.read(local as u32, &mut data);
+
+            if request.crypto_override() {
+                for chunk in data.chunks(16) {
+                    let mut block = [0; 16];
+                    block[..chunk.len()].copy_from_slice(chunk);
+                    cpu.scp.push_input(block);
                 }
-                RequestMode::DataLoad => todo!("Implement this"),
-                RequestMode::DataStore => todo!("Implement this"),
+            } else {
+                cpu.dma_engine.write(port, external, &data);
             }
         }
     }
@@ -0,0 +1,584 @@
+//! Emulation of the register file through which the host controls the Falcon.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::cpu::{Cpu, ExecutionState, PC};
+use crate::io::{IoBus, OverlapError, Peripheral};
+
+/// The offset of the register that raises interrupts when written.
+pub const IRQSSET: u32 = 0x000;
+/// The offset of the register that acknowledges interrupts when written.
+pub const IRQSCLR: u32 = 0x004;
+/// The offset of the register that holds the raised interrupts.
+pub const IRQSTAT: u32 = 0x008;
+/// The offset of the register that selects between edge-triggered and
+/// level-triggered interrupts.
+pub const IRQMODE: u32 = 0x00C;
+/// The offset of the register that enables interrupts when written.
+pub const IRQMSET: u32 = 0x010;
+/// The offset of the register that disables interrupts when written.
+pub const IRQMCLR: u32 = 0x014;
+/// The offset of the register that holds the enabled interrupts.
+pub const IRQMASK: u32 = 0x018;
+/// The offset of the register that routes interrupts to the host or to the
+/// Falcon.
+///
+/// Bits 0 through 15 route the corresponding interrupts to the host when set.
+/// Interrupts that are routed to the Falcon are delivered to the line that is
+/// selected by bits 16 through 31.
+pub const IRQDEST: u32 = 0x01C;
+/// The offset of the first scratch register for communication between the host
+/// and the firmware.
+pub const MAILBOX0: u32 = 0x040;
+/// The offset of the second scratch register for communication between the
+/// host and the firmware.
+pub const MAILBOX1: u32 = 0x044;
+/// The offset of the register that controls the execution of the processor.
+pub const CPUCTL: u32 = 0x100;
+/// The offset of the register that holds the address at which the processor
+/// starts execution.
+pub const BOOTVEC: u32 = 0x104;
+/// The offset of the register that describes the sizes of IMEM and DMEM, as
+/// laid out in [`UC_CAPS`].
+///
+/// [`UC_CAPS`]: ../cpu/constant.UC_CAPS.html
+pub const HWCFG: u32 = 0x108;
+/// The offset of the register that identifies the processor, as laid out in
+/// [`UC_CAPS2`].
+///
+/// [`UC_CAPS2`]: ../cpu/constant.UC_CAPS2.html
+pub const HWCFG1: u32 = 0x12C;
+/// The offset of the control register of the first IMEM access port.
+///
+/// The control registers of the further ports follow at intervals of
+/// [`IMEM_PORT_STRIDE`].
+///
+/// [`IMEM_PORT_STRIDE`]: constant.IMEM_PORT_STRIDE.html
+pub const IMEMC: u32 = 0x180;
+/// The offset of the data register of the first IMEM access port.
+pub const IMEMD: u32 = 0x184;
+/// The offset of the tag register of the first IMEM access port, which holds
+/// the virtual page that uploaded code is mapped to.
+///
+/// The tag is applied when the first word of a physical page is written, so
+/// it must be set before every page that is uploaded.
+pub const IMEMT: u32 = 0x188;
+/// The distance between the registers of two consecutive IMEM access ports.
+pub const IMEM_PORT_STRIDE: u32 = 0x10;
+/// The number of IMEM access ports.
+pub const IMEM_PORTS: usize = 4;
+/// The offset of the control register of the first DMEM access port.
+///
+/// The control registers of the further ports follow at intervals of
+/// [`DMEM_PORT_STRIDE`].
+///
+/// [`DMEM_PORT_STRIDE`]: constant.DMEM_PORT_STRIDE.html
+pub const DMEMC: u32 = 0x1C0;
+/// The offset of the data register of the first DMEM access port.
+pub const DMEMD: u32 = 0x1C4;
+/// The distance between the registers of two consecutive DMEM access ports.
+pub const DMEM_PORT_STRIDE: u32 = 0x08;
+/// The number of DMEM access ports.
+pub const DMEM_PORTS: usize = 4;
+
+/// The [`CPUCTL`] bit that starts the processor at [`BOOTVEC`] when written.
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+/// [`BOOTVEC`]: constant.BOOTVEC.html
+pub const CPUCTL_STARTCPU: u32 = 1 << 1;
+/// The [`CPUCTL`] bit that performs a soft reset, which halts the processor,
+/// when written.
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+pub const CPUCTL_SRESET: u32 = 1 << 2;
+/// The [`CPUCTL`] bit that performs a hard reset, which halts the processor,
+/// when written.
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+pub const CPUCTL_HRESET: u32 = 1 << 3;
+/// The [`CPUCTL`] bit that is set while the processor is halted.
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+pub const CPUCTL_HALTED: u32 = 1 << 4;
+/// The [`CPUCTL`] bit that is set while the processor is sleeping.
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+pub const CPUCTL_SLEEPING: u32 = 1 << 5;
+
+/// The bits of a memory port control register that hold the accessed address.
+pub const MEMC_ADDRESS_MASK: u32 = 0xFFFC;
+/// The memory port control bit that advances the address on writes to the
+/// data register.
+pub const MEMC_WRITE_AUTOINC: u32 = 1 << 24;
+/// The memory port control bit that advances the address on reads from the
+/// data register.
+pub const MEMC_READ_AUTOINC: u32 = 1 << 25;
+/// The IMEM port control bit that marks uploaded code as secret.
+pub const IMEMC_SECRET: u32 = 1 << 28;
+
+/// An error that occurs when code cannot be uploaded through
+/// [`Host::upload_code`].
+///
+/// [`Host::upload_code`]: struct.Host.html#method.upload_code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UploadError {
+    /// There is no IMEM access port with the given index.
+    InvalidPort(usize),
+    /// The given physical or virtual address is not the start of a page.
+    UnalignedAddress(u32),
+    /// The code ends at the given physical address, beyond the given size of
+    /// IMEM in bytes.
+    OutOfBounds(usize, usize),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::InvalidPort(port) => write!(f, "there is no IMEM access port {}", port),
+            UploadError::UnalignedAddress(address) => {
+                write!(f, "the address {:#x} is not the start of a page", address)
+            }
+            UploadError::OutOfBounds(end, imem_size) => write!(
+                f,
+                "the code ends at {:#x}, beyond the {:#x} bytes of IMEM",
+                end, imem_size
+            ),
+        }
+    }
+}
+
+// The registers that are shared between the host and the firmware.
+struct SharedRegisters {
+    irqstat: u32,
+    irqmode: u32,
+    irqmask: u32,
+    irqdest: u32,
+    mailbox: [u32; 2],
+}
+
+impl SharedRegisters {
+    fn read(&self, offset: u32) -> u32 {
+        match offset {
+            IRQSTAT => self.irqstat,
+            IRQMODE => self.irqmode,
+            IRQMASK => self.irqmask,
+            IRQDEST => self.irqdest,
+            MAILBOX0 => self.mailbox[0],
+            MAILBOX1 => self.mailbox[1],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, value: u32) {
+        match offset {
+            IRQSSET => self.irqstat |= value & 0xFFFF,
+            IRQSCLR => self.irqstat &= !value,
+            IRQMODE => self.irqmode = value,
+            IRQMSET => self.irqmask |= value & 0xFFFF,
+            IRQMCLR => self.irqmask &= !value,
+            IRQDEST => self.irqdest = value,
+            MAILBOX0 => self.mailbox[0] = value,
+            MAILBOX1 => self.mailbox[1] = value,
+            _ => {}
+        }
+    }
+
+    // Gets the raised and enabled interrupts that are routed to the given
+    // destination.
+    fn pending(&self, host: bool) -> u32 {
+        let routed = if host { self.irqdest } else { !self.irqdest };
+
+        self.irqstat & self.irqmask & routed & 0xFFFF
+    }
+}
+
+// A window into the shared registers, which is registered at the I/O bus.
+struct SharedWindow {
+    registers: Rc<RefCell<SharedRegisters>>,
+    offset: u32,
+}
+
+impl Peripheral for SharedWindow {
+    fn read32(&mut self, address: u32) -> u32 {
+        self.registers.borrow().read(self.offset + address)
+    }
+
+    fn write32(&mut self, address: u32, value: u32) {
+        self.registers
+            .borrow_mut()
+            .write(self.offset + address, value);
+    }
+
+    fn interrupt(&self) -> Option<u8> {
+        // Only the window over the interrupt registers asserts lines.
+        if self.offset != IRQSSET {
+            return None;
+        }
+
+        let registers = self.registers.borrow();
+        let pending = registers.pending(false);
+        if pending == 0 {
+            return None;
+        }
+
+        let irq = pending.trailing_zeros();
+        Some((registers.irqdest >> (16 + irq) & 1) as u8)
+    }
+}
+
+// The state of an IMEM or DMEM access port.
+#[derive(Clone, Copy)]
+struct MemoryPort {
+    control: u32,
+    tag: u32,
+}
+
+impl MemoryPort {
+    // Gets the accessed address and advances it, if requested for the kind
+    // of access.
+    fn advance(&mut self, autoinc: u32) -> u32 {
+        let address = self.control & MEMC_ADDRESS_MASK;
+        if self.control & autoinc != 0 {
+            let next = address.wrapping_add(4) & MEMC_ADDRESS_MASK;
+            self.control = self.control & !MEMC_ADDRESS_MASK | next;
+        }
+
+        address
+    }
+}
+
+/// The register file through which the host controls the Falcon, like a
+/// driver does over the PRI bus.
+///
+/// The host uploads code and data through the IMEM and DMEM access ports,
+/// starts and halts the processor through [`CPUCTL`] and exchanges messages
+/// and interrupts with the firmware. The interrupt and mailbox registers are
+/// shared with the firmware, which accesses them through the I/O space once
+/// the interface was attached to it.
+///
+/// ```
+/// use faucon_asm::assembler::assemble_str;
+/// use faucon_emu::cpu::{Cpu, CpuConfig};
+/// use faucon_emu::host::*;
+///
+/// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+/// let mut host = Host::new();
+/// host.attach(&mut cpu.io, 0).unwrap();
+///
+/// // Write the mailbox to signal completion and halt.
+/// let mut code = assemble_str(
+///     "iord $r1 I[$r0 + 0x40]\n\
+///      add b32 $r1 $r1 1\n\
+///      iowr I[$r0 + 0x44] $r1\n\
+///      exit",
+/// )
+/// .unwrap();
+/// code.resize(0x100, 0);
+/// host.upload_code(&mut cpu, 0, 0, 0, &code, false).unwrap();
+///
+/// host.write(&mut cpu, MAILBOX0, 41);
+/// host.write(&mut cpu, BOOTVEC, 0);
+/// host.write(&mut cpu, CPUCTL, CPUCTL_STARTCPU);
+/// while host.read(&mut cpu, CPUCTL) & CPUCTL_HALTED == 0 {
+///     cpu.step();
+/// }
+/// assert_eq!(host.read(&mut cpu, MAILBOX1), 42);
+/// ```
+///
+/// [`CPUCTL`]: constant.CPUCTL.html
+pub struct Host {
+    /// The registers that are shared with the firmware.
+    shared: Rc<RefCell<SharedRegisters>>,
+    /// The address at which the processor starts execution.
+    bootvec: u32,
+    /// The IMEM access ports.
+    imem_ports: [MemoryPort; IMEM_PORTS],
+    /// The DMEM access ports.
+    dmem_ports: [MemoryPort; DMEM_PORTS],
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        let port = MemoryPort { control: 0, tag: 0 };
+
+        Host {
+            shared: Rc::new(RefCell::new(SharedRegisters {
+                irqstat: 0,
+                irqmode: 0,
+                irqmask: 0,
+                irqdest: 0,
+                mailbox: [0; 2],
+            })),
+            bootvec: 0,
+            imem_ports: [port; IMEM_PORTS],
+            dmem_ports: [port; DMEM_PORTS],
+        }
+    }
+}
+
+impl Host {
+    /// Creates a new register file with all registers cleared.
+    pub fn new() -> Self {
+        Host::default()
+    }
+
+    /// Maps the registers that are shared with the firmware into the I/O space
+    /// at the given base address, keeping their offsets.
+    ///
+    /// Interrupts that are routed to the Falcon are then delivered through the
+    /// I/O bus.
+    pub fn attach(&self, bus: &mut IoBus, base: u32) -> Result<(), OverlapError> {
+        for range in &[IRQSSET..IRQDEST + 4, MAILBOX0..MAILBOX1 + 4] {
+            bus.register(
+                base + range.start..base + range.end,
+                Box::new(SharedWindow {
+                    registers: self.shared.clone(),
+                    offset: range.start,
+                }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Uploads code to the given page-aligned physical address in IMEM through
+    /// an IMEM access port, the way a driver does.
+    ///
+    /// The port is set up to advance its address on writes and, if `secret`
+    /// is set, to mark the uploaded pages as secret. Before every page, its
+    /// virtual page starting from `vaddress` is written to the tag register.
+    /// The last word is padded with zeroes, but the last page is not, so it
+    /// only becomes usable once it was uploaded completely.
+    ///
+    /// Nothing is uploaded if the port doesn't exist, either address is not
+    /// the start of a page or the code doesn't fit into IMEM.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    /// use faucon_emu::host::*;
+    /// use faucon_emu::memory::SECRET_CODE_PATTERN;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// let mut host = Host::new();
+    ///
+    /// host.upload_code(&mut cpu, 0, 0x100, 0x1000, &[0xAA; 0x200], true)
+    ///     .unwrap();
+    /// assert_eq!(cpu.memory.tlb.lookup(0x1004).unwrap().0, 0x1);
+    /// assert_eq!(cpu.memory.tlb.lookup(0x1104).unwrap().0, 0x2);
+    /// assert_eq!(cpu.memory.read_code_unprivileged(0x104), SECRET_CODE_PATTERN);
+    ///
+    /// assert_eq!(
+    ///     host.upload_code(&mut cpu, 1, 0x7F00, 0, &[0; 0x200], false),
+    ///     Err(UploadError::OutOfBounds(0x8100, 0x8000)),
+    /// );
+    /// assert_eq!(
+    ///     host.upload_code(&mut cpu, 1, 0x100, 0x1080, &[0; 0x100], false),
+    ///     Err(UploadError::UnalignedAddress(0x1080)),
+    /// );
+    /// assert_eq!(
+    ///     host.upload_code(&mut cpu, IMEM_PORTS, 0, 0, &[0; 0x100], false),
+    ///     Err(UploadError::InvalidPort(IMEM_PORTS)),
+    /// );
+    /// ```
+    pub fn upload_code(
+        &mut self,
+        cpu: &mut Cpu,
+        port: usize,
+        address: u16,
+        vaddress: u32,
+        code: &[u8],
+        secret: bool,
+    ) -> Result<(), UploadError> {
+        if port >= IMEM_PORTS {
+            return Err(UploadError::InvalidPort(port));
+        }
+        for &address in &[address as u32, vaddress] {
+            if address & 0xFF != 0 {
+                return Err(UploadError::UnalignedAddress(address));
+            }
+        }
+
+        let end = address as usize + code.len();
+        if end > cpu.imem_size() {
+            return Err(UploadError::OutOfBounds(end, cpu.imem_size()));
+        }
+
+        let stride = port as u32 * IMEM_PORT_STRIDE;
+        let mut control = address as u32 | MEMC_WRITE_AUTOINC;
+        if secret {
+            control |= IMEMC_SECRET;
+        }
+        self.write(cpu, IMEMC + stride, control);
+
+        for (offset, word) in code.chunks(4).enumerate() {
+            let offset = offset as u32 * 4;
+            if offset & 0xFF == 0 {
+                self.write(cpu, IMEMT + stride, (vaddress + offset) >> 8);
+            }
+
+            let mut bytes = [0; 4];
+            bytes[..word.len()].copy_from_slice(word);
+            self.write(cpu, IMEMD + stride, u32::from_le_bytes(bytes));
+        }
+
+        Ok(())
+    }
+
+    /// Reads from the external memory that is attached to the given DMA port,
+    /// like a driver accesses the buffers it exchanges with the firmware.
+    ///
+    /// ```
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    /// use faucon_emu::dma::SparseMemory;
+    /// use faucon_emu::host::Host;
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.dma_engine
+    ///     .attach(0, Box::new(SparseMemory::new(1 << 30)))
+    ///     .unwrap();
+    ///
+    /// let mut host = Host::new();
+    /// host.write_external(&mut cpu, 0, 0x3000_0000, &[0xAB; 4]);
+    ///
+    /// let mut buffer = [0; 4];
+    /// host.read_external(&cpu, 0, 0x3000_0000, &mut buffer);
+    /// assert_eq!(buffer, [0xAB; 4]);
+    /// ```
+    pub fn read_external(&self, cpu: &Cpu, port: u8, address: u64, buffer: &mut [u8]) {
+        cpu.dma_engine.read(port, address, buffer);
+    }
+
+    /// Writes to the external memory that is attached to the given DMA port,
+    /// like a driver fills the buffers that the firmware transfers.
+    pub fn write_external(&mut self, cpu: &mut Cpu, port: u8, address: u64, data: &[u8]) {
+        cpu.dma_engine.write(port, address, data);
+    }
+
+    /// Checks whether any raised and enabled interrupt is routed to the host.
+    pub fn interrupt_pending(&self) -> bool {
+        self.shared.borrow().pending(true) != 0
+    }
+
+    /// Reads the register at the given offset.
+    ///
+    /// Reads from data registers of memory ports may advance their addresses.
+    pub fn read(&mut self, cpu: &mut Cpu, offset: u32) -> u32 {
+        if let Some((port, register)) = imem_port(offset) {
+            let port = &mut self.imem_ports[port];
+            return match register {
+                IMEMC => port.control,
+                IMEMD => {
+                    let address = port.advance(MEMC_READ_AUTOINC) as usize;
+                    if address < cpu.imem_size() {
+                        cpu.memory.read_code_unprivileged(address as u16)
+                    } else {
+                        0
+                    }
+                }
+                IMEMT => port.tag,
+                _ => 0,
+            };
+        }
+        if let Some((port, register)) = dmem_port(offset) {
+            let port = &mut self.dmem_ports[port];
+            return match register {
+                DMEMC => port.control,
+                DMEMD => {
+                    let address = port.advance(MEMC_READ_AUTOINC);
+                    if (address as usize) < cpu.dmem_size() {
+                        cpu.memory.read_data_word(address)
+                    } else {
+                        0
+                    }
+                }
+                _ => 0,
+            };
+        }
+
+        match offset {
+            CPUCTL => match cpu.state() {
+                ExecutionState::Running => 0,
+                ExecutionState::Stopped => CPUCTL_HALTED,
+                ExecutionState::Sleeping => CPUCTL_SLEEPING,
+            },
+            BOOTVEC => self.bootvec,
+            HWCFG => cpu.config().uc_caps(),
+            HWCFG1 => cpu.config().uc_caps2(),
+            _ => self.shared.borrow().read(offset),
+        }
+    }
+
+    /// Writes the register at the given offset.
+    ///
+    /// Writes to data registers of memory ports may advance their addresses.
+    pub fn write(&mut self, cpu: &mut Cpu, offset: u32, value: u32) {
+        if let Some((port, register)) = imem_port(offset) {
+            let port = &mut self.imem_ports[port];
+            match register {
+                IMEMC => port.control = value,
+                IMEMD => {
+                    let secret = port.control & IMEMC_SECRET != 0;
+                    let address = port.advance(MEMC_WRITE_AUTOINC) as usize;
+                    if address < cpu.imem_size() {
+                        cpu.upload_code(address as u16, port.tag << 8, value, secret);
+                    }
+                }
+                IMEMT => port.tag = value & 0xFF,
+                _ => {}
+            }
+            return;
+        }
+        if let Some((port, register)) = dmem_port(offset) {
+            let port = &mut self.dmem_ports[port];
+            match register {
+                DMEMC => port.control = value,
+                DMEMD => {
+                    let address = port.advance(MEMC_WRITE_AUTOINC);
+                    if (address as usize) < cpu.dmem_size() {
+                        cpu.memory.write_data_word(address, value);
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match offset {
+            CPUCTL => {
+                if value & (CPUCTL_SRESET | CPUCTL_HRESET) != 0 {
+                    cpu.reset();
+                }
+                if value & CPUCTL_STARTCPU != 0 {
+                    cpu.registers[PC] = self.bootvec;
+                    cpu.start();
+                }
+            }
+            BOOTVEC => self.bootvec = value,
+            _ => self.shared.borrow_mut().write(offset, value),
+        }
+    }
+}
+
+// Splits an offset within the registers of the IMEM access ports into the
+// index of the port and the offset of the register of the first port.
+fn imem_port(offset: u32) -> Option<(usize, u32)> {
+    let port = offset.checked_sub(IMEMC)? / IMEM_PORT_STRIDE;
+    if port as usize >= IMEM_PORTS {
+        return None;
+    }
+
+    Some((port as usize, offset - port * IMEM_PORT_STRIDE))
+}
+
+// Splits an offset within the registers of the DMEM access ports into the
+// index of the port and the offset of the register of the first port.
+fn dmem_port(offset: u32) -> Option<(usize, u32)> {
+    let port = offset.checked_sub(DMEMC)? / DMEM_PORT_STRIDE;
+    if port as usize >= DMEM_PORTS {
+        return None;
+    }
+
+    Some((port as usize, offset - port * DMEM_PORT_STRIDE))
+}
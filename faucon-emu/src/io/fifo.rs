@@ -0,0 +1,168 @@
+//! Emulation of the FIFO through which the host submits methods to the Falcon.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::Peripheral;
+
+/// The offset of the register that holds the method of the entry at the head.
+pub const FIFO_METHOD: u32 = 0x00;
+/// The offset of the register that holds the data of the entry at the head.
+pub const FIFO_DATA: u32 = 0x04;
+/// The offset of the register that consumes the entry at the head when written.
+pub const FIFO_ACK: u32 = 0x08;
+/// The offset of the register that holds the number of pending entries.
+pub const FIFO_COUNT: u32 = 0x0C;
+/// The offset of the register that holds the index of the entry at the head.
+///
+/// Writing it consumes all the entries up to the given index.
+pub const FIFO_HEAD: u32 = 0x10;
+/// The offset of the register that holds the index of the next free entry.
+pub const FIFO_TAIL: u32 = 0x14;
+
+/// The size of the address range that a [`Fifo`] occupies in the I/O space.
+///
+/// [`Fifo`]: struct.Fifo.html
+pub const FIFO_SIZE: u32 = 0x18;
+
+/// An error that occurs when a method is pushed to a full [`Fifo`].
+///
+/// [`Fifo`]: struct.Fifo.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FifoFull;
+
+// The state of a FIFO, shared between the host and the peripheral.
+struct FifoState {
+    methods: Vec<u32>,
+    data: Vec<u32>,
+    head: usize,
+    tail: usize,
+    count: usize,
+    line: u8,
+}
+
+impl FifoState {
+    fn pop(&mut self) {
+        if self.count != 0 {
+            self.head = (self.head + 1) % self.methods.len();
+            self.count -= 1;
+        }
+    }
+}
+
+/// The FIFO through which the host submits methods to the Falcon.
+///
+/// Methods and their data are kept in a ring of entries, where the host pushes
+/// entries at the tail and the firmware consumes them from the head through
+/// the registers of the peripheral, such as [`FIFO_METHOD`] and [`FIFO_ACK`].
+/// While entries are pending, the FIFO asserts its interrupt line.
+///
+/// Clones of a FIFO share the same entries, so that one of them can be
+/// registered at the I/O bus while the other one is kept by the host.
+///
+/// ```
+/// use faucon_emu::io::{Fifo, IoBus, FIFO_ACK, FIFO_COUNT, FIFO_DATA, FIFO_METHOD, FIFO_SIZE};
+///
+/// let fifo = Fifo::new(4, 0);
+/// let mut bus = IoBus::new();
+/// bus.register(0x400..0x400 + FIFO_SIZE, Box::new(fifo.clone())).unwrap();
+///
+/// fifo.push(0x100, 0xCAFE).unwrap();
+/// fifo.push(0x104, 0xBABE).unwrap();
+/// assert_eq!(bus.read32(0x400 + FIFO_COUNT), 2);
+/// assert_eq!(bus.read32(0x400 + FIFO_METHOD), 0x100);
+/// assert_eq!(bus.read32(0x400 + FIFO_DATA), 0xCAFE);
+///
+/// bus.write32(0x400 + FIFO_ACK, 0);
+/// assert_eq!(bus.read32(0x400 + FIFO_METHOD), 0x104);
+/// assert_eq!(fifo.len(), 1);
+/// ```
+///
+/// [`FIFO_METHOD`]: constant.FIFO_METHOD.html
+/// [`FIFO_ACK`]: constant.FIFO_ACK.html
+#[derive(Clone)]
+pub struct Fifo {
+    state: Rc<RefCell<FifoState>>,
+}
+
+impl Fifo {
+    /// Creates a new, empty FIFO with room for the given number of entries,
+    /// which asserts the given interrupt line while entries are pending.
+    pub fn new(capacity: usize, line: u8) -> Self {
+        assert!(capacity > 0, "A FIFO must have room for at least one entry");
+
+        Fifo {
+            state: Rc::new(RefCell::new(FifoState {
+                methods: vec![0; capacity],
+                data: vec![0; capacity],
+                head: 0,
+                tail: 0,
+                count: 0,
+                line,
+            })),
+        }
+    }
+
+    /// Submits a method along with its data at the tail of the FIFO.
+    pub fn push(&self, method: u32, data: u32) -> Result<(), FifoFull> {
+        let mut state = self.state.borrow_mut();
+        if state.count == state.methods.len() {
+            return Err(FifoFull);
+        }
+
+        let tail = state.tail;
+        state.methods[tail] = method;
+        state.data[tail] = data;
+        state.tail = (tail + 1) % state.methods.len();
+        state.count += 1;
+
+        Ok(())
+    }
+
+    /// Gets the number of entries that were not yet consumed by the firmware.
+    pub fn len(&self) -> usize {
+        self.state.borrow().count
+    }
+
+    /// Checks whether all entries were consumed by the firmware.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Peripheral for Fifo {
+    fn read32(&mut self, address: u32) -> u32 {
+        let state = self.state.borrow();
+        match address {
+            FIFO_METHOD => state.methods[state.head],
+            FIFO_DATA => state.data[state.head],
+            FIFO_COUNT => state.count as u32,
+            FIFO_HEAD => state.head as u32,
+            FIFO_TAIL => state.tail as u32,
+            _ => 0,
+        }
+    }
+
+    fn write32(&mut self, address: u32, value: u32) {
+        let mut state = self.state.borrow_mut();
+        match address {
+            FIFO_ACK => state.pop(),
+            FIFO_HEAD => {
+                let head = value as usize % state.methods.len();
+                while state.count != 0 && state.head != head {
+                    state.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn interrupt(&self) -> Option<u8> {
+        let state = self.state.borrow();
+        if state.count != 0 {
+            Some(state.line)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Implementation of the Falcon I/O space, which is backed by peripherals.
+
+use std::fmt;
+use std::ops::Range;
+
+pub use fifo::*;
+pub use timer::*;
+
+mod fifo;
+mod timer;
+
+/// A device that is mapped into the I/O space of the Falcon.
+///
+/// Peripherals model the engine-specific registers that firmware accesses
+/// through `iord` and `iowr`. Addresses are given relative to the start of the
+/// range the peripheral was registered at.
+pub trait Peripheral {
+    /// Reads the word at the given address.
+    fn read32(&mut self, address: u32) -> u32;
+
+    /// Writes a word to the given address.
+    fn write32(&mut self, address: u32, value: u32);
+
+    /// Gets the interrupt line, from 0 through 2, that the peripheral asserts,
+    /// if any.
+    ///
+    /// The interrupt is delivered to the processor as long as it is asserted
+    /// and the line is enabled, so the peripheral should stop asserting it
+    /// once the firmware handled it.
+    fn interrupt(&self) -> Option<u8> {
+        None
+    }
+
+    /// Advances the state of the peripheral by the given number of processor
+    /// cycles.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// Gets the number of wait states of the given address, which is the number
+    /// of cycles that synchronous accesses through `iords` and `iowrs` stall
+    /// the processor for.
+    ///
+    /// ```
+    /// use faucon_asm::assembler::assemble_str;
+    /// use faucon_emu::cpu::{Cpu, CpuConfig};
+    /// use faucon_emu::io::Peripheral;
+    ///
+    /// struct Slow(u32);
+    ///
+    /// impl Peripheral for Slow {
+    ///     fn read32(&mut self, _: u32) -> u32 {
+    ///         self.0
+    ///     }
+    ///
+    ///     fn write32(&mut self, _: u32, value: u32) {
+    ///         self.0 = value;
+    ///     }
+    ///
+    ///     fn wait_states(&self, _: u32) -> u32 {
+    ///         10
+    ///     }
+    /// }
+    ///
+    /// let mut cpu = Cpu::new(CpuConfig::new()).unwrap();
+    /// cpu.io.register(0x2000..0x2004, Box::new(Slow(0))).unwrap();
+    /// let source = "mov $r1 0x2000\nmov $r2 0x1234\niowr I[$r1] $r2\niowrs I[$r1] $r2";
+    /// let code = assemble_str(source).unwrap();
+    /// cpu.load_code(&code);
+    ///
+    /// cpu.start();
+    /// cpu.step();
+    /// cpu.step();
+    /// let start = cpu.cycles();
+    /// cpu.step();
+    /// let posted = cpu.cycles() - start;
+    /// cpu.step();
+    /// assert_eq!(cpu.cycles() - start - posted, posted + 10);
+    /// assert_eq!(cpu.io.read32(0x2000), 0x1234);
+    /// ```
+    fn wait_states(&self, _address: u32) -> u32 {
+        0
+    }
+
+    /// Checks whether the peripheral requests a reset of the processor, which
+    /// is only reported once.
+    fn take_reset(&mut self) -> bool {
+        false
+    }
+}
+
+/// An error that occurs when a [`Peripheral`] is registered at an address range
+/// that overlaps with the range of another one.
+///
+/// [`Peripheral`]: trait.Peripheral.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverlapError {
+    /// The address range of the peripheral that is already registered.
+    pub existing: Range<u32>,
+}
+
+impl fmt::Display for OverlapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the address range overlaps with {:#x}..{:#x}",
+            self.existing.start, self.existing.end
+        )
+    }
+}
+
+/// The bus that dispatches accesses to the Falcon I/O space to [`Peripheral`]s.
+///
+/// Every peripheral is registered at a range of addresses. Reads from addresses
+/// without a peripheral return zeroes and writes to them are discarded.
+///
+/// ```
+/// use faucon_emu::io::{IoBus, Peripheral};
+///
+/// struct Scratch(u32);
+///
+/// impl Peripheral for Scratch {
+///     fn read32(&mut self, _: u32) -> u32 {
+///         self.0
+///     }
+///
+///     fn write32(&mut self, _: u32, value: u32) {
+///         self.0 = value;
+///     }
+/// }
+///
+/// let mut bus = IoBus::new();
+/// bus.register(0x1000..0x1004, Box::new(Scratch(0))).unwrap();
+/// assert!(bus.register(0x1000..0x1100, Box::new(Scratch(0))).is_err());
+///
+/// bus.write32(0x1000, 0xCAFE);
+/// assert_eq!(bus.read32(0x1000), 0xCAFE);
+/// assert_eq!(bus.read32(0x2000), 0);
+/// ```
+///
+/// [`Peripheral`]: trait.Peripheral.html
+#[derive(Default)]
+pub struct IoBus {
+    /// The registered peripherals along with their address ranges.
+    peripherals: Vec<(Range<u32>, Box<dyn Peripheral>)>,
+    /// The number of reads and writes that were dispatched to peripherals.
+    accesses: u64,
+}
+
+impl IoBus {
+    /// Creates a new I/O bus without any peripherals.
+    pub fn new() -> Self {
+        IoBus::default()
+    }
+
+    /// Registers a [`Peripheral`] that handles accesses to the given range of
+    /// addresses.
+    ///
+    /// [`Peripheral`]: trait.Peripheral.html
+    pub fn register(
+        &mut self,
+        range: Range<u32>,
+        peripheral: Box<dyn Peripheral>,
+    ) -> Result<(), OverlapError> {
+        if let Some((existing, _)) = self
+            .peripherals
+            .iter()
+            .find(|(existing, _)| existing.start < range.end && range.start < existing.end)
+        {
+            return Err(OverlapError {
+                existing: existing.clone(),
+            });
+        }
+
+        self.peripherals.push((range, peripheral));
+        Ok(())
+    }
+
+    /// Reads a word from the I/O space.
+    pub fn read32(&mut self, address: u32) -> u32 {
+        let value = match self.find(address) {
+            Some((start, peripheral)) => peripheral.read32(address - start),
+            None => return 0,
+        };
+        self.accesses += 1;
+
+        value
+    }
+
+    /// Gets the number of reads and writes that were dispatched to
+    /// peripherals, which may have changed their state.
+    pub(crate) fn accesses(&self) -> u64 {
+        self.accesses
+    }
+
+    /// Gets the interrupt lines that are asserted by peripherals.
+    pub fn interrupts(&self) -> Vec<u8> {
+        self.peripherals
+            .iter()
+            .filter_map(|(_, peripheral)| peripheral.interrupt())
+            .collect()
+    }
+
+    /// Advances the state of all peripherals by the given number of processor
+    /// cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        for (_, peripheral) in self.peripherals.iter_mut() {
+            peripheral.tick(cycles);
+        }
+    }
+
+    /// Gets the number of wait states of the given address in the I/O space,
+    /// which is zero for addresses without a peripheral.
+    pub fn wait_states(&self, address: u32) -> u32 {
+        self.peripherals
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map_or(0, |(range, peripheral)| {
+                peripheral.wait_states(address - range.start)
+            })
+    }
+
+    /// Checks whether any peripheral requests a reset of the processor.
+    pub fn take_reset(&mut self) -> bool {
+        let mut reset = false;
+        for (_, peripheral) in self.peripherals.iter_mut() {
+            reset |= peripheral.take_reset();
+        }
+
+        reset
+    }
+
+    /// Writes a word to the I/O space.
+    pub fn write32(&mut self, address: u32, value: u32) {
+        match self.find(address) {
+            Some((start, peripheral)) => peripheral.write32(address - start, value),
+            None => return,
+        }
+        self.accesses += 1;
+    }
+
+    // Finds the peripheral that handles the given address along with the start
+    // of its address range.
+    fn find(&mut self, address: u32) -> Option<(u32, &mut Box<dyn Peripheral>)> {
+        self.peripherals
+            .iter_mut()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, peripheral)| (range.start, peripheral))
+    }
+}
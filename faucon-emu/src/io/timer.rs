@@ -0,0 +1,154 @@
+//! Emulation of the periodic timer and the watchdog timer of the Falcon.
+
+use super::Peripheral;
+
+/// The offset of the register that holds the reload value of the periodic timer.
+pub const TIMER_PERIOD: u32 = 0x00;
+/// The offset of the register that holds the remaining cycles of the periodic
+/// timer until it expires.
+pub const TIMER_TIME: u32 = 0x04;
+/// The offset of the register that enables the periodic timer through bit 0.
+pub const TIMER_ENABLE: u32 = 0x08;
+/// The offset of the register that holds the remaining cycles of the watchdog
+/// timer until it resets the processor.
+pub const WATCHDOG_TIME: u32 = 0x0C;
+/// The offset of the register that enables the watchdog timer through bit 0.
+pub const WATCHDOG_ENABLE: u32 = 0x10;
+/// The offset of the register whose bit 0 indicates that the periodic timer
+/// expired. Writing the bit clears it.
+pub const TIMER_STATUS: u32 = 0x14;
+
+/// The size of the address range that a [`Timer`] occupies in the I/O space.
+///
+/// [`Timer`]: struct.Timer.html
+pub const TIMER_SIZE: u32 = 0x18;
+
+/// The periodic timer and the watchdog timer of the Falcon.
+///
+/// Both timers count down the cycles that the processor executes. When the
+/// periodic timer expires, it asserts its interrupt line until the firmware
+/// clears [`TIMER_STATUS`] and starts over from [`TIMER_PERIOD`]. When the
+/// watchdog timer expires, it disables itself and resets the processor, unless
+/// the firmware wrote [`WATCHDOG_TIME`] again in time.
+///
+/// ```
+/// use faucon_emu::io::*;
+///
+/// let mut timer = Timer::new(0);
+/// timer.write32(TIMER_PERIOD, 100);
+/// timer.write32(TIMER_TIME, 100);
+/// timer.write32(TIMER_ENABLE, 1);
+///
+/// timer.tick(99);
+/// assert_eq!(timer.interrupt(), None);
+/// timer.tick(1);
+/// assert_eq!(timer.interrupt(), Some(0));
+/// assert_eq!(timer.read32(TIMER_TIME), 100);
+///
+/// timer.write32(TIMER_STATUS, 1);
+/// assert_eq!(timer.interrupt(), None);
+/// ```
+///
+/// [`TIMER_STATUS`]: constant.TIMER_STATUS.html
+/// [`TIMER_PERIOD`]: constant.TIMER_PERIOD.html
+/// [`WATCHDOG_TIME`]: constant.WATCHDOG_TIME.html
+#[derive(Clone, Debug)]
+pub struct Timer {
+    /// The interrupt line that the periodic timer asserts.
+    line: u8,
+    /// The reload value of the periodic timer.
+    period: u32,
+    /// The remaining cycles until the periodic timer expires.
+    time: u32,
+    /// Whether the periodic timer is counting.
+    enabled: bool,
+    /// Whether the periodic timer expired and the firmware didn't notice yet.
+    expired: bool,
+    /// The remaining cycles until the watchdog timer expires.
+    watchdog_time: u32,
+    /// Whether the watchdog timer is counting.
+    watchdog_enabled: bool,
+    /// Whether the watchdog timer expired and the reset wasn't reported yet.
+    reset: bool,
+}
+
+impl Timer {
+    /// Creates new, disabled timers, where the periodic timer asserts the
+    /// given interrupt line.
+    pub fn new(line: u8) -> Self {
+        Timer {
+            line,
+            period: 0,
+            time: 0,
+            enabled: false,
+            expired: false,
+            watchdog_time: 0,
+            watchdog_enabled: false,
+            reset: false,
+        }
+    }
+}
+
+impl Peripheral for Timer {
+    fn read32(&mut self, address: u32) -> u32 {
+        match address {
+            TIMER_PERIOD => self.period,
+            TIMER_TIME => self.time,
+            TIMER_ENABLE => self.enabled as u32,
+            WATCHDOG_TIME => self.watchdog_time,
+            WATCHDOG_ENABLE => self.watchdog_enabled as u32,
+            TIMER_STATUS => self.expired as u32,
+            _ => 0,
+        }
+    }
+
+    fn write32(&mut self, address: u32, value: u32) {
+        match address {
+            TIMER_PERIOD => self.period = value,
+            TIMER_TIME => self.time = value,
+            TIMER_ENABLE => self.enabled = value & 1 != 0,
+            WATCHDOG_TIME => self.watchdog_time = value,
+            WATCHDOG_ENABLE => self.watchdog_enabled = value & 1 != 0,
+            TIMER_STATUS => self.expired &= value & 1 == 0,
+            _ => {}
+        }
+    }
+
+    fn interrupt(&self) -> Option<u8> {
+        if self.expired {
+            Some(self.line)
+        } else {
+            None
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        // A periodic timer without a period would expire on every cycle.
+        if self.enabled && self.period != 0 {
+            if cycles < self.time {
+                self.time -= cycles;
+            } else {
+                let overshoot = cycles - self.time;
+                self.time = self.period - overshoot % self.period;
+                self.expired = true;
+            }
+        }
+
+        if self.watchdog_enabled {
+            if cycles < self.watchdog_time {
+                self.watchdog_time -= cycles;
+            } else {
+                self.watchdog_time = 0;
+                self.watchdog_enabled = false;
+                self.reset = true;
+            }
+        }
+    }
+
+    fn take_reset(&mut self) -> bool {
+        let reset = self.reset;
+        self.reset = false;
+
+        reset
+    }
+}
@@ -3,4 +3,7 @@ extern crate enum_primitive;
 
 pub mod cpu;
 pub mod dma;
+pub mod host;
+pub mod io;
 pub mod memory;
+pub mod scp;
@@ -0,0 +1,119 @@
+use std::rc::Rc;
+
+use faucon_asm::{Instruction, MAX_INSTRUCTION_LENGTH};
+
+use super::PAGE_SIZE;
+
+/// A cache of decoded instructions in Falcon code space.
+///
+/// Instructions are memoized by the physical address they are located at, so
+/// that repeatedly executing the same code doesn't require the instruction bytes
+/// to be decoded over and over again. Because of that, cached entries are not
+/// affected by changes in the TLB and stay valid as long as the underlying code
+/// bytes are not modified.
+///
+/// The entries are kept in a table for each physical page, so that looking up
+/// an instruction is a matter of indexing. Cached instructions are shared with
+/// the processor rather than copied out for every execution.
+///
+/// Whenever code is written to IMEM, all instructions overlapping with the
+/// written range must be evicted through [`InstructionCache::invalidate`].
+/// Pages that are uploaded anew or invalidated through `iminv` are evicted as
+/// a whole through [`InstructionCache::invalidate_page`].
+///
+/// [`InstructionCache::invalidate`]: struct.InstructionCache.html#method.invalidate
+/// [`InstructionCache::invalidate_page`]: struct.InstructionCache.html#method.invalidate_page
+#[derive(Default)]
+pub struct InstructionCache {
+    /// The cached instructions, indexed by the physical page and the offset into
+    /// it. Pages without cached instructions have no table.
+    pages: Vec<Vec<Option<Rc<Instruction>>>>,
+}
+
+impl InstructionCache {
+    /// Creates a new, empty instruction cache.
+    pub fn new() -> Self {
+        InstructionCache::default()
+    }
+
+    /// Gets the cached instruction at a given physical address, if any.
+    pub fn get(&self, address: u16) -> Option<&Rc<Instruction>> {
+        let (page, offset) = split_address(address);
+        self.pages.get(page)?.get(offset)?.as_ref()
+    }
+
+    /// Caches a decoded instruction at a given physical address and returns the
+    /// shared entry.
+    pub fn insert(&mut self, address: u16, insn: Instruction) -> Rc<Instruction> {
+        let (page, offset) = split_address(address);
+        if self.pages.len() <= page {
+            self.pages.resize(page + 1, Vec::new());
+        }
+        if self.pages[page].is_empty() {
+            self.pages[page] = vec![None; PAGE_SIZE];
+        }
+
+        let insn = Rc::new(insn);
+        self.pages[page][offset] = Some(insn.clone());
+
+        insn
+    }
+
+    /// Evicts all cached instructions that overlap with `size` bytes starting
+    /// from the given physical address.
+    pub fn invalidate(&mut self, address: u16, size: usize) {
+        // Instructions starting before the address may still reach into the
+        // modified range, so these have to be considered as well.
+        let start = address.saturating_sub(MAX_INSTRUCTION_LENGTH as u16 - 1);
+        let end = address as usize + size;
+
+        for insn_address in start as usize..end {
+            let (page, offset) = split_address(insn_address as u16);
+            if let Some(entry) = self
+                .pages
+                .get_mut(page)
+                .and_then(|page| page.get_mut(offset))
+            {
+                let overlaps = match entry {
+                    Some(insn) => insn_address + insn.len() > address as usize,
+                    None => false,
+                };
+                if overlaps {
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    /// Evicts all cached instructions that overlap with the physical page at
+    /// the given index.
+    ///
+    /// ```
+    /// use faucon_emu::memory::InstructionCache;
+    ///
+    /// let mut cache = InstructionCache::new();
+    /// let insn = faucon_asm::read_instruction(&mut &[0xF8u8, 0x00u8][..]).unwrap();
+    /// cache.insert(0x1FE, insn.clone());
+    /// cache.insert(0x200, insn);
+    ///
+    /// cache.invalidate_page(0x1);
+    /// assert!(cache.get(0x1FE).is_none());
+    /// assert!(cache.get(0x200).is_some());
+    /// ```
+    pub fn invalidate_page(&mut self, page: u8) {
+        self.invalidate((page as u16) << 8, PAGE_SIZE);
+    }
+
+    /// Evicts all cached instructions.
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
+
+// Splits a physical address into the index of its page and the offset into it.
+fn split_address(address: u16) -> (usize, usize) {
+    (
+        address as usize / PAGE_SIZE,
+        address as usize & (PAGE_SIZE - 1),
+    )
+}
@@ -0,0 +1,160 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Representation of the Falcon data space in DMEM.
+///
+/// This is a linear piece of memory with byte-oriented addressing, used for
+/// variables and stack memory. It can be accessed in 8-bit, 16-bit and 32-bit
+/// quantities through the typed accessors, which carry out accesses like the
+/// hardware does:
+///
+/// - Address bits beyond the size of DMEM are ignored, so that addresses wrap
+///   around at its end.
+/// - Unaligned reads are rounded down to the alignment of their size.
+/// - Unaligned writes are rounded down as well, and the written value is
+///   shifted by the misalignment, which corrupts the bytes around it.
+///
+/// Whether an access stays within the bounds of DMEM can be checked through
+/// [`Dmem::contains`] before carrying it out.
+///
+/// ```
+/// use faucon_emu::memory::Dmem;
+///
+/// let mut dmem = Dmem::new(0x100);
+/// dmem.write32(0x10, 0xDEAD_BEEF);
+/// assert_eq!(dmem.read16(0x12), 0xDEAD);
+/// assert_eq!(dmem.read8(0x110), 0xEF);
+///
+/// dmem.write16(0x21, 0xCAFE);
+/// assert_eq!(dmem.read16(0x20), 0xFE00);
+///
+/// assert!(dmem.contains(0xFC, 4));
+/// assert!(!dmem.contains(0xFE, 4));
+/// ```
+///
+/// [`Dmem::contains`]: struct.Dmem.html#method.contains
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dmem {
+    /// The bytes in data space.
+    bytes: Vec<u8>,
+}
+
+impl Dmem {
+    /// Creates a data space of the given size in bytes, which must be a
+    /// multiple of 4, initialized to all zeroes.
+    pub fn new(size: usize) -> Self {
+        assert_eq!(size & 3, 0, "DMEM must consist of whole words");
+
+        Dmem {
+            bytes: vec![0; size],
+        }
+    }
+
+    /// Gets the size of the data space in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Checks whether the data space has no bytes at all.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Checks whether all of the `size` bytes starting from the given address
+    /// are within the bounds of the data space, without wrapping around.
+    pub fn contains(&self, address: u32, size: u32) -> bool {
+        address as u64 + size as u64 <= self.bytes.len() as u64
+    }
+
+    /// Gets the offset into the data space that the given address refers to,
+    /// aligned down to the given power of two size.
+    pub fn offset(&self, address: u32, size: u32) -> usize {
+        if self.bytes.is_empty() {
+            return 0;
+        }
+
+        (address & !(size - 1)) as usize % self.bytes.len()
+    }
+
+    /// Wraps the given address around at the end of the data space, like the
+    /// hardware ignores the address bits beyond its size.
+    pub fn wrap(&self, address: u32) -> u32 {
+        if self.bytes.is_empty() {
+            return 0;
+        }
+
+        (address as usize % self.bytes.len()) as u32
+    }
+
+    /// Reads a byte from the given address.
+    pub fn read8(&self, address: u32) -> u8 {
+        self.bytes[self.offset(address, 1)]
+    }
+
+    /// Reads a halfword from the given address, which is aligned down.
+    pub fn read16(&self, address: u32) -> u16 {
+        LittleEndian::read_u16(&self.bytes[self.offset(address, 2)..])
+    }
+
+    /// Reads a word from the given address, which is aligned down.
+    pub fn read32(&self, address: u32) -> u32 {
+        LittleEndian::read_u32(&self.bytes[self.offset(address, 4)..])
+    }
+
+    /// Writes a byte to the given address.
+    pub fn write8(&mut self, address: u32, value: u8) {
+        let offset = self.offset(address, 1);
+        self.bytes[offset] = value;
+    }
+
+    /// Writes a halfword to the given address, which is aligned down.
+    ///
+    /// At unaligned addresses, only the low byte of the value is written,
+    /// shifted by the misalignment, and the other byte is cleared.
+    pub fn write16(&mut self, address: u32, mut value: u16) {
+        if address & 1 != 0 {
+            value = (value & 0xFF) << ((address as u16 & 1) * 8);
+        }
+
+        let offset = self.offset(address, 2);
+        LittleEndian::write_u16(&mut self.bytes[offset..], value);
+    }
+
+    /// Writes a word to the given address, which is aligned down.
+    ///
+    /// At unaligned addresses, only the bytes of the value that fit into the
+    /// aligned halfword or byte are written, shifted by the misalignment, and
+    /// the other bytes are cleared.
+    pub fn write32(&mut self, address: u32, mut value: u32) {
+        if address & 1 != 0 {
+            value = (value & 0xFF) << ((address & 3) * 8);
+        } else if address & 2 != 0 {
+            value = (value & 0xFFFF) << ((address & 3) * 8);
+        }
+
+        let offset = self.offset(address, 4);
+        LittleEndian::write_u32(&mut self.bytes[offset..], value);
+    }
+
+    /// Reads a word-aligned block of bytes starting from the given address,
+    /// wrapping around at the end of the data space.
+    pub fn read(&self, address: u32, buffer: &mut [u8]) {
+        for (index, chunk) in buffer.chunks_mut(4).enumerate() {
+            let word = self.read32(address.wrapping_add(index as u32 * 4));
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    /// Writes a word-aligned block of bytes starting from the given address,
+    /// wrapping around at the end of the data space.
+    pub fn write(&mut self, address: u32, data: &[u8]) {
+        for (index, chunk) in data.chunks(4).enumerate() {
+            let offset = self.offset(address.wrapping_add(index as u32 * 4), 4);
+            self.bytes[offset..offset + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+
+    /// Gets the bytes in data space.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+}
@@ -2,13 +2,56 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 
+pub use cache::*;
+pub use dmem::*;
 pub use tlb::*;
 
+mod cache;
+mod dmem;
 mod tlb;
 
 /// The size of a physical memory page in Falcon code space.
 pub const PAGE_SIZE: usize = 0x100;
 
+/// The value that is read from secret code by anything but secret code itself.
+pub const SECRET_CODE_PATTERN: u32 = 0xDEAD_5EC1;
+
+/// The accesses that the executed code may perform on a region of memory, as
+/// reported by [`Cpu::permissions`].
+///
+/// [`Cpu::permissions`]: ../cpu/struct.Cpu.html#method.permissions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    /// Whether the region can be read.
+    pub read: bool,
+    /// Whether the region can be written.
+    pub write: bool,
+    /// Whether instructions can be fetched from the region.
+    pub execute: bool,
+}
+
+/// The previous contents of a word in memory that was overwritten.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Overwritten {
+    /// A word at an aligned address in data space, along with a mask of its
+    /// bytes that were initialized, if initialization is tracked.
+    Data(u32, u32, Option<u8>),
+    /// A word at a physical address in code space.
+    Code(u16, u32),
+}
+
+/// A position in the undo log of the [`Memory`], up to which writes can be
+/// undone.
+///
+/// [`Memory`]: struct.Memory.html
+#[derive(Debug)]
+pub(crate) struct Savepoint {
+    /// The number of logged words in front of the savepoint.
+    position: usize,
+    /// Whether the log was started for this savepoint.
+    outermost: bool,
+}
+
 /// Representation of the Falcon memory space.
 ///
 /// It consists of separate memory spaces for data and code,
@@ -17,55 +60,107 @@ pub const PAGE_SIZE: usize = 0x100;
 pub struct Memory {
     /// Representation of the Falcon data space.
     ///
-    /// This is a linear piece of memory with byte-oriented addressing,
-    /// used for variables and stack memory. It can be addressed in 8-bit,
-    /// 16-bit and 32-bit quantities where unaligned memory access leads
-    /// to data corruption.
-    pub data: Vec<u8>,
+    /// Writes that are not carried out through the methods of the memory are
+    /// neither undone nor tracked for initialization.
+    pub data: Dmem,
     /// Representation of the Falcon code space.
     ///
     /// Code segment uses primitive paging in 0x100 byte pages.
     /// Address translation is done in hidden TLB memory, with one entry
     /// for each physical page.
+    ///
+    /// When modifying code directly instead of going through
+    /// [`Memory::write_code_addr`], the [`Memory::insn_cache`] must be
+    /// invalidated manually.
+    ///
+    /// [`Memory::write_code_addr`]: struct.Memory.html#method.write_code_addr
+    /// [`Memory::insn_cache`]: struct.Memory.html#structfield.insn_cache
     pub code: Vec<u8>,
+    /// A cache of decoded instructions in code space.
+    ///
+    /// It memoizes instructions by their physical address so that stepping
+    /// through code doesn't decode the same bytes again and again.
+    pub insn_cache: InstructionCache,
     /// Representation of the hidden Falcon TLB.
     ///
     /// The TLB is used for address translation via an array of entries,
     /// each representing a physical page index.
     pub tlb: Tlb,
+    /// The words that were overwritten since the undo log was started, if it
+    /// was.
+    undo_log: Option<Vec<Overwritten>>,
+    /// Whether each byte in data space was written since initialization
+    /// tracking was started, if it was.
+    initialized: Option<Vec<bool>>,
 }
 
 impl Memory {
-    /// Creates a new instance of the memory, initialized to all zeroes by
-    /// default.
-    pub fn new() -> Self {
-        // TODO: Compute these values through UC_CAPS MMIO.
+    /// Creates a new instance of the memory with the given sizes of code and
+    /// data space in bytes, initialized to all zeroes by default.
+    pub fn new(code_size: usize, data_size: usize) -> Self {
         Memory {
-            data: vec![0; 0x4000],
-            code: vec![0; PAGE_SIZE * 0x80],
-            tlb: Tlb::new(),
+            data: Dmem::new(data_size),
+            code: vec![0; code_size],
+            insn_cache: InstructionCache::new(),
+            tlb: Tlb::new(code_size / PAGE_SIZE),
+            undo_log: None,
+            initialized: None,
+        }
+    }
+
+    /// Starts tracking which bytes in data space are initialized, where all of
+    /// them are considered uninitialized until they are written.
+    ///
+    /// Only writes through the methods of the memory are tracked, not direct
+    /// modifications of [`Memory::data`].
+    ///
+    /// ```
+    /// use faucon_emu::memory::Memory;
+    ///
+    /// let mut memory = Memory::new(0x100, 0x100);
+    /// assert!(memory.is_initialized(0x10, 4));
+    ///
+    /// memory.track_initialization();
+    /// memory.write_data_halfword(0x10, 0xCAFE);
+    /// assert!(memory.is_initialized(0x10, 2));
+    /// assert!(!memory.is_initialized(0x10, 4));
+    ///
+    /// // Accesses wrap around at the end of data space.
+    /// memory.write_data(0xFC, &[0; 8]);
+    /// assert!(memory.is_initialized(0xFE, 4));
+    /// ```
+    ///
+    /// [`Memory::data`]: struct.Memory.html#structfield.data
+    pub fn track_initialization(&mut self) {
+        self.initialized = Some(vec![false; self.data.len()]);
+    }
+
+    /// Checks whether all of the `size` bytes starting from the given address in
+    /// data space were written since initialization tracking was started.
+    ///
+    /// Without initialization tracking, all bytes are considered initialized.
+    pub fn is_initialized(&self, address: u32, size: u32) -> bool {
+        match &self.initialized {
+            Some(initialized) => self
+                .data_bytes(address, size)
+                .all(|index| initialized[index]),
+            None => true,
         }
     }
 
     /// Reads a byte from a given address in Falcon data space.
     pub fn read_data_byte(&self, address: u32) -> u8 {
-        self.data[address as usize]
+        self.data.read8(address)
     }
 
     /// Reads a halfword from a given address in Falcon data space.
-    pub fn read_data_halfword(&self, mut address: u32) -> u16 {
-        // Enforce aligned memory access.
-        address &= !1;
-
-        LittleEndian::read_u16(&self.data[address as usize..])
+    pub fn read_data_halfword(&self, address: u32) -> u16 {
+        self.data.read16(address)
     }
 
     /// Reads a word from a given address in Falcon data space.
-    pub fn read_data_word(&self, mut address: u32) -> u32 {
-        // Enforce aligned memory access.
-        address &= !3;
-
-        LittleEndian::read_u32(&self.data[address as usize..])
+    pub fn read_data_word(&self, address: u32) -> u32 {
+        self.data.read32(address)
     }
 
     /// Reads a word from a given physical address in code space.
@@ -73,41 +168,189 @@ impl Memory {
         LittleEndian::read_u32(&self.code[address as usize..])
     }
 
+    /// Reads a word from a given physical address in code space on behalf of
+    /// an agent that may not read secret code, such as the host or code that
+    /// doesn't execute in Heavy Secure mode.
+    ///
+    /// Words in secret pages read as [`SECRET_CODE_PATTERN`].
+    ///
+    /// ```
+    /// use faucon_emu::memory::{Memory, SECRET_CODE_PATTERN};
+    ///
+    /// let mut memory = Memory::new(0x200, 0x100);
+    /// memory.tlb.get_physical_entry(0x100).map(0x100, true);
+    /// memory.write_code_addr(0x104, 0x1234);
+    ///
+    /// assert_eq!(memory.read_code_addr(0x104), 0x1234);
+    /// assert_eq!(memory.read_code_unprivileged(0x104), SECRET_CODE_PATTERN);
+    /// assert_eq!(memory.read_code_unprivileged(0x4), 0);
+    /// ```
+    ///
+    /// [`SECRET_CODE_PATTERN`]: constant.SECRET_CODE_PATTERN.html
+    pub fn read_code_unprivileged(&self, address: u16) -> u32 {
+        match self.tlb.physical_entry(address) {
+            Some(entry) if entry.is_secret() => SECRET_CODE_PATTERN,
+            _ => self.read_code_addr(address),
+        }
+    }
+
     /// Writes a byte to a given address in Falcon data space.
     pub fn write_data_byte(&mut self, address: u32, value: u8) {
-        self.data[address as usize] = value;
+        self.log_data(address);
+        self.mark_initialized(address, 1);
+        self.data.write8(address, value);
     }
 
     /// Writes a halfword to a given address in Falcon data space.
-    pub fn write_data_halfword(&mut self, mut address: u32, mut value: u16) {
-        // If the address is unaligned, fuck up the written value.
-        if (address & 1) != 0 {
-            value = (value & 0xFF) << (address as u16 & 1) * 8;
-        }
-
-        // Enforce aligned memory access.
-        address &= !1;
-
-        LittleEndian::write_u16(&mut self.data[address as usize..], value);
+    pub fn write_data_halfword(&mut self, address: u32, value: u16) {
+        self.log_data(address);
+        self.mark_initialized(address & !1, 2);
+        self.data.write16(address, value);
     }
 
     /// Writes a word to a given address in Falcon data space.
-    pub fn write_data_word(&mut self, mut address: u32, mut value: u32) {
-        // If the address is unaligned, fuck up the written value.
-        if (address & 1) != 0 {
-            value = (value & 0xFF) << (address & 3) * 8;
-        } else if (address & 2) != 0 {
-            value = (value & 0xFFFF) << (address & 3) * 8;
-        }
+    pub fn write_data_word(&mut self, address: u32, value: u32) {
+        self.log_data(address);
+        self.mark_initialized(address & !3, 4);
+        self.data.write32(address, value);
+    }
 
-        // Enforce aligned memory access.
-        address &= !3;
+    /// Writes a word-aligned buffer to a given address in Falcon data space.
+    pub fn write_data(&mut self, address: u32, data: &[u8]) {
+        for offset in (0..data.len()).step_by(4) {
+            let address = address.wrapping_add(offset as u32);
+            self.log_data(address);
+            self.mark_initialized(address, (data.len() - offset).min(4) as u32);
+        }
 
-        LittleEndian::write_u32(&mut self.data[address as usize..], value);
+        self.data.write(address, data);
     }
 
     /// Writes a word to a given physical address in code space.
     pub fn write_code_addr(&mut self, address: u16, value: u32) {
+        let word = self.read_code_addr(address);
+        if let Some(log) = &mut self.undo_log {
+            log.push(Overwritten::Code(address, word));
+        }
+
         LittleEndian::write_u32(&mut self.code[address as usize..], value);
+        self.insn_cache.invalidate(address, 4);
+    }
+
+    /// Logs the previous contents of all words that are written from now on,
+    /// so that the writes can be undone up to the returned [`Savepoint`].
+    ///
+    /// Savepoints may be nested, where the log is kept until the outermost
+    /// one is released or rolled back.
+    ///
+    /// [`Savepoint`]: struct.Savepoint.html
+    pub(crate) fn savepoint(&mut self) -> Savepoint {
+        match &self.undo_log {
+            Some(log) => Savepoint {
+                position: log.len(),
+                outermost: false,
+            },
+            None => {
+                self.undo_log = Some(Vec::new());
+                Savepoint {
+                    position: 0,
+                    outermost: true,
+                }
+            }
+        }
+    }
+
+    /// Gets the words that were overwritten since the given [`Savepoint`],
+    /// which is released without undoing the writes.
+    ///
+    /// [`Savepoint`]: struct.Savepoint.html
+    pub(crate) fn release(&mut self, savepoint: Savepoint) -> Vec<Overwritten> {
+        if savepoint.outermost {
+            self.undo_log.take().unwrap_or_default()
+        } else {
+            self.undo_log
+                .as_ref()
+                .map_or_else(Vec::new, |log| log[savepoint.position..].to_vec())
+        }
+    }
+
+    /// Undoes all writes since the given [`Savepoint`].
+    ///
+    /// [`Savepoint`]: struct.Savepoint.html
+    pub(crate) fn rollback(&mut self, savepoint: Savepoint) {
+        let log = match &mut self.undo_log {
+            Some(log) => log.split_off(savepoint.position),
+            None => return,
+        };
+        if savepoint.outermost {
+            self.undo_log = None;
+        }
+
+        self.undo(log);
+    }
+
+    /// Restores the words that were overwritten, in the reverse order of the
+    /// writes.
+    pub(crate) fn undo(&mut self, log: Vec<Overwritten>) {
+        for overwritten in log.into_iter().rev() {
+            match overwritten {
+                Overwritten::Data(address, word, initialized) => {
+                    self.data.write32(address, word);
+
+                    let bytes = self.data_bytes(address, 4);
+                    if let (Some(shadow), Some(mask)) = (&mut self.initialized, initialized) {
+                        for (index, byte) in bytes.enumerate() {
+                            shadow[byte] = mask & 1 << index != 0;
+                        }
+                    }
+                }
+                Overwritten::Code(address, word) => {
+                    LittleEndian::write_u32(&mut self.code[address as usize..], word);
+                    self.insn_cache.invalidate(address, 4);
+                }
+            }
+        }
+    }
+
+    // Marks `size` bytes starting from the given address in data space as
+    // initialized, if initialization is tracked.
+    fn mark_initialized(&mut self, address: u32, size: u32) {
+        let bytes = self.data_bytes(address, size);
+        if let Some(initialized) = &mut self.initialized {
+            for index in bytes {
+                initialized[index] = true;
+            }
+        }
+    }
+
+    // Gets the offsets of `size` bytes starting from the given address in data
+    // space, which wrap around at its end like accesses do.
+    fn data_bytes(&self, address: u32, size: u32) -> impl Iterator<Item = usize> {
+        let start = self.data.offset(address, 1);
+        let len = self.data.len();
+        let count = if len == 0 { 0 } else { size as usize };
+
+        (0..count).map(move |index| (start + index) % len)
+    }
+
+    // Logs the previous contents of the word that the given address belongs
+    // to, if the undo log is started.
+    fn log_data(&mut self, address: u32) {
+        if self.undo_log.is_none() {
+            return;
+        }
+
+        let address = self.data.offset(address, 4) as u32;
+        let initialized = self.initialized.as_ref().map(|shadow| {
+            self.data_bytes(address, 4)
+                .enumerate()
+                .fold(0, |mask, (index, byte)| {
+                    mask | (shadow[byte] as u8) << index
+                })
+        });
+        let word = self.data.read32(address);
+        if let Some(log) = &mut self.undo_log {
+            log.push(Overwritten::Data(address, word, initialized));
+        }
     }
 }
@@ -1,3 +1,8 @@
+use std::fmt;
+
+// The number of virtual pages that can be mapped by the TLB.
+const VIRTUAL_PAGES: usize = 0x100;
+
 /// Flag bits for managing access to physical memory pages.
 #[derive(Debug)]
 #[repr(u8)]
@@ -6,12 +11,19 @@ pub enum PageFlag {
     Usable = 1 << 0,
     /// Indicates that the page is mapped but code is still being uploaded.
     Busy = 1 << 1,
+    /// Indicates that the page holds secret code, which executes in
+    /// Heavy Secure mode.
+    ///
+    /// Secret code cannot be read by anything but secret code itself, and
+    /// the page cannot be cleared from the TLB until it is re-uploaded as
+    /// non-secret code, which scrubs its previous contents.
+    Secret = 1 << 2,
 }
 
 /// Potential TLB lookup errors.
 ///
 /// These may occur when doing virtual <-> physical page translations.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum LookupError {
     /// A page fault that occurs when no TLB entries could be matched for a
     /// physical page.
@@ -21,6 +33,15 @@ pub enum LookupError {
     MultiplePageHits,
 }
 
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::NoPageHits => write!(f, "no TLB entry maps the page"),
+            LookupError::MultiplePageHits => write!(f, "multiple TLB entries map the page"),
+        }
+    }
+}
+
 /// The Falcon Translation Lookaside Buffer for mapping code pages in memory.
 ///
 /// It consists of multiple [`TlbEntry`]s, each representing one physical page.
@@ -30,17 +51,45 @@ pub enum LookupError {
 /// entry. If there is more than one match or no match at all, it is considered an
 /// error and a trap should be generated by the CPU.
 ///
+/// Lookups are served from an index of the physical pages that map every
+/// virtual page, so that translating an address takes constant time. The
+/// index is kept up to date as entries are modified.
+///
 /// [`TlbEntry`]: struct.TlbEntry.html
+#[derive(Clone, PartialEq, Eq)]
 pub struct Tlb {
     /// The entries of the TLB, used for page lookup.
     entries: Vec<TlbEntry>,
+    /// The physical pages that map each virtual page.
+    index: Vec<PageHits>,
+    /// The physical page whose entry was lent out for modification along with
+    /// its previous state, which is not yet reflected in the index.
+    pending: Option<(u8, TlbEntry)>,
+}
+
+// The valid entries of the TLB that map a virtual page.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct PageHits {
+    // The number of entries.
+    count: u16,
+    // The physical pages of the first two entries.
+    pages: [u8; 2],
 }
 
 impl Tlb {
-    /// Creates a new instance of the TLB for virtual address translation.
-    pub fn new() -> Self {
+    /// Creates a new instance of the TLB for virtual address translation, with
+    /// an entry for each of the given number of physical pages.
+    pub fn new(pages: usize) -> Self {
         Tlb {
-            entries: vec![TlbEntry::new(); 0x80],
+            entries: vec![TlbEntry::new(); pages],
+            index: vec![
+                PageHits {
+                    count: 0,
+                    pages: [0; 2],
+                };
+                VIRTUAL_PAGES
+            ],
+            pending: None,
         }
     }
 
@@ -49,7 +98,7 @@ impl Tlb {
     ///
     /// [`TlbEntry`]: struct.TlbEntry.html
     pub fn get_physical_entry(&mut self, address: u16) -> &mut TlbEntry {
-        &mut self.entries[(address >> 8) as usize]
+        self.lend((address >> 8) as u8)
     }
 
     /// Translates a virtual address to a physical address.
@@ -69,29 +118,27 @@ impl Tlb {
     /// returned, where `page_index` denotes the physical page index and `entry`
     /// the [`TlbEntry`] that was found.
     ///
+    /// ```
+    /// use faucon_emu::memory::{LookupError, Tlb};
+    ///
+    /// let mut tlb = Tlb::new(0x80);
+    /// tlb.get_physical_entry(0x100).map(0x2000, false);
+    /// tlb.get_physical_entry(0x200).map(0x2000, false);
+    /// assert_eq!(tlb.lookup(0x2010).unwrap_err(), LookupError::MultiplePageHits);
+    ///
+    /// tlb.get_physical_entry(0x100).clear();
+    /// assert_eq!(tlb.lookup(0x2010).unwrap().0, 0x2);
+    /// tlb.get_physical_entry(0x200).map(0x3000, false);
+    /// assert_eq!(tlb.lookup(0x2010).unwrap_err(), LookupError::NoPageHits);
+    /// assert_eq!(tlb.translate_addr(0x3010), Ok(0x210));
+    /// ```
+    ///
     /// [`TlbEntry`]: struct.TlbEntry.html
     /// [`LookupError`]: enum.LookupError.html
     pub fn lookup(&self, address: u32) -> Result<(u8, &TlbEntry), LookupError> {
-        // Calculate the virtual page number to look up.
-        let page_index = (address >> 8) as u16 & ((1 << 8) - 1);
+        let page = self.find((address >> 8) as u16 & ((1 << 8) - 1))?;
 
-        // Find all the valid entries that match the virtual page number.
-        let mut entries = self
-            .entries
-            .iter()
-            .enumerate()
-            .filter(|(_, e)| e.is_valid() && e.virtual_page_number == page_index)
-            .map(|(i, e)| (i as u8, e))
-            .collect::<Vec<_>>();
-
-        // Count the hits and derive the appropriate result.
-        if entries.len() == 1 {
-            Ok(entries.pop().unwrap())
-        } else if entries.len() == 0 {
-            Err(LookupError::NoPageHits)
-        } else {
-            Err(LookupError::MultiplePageHits)
-        }
+        Ok((page, &self.entries[page as usize]))
     }
 
     /// Finds a [`TlbEntry`] that corresponds to the given virtual address
@@ -103,7 +150,7 @@ impl Tlb {
     /// - Bit  30    - Set if multiple pages were hit
     /// - Bit  31    - Set if no pages were hit
     ///
-    /// The above format is generated by VTLB operations on the hardware and
+    /// The above format is generated by IMTAG operations on the hardware and
     /// thus, this method is used to emulate its behavior in instructions.
     ///
     /// [`TlbEntry`]: struct.TlbEntry.html
@@ -140,6 +187,72 @@ impl Tlb {
         result
     }
 
+    /// Builds a status value for the [`TlbEntry`] of the given physical page
+    /// in the following format:
+    ///
+    /// - Bits 0:7   - 0
+    /// - Bits 8:23  - virtual page number
+    /// - Bits 24:26 - flags
+    ///
+    /// The above format is generated by IMBLK operations on the hardware and
+    /// thus, this method is used to emulate its behavior in instructions.
+    /// Pages beyond the end of IMEM are reported as free.
+    ///
+    /// ```
+    /// use faucon_emu::memory::Tlb;
+    ///
+    /// let mut tlb = Tlb::new(0x80);
+    /// tlb.get_physical_entry(0x300).map(0x4200, false);
+    /// assert_eq!(tlb.lookup_block_raw(0x3), 0x2 << 24 | 0x42 << 8);
+    /// assert_eq!(tlb.lookup_block_raw(0xFF), 0);
+    /// ```
+    ///
+    /// [`TlbEntry`]: struct.TlbEntry.html
+    pub fn lookup_block_raw(&self, page: u8) -> u32 {
+        match self.entries.get(page as usize) {
+            Some(entry) => (entry.flags as u32) << 24 | (entry.virtual_page_number as u32) << 8,
+            None => 0,
+        }
+    }
+
+    /// Invalidates the [`TlbEntry`] of the given physical page, as done by
+    /// IMINV operations on the hardware, and indicates whether it was cleared.
+    ///
+    /// Pages that hold secret code cannot be invalidated and neither can pages
+    /// beyond the end of IMEM.
+    ///
+    /// ```
+    /// use faucon_emu::memory::Tlb;
+    ///
+    /// let mut tlb = Tlb::new(0x80);
+    /// tlb.get_physical_entry(0x100).map(0x2000, false);
+    /// tlb.get_physical_entry(0x200).map(0x3000, true);
+    ///
+    /// assert!(tlb.invalidate(0x1));
+    /// assert!(tlb.lookup(0x2000).is_err());
+    /// assert!(!tlb.invalidate(0x2));
+    /// assert!(tlb.lookup(0x3000).is_ok());
+    /// ```
+    ///
+    /// [`TlbEntry`]: struct.TlbEntry.html
+    pub fn invalidate(&mut self, page: u8) -> bool {
+        match self.entries.get(page as usize) {
+            Some(entry) if !entry.is_secret() => {
+                self.get_physical_entry((page as u16) << 8).clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gets the [`TlbEntry`] that corresponds to the given physical address, if
+    /// it is within IMEM.
+    ///
+    /// [`TlbEntry`]: struct.TlbEntry.html
+    pub fn physical_entry(&self, address: u16) -> Option<&TlbEntry> {
+        self.entries.get((address >> 8) as usize)
+    }
+
     /// Finds a [`TlbEntry`] that corresponds to the given virtual address
     /// and returns a mutable reference to it.
     ///
@@ -152,25 +265,75 @@ impl Tlb {
     /// [`TlbEntry`]: struct.TlbEntry.html
     /// [`LookupError`]: enum.LookupError.html
     pub fn lookup_mut(&mut self, address: u32) -> Result<(u8, &mut TlbEntry), LookupError> {
-        // Calculate the virtual page number to look up.
-        let page_index = (address >> 8) as u16 & ((1 << 8) - 1);
+        let page = self.find((address >> 8) as u16 & ((1 << 8) - 1))?;
 
-        // Find all the valid entries that match the virtual page number.
-        let mut entries = self
-            .entries
-            .iter_mut()
-            .enumerate()
-            .filter(|(_, e)| e.is_valid() && e.virtual_page_number == page_index)
-            .map(|(i, e)| (i as u8, e))
-            .collect::<Vec<_>>();
+        Ok((page, self.lend(page)))
+    }
 
-        // Count the hits and derive the appropriate result.
-        if entries.len() == 1 {
-            Ok(entries.pop().unwrap())
-        } else if entries.len() == 0 {
-            Err(LookupError::NoPageHits)
-        } else {
-            Err(LookupError::MultiplePageHits)
+    // Finds the physical page that maps the given virtual page through the
+    // index, accounting for the entry that was lent out since it was updated.
+    fn find(&self, virtual_page: u16) -> Result<u8, LookupError> {
+        let hits = self.index[virtual_page as usize];
+        let mut count = hits.count;
+        let mut page = hits.pages[0];
+
+        if let Some((lent, previous)) = self.pending {
+            let current = self.entries[lent as usize];
+            let was_hit = previous.maps(virtual_page);
+            let is_hit = current.maps(virtual_page);
+
+            if was_hit {
+                count -= 1;
+            }
+            if is_hit {
+                count += 1;
+                page = lent;
+            } else if page == lent {
+                // The other indexed entry is the remaining hit, if any.
+                page = hits.pages[1];
+            }
+        }
+
+        match count {
+            0 => Err(LookupError::NoPageHits),
+            1 => Ok(page),
+            _ => Err(LookupError::MultiplePageHits),
+        }
+    }
+
+    // Lends out the entry of the given physical page for modification, after
+    // bringing the index up to date with the previously lent entry.
+    fn lend(&mut self, page: u8) -> &mut TlbEntry {
+        if let Some((lent, previous)) = self.pending.take() {
+            let current = self.entries[lent as usize];
+            if current != previous {
+                self.reindex(previous.virtual_page_number);
+                self.reindex(current.virtual_page_number);
+            }
+        }
+
+        self.pending = Some((page, self.entries[page as usize]));
+        &mut self.entries[page as usize]
+    }
+
+    // Rebuilds the index entry of the given virtual page from the entries.
+    fn reindex(&mut self, virtual_page: u16) {
+        let hits = match self.index.get_mut(virtual_page as usize) {
+            Some(hits) => hits,
+            None => return,
+        };
+
+        *hits = PageHits {
+            count: 0,
+            pages: [0; 2],
+        };
+        for (page, entry) in self.entries.iter().enumerate() {
+            if entry.maps(virtual_page) {
+                if (hits.count as usize) < hits.pages.len() {
+                    hits.pages[hits.count as usize] = page as u8;
+                }
+                hits.count += 1;
+            }
         }
     }
 }
@@ -178,7 +341,7 @@ impl Tlb {
 /// An entry in the [`Tlb`] that represents a physical code page.
 ///
 /// [`Tlb`]: struct.Tlb.html
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TlbEntry {
     /// The virtual page number corresponding to a physical page.
     pub virtual_page_number: u16,
@@ -199,13 +362,19 @@ impl TlbEntry {
     /// Maps the physical page corresponding to the TLB entry to the virtual page
     /// space the given address belongs to.
     ///
-    /// NOTE: This sets [`PageFlag::Busy`]. It is within the caller's
-    /// responsibility to change this after code has been uploaded.
+    /// NOTE: This sets [`PageFlag::Busy`] and clears [`PageFlag::Usable`]. It is
+    /// within the caller's responsibility to change this after code has been
+    /// uploaded.
+    /// [`PageFlag::Secret`] is set or cleared based on the value of `secret`.
     ///
     /// [`PageFlag::Busy`]: enum.PageFlag.html#variant.Busy
-    pub fn map(&mut self, address: u32, _secret: bool) {
+    /// [`PageFlag::Usable`]: enum.PageFlag.html#variant.Usable
+    /// [`PageFlag::Secret`]: enum.PageFlag.html#variant.Secret
+    pub fn map(&mut self, address: u32, secret: bool) {
         self.virtual_page_number = (address >> 8) as u16 & ((1 << 8) - 1);
         self.set_flag(PageFlag::Busy, true);
+        self.set_flag(PageFlag::Usable, false);
+        self.set_flag(PageFlag::Secret, secret);
     }
 
     /// Toggles a flag in the page settings based on the value of `set`.
@@ -225,11 +394,21 @@ impl TlbEntry {
         (self.flags & flag as u8) != 0
     }
 
+    /// Checks whether the physical page holds secret code.
+    pub fn is_secret(&self) -> bool {
+        self.get_flag(PageFlag::Secret)
+    }
+
     /// Checks if the entry is considered valid.
     pub fn is_valid(&self) -> bool {
         self.flags != 0
     }
 
+    // Checks whether the entry is valid and maps the given virtual page.
+    fn maps(&self, virtual_page: u16) -> bool {
+        self.is_valid() && self.virtual_page_number == virtual_page
+    }
+
     /// Indicates whether the physical page corresponding to the TLB entry
     /// is currently unmapped.
     pub fn is_free(&self) -> bool {
@@ -241,6 +420,10 @@ impl TlbEntry {
     /// NOTE: Pages containing secret code cannot be cleared.
     /// The page has to be re-uploaded with non-secret data first.
     pub fn clear(&mut self) {
+        if self.is_secret() {
+            return;
+        }
+
         self.virtual_page_number = 0;
         self.flags = 0;
     }
@@ -0,0 +1,513 @@
+//! Implementation of the Falcon Secure Co-Processor (SCP).
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use faucon_asm::{Instruction, InstructionKind};
+
+use crate::cpu::SecureFault;
+
+pub use aes::Block;
+pub use secrets::*;
+
+mod aes;
+mod secrets;
+
+/// The number of crypto registers, `$c0` through `$c7`.
+pub const CRYPTO_REGISTERS: usize = 8;
+
+/// The number of secret keys that can be loaded through `csecret`, for each
+/// [`FuseMode`].
+///
+/// [`FuseMode`]: enum.FuseMode.html
+pub const SECRETS: usize = 64;
+
+/// The index of the secret key that signatures of secret code are encrypted
+/// with.
+pub const SIGNING_SECRET: usize = 1;
+
+/// The crypto register that holds the signature of secret code when it is
+/// entered, as verified by the boot ROM.
+pub const SIGNATURE_REGISTER: usize = 6;
+
+/// A command that is issued to the SCP through a `ccmd` instruction.
+///
+/// Registers are identified by their index in the crypto register file. The
+/// AES commands operate on the key held by the register that was selected
+/// through [`Command::KeyReg`].
+///
+/// [`Command::KeyReg`]: enum.Command.html#variant.KeyReg
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Does nothing.
+    Nop,
+    /// Copies the second register into the first one.
+    Mov(u8, u8),
+    /// Loads a block from the input stream, or zeroes if it is empty.
+    XsIn(u8),
+    /// Appends a register to the output stream.
+    XsOut(u8),
+    /// Fills a register with random data.
+    Rnd(u8),
+    /// Records the given number of subsequent commands into script 0.
+    S0Begin(u8),
+    /// Executes script 0 the given number of times.
+    S0Exec(u8),
+    /// Records the given number of subsequent commands into script 1.
+    S1Begin(u8),
+    /// Executes script 1 the given number of times.
+    S1Exec(u8),
+    /// Changes the access permissions of a register.
+    ///
+    /// Access control of crypto registers is not modeled, so this does nothing.
+    Chmod(u8, u8),
+    /// Computes the exclusive or of two registers.
+    Xor(u8, u8),
+    /// Adds an immediate to a register, as a little-endian 128-bit integer.
+    Add(u8, u8),
+    /// Computes the bitwise and of two registers.
+    And(u8, u8),
+    /// Reverses the bytes of the second register into the first one.
+    Rev(u8, u8),
+    /// Multiplies the second register by `x` in GF(2^128), as done to derive
+    /// CMAC subkeys, and stores the product in the first one.
+    GfMul(u8, u8),
+    /// Loads one of the [`SECRETS`] into a register.
+    ///
+    /// Secrets can only be loaded by code that executes in Heavy Secure mode,
+    /// otherwise the register is cleared.
+    ///
+    /// [`SECRETS`]: constant.SECRETS.html
+    Secret(u8, u8),
+    /// Selects the register that holds the key for AES operations.
+    KeyReg(u8),
+    /// Expands the key in the second register into the last round key of its
+    /// key schedule, which is the key that decryption starts from.
+    KExp(u8, u8),
+    /// Recovers the key from the last round key in the second register.
+    KRExp(u8, u8),
+    /// Encrypts the second register and stores the result in the first one.
+    Enc(u8, u8),
+    /// Decrypts the second register and stores the result in the first one.
+    ///
+    /// The key register must hold the last round key, as computed by
+    /// [`Command::KExp`].
+    ///
+    /// [`Command::KExp`]: enum.Command.html#variant.KExp
+    Dec(u8, u8),
+    /// Verifies that the first register holds the code signature, encrypted
+    /// with the key in the second register.
+    ///
+    /// A mismatch raises [`SecureFault::SignatureMismatch`].
+    ///
+    /// [`SecureFault::SignatureMismatch`]: ../cpu/enum.SecureFault.html#variant.SignatureMismatch
+    SigCmp(u8, u8),
+    /// Encrypts the code signature with the key in the second register and
+    /// stores the result in the first one, which is cleared if there is no
+    /// signature.
+    SigEnc(u8, u8),
+    /// Clears the code signature.
+    SigClr,
+}
+
+impl Command {
+    /// Decodes the command that is encoded in the immediate of a `ccmd`
+    /// instruction.
+    ///
+    /// The command is held in bits 10 through 15. Register operands are encoded
+    /// in bits 6 through 8 for the first and bits 0 through 2 for the second
+    /// operand, while immediates occupy bits 0 through 5.
+    ///
+    /// ```
+    /// use faucon_emu::scp::Command;
+    ///
+    /// assert_eq!(Command::decode(0x5042), Some(Command::Enc(1, 2)));
+    /// assert_eq!(Command::decode(0xFC00), None);
+    /// ```
+    pub fn decode(value: u16) -> Option<Self> {
+        let x = (value >> 6 & 0x7) as u8;
+        let y = (value & 0x7) as u8;
+        let imm = (value & 0x3F) as u8;
+
+        let command = match value >> 10 {
+            0x00 => Command::Nop,
+            0x01 => Command::Mov(x, y),
+            0x02 => Command::XsIn(x),
+            0x03 => Command::XsOut(x),
+            0x04 => Command::Rnd(x),
+            0x05 => Command::S0Begin(imm),
+            0x06 => Command::S0Exec(imm),
+            0x07 => Command::S1Begin(imm),
+            0x08 => Command::S1Exec(imm),
+            0x0A => Command::Chmod(x, imm),
+            0x0B => Command::Xor(x, y),
+            0x0C => Command::Add(x, imm),
+            0x0D => Command::And(x, y),
+            0x0E => Command::Rev(x, y),
+            0x0F => Command::GfMul(x, y),
+            0x10 => Command::Secret(x, imm),
+            0x11 => Command::KeyReg(x),
+            0x12 => Command::KExp(x, y),
+            0x13 => Command::KRExp(x, y),
+            0x14 => Command::Enc(x, y),
+            0x15 => Command::Dec(x, y),
+            0x16 => Command::SigCmp(x, y),
+            0x17 => Command::SigEnc(x, y),
+            0x18 => Command::SigClr,
+            _ => return None,
+        };
+
+        Some(command)
+    }
+
+    /// Decodes the command that is issued by a `ccmd` instruction. Other
+    /// instructions don't issue any command.
+    pub fn from_instruction(insn: &Instruction) -> Option<Self> {
+        if insn.kind() != InstructionKind::CCMD {
+            return None;
+        }
+
+        Command::decode(insn.operands()[0].as_imm()? as u16)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mnemonic, operands) = match *self {
+            Command::Nop => ("cnop", vec![]),
+            Command::Mov(x, y) => ("cmov", vec![reg(x), reg(y)]),
+            Command::XsIn(x) => ("cxsin", vec![reg(x)]),
+            Command::XsOut(x) => ("cxsout", vec![reg(x)]),
+            Command::Rnd(x) => ("crnd", vec![reg(x)]),
+            Command::S0Begin(imm) => ("cs0begin", vec![imm8(imm)]),
+            Command::S0Exec(imm) => ("cs0exec", vec![imm8(imm)]),
+            Command::S1Begin(imm) => ("cs1begin", vec![imm8(imm)]),
+            Command::S1Exec(imm) => ("cs1exec", vec![imm8(imm)]),
+            Command::Chmod(x, imm) => ("cchmod", vec![reg(x), imm8(imm)]),
+            Command::Xor(x, y) => ("cxor", vec![reg(x), reg(y)]),
+            Command::Add(x, imm) => ("cadd", vec![reg(x), imm8(imm)]),
+            Command::And(x, y) => ("cand", vec![reg(x), reg(y)]),
+            Command::Rev(x, y) => ("crev", vec![reg(x), reg(y)]),
+            Command::GfMul(x, y) => ("cgfmul", vec![reg(x), reg(y)]),
+            Command::Secret(x, imm) => ("csecret", vec![reg(x), imm8(imm)]),
+            Command::KeyReg(x) => ("ckeyreg", vec![reg(x)]),
+            Command::KExp(x, y) => ("ckexp", vec![reg(x), reg(y)]),
+            Command::KRExp(x, y) => ("ckrexp", vec![reg(x), reg(y)]),
+            Command::Enc(x, y) => ("cenc", vec![reg(x), reg(y)]),
+            Command::Dec(x, y) => ("cdec", vec![reg(x), reg(y)]),
+            Command::SigCmp(x, y) => ("csigcmp", vec![reg(x), reg(y)]),
+            Command::SigEnc(x, y) => ("csigenc", vec![reg(x), reg(y)]),
+            Command::SigClr => ("csigclr", vec![]),
+        };
+
+        write!(f, "{}", mnemonic)?;
+        for operand in operands {
+            write!(f, " {}", operand)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn reg(index: u8) -> String {
+    format!("$c{}", index)
+}
+
+fn imm8(value: u8) -> String {
+    format!("{:#x}", value)
+}
+
+/// Representation of the Falcon Secure Co-Processor.
+///
+/// The SCP performs AES operations on a file of [`CRYPTO_REGISTERS`] 128-bit
+/// registers, whose keys may be derived from [`SECRETS`] that are only
+/// available to authenticated code. Data is moved between the registers and
+/// DMEM through DMA transfers, which are redirected to the input and output
+/// streams of the SCP after a `cxset` instruction.
+///
+/// ```
+/// use faucon_emu::scp::{Command, Scp};
+///
+/// let mut scp = Scp::new();
+/// scp.registers[1] = [0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+///                     0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C];
+/// scp.registers[2] = [0x32, 0x43, 0xF6, 0xA8, 0x88, 0x5A, 0x30, 0x8D,
+///                     0x31, 0x31, 0x98, 0xA2, 0xE0, 0x37, 0x07, 0x34];
+///
+/// scp.execute(Command::KeyReg(1), false).unwrap();
+/// scp.execute(Command::Enc(3, 2), false).unwrap();
+/// assert_eq!(scp.registers[3], [0x39, 0x25, 0x84, 0x1D, 0x02, 0xDC, 0x09, 0xFB,
+///                               0xDC, 0x11, 0x85, 0x97, 0x19, 0x6A, 0x0B, 0x32]);
+///
+/// scp.execute(Command::KExp(1, 1), false).unwrap();
+/// scp.execute(Command::Dec(4, 3), false).unwrap();
+/// assert_eq!(scp.registers[4], scp.registers[2]);
+/// ```
+///
+/// [`CRYPTO_REGISTERS`]: constant.CRYPTO_REGISTERS.html
+/// [`SECRETS`]: constant.SECRETS.html
+#[derive(Clone, Debug)]
+pub struct Scp {
+    /// The crypto registers `$c0` through `$c7`.
+    pub registers: [Block; CRYPTO_REGISTERS],
+    /// The secret keys that can be loaded by authenticated code.
+    secrets: Secrets,
+    /// The index of the register that holds the key for AES operations.
+    key_register: usize,
+    /// The signature of the authenticated code, if any.
+    signature: Option<Block>,
+    /// The scripts of commands that were recorded through `cs0begin` and
+    /// `cs1begin`.
+    scripts: [Vec<Command>; 2],
+    /// The script that is being recorded along with the number of commands
+    /// that are still to be recorded.
+    recording: Option<(usize, u8)>,
+    /// The blocks that were transferred to the SCP and not yet loaded.
+    input: VecDeque<Block>,
+    /// The blocks that were output by the SCP and not yet transferred.
+    output: VecDeque<Block>,
+    /// The state of the generator for random data.
+    rng: u64,
+}
+
+impl Default for Scp {
+    fn default() -> Self {
+        Scp::with_secrets(Secrets::new(FuseMode::Production))
+    }
+}
+
+impl Scp {
+    /// Creates a new instance of the SCP of a production-fused part with all
+    /// secrets set to zeroes.
+    pub fn new() -> Self {
+        Scp::default()
+    }
+
+    /// Creates a new instance of the SCP that loads the given secrets.
+    pub fn with_secrets(secrets: Secrets) -> Self {
+        Scp {
+            registers: [[0; 16]; CRYPTO_REGISTERS],
+            secrets,
+            key_register: 0,
+            signature: None,
+            scripts: [Vec::new(), Vec::new()],
+            recording: None,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            rng: 0x5EED_5C90_0000_0001,
+        }
+    }
+
+    /// Gets the secrets that are loaded through `csecret`.
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Replaces the secrets that are loaded through `csecret`.
+    pub fn set_secrets(&mut self, secrets: Secrets) {
+        self.secrets = secrets;
+    }
+
+    /// Sets the secret key at the given index, which `csecret` loads, for the
+    /// mode the part is fused in.
+    pub fn set_secret(&mut self, index: usize, key: Block) {
+        let mode = self.secrets.mode();
+        self.secrets.set_key(mode, index, key);
+    }
+
+    /// Sets the signature of the code that was authenticated.
+    ///
+    /// Unless the boot ROM is emulated, the emulator doesn't compute signatures
+    /// of secret code by itself, so they must be provided for `csigcmp` and
+    /// `csigenc` to succeed.
+    pub fn set_signature(&mut self, signature: Option<Block>) {
+        self.signature = signature;
+    }
+
+    /// Computes the signature that secret code is signed with.
+    ///
+    /// The code is hashed in the Davies-Meyer construction with AES and the
+    /// hash is encrypted with the secret key at [`SIGNING_SECRET`].
+    ///
+    /// [`SIGNING_SECRET`]: constant.SIGNING_SECRET.html
+    pub fn sign_code(&self, code: &[u8]) -> Block {
+        aes::encrypt(&self.secrets.key(SIGNING_SECRET), &aes::davies_meyer(code))
+    }
+
+    /// Authenticates secret code like the boot ROM does before the code is
+    /// executed in Heavy Secure mode.
+    ///
+    /// The signature of the code is expected in [`SIGNATURE_REGISTER`], where
+    /// a mismatch raises [`SecureFault::SignatureMismatch`]. Otherwise, the
+    /// hash of the code becomes the signature that is used by `csigcmp` and
+    /// `csigenc`.
+    ///
+    /// ```
+    /// use faucon_emu::scp::{Scp, SIGNATURE_REGISTER, SIGNING_SECRET};
+    ///
+    /// let mut scp = Scp::new();
+    /// scp.set_secret(SIGNING_SECRET, [0x42; 16]);
+    ///
+    /// let code = [0xF8, 0x02, 0x00, 0x00];
+    /// assert!(scp.authenticate(&code).is_err());
+    ///
+    /// scp.registers[SIGNATURE_REGISTER] = scp.sign_code(&code);
+    /// assert!(scp.authenticate(&code).is_ok());
+    /// ```
+    ///
+    /// [`SIGNATURE_REGISTER`]: constant.SIGNATURE_REGISTER.html
+    /// [`SecureFault::SignatureMismatch`]: ../cpu/enum.SecureFault.html#variant.SignatureMismatch
+    pub fn authenticate(&mut self, code: &[u8]) -> Result<(), SecureFault> {
+        if self.sign_code(code) != self.registers[SIGNATURE_REGISTER] {
+            return Err(SecureFault::SignatureMismatch);
+        }
+
+        self.signature = Some(aes::davies_meyer(code));
+        Ok(())
+    }
+
+    /// Appends a block to the input stream of the SCP.
+    pub fn push_input(&mut self, block: Block) {
+        self.input.push_back(block);
+    }
+
+    /// Takes the oldest block from the output stream of the SCP, if any.
+    pub fn pop_output(&mut self) -> Option<Block> {
+        self.output.pop_front()
+    }
+
+    /// Executes a [`Command`], or records it if a script is being recorded.
+    ///
+    /// `secure` denotes whether the command is issued by code that executes in
+    /// Heavy Secure mode.
+    ///
+    /// [`Command`]: enum.Command.html
+    pub fn execute(&mut self, command: Command, secure: bool) -> Result<(), SecureFault> {
+        if let Some((script, remaining)) = self.recording {
+            self.scripts[script].push(command);
+            self.recording = if remaining > 1 {
+                Some((script, remaining - 1))
+            } else {
+                None
+            };
+
+            return Ok(());
+        }
+
+        match command {
+            Command::Nop | Command::Chmod(_, _) => {}
+            Command::Mov(x, y) => self.registers[x as usize] = self.registers[y as usize],
+            Command::XsIn(x) => {
+                self.registers[x as usize] = self.input.pop_front().unwrap_or([0; 16])
+            }
+            Command::XsOut(x) => self.output.push_back(self.registers[x as usize]),
+            Command::Rnd(x) => {
+                let mut block = [0; 16];
+                block[..8].copy_from_slice(&self.next_random().to_le_bytes());
+                block[8..].copy_from_slice(&self.next_random().to_le_bytes());
+                self.registers[x as usize] = block;
+            }
+            Command::S0Begin(count) => self.begin_script(0, count),
+            Command::S0Exec(count) => self.execute_script(0, count, secure)?,
+            Command::S1Begin(count) => self.begin_script(1, count),
+            Command::S1Exec(count) => self.execute_script(1, count, secure)?,
+            Command::Xor(x, y) => {
+                let y = self.registers[y as usize];
+                for (a, b) in self.registers[x as usize].iter_mut().zip(y.iter()) {
+                    *a ^= b;
+                }
+            }
+            Command::Add(x, imm) => {
+                let value = u128::from_le_bytes(self.registers[x as usize]);
+                self.registers[x as usize] = value.wrapping_add(imm as u128).to_le_bytes();
+            }
+            Command::And(x, y) => {
+                let y = self.registers[y as usize];
+                for (a, b) in self.registers[x as usize].iter_mut().zip(y.iter()) {
+                    *a &= b;
+                }
+            }
+            Command::Rev(x, y) => {
+                let mut block = self.registers[y as usize];
+                block.reverse();
+                self.registers[x as usize] = block;
+            }
+            Command::GfMul(x, y) => {
+                let value = u128::from_be_bytes(self.registers[y as usize]);
+                let reduction = if value >> 127 != 0 { 0x87 } else { 0 };
+                self.registers[x as usize] = (value << 1 ^ reduction).to_be_bytes();
+            }
+            Command::Secret(x, index) => {
+                self.registers[x as usize] = if secure {
+                    self.secrets.key(index as usize)
+                } else {
+                    [0; 16]
+                };
+            }
+            Command::KeyReg(x) => self.key_register = x as usize,
+            Command::KExp(x, y) => {
+                self.registers[x as usize] = aes::last_round_key(&self.registers[y as usize])
+            }
+            Command::KRExp(x, y) => {
+                self.registers[x as usize] = aes::reverse_key(&self.registers[y as usize])
+            }
+            Command::Enc(x, y) => {
+                let key = self.registers[self.key_register];
+                self.registers[x as usize] = aes::encrypt(&key, &self.registers[y as usize]);
+            }
+            Command::Dec(x, y) => {
+                let key = aes::reverse_key(&self.registers[self.key_register]);
+                self.registers[x as usize] = aes::decrypt(&key, &self.registers[y as usize]);
+            }
+            Command::SigCmp(x, y) => {
+                if self.encrypt_signature(y) != Some(self.registers[x as usize]) {
+                    return Err(SecureFault::SignatureMismatch);
+                }
+            }
+            Command::SigEnc(x, y) => {
+                self.registers[x as usize] = self.encrypt_signature(y).unwrap_or([0; 16]);
+            }
+            Command::SigClr => self.signature = None,
+        }
+
+        Ok(())
+    }
+
+    fn begin_script(&mut self, script: usize, count: u8) {
+        self.scripts[script].clear();
+        if count != 0 {
+            self.recording = Some((script, count));
+        }
+    }
+
+    fn execute_script(
+        &mut self,
+        script: usize,
+        count: u8,
+        secure: bool,
+    ) -> Result<(), SecureFault> {
+        let commands = self.scripts[script].clone();
+        for _ in 0..count {
+            for command in commands.iter() {
+                self.execute(*command, secure)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Encrypts the code signature with the key in the given register.
+    fn encrypt_signature(&self, key: u8) -> Option<Block> {
+        self.signature
+            .map(|signature| aes::encrypt(&self.registers[key as usize], &signature))
+    }
+
+    // Generates random data through a xorshift64* generator. The sequence is
+    // deterministic, so that emulation runs are reproducible.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
@@ -0,0 +1,93 @@
+//! Modeling of the fuses and the secret keys that are burnt into a Falcon.
+
+use super::{Block, SECRETS};
+
+/// The modes a Falcon can be fused in, which determine the key material that
+/// is exposed through `csecret`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FuseMode {
+    /// The part is fused for debugging and exposes the debug keys.
+    Debug,
+    /// The part is fused for production and exposes the production keys.
+    Production,
+}
+
+/// The secret keys of a Falcon, as consumed by the [`Scp`].
+///
+/// Every index holds a pair of keys, one for debug-fused and one for
+/// production-fused parts. Which of them is loaded through `csecret` is
+/// determined by the [`FuseMode`] of the part.
+///
+/// ```
+/// use faucon_emu::scp::{Command, FuseMode, Scp, Secrets};
+///
+/// let mut secrets = Secrets::new(FuseMode::Production);
+/// secrets.set_key(FuseMode::Production, 0x3F, [0xAA; 16]);
+/// secrets.set_key(FuseMode::Debug, 0x3F, [0x55; 16]);
+///
+/// let mut scp = Scp::with_secrets(secrets.clone());
+/// scp.execute(Command::Secret(0, 0x3F), true).unwrap();
+/// assert_eq!(scp.registers[0], [0xAA; 16]);
+///
+/// secrets.set_mode(FuseMode::Debug);
+/// scp.set_secrets(secrets);
+/// scp.execute(Command::Secret(0, 0x3F), true).unwrap();
+/// assert_eq!(scp.registers[0], [0x55; 16]);
+/// ```
+///
+/// [`Scp`]: struct.Scp.html
+/// [`FuseMode`]: enum.FuseMode.html
+#[derive(Clone, Debug)]
+pub struct Secrets {
+    /// The mode the part is fused in.
+    mode: FuseMode,
+    /// The keys of debug-fused parts.
+    debug: [Block; SECRETS],
+    /// The keys of production-fused parts.
+    production: [Block; SECRETS],
+}
+
+impl Secrets {
+    /// Creates the secrets of a part that is fused in the given mode, with all
+    /// keys set to zeroes.
+    pub fn new(mode: FuseMode) -> Self {
+        Secrets {
+            mode,
+            debug: [[0; 16]; SECRETS],
+            production: [[0; 16]; SECRETS],
+        }
+    }
+
+    /// Gets the mode the part is fused in.
+    pub fn mode(&self) -> FuseMode {
+        self.mode
+    }
+
+    /// Sets the mode the part is fused in.
+    pub fn set_mode(&mut self, mode: FuseMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the key at the given index for parts that are fused in the given
+    /// mode.
+    pub fn set_key(&mut self, mode: FuseMode, index: usize, key: Block) {
+        self.keys_mut(mode)[index] = key;
+    }
+
+    /// Gets the key at the given index that is exposed in the mode the part is
+    /// fused in.
+    pub fn key(&self, index: usize) -> Block {
+        match self.mode {
+            FuseMode::Debug => self.debug[index],
+            FuseMode::Production => self.production[index],
+        }
+    }
+
+    // Gets the keys of parts that are fused in the given mode.
+    fn keys_mut(&mut self, mode: FuseMode) -> &mut [Block; SECRETS] {
+        match mode {
+            FuseMode::Debug => &mut self.debug,
+            FuseMode::Production => &mut self.production,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Implementation of the `asm` command, which assembles source files into
+//! section images or relocatable objects.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+
+use faucon_asm::assembler::{Assembler, Program};
+use faucon_asm::image::Format;
+
+const USAGE: &str = "faucon asm [-o <prefix>] [-c | -g] [-O] [-t] [-f bin|ihex|srec] \
+                     [-I <dir>]... [-D <name>[=<value>]]... <source>";
+
+/// Runs the assembler with the given command-line arguments.
+///
+/// Every section of the assembled program is written to
+/// `<prefix>.<section>.bin`, where the prefix defaults to `out`. `-f` selects
+/// Intel HEX (`.hex`) or S-record (`.srec`) files instead. When the program
+/// defines secure regions, they are listed in `<prefix>.secure` along with the
+/// signature blocks, for signing tools to pick up. With `-g`,
+/// the debug information of the code is written to `<prefix>.dbg` as well.
+/// With `-c`, a relocatable object is written to `<prefix>.o` instead. `-O`
+/// optimizes the code for size. `-t` truncates values that don't fit into
+/// their fields with a warning, rather than failing.
+pub fn run(args: &[String]) {
+    if let Err((title, message)) = assemble(args) {
+        error!(title, &message);
+    }
+}
+
+fn assemble(args: &[String]) -> Result<(), (&'static str, String)> {
+    let usage = || ("Usage:", USAGE.to_string());
+
+    let mut assembler = Assembler::new();
+    let mut prefix = "out";
+    let mut object = false;
+    let mut debug_info = false;
+    let mut format = Format::Binary;
+    let mut source = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(value) => prefix = value,
+                None => return Err(usage()),
+            },
+            "-c" => object = true,
+            "-g" => debug_info = true,
+            "-O" => assembler.optimize = true,
+            "-t" => assembler.truncate_immediates = true,
+            "-f" => match args.next().and_then(|value| Format::from_name(value)) {
+                Some(value) => format = value,
+                None => return Err(usage()),
+            },
+            "-I" => match args.next() {
+                Some(value) => assembler.include_paths.push(value.into()),
+                None => return Err(usage()),
+            },
+            "-D" => match args.next().and_then(|value| parse_define(value)) {
+                Some((name, value)) => {
+                    assembler.defines.insert(name.to_string(), value);
+                }
+                None => return Err(usage()),
+            },
+            path if source.is_none() => source = Some(path),
+            _ => return Err(usage()),
+        }
+    }
+    let source = source.ok_or_else(usage)?;
+    if object && debug_info {
+        return Err(usage());
+    }
+
+    if object {
+        let object = assembler
+            .assemble_object_file(source)
+            .map_err(|e| ("Failed to assemble:", format!("\n{}", e.render(true))))?;
+
+        let path = format!("{}.o", prefix);
+        File::create(&path)
+            .and_then(|file| object.write(&mut BufWriter::new(file)))
+            .map_err(|e| ("Failed to write object:", format!("{}: {}", path, e)))?;
+
+        ok!("Assembled:", "{}", path);
+    } else {
+        let program = assembler
+            .assemble_program_file(source)
+            .map_err(|e| ("Failed to assemble:", format!("\n{}", e.render(true))))?;
+        for warning in &program.warnings {
+            warning!("Warning:", "\n{}", warning.render_warning(true));
+        }
+
+        for section in &program.sections {
+            let path = format!("{}.{}.{}", prefix, section.name, format.extension());
+            File::create(&path)
+                .and_then(|file| format.write(section, &mut BufWriter::new(file)))
+                .map_err(|e| ("Failed to write image:", format!("{}: {}", path, e)))?;
+
+            ok!(
+                "Assembled:",
+                "{} ({:#x} bytes at {:#x})",
+                path,
+                section.data.len(),
+                section.origin
+            );
+        }
+
+        if !program.secure_regions.is_empty() {
+            let path = format!("{}.secure", prefix);
+            fs::write(&path, secure_manifest(&program)).map_err(|e| {
+                (
+                    "Failed to write secure regions:",
+                    format!("{}: {}", path, e),
+                )
+            })?;
+
+            ok!("Assembled:", "{}", path);
+        }
+
+        if let (true, Some(info)) = (debug_info, &program.debug_info) {
+            let path = format!("{}.dbg", prefix);
+            File::create(&path)
+                .and_then(|file| info.write(&mut BufWriter::new(file)))
+                .map_err(|e| {
+                    (
+                        "Failed to write debug information:",
+                        format!("{}: {}", path, e),
+                    )
+                })?;
+
+            ok!("Assembled:", "{}", path);
+        }
+    }
+
+    Ok(())
+}
+
+// Lists the secure regions and signature blocks of a program, one per line,
+// in the form `region <name> <section> <start> <end>` and
+// `signature <region> <section> <address> <size>`.
+fn secure_manifest(program: &Program) -> String {
+    let mut manifest = String::new();
+    for region in &program.secure_regions {
+        manifest += &format!(
+            "region {} {} {:#x} {:#x}\n",
+            region.name, region.section, region.start, region.end
+        );
+    }
+    for signature in &program.signatures {
+        manifest += &format!(
+            "signature {} {} {:#x} {:#x}\n",
+            signature.region, signature.section, signature.address, signature.size
+        );
+    }
+
+    manifest
+}
+
+// Parses a define in the form `<name>[=<value>]`, where the value defaults
+// to one.
+fn parse_define(value: &str) -> Option<(&str, i64)> {
+    let index = match value.find('=') {
+        Some(index) => index,
+        None => return Some((value, 1)),
+    };
+    let (name, value) = (&value[..index], &value[index + 1..]);
+
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+
+    Some((name, value))
+}
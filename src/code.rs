@@ -1,12 +1,9 @@
-//! Helpers for reading and uploading executables into the Falcon code segment.
+//! Helpers for reading executables for the Falcon code segment.
 
-use std::convert::TryInto;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
-use faucon_emu::cpu::Cpu;
-
 const CODE_ALIGN_BITS: usize = 8;
 const CODE_ALIGNMENT: usize = 1 << CODE_ALIGN_BITS;
 
@@ -49,40 +46,3 @@ pub fn read_falcon_binary<P: AsRef<Path>>(path: P) -> Box<[u8]> {
 
     binary.into_boxed_slice()
 }
-
-/// Uploads a Falcon binary that was obtained from [`read_falcon_binary`] into the
-/// code segment of the processor.
-///
-/// Returns an error if the binary is too large to fit into the Falcon code segment.
-///
-/// [`read_falcon_binary`]: fn.read_falcon_binary.html
-pub fn upload_to_imem(cpu: &mut Cpu, address: u16, vaddress: u32, binary: &[u8]) -> Result<(), ()> {
-    assert_eq!((address & 0xFC), 0);
-    assert_eq!((vaddress & 0xFC), 0);
-
-    // Check if the binary would fit the Falcon code segment.
-    if binary.len() > cpu.imem_size() {
-        return Err(());
-    }
-
-    for (i, page) in binary.chunks(CODE_ALIGNMENT).enumerate() {
-        upload_page_to_imem(
-            cpu,
-            address + (i << CODE_ALIGN_BITS) as u16,
-            vaddress + (i << CODE_ALIGN_BITS) as u32,
-            page,
-        );
-    }
-
-    Ok(())
-}
-
-fn upload_page_to_imem(cpu: &mut Cpu, address: u16, vaddress: u32, page: &[u8]) {
-    for (offset, word) in page.chunks(4).enumerate() {
-        cpu.upload_code(
-            address + (offset << 2) as u16,
-            vaddress,
-            u32::from_le_bytes(word.try_into().unwrap()),
-        );
-    }
-}
@@ -14,9 +14,20 @@ pub enum Command {
     Repeat,
     /// Steps through a given amount of CPU instructions.
     Step(u32),
+    /// Undoes a given amount of previously executed steps.
+    StepBack(u32),
+    /// Executes instructions until the processor stops running.
+    Continue,
     /// Disassembles the next few instructions starting from the given
     /// address.
     Disassemble(u32, u32),
+    /// Shows the subroutines and handlers that execution is currently in.
+    Backtrace,
+    /// Shows the given amount of most frequently executed instruction kinds
+    /// and addresses.
+    Profile(u32),
+    /// Shows the given amount of words on top of the stack.
+    Stack(u32),
 }
 
 impl FromStr for Command {
@@ -37,7 +48,12 @@ named!(
         | command_exit
         | command_repeat
         | command_step
+        | command_step_back
+        | command_continue
         | command_disassemble
+        | command_backtrace
+        | command_profile
+        | command_stack
     )
 );
 
@@ -83,6 +99,25 @@ named!(
     )
 );
 
+named!(
+    command_step_back<&str, Command>,
+    do_parse!(
+        alt!(complete!(tag_no_case!("back")) | complete!(tag_no_case!("b")))
+            >> count: opt!(preceded!(space1, integer))
+            >> eof!()
+            >> (Command::StepBack(count.unwrap_or(1)))
+    )
+);
+
+named!(
+    command_continue<&str, Command>,
+    do_parse!(
+        alt!(complete!(tag_no_case!("continue")) | complete!(tag_no_case!("c")))
+            >> eof!()
+            >> (Command::Continue)
+    )
+);
+
 named!(
     command_disassemble<&str, Command>,
     do_parse!(
@@ -94,6 +129,35 @@ named!(
     )
 );
 
+named!(
+    command_backtrace<&str, Command>,
+    do_parse!(
+        alt!(complete!(tag_no_case!("backtrace")) | complete!(tag_no_case!("bt")))
+            >> eof!()
+            >> (Command::Backtrace)
+    )
+);
+
+named!(
+    command_profile<&str, Command>,
+    do_parse!(
+        alt!(complete!(tag_no_case!("profile")) | complete!(tag_no_case!("p")))
+            >> count: opt!(preceded!(space1, integer))
+            >> eof!()
+            >> (Command::Profile(count.unwrap_or(10)))
+    )
+);
+
+named!(
+    command_stack<&str, Command>,
+    do_parse!(
+        alt!(complete!(tag_no_case!("stack")) | complete!(tag_no_case!("sk")))
+            >> count: opt!(preceded!(space1, integer))
+            >> eof!()
+            >> (Command::Stack(count.unwrap_or(8)))
+    )
+);
+
 named!(
     integer<&str, u32>,
     alt!(
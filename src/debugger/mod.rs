@@ -1,14 +1,21 @@
 //! Implementation of a CLI debugger for driving the emulator.
 
+use std::collections::HashMap;
+use std::fs;
 use std::io::{stdin, stdout, Write};
 
-use faucon_asm::read_instruction;
-use faucon_emu::cpu::Cpu;
+use faucon_asm::debug_info::DebugInfo;
+use faucon_asm::{disassemble, DecodedAs};
+use faucon_emu::cpu::{Cpu, DebugStop, ExecutionState, FrameKind, SecureFault, SecurityMode, PC};
+use faucon_emu::{dma, scp};
 
 use commands::Command;
 
 mod commands;
 
+/// The number of executed steps that can be undone.
+const HISTORY_SIZE: usize = 0x10000;
+
 /// The debugger used by the faucon emulator.
 ///
 /// The debugger is a bridge between the user and the actual emulator.
@@ -21,6 +28,10 @@ pub struct Debugger {
     falcon: Cpu,
     /// The last command that was processed.
     last_command: Option<Command>,
+    /// The debug information of the emulated binary, if available.
+    debug_info: Option<DebugInfo>,
+    /// The lines of the source files that were read, by their indices.
+    sources: HashMap<usize, Vec<String>>,
 }
 
 impl Debugger {
@@ -28,13 +39,26 @@ impl Debugger {
     /// emulation.
     ///
     /// [`Cpu`]: ../cpu/struct.Cpu.html
-    pub fn new(falcon: Cpu) -> Self {
+    pub fn new(mut falcon: Cpu) -> Self {
+        falcon.start_recording(HISTORY_SIZE);
+
         Debugger {
             falcon,
             last_command: None,
+            debug_info: None,
+            sources: HashMap::new(),
         }
     }
 
+    /// Loads the [`DebugInfo`] of the emulated binary, which is used to show
+    /// the assembly source code that is being executed.
+    ///
+    /// [`DebugInfo`]: ../../faucon_asm/debug_info/struct.DebugInfo.html
+    pub fn load_debug_info(&mut self, debug_info: DebugInfo) {
+        self.debug_info = Some(debug_info);
+        self.sources.clear();
+    }
+
     /// Runs the debugger.
     ///
     /// The debugger reads and processes input in an infinite loop,
@@ -65,7 +89,12 @@ impl Debugger {
                 Ok(Command::Exit) => break,
                 Ok(Command::Repeat) => unreachable!(),
                 Ok(Command::Step(count)) => self.step(count),
+                Ok(Command::StepBack(count)) => self.step_back(count),
+                Ok(Command::Continue) => self.resume(),
                 Ok(Command::Disassemble(address, amount)) => self.disassemble(address, amount),
+                Ok(Command::Backtrace) => self.backtrace(),
+                Ok(Command::Profile(count)) => self.profile(count),
+                Ok(Command::Stack(count)) => self.stack(count),
                 Err(ref e) => error!("Failed to parse command:", "{:?}", e),
             }
 
@@ -81,27 +110,329 @@ impl Debugger {
         ok!("(e)xit/(q)uit", "- Exits the debugger");
         ok!("(r)epeat", "- Repeats the last command");
         ok!("(s)tep [count]", "- Steps through [count|1] instructions.");
+        ok!("(b)ack [count]", "- Undoes the last [count|1] steps.");
+        ok!(
+            "(c)ontinue",
+            "- Executes instructions until the processor sleeps or stops."
+        );
         ok!(
             "(dis)asm [addr] [amount]",
             "- Disassembles the next [amount|10] instructions starting from virtual address [addr]."
         );
+        ok!(
+            "(b)ack(t)race",
+            "- Shows the subroutines and handlers that execution is currently in."
+        );
+        ok!(
+            "(p)rofile [count]",
+            "- Shows the [count|10] most frequently executed instructions and addresses."
+        );
+        ok!(
+            "(s)tac(k) [count]",
+            "- Shows the [count|8] words on top of the stack."
+        );
     }
 
     fn step(&mut self, count: u32) {
         for _ in 0..count {
-            // TODO: Print stepped instruction?
+            if self.falcon.state() != ExecutionState::Running {
+                break;
+            }
+            self.falcon.step();
+            if self.falcon.debug_stop().is_some() {
+                break;
+            }
+        }
+
+        self.report_uninitialized_reads();
+        self.show_location(self.falcon.registers[PC]);
+        self.show_state();
+    }
+
+    fn step_back(&mut self, count: u32) {
+        for _ in 0..count {
+            if let Err(e) = self.falcon.step_back() {
+                warning!("Cannot step back:", "{}", e);
+                break;
+            }
+        }
+
+        self.show_location(self.falcon.registers[PC]);
+        self.show_state();
+    }
+
+    fn resume(&mut self) {
+        let cycles = self.falcon.cycles();
+        let stalled_cycles = self.falcon.stalled_cycles();
+        while self.falcon.state() == ExecutionState::Running {
             self.falcon.step();
+            if self.falcon.debug_stop().is_some() {
+                break;
+            }
+        }
+
+        self.report_uninitialized_reads();
+        info!(
+            "Timing:",
+            "{} cycles, {} of them stalled on I/O",
+            self.falcon.cycles() - cycles,
+            self.falcon.stalled_cycles() - stalled_cycles
+        );
+        self.show_location(self.falcon.registers[PC]);
+        self.show_state();
+    }
+
+    /// Reports the reads of uninitialized DMEM that happened since the last
+    /// report.
+    fn report_uninitialized_reads(&mut self) {
+        for read in self.falcon.take_uninitialized_reads() {
+            warning!(
+                "Uninitialized read:",
+                "{} bytes at {:#x} by {:#06x}{}",
+                read.size,
+                read.address,
+                read.pc,
+                self.symbolize(read.pc)
+            );
+        }
+    }
+
+    /// Tells why the processor doesn't execute instructions, if it doesn't, and
+    /// whether it executes authenticated code.
+    fn show_state(&self) {
+        match self.falcon.state() {
+            ExecutionState::Running => {}
+            ExecutionState::Sleeping => {
+                warning!("Processor is sleeping:", "Waiting for an interrupt")
+            }
+            ExecutionState::Stopped => warning!("Processor is stopped:", "It must be restarted"),
+        }
+
+        match self.falcon.secure_fault() {
+            Some(SecureFault::InvalidEntry) => error!(
+                "Secure fault:",
+                "Secret code was entered elsewhere than at the start of a page"
+            ),
+            Some(SecureFault::LockedRegister) => {
+                error!("Secure fault:", "$cauth was written in Heavy Secure mode")
+            }
+            Some(SecureFault::SignatureMismatch) => {
+                error!("Secure fault:", "The code signature could not be verified")
+            }
+            None => {}
+        }
+        if let Some(DebugStop::UnalignedAccess(access)) = self.falcon.debug_stop() {
+            error!(
+                "Unaligned access:",
+                "{}-byte {:?} of {:?} at {:#x}",
+                access.size,
+                access.kind,
+                access.space,
+                access.address
+            );
+        }
+        if let Some(DebugStop::OutOfBoundsAccess(access)) = self.falcon.debug_stop() {
+            error!(
+                "Out-of-bounds access:",
+                "{}-byte {:?} of {:?} at {:#x}",
+                access.size,
+                access.kind,
+                access.space,
+                access.address
+            );
+        }
+        if let Some(DebugStop::TraceDivergence(index)) = self.falcon.debug_stop() {
+            let divergence = self.falcon.golden_trace().unwrap().divergence().unwrap();
+            error!(
+                "Trace divergence:",
+                "Instruction {} deviated from the reference trace", index
+            );
+            info!("Expected:", "{}", divergence.expected);
+            info!("Actual:", "{}", divergence.actual);
+        }
+        if let Some(DebugStop::PageFault { address, error }) = self.falcon.debug_stop() {
+            error!("Page fault in trap handler:", "{:#x}: {}", address, error);
+        }
+        match self.falcon.debug_stop() {
+            Some(DebugStop::StackOverflow(address)) => {
+                error!("Stack overflow:", "Push to {:#x} below the stack", address)
+            }
+            Some(DebugStop::StackUnderflow(address)) => {
+                error!(
+                    "Stack underflow:",
+                    "Pop from {:#x} above the stack", address
+                )
+            }
+            _ => {}
+        }
+        if self.falcon.security_mode() == SecurityMode::HeavySecure {
+            info!("Heavy Secure mode:", "Executing authenticated code");
+        }
+    }
+
+    /// Shows the label and the source line of the code at the given address,
+    /// if debug information is available.
+    fn show_location(&mut self, address: u32) {
+        let debug_info = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return,
+        };
+
+        let symbol = self.symbolize(address);
+        let line = match debug_info.line(address) {
+            Some(line) => line,
+            None => {
+                println!("{:#06x}{}: no source information", address, symbol);
+                return;
+            }
+        };
+
+        let file = debug_info.files.get(line.file).and_then(Option::as_ref);
+        let lines = self.sources.entry(line.file).or_insert_with(|| {
+            file.and_then(|path| fs::read_to_string(path).ok())
+                .map(|source| source.lines().map(str::to_string).collect())
+                .unwrap_or_default()
+        });
+
+        let name = file.map_or_else(|| "<source>".into(), |path| path.display().to_string());
+        match lines.get(line.line.wrapping_sub(1)) {
+            Some(text) => println!(
+                "{:#06x}{}: {}:{}: {}",
+                address,
+                symbol,
+                name,
+                line.line,
+                text.trim()
+            ),
+            None => println!("{:#06x}{}: {}:{}", address, symbol, name, line.line),
+        }
+    }
+
+    /// Formats the label that the given address belongs to, if debug information
+    /// is available.
+    fn symbolize(&self, address: u32) -> String {
+        let symbol = self
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.symbol(address));
+        match symbol {
+            Some((symbol, 0)) => format!(" <{}>", symbol.name),
+            Some((symbol, offset)) => format!(" <{}+{:#x}>", symbol.name, offset),
+            None => String::new(),
+        }
+    }
+
+    fn backtrace(&self) {
+        println!(
+            "#0  {:#06x}{}",
+            self.falcon.registers[PC],
+            self.symbolize(self.falcon.registers[PC])
+        );
+
+        // Every frame continues at its return address in the frame below it.
+        for (i, frame) in self.falcon.backtrace().iter().rev().enumerate() {
+            let entered = match frame.kind {
+                FrameKind::Call => format!("called {:#06x}", frame.entry),
+                FrameKind::Interrupt(line) => format!("interrupt {}", line),
+                FrameKind::Trap(trap) => format!("trap {:?}", trap),
+            };
+            // Point out return addresses that were overwritten in DMEM.
+            let stored = self.falcon.memory.read_data_word(frame.stack_pointer);
+            let note = if stored == frame.return_address {
+                String::new()
+            } else {
+                format!(", overwritten with {:#06x} on the stack", stored)
+            };
+
+            println!(
+                "#{:<2} {:#06x}{} ({}{})",
+                i + 1,
+                frame.return_address,
+                self.symbolize(frame.return_address),
+                entered,
+                note
+            );
+        }
+    }
+
+    fn stack(&self, count: u32) {
+        let top = self.falcon.stack_top();
+        info!(
+            "Stack:",
+            "{:#x} bytes below {:#06x}",
+            self.falcon.stack_depth(),
+            top
+        );
+
+        for (address, word) in self.falcon.stack().into_iter().take(count as usize) {
+            println!("{:#06x}: {:#010x}{}", address, word, self.symbolize(word));
+        }
+    }
+
+    fn profile(&self, count: u32) {
+        let stats = self.falcon.stats();
+        let total = stats.instructions();
+        if total == 0 {
+            warning!("No profile available:", "No instructions were executed yet");
+            return;
+        }
+
+        info!("Instructions:", "{} executed", total);
+        for (kind, hits) in stats.kinds().into_iter().take(count as usize) {
+            println!(
+                "{:>10} {:>5.1}%  {}",
+                hits,
+                hits as f64 * 100.0 / total as f64,
+                kind
+            );
+        }
+
+        info!("Addresses:", "{} distinct", stats.pcs().len());
+        for (pc, hits) in stats.pcs().into_iter().take(count as usize) {
+            println!(
+                "{:>10} {:>5.1}%  {:#06x}{}",
+                hits,
+                hits as f64 * 100.0 / total as f64,
+                pc,
+                self.symbolize(pc)
+            );
         }
     }
 
     fn disassemble(&mut self, vaddress: u32, amount: u32) {
-        let address = self.falcon.memory.tlb.translate_addr(vaddress).unwrap() as usize;
-        let code = &mut &self.falcon.memory.code[address..];
+        let address = match self.falcon.memory.tlb.translate_addr(vaddress) {
+            Ok(address) => address as usize,
+            Err(e) => {
+                error!("Failed to disassemble code:", "{:#x}: {}", vaddress, e);
+                return;
+            }
+        };
+        let code = &self.falcon.memory.code[address..];
 
-        for _ in 0..amount {
-            match read_instruction(code) {
-                Ok(insn) => println!("{}", insn),
-                Err(faucon_asm::Error::Eof) => break,
+        for result in disassemble(code, vaddress).take(amount as usize) {
+            match result {
+                Ok((pc, insn)) => {
+                    let note = match insn.decoded_as() {
+                        DecodedAs::Defined => String::new(),
+                        DecodedAs::Unpredictable => " (unpredictable)".to_string(),
+                    };
+
+                    // The effect of DMA instructions depends on the register values,
+                    // which are only known for the instruction that executes next.
+                    let request = if pc == self.falcon.registers[PC] {
+                        dma::Request::from_instruction(&insn, &self.falcon.registers)
+                    } else {
+                        None
+                    };
+                    // Crypto commands are hidden in an immediate, so show them decoded.
+                    let comment = request.map(|request| request.to_string()).or_else(|| {
+                        scp::Command::from_instruction(&insn).map(|command| command.to_string())
+                    });
+                    match comment {
+                        Some(comment) => println!("{:#06x}: {}{} ; {}", pc, insn, note, comment),
+                        None => println!("{:#06x}: {}{}", pc, insn, note),
+                    }
+                }
                 Err(e) => {
                     match e {
                         faucon_asm::Error::UnknownInstruction(_) => {
@@ -110,7 +441,9 @@ impl Debugger {
                         faucon_asm::Error::IoError => {
                             error!("Aborting due to error:", "Rust exploded")
                         }
-                        faucon_asm::Error::Eof => {}
+                        faucon_asm::Error::Eof => {
+                            error!("Aborting due to error:", "An instruction is cut off")
+                        }
                     };
                     break;
                 }
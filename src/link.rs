@@ -0,0 +1,89 @@
+//! Implementation of the `link` command, which combines object files into the
+//! final images of their sections.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+
+use faucon_asm::linker::Linker;
+use faucon_asm::object::Object;
+
+const USAGE: &str = "faucon link [-o <prefix>] [--base <section>=<address>]... <object>...";
+
+/// Runs the linker with the given command-line arguments.
+///
+/// Every section of the linked program is written to `<prefix>.<section>.bin`,
+/// where the prefix defaults to `out`.
+pub fn run(args: &[String]) {
+    if let Err((title, message)) = link(args) {
+        error!(title, &message);
+    }
+}
+
+fn link(args: &[String]) -> Result<(), (&'static str, String)> {
+    let usage = || ("Usage:", USAGE.to_string());
+
+    let mut linker = Linker::new();
+    let mut prefix = "out";
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(value) => prefix = value,
+                None => return Err(usage()),
+            },
+            "--base" => match args.next().and_then(|value| parse_base(value)) {
+                Some((section, base)) => {
+                    linker.bases.insert(section.to_string(), base);
+                }
+                None => return Err(usage()),
+            },
+            path => paths.push(path),
+        }
+    }
+    if paths.is_empty() {
+        return Err(usage());
+    }
+
+    let mut objects = Vec::with_capacity(paths.len());
+    for path in paths {
+        let object = File::open(path)
+            .and_then(|file| Object::read(&mut BufReader::new(file)))
+            .map_err(|e| ("Failed to read object:", format!("{}: {}", path, e)))?;
+        objects.push(object);
+    }
+
+    let program = linker
+        .link(&objects)
+        .map_err(|e| ("Failed to link:", e.to_string()))?;
+
+    for section in &program.sections {
+        let path = format!("{}.{}.bin", prefix, section.name);
+        fs::write(&path, &section.data)
+            .map_err(|e| ("Failed to write image:", format!("{}: {}", path, e)))?;
+
+        ok!(
+            "Linked:",
+            "{} ({:#x} bytes at {:#x})",
+            path,
+            section.data.len(),
+            section.origin
+        );
+    }
+
+    Ok(())
+}
+
+// Parses a section base in the form `<section>=<address>`.
+fn parse_base(value: &str) -> Option<(&str, u32)> {
+    let index = value.find('=')?;
+    let (section, address) = (&value[..index], &value[index + 1..]);
+
+    let address = match address.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => address.parse().ok()?,
+    };
+
+    Some((section, address))
+}
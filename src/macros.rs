@@ -23,6 +23,16 @@ macro_rules! info {
     };
 }
 
+macro_rules! warning {
+    ($title:expr, $msg:expr) => {
+        $crate::macros::print($title, $msg, termcolor::Color::Yellow).unwrap();
+    };
+
+    ($title:expr, $msg:expr, $($arg:tt)*) => {
+        warning!($title, format!($msg, $($arg)*).as_str())
+    };
+}
+
 macro_rules! error {
     ($title:expr, $msg:expr) => {
         $crate::macros::print($title, $msg, termcolor::Color::Red).unwrap();
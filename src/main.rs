@@ -4,24 +4,132 @@
 extern crate nom;
 
 use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
 
 use debugger::Debugger;
-use faucon_emu::cpu::Cpu;
+use faucon_asm::debug_info::DebugInfo;
+use faucon_emu::cpu::{parse_trace, Cpu, CpuConfig};
+use faucon_emu::host::Host;
 
 #[macro_use]
 mod macros;
+mod asm;
 mod code;
 mod debugger;
+mod link;
 
 fn main() {
-    let binary = code::read_falcon_binary(env::args().nth(1).unwrap());
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("asm") => asm::run(&args[1..]),
+        Some("link") => link::run(&args[1..]),
+        Some(_) => debug(&args),
+        None => {
+            error!(
+                "Usage:",
+                "faucon <binary> [-g <debug info>] [--valgrind] [--entry <address>] [--golden <trace>] | faucon asm ... | faucon link ..."
+            );
+        }
+    }
+}
+
+const DEBUG_USAGE: &str =
+    "faucon <binary> [-g <debug info>] [--valgrind] [--entry <address>] [--golden <trace>]";
+
+fn debug(args: &[String]) {
+    // Reads of uninitialized DMEM are reported in valgrind mode, execution
+    // starts at the entry point, if any, and stops where it deviates from the
+    // golden trace, if any.
+    let mut valgrind = false;
+    let mut boot_vector = 0;
+    let mut golden_trace = None;
+    let mut positional = Vec::new();
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--valgrind" => valgrind = true,
+            "--entry" => match options.next().and_then(|value| parse_address(value)) {
+                Some(address) => boot_vector = address,
+                None => {
+                    error!("Usage:", DEBUG_USAGE);
+                    return;
+                }
+            },
+            "--golden" => match options.next() {
+                Some(path) => golden_trace = Some(path.clone()),
+                None => {
+                    error!("Usage:", DEBUG_USAGE);
+                    return;
+                }
+            },
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let args = positional;
+
+    let debug_info = match args.as_slice() {
+        [_] => None,
+        [_, flag, path] if flag == "-g" => {
+            match File::open(path).and_then(|file| DebugInfo::read(&mut BufReader::new(file))) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    error!("Failed to read debug information:", "{}: {}", path, e);
+                    return;
+                }
+            }
+        }
+        _ => {
+            error!("Usage:", DEBUG_USAGE);
+            return;
+        }
+    };
 
-    let mut cpu = Cpu::new();
-    if let Err(()) = code::upload_to_imem(&mut cpu, 0, 0, &binary) {
-        error!("Failed to upload code:", "The binary is too large!");
+    let binary = code::read_falcon_binary(&args[0]);
+
+    let mut cpu = match Cpu::new(CpuConfig {
+        track_uninitialized: valgrind,
+        boot_vector,
+        ..CpuConfig::new()
+    }) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            error!("Invalid processor configuration:", "{}", e);
+            return;
+        }
+    };
+    if let Err(e) = Host::new().upload_code(&mut cpu, 0, 0, 0, &binary, false) {
+        error!("Failed to upload code:", "{}", e);
         return;
     }
+    if let Some(path) = golden_trace {
+        match fs::read_to_string(&path) {
+            Ok(trace) => match parse_trace(&trace) {
+                Ok(reference) => cpu.set_golden_trace(reference),
+                Err(e) => {
+                    error!("Failed to parse golden trace:", "{}: {}", path, e);
+                    return;
+                }
+            },
+            Err(e) => {
+                error!("Failed to read golden trace:", "{}: {}", path, e);
+                return;
+            }
+        }
+    }
+    cpu.start();
 
     let mut debugger = Debugger::new(cpu);
+    if let Some(info) = debug_info {
+        debugger.load_debug_info(info);
+    }
     debugger.run();
 }
+
+// Parses an address in decimal or, with a `0x` prefix, in hexadecimal notation.
+fn parse_address(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}